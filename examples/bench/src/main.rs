@@ -0,0 +1,191 @@
+// Measures streaming throughput from an endpoint with different concurrency and batch size
+// settings and recommends the fastest combination for StreamConfig, then compares the parse time
+// of responses with ClientConfig::parallel_ipc_decode off and on, and with
+// ClientConfig::skip_ipc_validation on top.
+//
+// Usage: bench [url] [from_block] [num_blocks]
+// The bearer token is read from the HYPERSYNC_BEARER_TOKEN environment variable if it is set.
+
+use std::{sync::Arc, time::Instant};
+
+use anyhow::{Context, Result};
+use hypersync_client::{
+    net_types::Query, Client, ClientConfig, ExecutionReport, StreamConfig, StreamMetrics,
+};
+
+const CONCURRENCIES: &[usize] = &[4, 8, 16, 32];
+const BATCH_SIZES: &[u64] = &[1_000, 10_000, 50_000];
+
+struct Trial {
+    concurrency: usize,
+    batch_size: u64,
+    blocks_per_sec: f64,
+    mib_per_sec: f64,
+    report: ExecutionReport,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init().unwrap();
+
+    let mut args = std::env::args().skip(1);
+    let url = args
+        .next()
+        .unwrap_or_else(|| "https://eth.hypersync.xyz".to_owned());
+    let from_block = match args.next() {
+        Some(v) => v.parse().context("parse from_block")?,
+        None => 18_000_000,
+    };
+    let num_blocks: u64 = match args.next() {
+        Some(v) => v.parse().context("parse num_blocks")?,
+        None => 100_000,
+    };
+
+    let client_config = ClientConfig {
+        url: Some(url.parse().context("parse url")?),
+        bearer_token: std::env::var("HYPERSYNC_BEARER_TOKEN").ok(),
+        ..Default::default()
+    };
+    let client = Arc::new(Client::new(client_config.clone())?);
+
+    // A small field selection keeps the benchmark about the server and the network instead of
+    // the local conversion of huge payloads.
+    let query: Query = serde_json::from_value(serde_json::json!({
+        "from_block": from_block,
+        "to_block": from_block + num_blocks,
+        "logs": [{}],
+        "field_selection": {
+            "log": ["block_number", "log_index", "address", "topic0"],
+        },
+    }))?;
+
+    println!(
+        "benchmarking {} over blocks [{}, {})",
+        url,
+        from_block,
+        from_block + num_blocks
+    );
+    println!(
+        "{:>11} {:>10} {:>12} {:>9} {:>8} {:>8}",
+        "concurrency", "batch_size", "blocks/s", "MiB/s", "requests", "retries"
+    );
+
+    let mut trials = Vec::new();
+    for &concurrency in CONCURRENCIES {
+        for &batch_size in BATCH_SIZES {
+            let trial = run_trial(&client, &query, concurrency, batch_size, num_blocks)
+                .await
+                .with_context(|| {
+                    format!(
+                        "run trial with concurrency {} and batch size {}",
+                        concurrency, batch_size
+                    )
+                })?;
+            println!(
+                "{:>11} {:>10} {:>12.0} {:>9.1} {:>8} {:>8}",
+                trial.concurrency,
+                trial.batch_size,
+                trial.blocks_per_sec,
+                trial.mib_per_sec,
+                trial.report.num_requests,
+                trial.report.num_retries
+            );
+            trials.push(trial);
+        }
+    }
+
+    // Retries mean the server was pushed beyond what it serves reliably, so settings that
+    // needed them are only recommended if every setting did.
+    let best = trials
+        .iter()
+        .filter(|t| t.report.num_retries == 0)
+        .max_by(|a, b| a.blocks_per_sec.total_cmp(&b.blocks_per_sec))
+        .or_else(|| {
+            trials
+                .iter()
+                .max_by(|a, b| a.blocks_per_sec.total_cmp(&b.blocks_per_sec))
+        })
+        .context("no trials were run")?;
+
+    println!();
+    println!(
+        "recommendation: StreamConfig {{ concurrency: Some({}), batch_size: Some({}), .. }}",
+        best.concurrency, best.batch_size
+    );
+    if best.report.final_batch_size != best.batch_size {
+        println!(
+            "the adaptive batch size settled on {} blocks, consider it as the starting batch_size",
+            best.report.final_batch_size
+        );
+    }
+
+    // Parse time of the recommended settings, decoding the arrow tables of each response one
+    // after another, in parallel and in parallel without validating them.
+    println!();
+    println!(
+        "{:>14} {:>14} {:>12}",
+        "table decoding", "parse time ms", "ms per MiB"
+    );
+    for (name, parallel_ipc_decode, skip_ipc_validation) in [
+        ("sequential", false, false),
+        ("parallel", true, false),
+        ("unvalidated", true, true),
+    ] {
+        let client = Arc::new(Client::new(ClientConfig {
+            parallel_ipc_decode,
+            skip_ipc_validation,
+            ..client_config.clone()
+        })?);
+        let trial = run_trial(
+            &client,
+            &query,
+            best.concurrency,
+            best.batch_size,
+            num_blocks,
+        )
+        .await
+        .context("run parse trial")?;
+        let parse_ms = trial.report.parse_time.as_secs_f64() * 1000.0;
+        let mib = trial.report.decompressed_bytes as f64 / (1024.0 * 1024.0);
+        println!(
+            "{:>14} {:>14.1} {:>12.2}",
+            name,
+            parse_ms,
+            parse_ms / mib.max(f64::EPSILON)
+        );
+    }
+
+    Ok(())
+}
+
+async fn run_trial(
+    client: &Arc<Client>,
+    query: &Query,
+    concurrency: usize,
+    batch_size: u64,
+    num_blocks: u64,
+) -> Result<Trial> {
+    let metrics = Arc::new(StreamMetrics::default());
+    let config = StreamConfig {
+        concurrency: Some(concurrency),
+        batch_size: Some(batch_size),
+        metrics: Some(metrics.clone()),
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    let mut receiver = client.clone().stream_arrow(query.clone(), config).await?;
+    while let Some(res) = receiver.recv().await {
+        res.context("get response")?;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let report = metrics.report();
+    Ok(Trial {
+        concurrency,
+        batch_size,
+        blocks_per_sec: num_blocks as f64 / elapsed,
+        mib_per_sec: report.wire_bytes as f64 / (1024.0 * 1024.0) / elapsed,
+        report,
+    })
+}
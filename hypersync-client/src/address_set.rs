@@ -0,0 +1,257 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use hypersync_format::{Address, InvalidEntry, ParseReport};
+use hypersync_net_types::LogSelection;
+use serde::de::{Deserializer, SeqAccess, Visitor};
+
+/// A deduplicated list of addresses, usually loaded from a file, to build selections from.
+///
+/// Addresses keep the order they were first seen in. Mixed case addresses are validated
+/// against their EIP-55 checksum, all lowercase or all uppercase ones are accepted as is.
+/// Loading fails with a `ParseReport` listing every invalid entry if any entry is malformed.
+#[derive(Debug, Clone, Default)]
+pub struct AddressSet {
+    addresses: Vec<Address>,
+    seen: HashSet<Address>,
+}
+
+impl AddressSet {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads addresses from the first column of a csv file.
+    ///
+    /// The file is read line by line so it doesn't need to fit in memory. A header line is
+    /// skipped if its first column isn't a `0x` prefixed value, empty lines are ignored.
+    pub fn from_csv(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+
+        let mut set = Self::new();
+        let mut invalid = Vec::new();
+
+        for (idx, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.with_context(|| format!("read {}", path.display()))?;
+            let value = line.split(',').next().unwrap_or_default().trim();
+            let value = value.trim_matches('"');
+            if value.is_empty() || (idx == 0 && !value.starts_with("0x")) {
+                continue;
+            }
+            if let Err(error) = set.insert_str(value) {
+                invalid.push(InvalidEntry {
+                    line: idx + 1,
+                    value: value.to_owned(),
+                    error,
+                });
+            }
+        }
+
+        set.finish(invalid)
+            .with_context(|| format!("parse addresses in {}", path.display()))
+    }
+
+    /// Reads addresses from a json file containing an array of strings.
+    ///
+    /// Entries are parsed as they are read so the file isn't buffered. `InvalidEntry::line` is
+    /// the 1-based position of the entry in the array.
+    pub fn from_json(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+
+        let mut de = serde_json::Deserializer::from_reader(BufReader::new(file));
+        let (set, invalid) = de
+            .deserialize_seq(JsonVisitor)
+            .with_context(|| format!("read json array from {}", path.display()))?;
+        de.end()
+            .with_context(|| format!("read json array from {}", path.display()))?;
+
+        set.finish(invalid)
+            .with_context(|| format!("parse addresses in {}", path.display()))
+    }
+
+    /// Parses and inserts an address, returns false if the address was already in the set.
+    pub fn insert_str(&mut self, value: &str) -> hypersync_format::Result<bool> {
+        let address: Address = value.parse()?;
+        check_checksum(value, &address)?;
+        Ok(self.insert(address))
+    }
+
+    /// Inserts an address, returns false if the address was already in the set.
+    pub fn insert(&mut self, address: Address) -> bool {
+        if !self.seen.insert(address.clone()) {
+            return false;
+        }
+        self.addresses.push(address);
+        true
+    }
+
+    /// Number of distinct addresses in the set.
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// Returns true if the set has no addresses.
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Addresses in the order they were first inserted.
+    pub fn addresses(&self) -> &[Address] {
+        &self.addresses
+    }
+
+    /// Consumes the set into its addresses.
+    pub fn into_vec(self) -> Vec<Address> {
+        self.addresses
+    }
+
+    /// Returns log selections matching the addresses, with at most `max_per_selection`
+    /// addresses each so they can be spread over several queries or a query too large for the
+    /// server can be split.
+    pub fn log_selections(&self, max_per_selection: usize) -> Vec<LogSelection> {
+        self.addresses
+            .chunks(max_per_selection.max(1))
+            .map(|chunk| LogSelection {
+                address: chunk.to_vec(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn finish(self, invalid: Vec<InvalidEntry>) -> Result<Self> {
+        if invalid.is_empty() {
+            Ok(self)
+        } else {
+            Err(ParseReport { invalid }.into())
+        }
+    }
+}
+
+/// Validates the EIP-55 checksum of mixed case addresses.
+fn check_checksum(value: &str, address: &Address) -> hypersync_format::Result<()> {
+    let hex = value.trim_start_matches("0x");
+    let has_lower = hex.bytes().any(|b| b.is_ascii_lowercase());
+    let has_upper = hex.bytes().any(|b| b.is_ascii_uppercase());
+    if !(has_lower && has_upper) {
+        return Ok(());
+    }
+
+    let checksummed = alloy_primitives::Address::from_slice(address.as_slice()).to_checksum(None);
+    if checksummed[2..] == *hex {
+        Ok(())
+    } else {
+        Err(hypersync_format::Error::InvalidChecksum(value.to_owned()))
+    }
+}
+
+struct JsonVisitor;
+
+impl<'de> Visitor<'de> for JsonVisitor {
+    type Value = (AddressSet, Vec<InvalidEntry>);
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of address strings")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut set = AddressSet::new();
+        let mut invalid = Vec::new();
+        let mut idx = 0;
+
+        while let Some(value) = seq.next_element::<String>()? {
+            idx += 1;
+            let value = value.trim();
+            if let Err(error) = set.insert_str(value) {
+                invalid.push(InvalidEntry {
+                    line: idx,
+                    value: value.to_owned(),
+                    error,
+                });
+            }
+        }
+
+        Ok((set, invalid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDT: &str = "0xdAC17F958D2ee523a2206206994597C13D831ec7";
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+
+    fn write_tmp(dir: &Path, name: &str, contents: &str) -> std::path::PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = write_tmp(
+            &dir,
+            "ok.csv",
+            &format!(
+                "address,name\n{},usdt\n\n{},usdc\n{},usdt again\n",
+                USDT, USDC, USDT
+            ),
+        );
+        let set = AddressSet::from_csv(&path).unwrap();
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.addresses()[0], USDT.parse().unwrap());
+        assert_eq!(set.log_selections(1).len(), 2);
+
+        // bad checksum on line 2, too short on line 3
+        let bad_checksum = USDT.replace('d', "D");
+        let path = write_tmp(
+            &dir,
+            "bad.csv",
+            &format!("{}\n{}\n0x1234\n", USDC, bad_checksum),
+        );
+        let err = AddressSet::from_csv(&path).unwrap_err();
+        let report = err.downcast_ref::<ParseReport>().unwrap();
+        assert_eq!(
+            report.invalid.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert!(matches!(
+            report.invalid[0].error,
+            hypersync_format::Error::InvalidChecksum(_)
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_from_json() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = write_tmp(
+            &dir,
+            "ok.json",
+            &format!(r#"["{}", "{}", "{}"]"#, USDT, USDC, USDC),
+        );
+        assert_eq!(AddressSet::from_json(&path).unwrap().len(), 2);
+
+        let path = write_tmp(&dir, "bad.json", &format!(r#"["{}", "nope"]"#, USDT));
+        let err = AddressSet::from_json(&path).unwrap_err();
+        let report = err.downcast_ref::<ParseReport>().unwrap();
+        assert_eq!(report.invalid[0].line, 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+
+/// Supplies bearer tokens to the client, e.g. by exchanging a refresh token with an auth server.
+///
+/// Registered with `ClientConfig::token_provider`. The client asks for a token before its first
+/// request if `ClientConfig::bearer_token` isn't set, and asks for a new one whenever the server
+/// responds with 401 or the current token is about to expire.
+pub trait TokenProvider: Send + Sync {
+    /// Returns a fresh bearer token.
+    fn fetch_token(&self) -> BoxFuture<'_, Result<String>>;
+
+    /// Returns a fresh bearer token along with how long it stays valid.
+    ///
+    /// Providers that know the lifetime of their tokens (e.g. the `expires_in` of an OAuth
+    /// response) can implement this so the token is replaced before requests start failing
+    /// with 401. Defaults to `fetch_token` with no expiry.
+    fn fetch_token_with_expiry(&self) -> BoxFuture<'_, Result<(String, Option<Duration>)>> {
+        Box::pin(async move { self.fetch_token().await.map(|token| (token, None)) })
+    }
+}
+
+/// Tokens are refreshed this long before they expire so requests in flight don't get rejected.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+impl fmt::Debug for dyn TokenProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TokenProvider")
+    }
+}
+
+/// The bearer token the client currently sends, shared between clones of the client.
+#[derive(Debug)]
+pub(crate) struct BearerToken {
+    token: RwLock<Option<String>>,
+    /// When the current token should be replaced, None if it doesn't expire.
+    refresh_at: RwLock<Option<Instant>>,
+    /// Incremented every time the token is refreshed.
+    generation: AtomicU64,
+    provider: Option<Arc<dyn TokenProvider>>,
+    refresh_lock: tokio::sync::Mutex<()>,
+}
+
+impl BearerToken {
+    pub(crate) fn new(token: Option<String>, provider: Option<Arc<dyn TokenProvider>>) -> Self {
+        Self {
+            token: RwLock::new(token),
+            refresh_at: RwLock::new(None),
+            generation: AtomicU64::new(0),
+            provider,
+            refresh_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn has_provider(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Returns the current token, fetching one from the provider if there is none yet or the
+    /// current one is about to expire.
+    pub(crate) async fn get(&self) -> Result<Option<String>> {
+        let expired = self
+            .refresh_at
+            .read()
+            .unwrap()
+            .is_some_and(|refresh_at| Instant::now() >= refresh_at);
+        if !expired {
+            if let Some(token) = self.token.read().unwrap().clone() {
+                return Ok(Some(token));
+            }
+        }
+        if self.provider.is_none() {
+            return Ok(self.token.read().unwrap().clone());
+        }
+        self.refresh(self.generation()).await?;
+        Ok(self.token.read().unwrap().clone())
+    }
+
+    /// Replaces the token with a fresh one from the provider.
+    ///
+    /// `seen_generation` is the generation the caller got rejected with. If another request
+    /// already refreshed the token since then it is reused instead of fetching another one.
+    pub(crate) async fn refresh(&self, seen_generation: u64) -> Result<()> {
+        let provider = match self.provider.as_ref() {
+            Some(provider) => provider,
+            None => return Ok(()),
+        };
+
+        let _guard = self.refresh_lock.lock().await;
+        if self.generation() != seen_generation {
+            return Ok(());
+        }
+
+        let (token, expires_in) = provider
+            .fetch_token_with_expiry()
+            .await
+            .context("fetch bearer token")?;
+        *self.token.write().unwrap() = Some(token);
+        *self.refresh_at.write().unwrap() =
+            expires_in.map(|expires_in| Instant::now() + expires_in.saturating_sub(EXPIRY_MARGIN));
+        self.generation.fetch_add(1, Ordering::SeqCst);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    struct Counter(AtomicUsize);
+
+    impl TokenProvider for Counter {
+        fn fetch_token(&self) -> BoxFuture<'_, Result<String>> {
+            Box::pin(async move {
+                let n = self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(format!("token-{}", n))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_is_shared() {
+        let provider = Arc::new(Counter(AtomicUsize::new(0)));
+        let token = BearerToken::new(None, Some(provider.clone()));
+
+        assert_eq!(token.get().await.unwrap().as_deref(), Some("token-0"));
+
+        let seen = token.generation();
+        token.refresh(seen).await.unwrap();
+        // a request that was rejected with the same stale token doesn't fetch again
+        token.refresh(seen).await.unwrap();
+
+        assert_eq!(token.get().await.unwrap().as_deref(), Some("token-1"));
+        assert_eq!(provider.0.load(Ordering::SeqCst), 2);
+    }
+
+    struct ShortLived(AtomicUsize, Duration);
+
+    impl TokenProvider for ShortLived {
+        fn fetch_token(&self) -> BoxFuture<'_, Result<String>> {
+            unreachable!()
+        }
+
+        fn fetch_token_with_expiry(&self) -> BoxFuture<'_, Result<(String, Option<Duration>)>> {
+            Box::pin(async move {
+                let n = self.0.fetch_add(1, Ordering::SeqCst);
+                Ok((format!("token-{}", n), Some(self.1)))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_before_expiry() {
+        // expires within the margin so it is replaced on every use
+        let token = BearerToken::new(
+            None,
+            Some(Arc::new(ShortLived(AtomicUsize::new(0), EXPIRY_MARGIN))),
+        );
+        assert_eq!(token.get().await.unwrap().as_deref(), Some("token-0"));
+        assert_eq!(token.get().await.unwrap().as_deref(), Some("token-1"));
+
+        let token = BearerToken::new(
+            None,
+            Some(Arc::new(ShortLived(
+                AtomicUsize::new(0),
+                Duration::from_secs(3600),
+            ))),
+        );
+        assert_eq!(token.get().await.unwrap().as_deref(), Some("token-0"));
+        assert_eq!(token.get().await.unwrap().as_deref(), Some("token-0"));
+    }
+}
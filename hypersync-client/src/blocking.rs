@@ -0,0 +1,121 @@
+//! Synchronous facade over [`crate::Client`] for codebases that don't use async.
+//!
+//! The blocking client owns a tokio runtime that runs the requests. It must not be created or
+//! used from within an async context, since blocking on the runtime from there panics.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use hypersync_net_types::Query;
+use tokio::sync::mpsc;
+
+use crate::{ClientConfig, Error, QueryResponse, StreamConfig};
+
+/// Synchronous version of [`crate::Client`].
+pub struct Client {
+    inner: Arc<crate::Client>,
+    rt: Arc<tokio::runtime::Runtime>,
+}
+
+impl Client {
+    /// Creates a new client with the given configuration and a runtime to run it on.
+    pub fn new(cfg: ClientConfig) -> Result<Self, Error> {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .context("build tokio runtime")?;
+        let inner = crate::Client::new(cfg)?;
+
+        Ok(Self {
+            inner: Arc::new(inner),
+            rt: Arc::new(rt),
+        })
+    }
+
+    /// Returns the async client this wraps.
+    pub fn inner(&self) -> &Arc<crate::Client> {
+        &self.inner
+    }
+
+    /// Get the height of the server with retries.
+    pub fn get_height(&self) -> Result<u64, Error> {
+        self.rt.block_on(self.inner.get_height())
+    }
+
+    /// Get the chain_id of the server with retries.
+    pub fn get_chain_id(&self) -> Result<u64, Error> {
+        self.rt.block_on(self.inner.get_chain_id())
+    }
+
+    /// Executes a single query with retries, see [`crate::Client::get`].
+    pub fn get(&self, query: &Query) -> Result<QueryResponse, Error> {
+        self.rt.block_on(self.inner.get(query))
+    }
+
+    /// Runs the query to completion and returns all of the data, see [`crate::Client::collect`].
+    pub fn collect(&self, query: Query, config: StreamConfig) -> Result<QueryResponse, Error> {
+        self.rt.block_on(self.inner.clone().collect(query, config))
+    }
+
+    /// Writes the query results to parquet files under the given path, see
+    /// [`crate::Client::collect_parquet`].
+    pub fn collect_parquet(
+        &self,
+        path: &str,
+        query: Query,
+        config: StreamConfig,
+    ) -> Result<(), Error> {
+        self.rt
+            .block_on(self.inner.clone().collect_parquet(path, query, config))
+    }
+
+    /// Streams the query results, see [`crate::Client::stream`].
+    ///
+    /// The stream keeps running in the background while the iterator isn't polled, up to
+    /// `config.concurrency` buffered responses.
+    pub fn stream(&self, query: Query, config: StreamConfig) -> Result<StreamIter, Error> {
+        let rx = self.rt.block_on(self.inner.clone().stream(query, config))?;
+        Ok(StreamIter {
+            rx,
+            rt: self.rt.clone(),
+        })
+    }
+}
+
+/// Iterator over the responses of [`Client::stream`].
+///
+/// Ends after the last response or after the first error.
+pub struct StreamIter {
+    rx: mpsc::Receiver<Result<QueryResponse, Error>>,
+    rt: Arc<tokio::runtime::Runtime>,
+}
+
+impl Iterator for StreamIter {
+    type Item = Result<QueryResponse, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rt.block_on(self.rx.recv())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocking_get_height() {
+        let (port, server) = crate::tests::serve_once(r#"{"height":42}"#, |req| {
+            assert!(req.starts_with("GET /height"));
+        });
+
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            max_num_retries: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(client.get_height().unwrap(), 42);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+use alloy_primitives::{Bloom, BloomInput};
+use anyhow::{anyhow, Result};
+use hypersync_net_types::{LogSelection, Query};
+
+/// Size of the logs bloom of a block header in bytes.
+const BLOOM_SIZE: usize = 256;
+
+/// Checks that the logs of the query are all it selects, since a logs bloom says nothing about
+/// transactions, traces or blocks without logs.
+pub(crate) fn check_query(query: &Query) -> Result<()> {
+    if query.logs.is_empty() {
+        return Err(anyhow!(
+            "config.bloom_prescan needs the query to have a log selection"
+        ));
+    }
+    if !query.transactions.is_empty() || !query.traces.is_empty() || !query.blocks.is_empty() {
+        return Err(anyhow!(
+            "config.bloom_prescan can only be used with queries that only select logs"
+        ));
+    }
+    if query.include_all_blocks {
+        return Err(anyhow!(
+            "config.bloom_prescan can't be combined with query.include_all_blocks"
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the block with the given logs bloom may have a log matching any of the selections.
+///
+/// Blooms have false positives but no false negatives, so `false` means the block can be
+/// skipped. Blooms that aren't 256 bytes long are treated as matching everything.
+pub(crate) fn may_match(bloom: &[u8], selections: &[LogSelection]) -> bool {
+    if bloom.len() != BLOOM_SIZE {
+        return true;
+    }
+    let bloom = Bloom::from_slice(bloom);
+    selections
+        .iter()
+        .any(|sel| selection_may_match(&bloom, sel))
+}
+
+fn selection_may_match(bloom: &Bloom, selection: &LogSelection) -> bool {
+    // an address filter can't be checked against the bloom, so it could match any address
+    let address_matches = selection.address.is_empty()
+        || selection.address_filter.is_some()
+        || selection
+            .address
+            .iter()
+            .any(|addr| bloom.contains_input(BloomInput::Raw(addr.as_slice())));
+
+    address_matches
+        && selection.topics.iter().all(|topics| {
+            topics.is_empty()
+                || topics
+                    .iter()
+                    .any(|topic| bloom.contains_input(BloomInput::Raw(topic.as_slice())))
+        })
+}
+
+/// Collects the candidate blocks of a scan into ranges of consecutive blocks.
+#[derive(Debug, Default)]
+pub(crate) struct CandidateRanges {
+    ranges: Vec<(u64, u64)>,
+}
+
+impl CandidateRanges {
+    /// Adds a candidate block, blocks have to be added in increasing order.
+    pub(crate) fn push(&mut self, block: u64) {
+        match self.ranges.last_mut() {
+            Some((_, end)) if *end == block => *end = block + 1,
+            _ => self.ranges.push((block, block + 1)),
+        }
+    }
+
+    /// `[from_block, to_block)` ranges of the candidate blocks.
+    pub(crate) fn into_ranges(self) -> Vec<(u64, u64)> {
+        self.ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::keccak256;
+    use hypersync_format::{Address, LogArgument};
+
+    use super::*;
+
+    fn bloom_of(inputs: &[&[u8]]) -> Vec<u8> {
+        let mut bloom = Bloom::default();
+        for input in inputs {
+            bloom.accrue(BloomInput::Hash(keccak256(input)));
+        }
+        bloom.as_slice().to_vec()
+    }
+
+    #[test]
+    fn test_may_match() {
+        let addr = Address::from([1; 20]);
+        let other_addr = Address::from([2; 20]);
+        let topic = LogArgument::from([3; 32]);
+        let other_topic = LogArgument::from([4; 32]);
+        let bloom = bloom_of(&[addr.as_slice(), topic.as_slice()]);
+
+        let selection = |address: Vec<Address>, topics: Vec<Vec<LogArgument>>| LogSelection {
+            address,
+            topics: topics.into_iter().collect(),
+            ..Default::default()
+        };
+
+        assert!(may_match(&bloom, &[selection(vec![addr.clone()], vec![])]));
+        assert!(may_match(
+            &bloom,
+            &[selection(
+                vec![other_addr.clone(), addr.clone()],
+                vec![vec![topic.clone()]]
+            )]
+        ));
+        assert!(!may_match(
+            &bloom,
+            &[selection(vec![other_addr.clone()], vec![])]
+        ));
+        assert!(!may_match(
+            &bloom,
+            &[selection(
+                vec![addr.clone()],
+                vec![vec![], vec![other_topic.clone()]]
+            )]
+        ));
+        assert!(may_match(
+            &bloom,
+            &[
+                selection(vec![other_addr.clone()], vec![]),
+                selection(vec![], vec![vec![topic.clone()]])
+            ]
+        ));
+        assert!(may_match(&[0; 3], &[selection(vec![other_addr], vec![])]));
+    }
+
+    #[test]
+    fn test_candidate_ranges() {
+        let mut ranges = CandidateRanges::default();
+        for block in [3, 4, 5, 9, 11, 12] {
+            ranges.push(block);
+        }
+        assert_eq!(ranges.into_ranges(), vec![(3, 6), (9, 10), (11, 13)]);
+    }
+}
@@ -0,0 +1,380 @@
+use std::{
+    fmt,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use futures::future::BoxFuture;
+use hypersync_net_types::Query;
+use serde::{de::DeserializeOwned, Serialize};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Persists the block a stream should resume from.
+///
+/// Registered with `StreamConfig::checkpoint`. Streams start from the stored block instead of
+/// `Query::from_block` if there is one, and store the `next_block` of every response once it is
+/// committed. Implemented by [`CheckpointStore`] for files and [`MemoryCheckpoint`] for state
+/// that only has to survive a restarted stream within the same process.
+pub trait Checkpoint: Send + Sync {
+    /// Returns the stored block, None if nothing was stored yet.
+    fn load_next_block(&self) -> BoxFuture<'_, Result<Option<u64>>>;
+
+    /// Replaces the stored block.
+    fn save_next_block(&self, next_block: u64) -> BoxFuture<'_, Result<()>>;
+}
+
+impl fmt::Debug for dyn Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Checkpoint")
+    }
+}
+
+/// Stores a json serializable value, e.g. the resume state of a long running export, in a file.
+///
+/// Writes go to a temporary file which is synced and then renamed over the previous one so a
+/// crash in the middle of a write never leaves a partially written file behind. The previous
+/// `history` versions are kept next to it as `<path>.1`, `<path>.2` etc. and are used by `load`
+/// if the latest file can't be read.
+#[derive(Debug, Clone)]
+pub struct CheckpointStore {
+    path: PathBuf,
+    gzip: bool,
+    history: usize,
+}
+
+impl CheckpointStore {
+    /// Create a store writing to the given path, without compression or history.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            gzip: false,
+            history: 0,
+        }
+    }
+
+    /// Gzip the written files. Files are decompressed on load based on their content so this can
+    /// be changed between runs.
+    pub fn gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+
+    /// Number of previous versions to keep.
+    pub fn history(mut self, history: usize) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Path of the latest version.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Atomically replaces the stored value.
+    pub async fn save<T: Serialize>(&self, value: &T) -> Result<()> {
+        let json = serde_json::to_vec(value).context("serialize checkpoint")?;
+        let data = if self.gzip {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&json).context("compress checkpoint")?;
+            encoder.finish().context("compress checkpoint")?
+        } else {
+            json
+        };
+
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || store.write_blocking(&data))
+            .await
+            .context("join write task")?
+    }
+
+    fn write_blocking(&self, data: &[u8]) -> Result<()> {
+        let tmp_path = self.suffixed("tmp");
+        let mut file = std::fs::File::create(&tmp_path).context("create tmp checkpoint file")?;
+        file.write_all(data).context("write tmp checkpoint file")?;
+        file.sync_all().context("sync tmp checkpoint file")?;
+        drop(file);
+
+        if self.history > 0 {
+            for i in (1..self.history).rev() {
+                rename_if_exists(
+                    &self.suffixed(&i.to_string()),
+                    &self.suffixed(&(i + 1).to_string()),
+                )?;
+            }
+            rename_if_exists(&self.path, &self.suffixed("1"))?;
+        }
+
+        std::fs::rename(&tmp_path, &self.path).context("rename tmp checkpoint file")?;
+
+        // sync the directory so the rename itself survives a crash
+        #[cfg(unix)]
+        if let Some(dir) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::File::open(dir)
+                .and_then(|d| d.sync_all())
+                .context("sync checkpoint dir")?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads the latest readable version, falling back to previous versions if the latest one
+    /// is missing or corrupt. Returns None if there is no readable version.
+    pub async fn load<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        let mut paths = vec![self.path.clone()];
+        paths.extend((1..=self.history).map(|i| self.suffixed(&i.to_string())));
+
+        for path in paths {
+            let data = match tokio::fs::read(&path).await {
+                Ok(data) => data,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("read checkpoint {}", path.display()))
+                }
+            };
+
+            match decode(&data) {
+                Ok(value) => return Ok(Some(value)),
+                Err(e) => log::warn!("skipping unreadable checkpoint {}: {:?}", path.display(), e),
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn suffixed(&self, suffix: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".");
+        path.push(suffix);
+        path.into()
+    }
+}
+
+/// Moves the start of the query to the block stored in the checkpoint. Returns false if the
+/// checkpoint is already at or past the end of the query.
+pub(crate) async fn resume_query(
+    query: &mut Query,
+    checkpoint: &dyn Checkpoint,
+    reverse: bool,
+) -> Result<bool> {
+    if reverse {
+        return Err(anyhow!("checkpoints aren't supported for reverse streams"));
+    }
+
+    let next_block = match checkpoint
+        .load_next_block()
+        .await
+        .context("load checkpoint")?
+    {
+        Some(next_block) => next_block,
+        None => return Ok(true),
+    };
+    if query
+        .to_block
+        .is_some_and(|to_block| next_block >= to_block)
+    {
+        return Ok(false);
+    }
+    log::debug!(
+        "resuming from block {} of the checkpoint instead of {}",
+        next_block,
+        query.from_block
+    );
+    query.from_block = next_block;
+
+    Ok(true)
+}
+
+/// Stores the block as a json number, atomically replacing the file on every save.
+impl Checkpoint for CheckpointStore {
+    fn load_next_block(&self) -> BoxFuture<'_, Result<Option<u64>>> {
+        Box::pin(self.load())
+    }
+
+    fn save_next_block(&self, next_block: u64) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move { self.save(&next_block).await })
+    }
+}
+
+/// Keeps the block in memory.
+#[derive(Debug, Default)]
+pub struct MemoryCheckpoint {
+    next_block: Mutex<Option<u64>>,
+}
+
+impl MemoryCheckpoint {
+    /// Create a checkpoint holding the given block.
+    pub fn new(next_block: Option<u64>) -> Self {
+        Self {
+            next_block: Mutex::new(next_block),
+        }
+    }
+
+    /// The stored block.
+    pub fn next_block(&self) -> Option<u64> {
+        *self.next_block.lock().unwrap()
+    }
+}
+
+impl Checkpoint for MemoryCheckpoint {
+    fn load_next_block(&self) -> BoxFuture<'_, Result<Option<u64>>> {
+        Box::pin(async move { Ok(self.next_block()) })
+    }
+
+    fn save_next_block(&self, next_block: u64) -> BoxFuture<'_, Result<()>> {
+        *self.next_block.lock().unwrap() = Some(next_block);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+    if data.starts_with(&GZIP_MAGIC) {
+        let mut json = Vec::new();
+        GzDecoder::new(data)
+            .read_to_end(&mut json)
+            .context("decompress checkpoint")?;
+        serde_json::from_slice(&json).context("parse checkpoint")
+    } else {
+        serde_json::from_slice(data).context("parse checkpoint")
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("rename {} to {}", from.display(), to.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::mock_server::{blocks_query, mock_client, MockChain, MockServer};
+    #[cfg(feature = "test-util")]
+    use crate::StreamConfig;
+    #[cfg(feature = "test-util")]
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_history_and_corrupt_fallback() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = CheckpointStore::new(dir.join("checkpoint.json"))
+            .gzip(true)
+            .history(2);
+
+        assert_eq!(store.load::<u64>().await.unwrap(), None);
+
+        for i in 0..4u64 {
+            store.save(&i).await.unwrap();
+        }
+        assert_eq!(store.load::<u64>().await.unwrap(), Some(3));
+        assert!(!dir.join("checkpoint.json.3").exists());
+        assert!(!dir.join("checkpoint.json.tmp").exists());
+
+        std::fs::write(store.path(), b"{trunc").unwrap();
+        assert_eq!(store.load::<u64>().await.unwrap(), Some(2));
+
+        // plain files are still readable after turning compression off
+        let plain = CheckpointStore::new(dir.join("checkpoint.json")).history(2);
+        plain.save(&7u64).await.unwrap();
+        assert_eq!(store.load::<u64>().await.unwrap(), Some(7));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_impls() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file = CheckpointStore::new(dir.join("next_block.json"));
+        let memory = MemoryCheckpoint::default();
+        for checkpoint in [&file as &dyn Checkpoint, &memory] {
+            assert_eq!(checkpoint.load_next_block().await.unwrap(), None);
+            checkpoint.save_next_block(10).await.unwrap();
+            checkpoint.save_next_block(25).await.unwrap();
+            assert_eq!(checkpoint.load_next_block().await.unwrap(), Some(25));
+        }
+        assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "25");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_resume_from_checkpoint() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+        let checkpoint = Arc::new(MemoryCheckpoint::default());
+        let config = StreamConfig {
+            concurrency: Some(1),
+            batch_size: Some(10),
+            ..StreamConfig::resume_from_checkpoint(checkpoint.clone())
+        };
+
+        let numbers = |resp: &crate::ArrowResponse| {
+            resp.data
+                .blocks
+                .iter()
+                .flat_map(|b| {
+                    b.u64_column("number")
+                        .unwrap()
+                        .values_iter()
+                        .copied()
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the consumer stops while handling the fourth response
+        let mut rx = client
+            .clone()
+            .stream_arrow(blocks_query(0, 100), config.clone())
+            .await
+            .unwrap();
+        for _ in 0..4 {
+            rx.recv().await.unwrap().unwrap();
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        drop(rx);
+        assert_eq!(checkpoint.next_block(), Some(30));
+
+        // the second run picks up the unfinished response again
+        let mut rx = client
+            .clone()
+            .stream_arrow(blocks_query(0, 100), config.clone())
+            .await
+            .unwrap();
+        let mut blocks = Vec::new();
+        while let Some(resp) = rx.recv().await {
+            blocks.extend(numbers(&resp.unwrap()));
+        }
+        assert_eq!(blocks, (30..100).collect::<Vec<_>>());
+        assert_eq!(checkpoint.next_block(), Some(100));
+
+        // nothing is left to fetch
+        let num_queries = server.num_queries();
+        let mut rx = client
+            .clone()
+            .stream_arrow(blocks_query(0, 100), config.clone())
+            .await
+            .unwrap();
+        assert!(rx.recv().await.is_none());
+        assert_eq!(server.num_queries(), num_queries);
+
+        // collected data would be lost after the checkpoint moved past it
+        let err = client
+            .collect(blocks_query(0, 100), config)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("config.checkpoint"));
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::error::CircuitOpen;
+
+/// Stops sending requests to an endpoint for a cool-down period after it fails too many times
+/// in a row.
+///
+/// Once the cool-down has passed requests are let through again, but the failure count is only
+/// reset by a successful request, so a single further failure reopens the circuit.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    threshold: usize,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    consecutive_failures: usize,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(threshold: usize, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Returns an error if the circuit is open at the given time.
+    pub(crate) fn check(&self, now: Instant) -> Result<(), CircuitOpen> {
+        let state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(open_until) if open_until > now => Err(CircuitOpen {
+                retry_after: open_until - now,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn record_success(&self) {
+        *self.state.lock().unwrap() = State::default();
+    }
+
+    pub(crate) fn record_failure(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.threshold {
+            state.open_until = Some(now + self.cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_cooldown_and_reopen() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(10));
+        let start = Instant::now();
+
+        breaker.record_failure(start);
+        breaker.record_failure(start);
+        assert!(breaker.check(start).is_ok());
+        breaker.record_failure(start);
+
+        let err = breaker.check(start + Duration::from_secs(4)).unwrap_err();
+        assert_eq!(err.retry_after, Duration::from_secs(6));
+
+        // after the cool-down a single failure reopens the circuit
+        let later = start + Duration::from_secs(10);
+        assert!(breaker.check(later).is_ok());
+        breaker.record_failure(later);
+        assert!(breaker.check(later).is_err());
+
+        breaker.record_success();
+        assert!(breaker.check(later).is_ok());
+        breaker.record_failure(later);
+        assert!(breaker.check(later).is_ok());
+    }
+}
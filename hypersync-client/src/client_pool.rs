@@ -0,0 +1,155 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use hypersync_net_types::Query;
+use tokio::sync::mpsc;
+use url::Url;
+
+use crate::{
+    endpoint_for_chain, ArrowResponse, Client, ClientConfig, Error, EventResponse, QueryResponse,
+    StreamConfig,
+};
+
+/// Clients of several chains that share one configuration.
+///
+/// Every client is created from the base config with only the url replaced, so retry,
+/// authentication and timeout settings apply to all chains. A `shared_quota` in the base config
+/// is shared by all clients, keeping them under the rate limit of a common API key together.
+/// Clients of chains that weren't added explicitly are created on first use from the built in
+/// endpoint registry.
+#[derive(Debug)]
+pub struct ClientPool {
+    base: ClientConfig,
+    clients: Mutex<BTreeMap<u64, Arc<Client>>>,
+}
+
+impl ClientPool {
+    /// Creates an empty pool that creates its clients from the given config.
+    pub fn new(base: ClientConfig) -> Self {
+        Self {
+            base,
+            clients: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Adds a client for the given chain that sends requests to `url`, replacing the existing
+    /// one.
+    pub fn add_chain(&self, chain_id: u64, url: Url) -> Result<Arc<Client>, Error> {
+        let cfg = ClientConfig {
+            url: Some(url),
+            ..self.base.clone()
+        };
+        self.insert(chain_id, cfg)
+    }
+
+    /// Adds a client for the given chain created from its own config, for chains that need
+    /// settings that differ from the base config. Replaces the existing client.
+    pub fn insert(&self, chain_id: u64, cfg: ClientConfig) -> Result<Arc<Client>, Error> {
+        let client = Arc::new(Client::new(cfg).context("create client")?);
+        self.clients
+            .lock()
+            .unwrap()
+            .insert(chain_id, client.clone());
+        Ok(client)
+    }
+
+    /// Returns the client of the given chain, creating it with the endpoint from the built in
+    /// registry if it wasn't added.
+    pub fn client(&self, chain_id: u64) -> Result<Arc<Client>, Error> {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(&chain_id) {
+            return Ok(client.clone());
+        }
+
+        let url = endpoint_for_chain(chain_id).with_context(|| {
+            format!(
+                "no client for chain id {} and no known hypersync endpoint, add it with \
+                 ClientPool::add_chain",
+                chain_id
+            )
+        })?;
+        let client = Arc::new(
+            Client::new(ClientConfig {
+                url: Some(url),
+                ..self.base.clone()
+            })
+            .context("create client")?,
+        );
+        clients.insert(chain_id, client.clone());
+        Ok(client)
+    }
+
+    /// Ids of the chains that currently have a client, in ascending order.
+    pub fn chain_ids(&self) -> Vec<u64> {
+        self.clients.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Get the height of the given chain's server with retries.
+    pub async fn get_height(&self, chain_id: u64) -> Result<u64, Error> {
+        self.client(chain_id)?.get_height().await
+    }
+
+    /// Executes the query on the given chain with retries and returns the response.
+    pub async fn get(&self, chain_id: u64, query: &Query) -> Result<QueryResponse, Error> {
+        self.client(chain_id)?.get(query).await
+    }
+
+    /// Streams the query on the given chain, see [`Client::stream`].
+    pub async fn stream(
+        &self,
+        chain_id: u64,
+        query: Query,
+        config: StreamConfig,
+    ) -> Result<mpsc::Receiver<Result<QueryResponse, Error>>, Error> {
+        self.client(chain_id)?.stream(query, config).await
+    }
+
+    /// Streams the query on the given chain, see [`Client::stream_events`].
+    pub async fn stream_events(
+        &self,
+        chain_id: u64,
+        query: Query,
+        config: StreamConfig,
+    ) -> Result<mpsc::Receiver<Result<EventResponse, Error>>, Error> {
+        self.client(chain_id)?.stream_events(query, config).await
+    }
+
+    /// Streams the query on the given chain, see [`Client::stream_arrow`].
+    pub async fn stream_arrow(
+        &self,
+        chain_id: u64,
+        query: Query,
+        config: StreamConfig,
+    ) -> Result<mpsc::Receiver<Result<ArrowResponse, Error>>, Error> {
+        self.client(chain_id)?.stream_arrow(query, config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clients() {
+        let pool = ClientPool::new(ClientConfig {
+            max_num_retries: Some(2),
+            ..Default::default()
+        });
+
+        let custom = pool
+            .add_chain(999_999, "http://127.0.0.1:1".parse().unwrap())
+            .unwrap();
+        assert!(Arc::ptr_eq(&custom, &pool.client(999_999).unwrap()));
+        assert_eq!(custom.url().as_str(), "http://127.0.0.1:1/");
+
+        let eth = pool.client(1).unwrap();
+        assert_eq!(eth.url().as_str(), "https://eth.hypersync.xyz/");
+        assert!(Arc::ptr_eq(&eth, &pool.client(1).unwrap()));
+
+        assert_eq!(pool.chain_ids(), [1, 999_999]);
+        assert!(pool.client(123_456_789).is_err());
+    }
+}
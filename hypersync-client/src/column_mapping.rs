@@ -1,16 +1,17 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use alloy_primitives::I256;
 use anyhow::{anyhow, Context, Result};
+use hypersync_net_types::FieldSelection;
 use hypersync_schema::ArrowChunk;
 use polars_arrow::array::{
-    Array, BinaryArray, Float32Array, Float64Array, Int128Array, Int256Array, Int256Vec,
-    Int32Array, Int64Array, MutablePrimitiveArray, MutableUtf8Array, PrimitiveArray, UInt32Array,
-    UInt64Array, Utf8Array,
+    Array, BinaryArray, DictionaryArray, Float32Array, Float64Array, Int128Array, Int256Array,
+    Int256Vec, Int32Array, Int64Array, MutablePlString, MutablePrimitiveArray, MutableUtf8Array,
+    PrimitiveArray, UInt32Array, UInt64Array, Utf8Array, Utf8ViewArray,
 };
 use polars_arrow::compute::cast::CastOptionsImpl as CastOptions;
 use polars_arrow::compute::{self, cast};
-use polars_arrow::datatypes::{ArrowDataType, ArrowSchema as Schema, Field};
+use polars_arrow::datatypes::{ArrowDataType, ArrowSchema as Schema, Field, IntegerType};
 use polars_arrow::types::{i256 as Decimal, NativeType};
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use ruint::aliases::U256;
@@ -42,6 +43,10 @@ pub struct ColumnMapping {
 #[allow(missing_docs)]
 /// `DataType` is an enumeration representing the different data types that can be used in the column mapping.
 /// Each variant corresponds to a specific data type.
+///
+/// `Dictionary` stores a string column as a dictionary of its distinct values with u32 keys, which
+/// takes much less memory for low cardinality columns like `call_type` of traces or decoded
+/// string parameters, and is written to parquet with dictionary encoding.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum DataType {
@@ -54,6 +59,7 @@ pub enum DataType {
     IntStr,
     Decimal256,
     Decimal128,
+    Dictionary,
 }
 
 impl From<DataType> for ArrowDataType {
@@ -68,10 +74,127 @@ impl From<DataType> for ArrowDataType {
             DataType::IntStr => Self::Utf8,
             DataType::Decimal256 => Self::Decimal256(76, 0),
             DataType::Decimal128 => Self::Decimal(38, 0),
+            DataType::Dictionary => {
+                Self::Dictionary(IntegerType::UInt32, Box::new(Self::Utf8View), false)
+            }
         }
     }
 }
 
+/// Checks that every column named in the mapping exists in the schema of its table and is
+/// selected by the query, so a typo doesn't silently leave a column unmapped.
+///
+/// `decoded_log_columns` is the set of columns produced by log decoding, or `None` if the stream
+/// doesn't decode logs.
+pub fn validate(
+    mapping: &ColumnMapping,
+    field_selection: &FieldSelection,
+    decoded_log_columns: Option<&BTreeSet<String>>,
+) -> Result<()> {
+    let tables = [
+        (
+            "block",
+            &mapping.block,
+            &field_selection.block,
+            hypersync_schema::block_header(),
+        ),
+        (
+            "transaction",
+            &mapping.transaction,
+            &field_selection.transaction,
+            hypersync_schema::transaction(),
+        ),
+        (
+            "log",
+            &mapping.log,
+            &field_selection.log,
+            hypersync_schema::log(),
+        ),
+        (
+            "trace",
+            &mapping.trace,
+            &field_selection.trace,
+            hypersync_schema::trace(),
+        ),
+    ];
+
+    let mut problems = Vec::new();
+
+    for (table, mapping, selection, schema) in tables {
+        let schema_columns = schema.fields.iter().map(|f| f.name.as_str());
+        for name in mapping.keys() {
+            if !schema.fields.iter().any(|f| &f.name == name) {
+                problems.push(unknown_column_message(table, name, schema_columns.clone()));
+            } else if !selection.contains(name) {
+                problems.push(format!(
+                    "{}.{} isn't in query.field_selection.{}",
+                    table, name, table
+                ));
+            }
+        }
+    }
+
+    if !mapping.decoded_log.is_empty() {
+        match decoded_log_columns {
+            Some(columns) => {
+                for name in mapping.decoded_log.keys() {
+                    if !columns.contains(name) {
+                        problems.push(unknown_column_message(
+                            "decoded_log",
+                            name,
+                            columns.iter().map(|c| c.as_str()),
+                        ));
+                    }
+                }
+            }
+            None => problems
+                .push("decoded_log mapping is set but the stream doesn't decode logs".to_owned()),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("invalid column mapping: {}", problems.join("; ")))
+    }
+}
+
+pub(crate) fn unknown_column_message<'a>(
+    table: &str,
+    name: &str,
+    columns: impl Iterator<Item = &'a str>,
+) -> String {
+    let closest = columns
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|&(dist, _)| dist <= 3)
+        .min();
+
+    match closest {
+        Some((_, c)) => format!(
+            "{}.{} doesn't exist, did you mean {}.{}?",
+            table, name, table, c
+        ),
+        None => format!("{}.{} doesn't exist", table, name),
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, &ca) in a.as_bytes().iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
 pub fn apply_to_batch(
     batch: &ArrowBatch,
     mapping: &BTreeMap<String, DataType>,
@@ -154,9 +277,48 @@ fn map_column(col: &dyn Array, target_data_type: DataType) -> Result<Box<dyn Arr
         DataType::IntStr => map_to_int_str(col).map(to_box),
         DataType::Decimal256 => map_to_decimal(col).map(to_box),
         DataType::Decimal128 => map_to_decimal128(col).map(to_box),
+        DataType::Dictionary => map_to_dictionary(col).map(to_box),
     }
 }
 
+fn map_to_dictionary(col: &dyn Array) -> Result<DictionaryArray<u32>> {
+    match col.data_type() {
+        &ArrowDataType::Utf8 => utf8_to_dictionary(
+            col.as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .unwrap()
+                .iter(),
+        ),
+        &ArrowDataType::Utf8View => {
+            utf8_to_dictionary(col.as_any().downcast_ref::<Utf8ViewArray>().unwrap().iter())
+        }
+        dt => Err(anyhow!("Can't convert {:?} to dictionary", dt)),
+    }
+}
+
+/// Builds a dictionary with the distinct values in order of their first appearance.
+fn utf8_to_dictionary<'a>(
+    values: impl Iterator<Item = Option<&'a str>>,
+) -> Result<DictionaryArray<u32>> {
+    let mut keys_by_value = HashMap::new();
+    let mut dictionary = MutablePlString::new();
+    let mut keys = MutablePrimitiveArray::<u32>::new();
+
+    for val in values {
+        keys.push(val.map(|val| {
+            let next_key = keys_by_value.len() as u32;
+            *keys_by_value.entry(val).or_insert_with(|| {
+                dictionary.push(Some(val));
+                next_key
+            })
+        }));
+    }
+
+    let dictionary: Utf8ViewArray = dictionary.into();
+    DictionaryArray::try_new(DataType::Dictionary.into(), keys.into(), dictionary.boxed())
+        .context("build dictionary array")
+}
+
 fn map_to_decimal(col: &dyn Array) -> Result<Int256Array> {
     match col.data_type() {
         &ArrowDataType::Binary => {
@@ -356,6 +518,39 @@ fn binary_to_f32(src: &[u8]) -> Result<f32> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_validate() {
+        let mut field_selection = FieldSelection::default();
+        field_selection.block.insert("timestamp".to_owned());
+        field_selection.log.insert("data".to_owned());
+
+        let mut mapping = ColumnMapping::default();
+        mapping
+            .block
+            .insert("timestamp".to_owned(), DataType::UInt64);
+        validate(&mapping, &field_selection, None).unwrap();
+
+        mapping
+            .block
+            .insert("timestmap".to_owned(), DataType::UInt64);
+        mapping
+            .transaction
+            .insert("value".to_owned(), DataType::Float64);
+        let err = validate(&mapping, &field_selection, None)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("block.timestmap doesn't exist, did you mean block.timestamp?"));
+        assert!(err.contains("transaction.value isn't in query.field_selection.transaction"));
+
+        let mapping = ColumnMapping {
+            decoded_log: [("amount".to_owned(), DataType::Float64)].into(),
+            ..Default::default()
+        };
+        assert!(validate(&mapping, &field_selection, None).is_err());
+        let columns = ["amount".to_owned()].into();
+        validate(&mapping, &field_selection, Some(&columns)).unwrap();
+    }
+
     #[test]
     fn test_signed_binary_to_target() {
         const RAW_INPUT: &[i64] = &[-69, 0, 69, -1, 1, i64::MAX, i64::MIN];
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use polars_arrow::array::{BinaryArray, BinaryViewArray, Utf8Array, Utf8ViewArray};
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{types::as_u64_array, util::normalize_hex_address, ArrowBatch};
+
+/// Columns that distinct value estimates are computed for when present in a table.
+const ADDRESS_COLUMNS: &[&str] = &[
+    "address",
+    "from",
+    "to",
+    "contract_address",
+    "miner",
+    "author",
+];
+
+/// Lightweight statistics of a table, collected while streaming when
+/// `StreamConfig::collect_column_stats` is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableStats {
+    /// Total number of rows.
+    pub num_rows: u64,
+    /// Smallest value of the `block_number` (or `number` for blocks) column.
+    pub min_block_number: Option<u64>,
+    /// Largest value of the `block_number` (or `number` for blocks) column.
+    pub max_block_number: Option<u64>,
+    /// Number of null values of each column.
+    pub null_counts: BTreeMap<String, u64>,
+    /// Estimated number of distinct values of each address column, within about 2% error.
+    /// Hex encoded addresses are compared ignoring case and the `0x` prefix.
+    pub distinct_addresses: BTreeMap<String, u64>,
+}
+
+/// Accumulates [`TableStats`] for every table of a stream.
+#[derive(Debug, Default)]
+pub(crate) struct StatsCollector {
+    tables: BTreeMap<String, TableCollector>,
+}
+
+#[derive(Debug, Default)]
+struct TableCollector {
+    stats: TableStats,
+    addresses: BTreeMap<String, HyperLogLog>,
+}
+
+impl StatsCollector {
+    pub(crate) fn observe(&mut self, table: &str, batch: &ArrowBatch) {
+        let collector = self.tables.entry(table.to_owned()).or_default();
+        let stats = &mut collector.stats;
+
+        stats.num_rows += batch.chunk.len() as u64;
+
+        for (field, col) in batch.schema.fields.iter().zip(batch.chunk.columns()) {
+            *stats.null_counts.entry(field.name.clone()).or_default() += col.null_count() as u64;
+
+            if field.name == "block_number" || (table == "blocks" && field.name == "number") {
+                if let Ok(col) = as_u64_array(col.as_ref()) {
+                    for v in col.iter().flatten() {
+                        stats.min_block_number =
+                            Some(stats.min_block_number.map_or(*v, |m| m.min(*v)));
+                        stats.max_block_number =
+                            Some(stats.max_block_number.map_or(*v, |m| m.max(*v)));
+                    }
+                }
+            }
+
+            if ADDRESS_COLUMNS.contains(&field.name.as_str()) {
+                let hll = collector
+                    .addresses
+                    .entry(field.name.clone())
+                    .or_insert_with(HyperLogLog::new);
+                if let Some(col) = col.as_any().downcast_ref::<BinaryArray<i32>>() {
+                    col.iter().flatten().for_each(|v| hll.insert(xxh3_64(v)));
+                } else if let Some(col) = col.as_any().downcast_ref::<BinaryViewArray>() {
+                    col.iter().flatten().for_each(|v| hll.insert(xxh3_64(v)));
+                } else if let Some(col) = col.as_any().downcast_ref::<Utf8Array<i32>>() {
+                    // hex encoded by `StreamConfig::hex_output`
+                    col.iter()
+                        .flatten()
+                        .for_each(|v| hll.insert(xxh3_64(normalize_hex_address(v).as_bytes())));
+                } else if let Some(col) = col.as_any().downcast_ref::<Utf8ViewArray>() {
+                    col.iter()
+                        .flatten()
+                        .for_each(|v| hll.insert(xxh3_64(normalize_hex_address(v).as_bytes())));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> BTreeMap<String, TableStats> {
+        self.tables
+            .into_iter()
+            .map(|(table, collector)| {
+                let mut stats = collector.stats;
+                stats.distinct_addresses = collector
+                    .addresses
+                    .into_iter()
+                    .map(|(name, hll)| (name, hll.estimate()))
+                    .collect();
+                (table, stats)
+            })
+            .collect()
+    }
+}
+
+/// Number of bits of the hash used to pick a register.
+const HLL_PRECISION: u32 = 12;
+const HLL_REGISTERS: usize = 1 << HLL_PRECISION;
+
+/// Estimates the number of distinct hashes inserted into it using a fixed 4KiB of memory.
+#[derive(Debug)]
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        Self {
+            registers: vec![0; HLL_REGISTERS],
+        }
+    }
+
+    fn insert(&mut self, hash: u64) {
+        let idx = (hash >> (64 - HLL_PRECISION)) as usize;
+        // the set bit bounds the rank when the remaining bits are all zero
+        let rest = (hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[idx] = self.registers[idx].max(rank);
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = HLL_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&r| 2f64.powi(-i32::from(r)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting is more accurate for small cardinalities
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use polars_arrow::{
+        array::UInt64Array,
+        datatypes::{ArrowDataType as DataType, ArrowSchema as Schema, Field},
+        record_batch::RecordBatchT as Chunk,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_hyperloglog_estimate() {
+        for n in [10u64, 1_000, 100_000] {
+            let mut hll = HyperLogLog::new();
+            for i in 0..n {
+                // duplicates don't change the estimate
+                hll.insert(xxh3_64(&i.to_be_bytes()));
+                hll.insert(xxh3_64(&i.to_be_bytes()));
+            }
+            let estimate = hll.estimate() as f64;
+            assert!(
+                (estimate - n as f64).abs() / (n as f64) < 0.05,
+                "{n} {estimate}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_collect_log_stats() {
+        let batch = |blocks: Vec<Option<u64>>, addresses: Vec<Option<&[u8]>>| ArrowBatch {
+            chunk: Arc::new(Chunk::new(vec![
+                UInt64Array::from(blocks).boxed(),
+                BinaryArray::<i32>::from(addresses).boxed(),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("block_number", DataType::UInt64, true),
+                Field::new("address", DataType::Binary, true),
+            ])),
+        };
+
+        let mut collector = StatsCollector::default();
+        collector.observe(
+            "logs",
+            &batch(vec![Some(10), Some(12)], vec![Some(b"a"), Some(b"b")]),
+        );
+        collector.observe("logs", &batch(vec![Some(7), None], vec![Some(b"a"), None]));
+
+        let stats = collector.finish().remove("logs").unwrap();
+        assert_eq!(stats.num_rows, 4);
+        assert_eq!(stats.min_block_number, Some(7));
+        assert_eq!(stats.max_block_number, Some(12));
+        assert_eq!(stats.null_counts["block_number"], 1);
+        assert_eq!(stats.null_counts["address"], 1);
+        assert_eq!(stats.distinct_addresses["address"], 2);
+    }
+
+    #[test]
+    fn test_hex_address_stats() {
+        let batch = ArrowBatch {
+            chunk: Arc::new(Chunk::new(vec![Utf8Array::<i32>::from(vec![
+                Some("0xAbCd"),
+                Some("abcd"),
+                Some("0xabcd"),
+                Some("0x1234"),
+                None,
+            ])
+            .boxed()])),
+            schema: Arc::new(Schema::from(vec![Field::new(
+                "address",
+                DataType::Utf8,
+                true,
+            )])),
+        };
+
+        let mut collector = StatsCollector::default();
+        collector.observe("logs", &batch);
+
+        let stats = collector.finish().remove("logs").unwrap();
+        assert_eq!(stats.distinct_addresses["address"], 2);
+    }
+}
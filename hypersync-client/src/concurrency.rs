@@ -0,0 +1,171 @@
+use std::{sync::Mutex, time::Instant};
+
+/// Throughput has to stay above this fraction of the throughput at the previous limit for the
+/// limit to keep growing.
+const MIN_THROUGHPUT_RATIO: f64 = 0.9;
+
+/// Number of range requests a stream runs in parallel with `StreamConfig::auto_concurrency`.
+///
+/// The limit grows by one after every round of `limit` requests that completed without retries
+/// and halves when requests had to be retried, like TCP congestion control. A round that was
+/// slower than the round before it at a lower limit means the server or the connection is
+/// saturated, so the limit goes back down by one instead of growing further.
+#[derive(Debug)]
+pub(crate) struct AimdConcurrency {
+    max: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    num_retries: u64,
+    round_start: Instant,
+    round_requests: usize,
+    round_bytes: u64,
+    /// Bytes per second of the last round, if the limit was raised after it.
+    last_throughput: Option<f64>,
+}
+
+impl AimdConcurrency {
+    pub(crate) fn new(initial: usize, max: usize, now: Instant) -> Self {
+        let max = max.max(1);
+        Self {
+            max,
+            state: Mutex::new(State {
+                limit: initial.clamp(1, max),
+                num_retries: 0,
+                round_start: now,
+                round_requests: 0,
+                round_bytes: 0,
+                last_throughput: None,
+            }),
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Records a completed range request of `bytes`, with `num_retries` being the total number of
+    /// retries of the stream so far.
+    pub(crate) fn record(&self, num_retries: u64, bytes: u64, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+
+        if num_retries > state.num_retries {
+            state.num_retries = num_retries;
+            state.limit = (state.limit / 2).max(1);
+            state.last_throughput = None;
+            state.start_round(now);
+            log::debug!(
+                "requests were retried, lowering concurrency to {}",
+                state.limit
+            );
+            return;
+        }
+
+        state.round_requests += 1;
+        state.round_bytes += bytes;
+        if state.round_requests < state.limit {
+            return;
+        }
+
+        let elapsed = now.duration_since(state.round_start).as_secs_f64();
+        let throughput = state.round_bytes as f64 / elapsed.max(f64::EPSILON);
+        if state
+            .last_throughput
+            .is_some_and(|last| throughput < last * MIN_THROUGHPUT_RATIO)
+        {
+            state.limit = (state.limit - 1).max(1);
+            state.last_throughput = None;
+            log::debug!(
+                "throughput dropped, lowering concurrency to {}",
+                state.limit
+            );
+        } else if state.limit < self.max {
+            state.limit += 1;
+            state.last_throughput = Some(throughput);
+        }
+        state.start_round(now);
+    }
+}
+
+impl State {
+    fn start_round(&mut self, now: Instant) {
+        self.round_start = now;
+        self.round_requests = 0;
+        self.round_bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::mock_server::{blocks_query, mock_client, MockChain, MockServer};
+    #[cfg(feature = "test-util")]
+    use crate::StreamConfig;
+
+    #[test]
+    fn test_aimd() {
+        let mut now = Instant::now();
+        let aimd = AimdConcurrency::new(2, 6, now);
+
+        // grows by one per round of requests at a steady rate
+        for expected in [3, 4, 5, 6, 6] {
+            for _ in 0..aimd.limit() {
+                now += Duration::from_millis(100);
+                aimd.record(0, 1000, now);
+            }
+            assert_eq!(aimd.limit(), expected);
+        }
+
+        // retries halve it
+        aimd.record(3, 1000, now);
+        assert_eq!(aimd.limit(), 3);
+
+        // a round that gets less done than the one before it at a lower limit shrinks it
+        for _ in 0..3 {
+            now += Duration::from_millis(10);
+            aimd.record(3, 1000, now);
+        }
+        assert_eq!(aimd.limit(), 4);
+        for _ in 0..4 {
+            now += Duration::from_millis(100);
+            aimd.record(3, 1000, now);
+        }
+        assert_eq!(aimd.limit(), 3);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_auto_concurrency() {
+        let server = MockServer::start(MockChain::new(500)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+
+        let res = client
+            .collect(
+                blocks_query(0, 500),
+                StreamConfig {
+                    auto_concurrency: true,
+                    batch_size: Some(10),
+                    max_batch_size: Some(10),
+                    min_batch_size: Some(10),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let numbers = res
+            .data
+            .blocks
+            .concat()
+            .iter()
+            .map(|b| b.number.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, (0..500).collect::<Vec<_>>());
+    }
+}
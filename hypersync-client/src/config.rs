@@ -1,19 +1,180 @@
 use serde::{Deserialize, Serialize};
-use std::num::NonZeroU64;
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    sync::Arc,
+};
+use tokio_util::sync::CancellationToken;
 use url::Url;
 
-use crate::ColumnMapping;
+use crate::{
+    Checkpoint, ColumnMapping, Interceptor, ProgressListener, ResponseTransform, StopCondition,
+    StreamControl, StreamMetrics, TokenProvider,
+};
+
+/// Custom DNS resolver for [`ClientConfig::dns_resolver`].
+#[derive(Clone)]
+pub struct DnsResolver(Arc<dyn reqwest::dns::Resolve>);
+
+impl DnsResolver {
+    /// Wraps the given resolver.
+    pub fn new<R: reqwest::dns::Resolve + 'static>(resolver: R) -> Self {
+        Self(Arc::new(resolver))
+    }
+}
+
+impl reqwest::dns::Resolve for DnsResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        self.0.resolve(name)
+    }
+}
+
+impl std::fmt::Debug for DnsResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DnsResolver")
+    }
+}
+
+/// Compression encoding of an http response body.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentEncoding {
+    /// gzip compression.
+    Gzip,
+    /// zstd compression, usually faster to decode than gzip at a similar ratio.
+    Zstd,
+}
+
+impl ContentEncoding {
+    /// Name of the encoding in `Accept-Encoding` and `Content-Encoding` headers.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// Proxy configuration for [`ClientConfig::proxy`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProxyConfig {
+    /// Proxy url, e.g. `http://proxy.internal:3128` or `socks5://127.0.0.1:1080`. Use the
+    /// `socks5h` scheme to have the proxy resolve hostnames.
+    pub url: Url,
+    /// Username to authenticate to the proxy with.
+    pub username: Option<String>,
+    /// Password to authenticate to the proxy with, only used if `username` is set.
+    pub password: Option<String>,
+    /// Hosts that are connected to directly instead of through the proxy. Entries are domain
+    /// names, which also match their subdomains, IP addresses or subnets like `10.0.0.0/8`.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+/// TLS configuration for [`ClientConfig::tls`], for servers behind private TLS terminating
+/// gateways. Only applies to http requests, not to the websocket stream transport.
+///
+/// Requires the `rustls-tls` or `native-tls` feature. With only `native-tls` the client key has
+/// to be a PKCS #8 PEM file given in `client_key_path`.
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct TlsConfig {
+    /// PEM files with additional CA certificates to trust. A file can contain several
+    /// certificates.
+    #[serde(default)]
+    pub ca_cert_paths: Vec<PathBuf>,
+    /// Only trust the certificates in `ca_cert_paths`, not the built in web PKI roots.
+    #[serde(default)]
+    pub disable_built_in_roots: bool,
+    /// PEM file with the client certificate chain to present for mutual TLS.
+    pub client_cert_path: Option<PathBuf>,
+    /// PEM file with the private key of the client certificate. Can be omitted if the key is
+    /// in `client_cert_path`.
+    pub client_key_path: Option<PathBuf>,
+}
+
+/// API key sent with every request, for deployments that don't authenticate with
+/// `Authorization: Bearer`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "in", rename_all = "snake_case")]
+pub enum ApiKey {
+    /// Sent as the value of the given header, e.g. `x-api-key`.
+    Header {
+        /// Name of the header.
+        name: String,
+        /// The api key.
+        value: String,
+    },
+    /// Appended to the url as the given query parameter, e.g. `?api_key=...`.
+    QueryParam {
+        /// Name of the query parameter.
+        name: String,
+        /// The api key.
+        value: String,
+    },
+}
+
+impl ApiKey {
+    /// Adds the key to a request going to the given url.
+    pub(crate) fn apply(
+        &self,
+        url: &mut Url,
+        headers: &mut reqwest::header::HeaderMap,
+    ) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        match self {
+            Self::Header { name, value } => {
+                let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                    .context("parse api key header name")?;
+                let mut value = reqwest::header::HeaderValue::from_str(value)
+                    .context("build api key header")?;
+                value.set_sensitive(true);
+                headers.insert(name, value);
+            }
+            Self::QueryParam { name, value } => {
+                url.query_pairs_mut().append_pair(name, value);
+            }
+        }
+
+        Ok(())
+    }
+}
 
 /// Configuration for the hypersync client.
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct ClientConfig {
     /// HyperSync server URL.
     pub url: Option<Url>,
+    /// Additional server URLs to fail over to, in order, when requests to the current one keep
+    /// failing. Useful when running against regional mirrors of the same dataset.
+    #[serde(default)]
+    pub fallback_urls: Vec<Url>,
+    /// Number of consecutive failed attempts on an endpoint before the retry loop moves on to the
+    /// next one in `fallback_urls`. Defaults to 3.
+    pub failover_after_num_failures: Option<usize>,
+    /// Number of consecutive failed requests to an endpoint after which the client stops
+    /// sending it requests for `circuit_breaker_cooldown_ms`. Requests fail with a
+    /// `CircuitOpen` error while the circuit of every endpoint is open. Disabled if not set.
+    pub circuit_breaker_threshold: Option<NonZeroUsize>,
+    /// Milliseconds an endpoint's circuit breaker stays open. Defaults to 30 seconds.
+    pub circuit_breaker_cooldown_ms: Option<u64>,
     /// HyperSync server bearer token.
     pub bearer_token: Option<String>,
+    /// Supplies bearer tokens, used before the first request if `bearer_token` isn't set and
+    /// whenever the server rejects the current token with 401.
+    #[serde(skip)]
+    pub token_provider: Option<Arc<dyn TokenProvider>>,
+    /// Number of times a request is retried with a fresh token from `token_provider` after a 401
+    /// before it counts as a failed attempt. Defaults to 1.
+    pub max_num_reauth_attempts: Option<usize>,
+    /// API key to send with every request, in addition to or instead of a bearer token.
+    pub api_key: Option<ApiKey>,
     /// Milliseconds to wait for a response before timing out.
     pub http_req_timeout_millis: Option<NonZeroU64>,
-    /// Number of retries to attempt before returning error.
+    /// Number of retries to attempt before returning error. Requests the server rejects with a
+    /// 4xx status, e.g. invalid queries or missing permissions, fail without retrying.
     pub max_num_retries: Option<usize>,
     /// Milliseconds that would be used for retry backoff increasing.
     pub retry_backoff_ms: Option<u64>,
@@ -21,6 +182,91 @@ pub struct ClientConfig {
     pub retry_base_ms: Option<u64>,
     /// Ceiling time for request backoff.
     pub retry_ceiling_ms: Option<u64>,
+    /// Interval in milliseconds between HTTP/2 keepalive pings. Keepalive pings are disabled if
+    /// this isn't set.
+    pub http2_keep_alive_interval_millis: Option<NonZeroU64>,
+    /// Milliseconds to wait for a keepalive ping to be acknowledged before closing the connection.
+    pub http2_keep_alive_timeout_millis: Option<NonZeroU64>,
+    /// Whether to send HTTP/2 keepalive pings on connections that have no open requests.
+    pub http2_keep_alive_while_idle: Option<bool>,
+    /// Milliseconds an idle pooled connection is kept open before being closed.
+    pub pool_idle_timeout_millis: Option<u64>,
+    /// Maximum number of idle connections kept open per host. Defaults to `max_connections` if
+    /// that is set.
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Maximum number of requests the client has in flight at once, across all endpoints.
+    /// Further requests wait for a free slot. Streams run at most this many block ranges in
+    /// parallel, even if `StreamConfig::concurrency` is higher, so large concurrency settings
+    /// don't pile up requests waiting on the connection pool.
+    pub max_connections: Option<NonZeroUsize>,
+    /// Whether to set `TCP_NODELAY` on connections. Defaults to `true`.
+    pub tcp_nodelay: Option<bool>,
+    /// Addresses to connect to for the given hostnames instead of resolving them through DNS,
+    /// e.g. `{"eth.hypersync.xyz": ["203.0.113.7"]}`. The port is taken from the url. Useful for
+    /// split-horizon DNS setups and to keep long running exports going through resolver outages.
+    #[serde(default)]
+    pub pinned_ips: BTreeMap<String, Vec<IpAddr>>,
+    /// Custom DNS resolver for hostnames that aren't in `pinned_ips`.
+    #[serde(skip)]
+    pub dns_resolver: Option<DnsResolver>,
+    /// Compression encodings to accept for query responses, in order of preference. Arrow IPC
+    /// responses compress well, so this can speed up queries over slow links at the cost of
+    /// some cpu. Responses are uncompressed if this is empty.
+    #[serde(default)]
+    pub accept_encodings: Vec<ContentEncoding>,
+    /// Fall back to the server's json query endpoint in `Client::get` and `Client::get_events`
+    /// if the arrow-ipc endpoint isn't served, e.g. by older or proxied endpoints. The client
+    /// switches after the first such response and sends the following queries as json right
+    /// away. Streams always use arrow-ipc, and pinned schema fingerprints aren't checked for
+    /// json responses.
+    #[serde(default)]
+    pub json_fallback: bool,
+    /// Headers added to every request the client sends, including the ones sent by streams and
+    /// the websocket transport. Headers set by the client itself, like authorization, take
+    /// precedence.
+    #[serde(default)]
+    pub default_headers: BTreeMap<String, String>,
+    /// `User-Agent` header to send with every request.
+    pub user_agent: Option<String>,
+    /// Proxy to send requests through.
+    pub proxy: Option<ProxyConfig>,
+    /// Custom CA certificates and client certificate for TLS connections.
+    pub tls: Option<TlsConfig>,
+    /// Prebuilt http client to send requests with. `http_req_timeout_millis`, `tcp_nodelay`, the
+    /// keepalive and pool options, `pinned_ips`, `dns_resolver`, `proxy` and `tls` are ignored if
+    /// this is set, they should be configured on the given client instead.
+    #[serde(skip)]
+    pub http_client: Option<reqwest::Client>,
+    /// Hooks that are called around every http request the client sends, including the
+    /// handshake of the websocket transport.
+    #[serde(skip)]
+    pub interceptors: Vec<Arc<dyn Interceptor>>,
+    /// Rate limit shared with other clients, e.g. the clients of the other chains using the same
+    /// API key. Requests wait for a free slot before being sent, and a throttled response
+    /// holds back the requests of every client sharing it.
+    #[serde(skip)]
+    pub shared_quota: Option<Arc<crate::SharedQuota>>,
+    /// Decode the arrow IPC tables of each response in parallel on the rayon thread pool,
+    /// including the decompression of their buffers, instead of one after another on the task
+    /// that received the response. Speeds up parsing of very large responses with several
+    /// tables, small responses can get slower from the overhead.
+    #[serde(default)]
+    pub parallel_ipc_decode: bool,
+    /// Decode the arrow IPC tables of responses without validating their arrays, i.e. without
+    /// checking that the offsets and views of binary and string columns stay within their
+    /// buffers and that strings are valid utf8. Speeds up parsing of large responses, but a
+    /// malformed response leads to undefined behavior instead of an error, so only set this for
+    /// endpoints you trust. Validation is on by default.
+    #[serde(default)]
+    pub skip_ipc_validation: bool,
+    /// Expected fingerprint of the response schema, as returned by `Client::get_schema_fingerprint`.
+    /// Queries fail without retrying if the server returns a response with a different schema.
+    pub pin_schema_fingerprint: Option<u64>,
+    /// Artificial failures and latency to inject into http requests and websocket connections,
+    /// for testing how applications handle retries and resumption.
+    #[cfg(feature = "test-util")]
+    #[serde(default)]
+    pub fault_injection: Option<crate::FaultInjectionConfig>,
 }
 
 /// Config for hypersync event streaming.
@@ -30,7 +276,18 @@ pub struct StreamConfig {
     /// It lets you map columns you want into the DataTypes you want.
     pub column_mapping: Option<ColumnMapping>,
     /// Event signature used to populate decode logs. Decode logs would be empty if set to None.
+    ///
+    /// The simple type functions like `Client::stream` and `Client::collect_events` return the
+    /// decoded logs in `ResponseData::decoded_logs` and `Event::decoded`.
     pub event_signature: Option<String>,
+    /// Event signatures keyed by output name, used to decode each event type into its own output.
+    ///
+    /// Logs are routed to an output by matching their topic0 against the selector of the event
+    /// signature, so topic0 has to be selected. Results are put in `decoded_events` and
+    /// `collect_parquet` writes each of them into a `{name}.parquet` file.
+    /// The `decoded_log` column mapping is applied to all of them.
+    #[serde(default)]
+    pub event_routes: BTreeMap<String, String>,
     /// Determines formatting of binary columns numbers into utf8 hex.
     #[serde(default)]
     pub hex_output: HexOutput,
@@ -41,36 +298,445 @@ pub struct StreamConfig {
     /// Minimum batch size that could be used during dynamic adjustment.
     pub min_batch_size: Option<u64>,
     /// Number of async threads that would be spawned to execute different block ranges of queries.
+    /// Capped at `ClientConfig::max_connections` if that is set.
+    ///
+    /// Responses are passed on in block order, so the rows of a stream are the same and in the
+    /// same order whatever the concurrency. Only how they are split into responses changes, since
+    /// the batch size is adjusted while requests are in flight. This doesn't hold with
+    /// `unordered`.
     pub concurrency: Option<usize>,
-    /// Max number of blocks to fetch in a single request.
+    /// Tune the number of requests in flight while the stream runs instead of always running
+    /// `concurrency` of them. Starts at 2 and grows by one after each round of requests that
+    /// needed no retries and didn't lower the throughput, halving whenever requests are retried.
+    /// `concurrency` is the upper bound.
+    #[serde(default)]
+    pub auto_concurrency: bool,
+    /// Pass on the responses of range requests as soon as they finish instead of in block
+    /// order, so a slow range doesn't hold back the ranges after it. Meant for consumers that
+    /// handle each response on its own, e.g. converting ranges to parquet. The initial request
+    /// still comes first, and the `max_num_*` limits count rows in the order the responses
+    /// arrive. Can't be used with `follow`, `reverse`, `bloom_prescan`, `checkpoint` or the
+    /// websocket transport, and collect functions can't combine it with the `max_num_*`
+    /// limits, `max_bytes` or `stop_condition`.
+    #[serde(default)]
+    pub unordered: bool,
+    /// Milliseconds the requests of the stream may spend retrying in total, adding up failed
+    /// attempts and the waits between them over all requests, including the ones running in
+    /// parallel. The stream fails with a `RetryDeadlineExceeded` error carrying the last
+    /// failure once this is used up. Unlimited if not set, each request is still limited by
+    /// `ClientConfig::max_num_retries`.
+    pub max_total_retry_duration_millis: Option<u64>,
+    /// Times a range request of the stream is started again after it failed with a retryable
+    /// error (see `ErrorKind::is_retryable`) once the client used up `max_num_retries`. It
+    /// continues after the part of the range it already fetched, and the other ranges keep
+    /// running in the meantime. Every restart waits `ClientConfig::retry_base_ms` times the
+    /// number of failures of the range so far, up to `ClientConfig::retry_ceiling_ms`. A failed
+    /// range fails the stream right away if not set.
+    #[serde(default)]
+    pub range_retries: Option<usize>,
+    /// Skip a range that still fails after `range_retries` with a retryable or parse error and
+    /// keep streaming the ranges after it, instead of failing the stream. The data of the
+    /// skipped part of the range is missing from the output. Skipped ranges are logged and
+    /// recorded in `StreamMetrics::skipped_ranges`, the metrics are created for the stream if
+    /// they aren't set. Can't be used with `checkpoint`, which would save progress past them.
+    #[serde(default)]
+    pub skip_failed_ranges: bool,
+    /// Stop sending new range requests while the responses that arrived but haven't been passed
+    /// to the receiver take up more than this many bytes, instead of only limiting the number
+    /// of buffered responses. The size of a response is its decompressed size, which is close
+    /// to the memory its Arrow data takes up. Requests that are already in flight still
+    /// complete, so the buffer can go over the budget by up to `concurrency` responses. The
+    /// receiver of the stream buffers a single response if this is set. See `spill_dir` to
+    /// move the responses over the budget to disk instead.
+    pub max_buffered_bytes: Option<u64>,
+    /// Directory to move responses to once the buffered responses take up more than
+    /// `max_buffered_bytes`, instead of holding back new range requests. Responses that wait
+    /// for a slower range before them are written to a file in this directory with their
+    /// tables compressed with lz4, and are memory mapped back in when it is their turn. The
+    /// directory is created if it doesn't exist, the files are removed once they are read or
+    /// the stream is dropped. Spilled responses are counted in `StreamMetrics`. Needs
+    /// `max_buffered_bytes`.
+    pub spill_dir: Option<PathBuf>,
+    /// End the stream once it has returned this many blocks. The response that reaches the
+    /// limit is passed on whole, so the stream can return more blocks than this.
     pub max_num_blocks: Option<usize>,
-    /// Max number of transactions to fetch in a single request.
+    /// End the stream once it has returned this many transactions, see `max_num_blocks`.
     pub max_num_transactions: Option<usize>,
-    /// Max number of logs to fetch in a single request.
+    /// End the stream once it has returned this many logs, see `max_num_blocks`.
     pub max_num_logs: Option<usize>,
-    /// Max number of traces to fetch in a single request.
+    /// End the stream once it has returned this many traces, see `max_num_blocks`.
     pub max_num_traces: Option<usize>,
+    /// End the stream once its responses add up to this many decompressed bytes. The response
+    /// that reaches the limit is passed on whole.
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    /// Called with every response to decide if the stream should end after it, e.g. once a
+    /// wanted event was found.
+    #[serde(skip)]
+    pub stop_condition: Option<Arc<dyn StopCondition>>,
+    /// Called with every response before it is passed on, e.g. to filter rows or add derived
+    /// columns off the consumer task, see [`ResponseTransform`].
+    #[serde(skip)]
+    pub transform: Option<Arc<dyn ResponseTransform>>,
     /// Size of a response in bytes from which step size will be lowered
     pub response_bytes_ceiling: Option<u64>,
     /// Size of a response in bytes from which step size will be increased
     pub response_bytes_floor: Option<u64>,
+    /// Milliseconds a range request may take from which step size will be lowered, even if the
+    /// response is below `response_bytes_ceiling`. Step size also isn't increased past what
+    /// would keep requests under this. Latency isn't taken into account if this isn't set.
+    pub response_time_ceiling_millis: Option<u64>,
+    /// Start at the first block with a timestamp at or after this unix timestamp in seconds,
+    /// instead of at `query.from_block`. Resolved to a block number with a binary search over
+    /// block headers before the stream starts, see `Client::get_block_at_timestamp`.
+    #[serde(default)]
+    pub from_timestamp: Option<u64>,
+    /// End before the first block with a timestamp at or after this unix timestamp in seconds,
+    /// instead of at `query.to_block`, so `from_timestamp..to_timestamp` covers a half open time
+    /// range like the block range of a query. Can't be used with `follow`.
+    #[serde(default)]
+    pub to_timestamp: Option<u64>,
     /// Stream data in reverse order
     pub reverse: Option<bool>,
+    /// Keep streaming new blocks once the stream has reached the height of the server instead
+    /// of ending, polling the height every `follow_poll_interval_millis`. The query can't have a
+    /// `to_block`, and the stream only ends when the receiver is dropped, a request fails or one
+    /// of the `max_num_*` limits is hit. Can't be used with the `collect` functions.
+    ///
+    /// Reorgs are detected from the rollback guards of the responses and the `hash` and
+    /// `parent_hash` block columns if they are selected without hex encoding. The stream then
+    /// fetches the replaced blocks again and sets `QueryResponse::rollback` on the next response.
+    #[serde(default)]
+    pub follow: bool,
+    /// Fetch only the `logs_bloom` of every block in the range first, then run the query only
+    /// for the blocks whose bloom may match the addresses and topics of its log selections.
+    /// Cuts down the data transferred for searches of rare events over long ranges, at the cost
+    /// of scanning the range twice. The query can only have log selections, address filters
+    /// are treated as matching every block. The stream ends with a response without data whose
+    /// `next_block` is the end of the scanned range. Can't be used with `follow` or `reverse`.
+    #[serde(default)]
+    pub bloom_prescan: bool,
+    /// Milliseconds between height polls while following the tip of the chain, defaults to
+    /// 1000.
+    pub follow_poll_interval_millis: Option<u64>,
+    /// Send a height request this often while a follow stream waits for its next height poll,
+    /// so pooled connections don't go idle and the first request after a quiet period doesn't
+    /// have to reconnect. Only useful if the poll interval is longer than the idle timeouts of
+    /// the connections, e.g. `ClientConfig::pool_idle_timeout_millis` or the ones of proxies in
+    /// between. Failed requests are ignored.
+    pub keep_warm_interval_millis: Option<u64>,
+    /// Fetch only a few blocks per request while following the tip of the chain, so new blocks
+    /// are returned as soon as the server has them instead of waiting for a full batch. Only
+    /// used with `follow`.
+    pub head_mode: Option<HeadModeConfig>,
+    /// Metrics that the stream will record into while running.
+    #[serde(skip)]
+    pub metrics: Option<Arc<StreamMetrics>>,
+    /// Called with the progress of the stream after every response it sends, e.g. to render a
+    /// progress bar. Progress is tracked through `metrics`, which are created for the stream if
+    /// they aren't set. Not called for reverse streams.
+    #[serde(skip)]
+    pub on_progress: Option<Arc<dyn ProgressListener>>,
+    /// Return the `access_list` and `blob_versioned_hashes` transaction columns as nested list
+    /// columns instead of their binary encoding, so outputs like parquet keep them as structured
+    /// data.
+    #[serde(default)]
+    pub nest_list_columns: bool,
+    /// Only return logs emitted by addresses in the given shard, so several workers can split one
+    /// export between them without overlap. Decoded logs are derived from the filtered logs,
+    /// blocks, transactions and traces are returned unfiltered to every worker.
+    ///
+    /// The shard is filtered on the client after the response is received, the server isn't
+    /// aware of it. Every worker still downloads all logs matched by the query, so sharding
+    /// splits the decoding and output work but not the download.
+    pub address_shard: Option<AddressShard>,
+    /// Drop duplicate transactions from each response, e.g. a transaction that matched a
+    /// transaction selection and was also joined in through a log selection. The first
+    /// occurrence is kept, transactions are identified by `hash` or by `block_number` and
+    /// `transaction_index` if the hash isn't selected, so one of them needs to be in the field
+    /// selection. Duplicates are returned as the server sent them if this isn't set.
+    #[serde(default)]
+    pub dedup_transactions: bool,
+    /// Drop the rows at or before this position, so a consumer that stopped in the middle of a
+    /// response and restarts the stream from the block it stopped at doesn't see the rows it
+    /// already handled twice. See [`ResumePoint`] for which rows are dropped.
+    ///
+    /// Reads the `number` of blocks, the `block_number` of the other tables and the index
+    /// columns of the position, so these need to be selected. Can't be combined with `reverse`.
+    pub resume_after: Option<ResumePoint>,
+    /// Derive a `trace_summary` table from the traces of each response, with one row per
+    /// transaction holding its number of traces, internal calls, created contracts, failed
+    /// subcalls and its maximum call depth. Needs the `block_number`, `transaction_position`,
+    /// `trace_address`, `type` and `error` trace fields to be selected, `transaction_hash` is
+    /// added to the table if it is selected too.
+    #[serde(default)]
+    pub trace_summary: bool,
+    /// Convert responses into the simple types of `collect`, `collect_events`, `stream` and
+    /// `stream_events` in parallel on the rayon thread pool instead of on the task receiving
+    /// them. Speeds up large exports where conversion is slower than the download.
+    #[serde(default)]
+    pub parallel_conversion: bool,
+    /// Distributes the concurrent range requests of the stream across the client's url and
+    /// `ClientConfig::fallback_urls`, which are expected to be mirrors serving the same data.
+    /// All requests go to the active endpoint if this isn't set.
+    pub load_balancing: Option<LoadBalancing>,
+    /// Collect row counts, block number ranges, null counts and distinct address estimates of
+    /// every table while streaming. `collect_parquet` writes them into the export manifest.
+    #[serde(default)]
+    pub collect_column_stats: bool,
+    /// Unix timestamp in seconds written as `hypersync.export_timestamp` into the metadata of
+    /// exported files instead of the time the export started. Set it to write byte identical
+    /// files when exporting the same blocks again.
+    #[serde(default)]
+    pub export_timestamp: Option<u64>,
+    /// Options of the parquet files written by `collect_parquet`.
+    #[serde(default)]
+    pub parquet: ParquetConfig,
+    /// Where the stream keeps the block to resume from. The stream starts at the stored block
+    /// instead of `Query::from_block` if there is one and ends right away if it is at or past
+    /// `Query::to_block`.
+    ///
+    /// Streams store the `next_block` of a response once the receiver takes the response after
+    /// it, so a consumer that handles one response at a time and stops halfway through one sees
+    /// it again when resuming. The last response of a stream is stored once it is taken, before
+    /// the stream ends. `collect_parquet` stores the block once all files of the export are
+    /// finished since parquet files can't be appended to. Not supported for reverse streams or
+    /// the in-memory `collect` functions, which lose their data if they fail.
+    #[serde(skip)]
+    pub checkpoint: Option<Arc<dyn Checkpoint>>,
+    /// Pauses and resumes the stream while it runs. Keep a clone of the handle to control the
+    /// stream with. Not supported with the websocket transport, where the server pushes the
+    /// responses.
+    #[serde(skip)]
+    pub control: Option<StreamControl>,
+    /// Stops the stream when cancelled, aborting its in flight requests and the parsing of
+    /// their responses. The stream then ends with a `Cancelled` error, so the `collect` functions
+    /// fail instead of returning partial data. Streams also stop as soon as their receiver is
+    /// dropped, without a token.
+    #[serde(skip)]
+    pub cancellation_token: Option<CancellationToken>,
+    /// Fail the stream if it ends without having returned a single row in any table, e.g.
+    /// because the filters of the query don't match anything. Meant for scheduled exports
+    /// that always expect data, so a misconfigured query fails instead of writing empty
+    /// outputs. Responses are held back until the first row arrives, so nothing is
+    /// checkpointed or written by `collect_parquet` before the stream fails. The responses
+    /// without rows before it are merged into one.
+    #[serde(default)]
+    pub error_on_empty: bool,
+    /// Transport used to receive responses from the server.
+    #[cfg(feature = "websocket")]
+    #[serde(default)]
+    pub transport: StreamTransport,
+}
+
+/// Options of the parquet files written by `collect_parquet`.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct ParquetConfig {
+    /// Encoding of columns keyed by column name, applied to the matching column of every table.
+    ///
+    /// The `block_number`, `number`, `log_index`, `transaction_index` and
+    /// `transaction_position` integer columns default to `DeltaBinaryPacked` since they increase
+    /// monotonically within a row group. This stores each value in a few bits instead of 8 bytes,
+    /// e.g. the block_number column of a log export ends up around 9x smaller than with `Plain`
+    /// even after compression. Other columns default to
+    /// `Plain`. Columns fall back to `Plain` if the requested encoding doesn't support their
+    /// data type, nested columns are always written with `Plain` and columns mapped to
+    /// `DataType::Dictionary` always with `Dictionary`.
+    #[serde(default)]
+    pub column_encodings: BTreeMap<String, ParquetEncoding>,
+    /// Columns to write bloom filters for, keyed by column name with the false positive
+    /// probability of the filter, e.g. 0.01. Applied to the matching binary and string columns
+    /// of every table, other columns are skipped.
+    ///
+    /// Each row group gets its own filter sized for its distinct values, so engines like DuckDB
+    /// or Trino can skip the row groups that can't contain the value of a point lookup, e.g. of
+    /// an `address`, `topic0` or transaction `hash`. A 1% filter takes about 1.2 bytes per
+    /// distinct value before rounding up to a power of two.
+    #[serde(default)]
+    pub bloom_filters: BTreeMap<String, f64>,
+    /// Write the `logs` and `decoded_logs` tables as one file per contract address instead of a
+    /// single file, in hive style directories like `logs/address=0x.../data.parquet`. The
+    /// `address` field of logs has to be selected. Other tables are written as usual.
+    #[serde(default)]
+    pub partition_by_address: bool,
+    /// Fail the export once it would write more than this many address partitions, since every
+    /// partition keeps a file and a row group buffer open until the export finishes. Defaults to
+    /// 1000.
+    #[serde(default)]
+    pub max_address_partitions: Option<usize>,
+}
+
+/// Parquet page encoding of a column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParquetEncoding {
+    /// Values are stored as is.
+    Plain,
+    /// Integer values are stored as bit packed deltas. Best for sorted or sequential columns.
+    DeltaBinaryPacked,
+    /// Binary values are stored with delta encoded lengths.
+    DeltaLengthByteArray,
+    /// Values are stored as indices into a dictionary of the distinct values of the page. Best
+    /// for columns with few distinct values.
+    Dictionary,
+}
+
+/// Determines how a stream receives responses from the server.
+#[cfg(feature = "websocket")]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamTransport {
+    /// Issue concurrent requests to the `query/arrow-ipc` endpoint.
+    #[default]
+    Http,
+    /// Send the query once over a websocket connection and receive responses, including new
+    /// blocks at the tip of the chain, as the server pushes them.
+    WebSocket,
+}
+
+impl StreamConfig {
+    /// Returns a config with batch sizing tuned for the block density of the given chain.
+    ///
+    /// Chains with dense blocks start with smaller block ranges so the first requests don't
+    /// produce huge responses, while mainnet-like chains use the regular defaults.
+    pub fn for_chain(chain: ChainKind) -> Self {
+        let (batch_size, min_batch_size, max_batch_size) = match chain {
+            ChainKind::Ethereum => (1_000, 200, 200_000),
+            ChainKind::Optimism | ChainKind::Polygon => (500, 100, 100_000),
+            ChainKind::Arbitrum => (250, 50, 100_000),
+            ChainKind::Base | ChainKind::Bsc => (200, 50, 50_000),
+        };
+
+        Self {
+            batch_size: Some(batch_size),
+            min_batch_size: Some(min_batch_size),
+            max_batch_size: Some(max_batch_size),
+            ..Default::default()
+        }
+    }
+
+    /// Returns a config that resumes from and keeps updating the given checkpoint, see
+    /// `StreamConfig::checkpoint`.
+    pub fn resume_from_checkpoint(checkpoint: Arc<dyn Checkpoint>) -> Self {
+        Self {
+            checkpoint: Some(checkpoint),
+            ..Default::default()
+        }
+    }
+}
+
+/// Chains that have tuned `StreamConfig` presets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainKind {
+    /// Ethereum mainnet
+    Ethereum,
+    /// OP Mainnet
+    Optimism,
+    /// Base
+    Base,
+    /// Arbitrum One
+    Arbitrum,
+    /// Polygon PoS
+    Polygon,
+    /// BNB Smart Chain
+    Bsc,
+}
+
+impl ChainKind {
+    /// Returns the chain kind for the given chain id if there is a preset for it.
+    pub fn from_chain_id(chain_id: u64) -> Option<Self> {
+        match chain_id {
+            1 => Some(Self::Ethereum),
+            10 => Some(Self::Optimism),
+            8453 => Some(Self::Base),
+            42161 => Some(Self::Arbitrum),
+            137 => Some(Self::Polygon),
+            56 => Some(Self::Bsc),
+            _ => None,
+        }
+    }
+}
+
+/// Deterministic partition of logs by contract address.
+///
+/// A log belongs to shard `address % num_shards`, with the address interpreted as a big endian
+/// integer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressShard {
+    /// Total number of shards.
+    pub num_shards: NonZeroU64,
+    /// Index of the shard to keep, in range [0, num_shards).
+    pub index: u64,
+}
+
+/// Position of the last row a consumer handled, see `StreamConfig::resume_after`.
+///
+/// Rows of earlier blocks and the header of `block_number` are dropped. Within `block_number`,
+/// transactions and traces are dropped up to and including `transaction_index`, matched against
+/// their `transaction_index` and `transaction_position`, and logs up to and including
+/// `log_index`. Rows without a block number or index can't be placed and are kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumePoint {
+    /// Block of the last handled row.
+    pub block_number: u64,
+    /// Index of the last handled transaction of the block. All transactions and traces of the
+    /// block were handled if this is `None`.
+    pub transaction_index: Option<u64>,
+    /// Index of the last handled log of the block. All logs of the block were handled if this
+    /// is `None`.
+    pub log_index: Option<u64>,
+}
+
+/// Request pacing of a stream that is close to the tip of the chain, see
+/// `StreamConfig::head_mode`.
+///
+/// The stream switches to requests of `batch_size` blocks once it is within `distance` blocks of
+/// the height of the server and goes back to the regular batch sizing if it falls further
+/// behind, e.g. after a burst of blocks or a slow consumer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeadModeConfig {
+    /// Number of blocks behind the height from which the stream is at the head.
+    pub distance: u64,
+    /// Blocks per request at the head, defaults to 1.
+    pub batch_size: Option<u64>,
+    /// Milliseconds between height polls at the head, replacing
+    /// `StreamConfig::follow_poll_interval_millis`. Defaults to 100.
+    pub poll_interval_millis: Option<u64>,
+}
+
+/// Strategy for picking the endpoint each range request of a stream is sent to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalancing {
+    /// Send range requests to each endpoint in turn.
+    RoundRobin,
+    /// Pick endpoints randomly, weighted by the inverse of their recent response latency.
+    LatencyWeighted,
+}
+
+/// Format of the output written by `Client::collect_to_writer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// One json object per row, with a `table` key naming the table the row belongs to.
+    JsonLines,
+    /// Comma separated rows with a leading `table` column. A header row is written whenever the
+    /// table or its columns change from the previous batch.
+    Csv,
+    /// A sequence of Arrow IPC streams, one per batch, with the table name in the
+    /// `hypersync.table` schema metadata key. The schema metadata also describes the export like
+    /// the key value metadata of the files written by `Client::collect_parquet`.
+    ArrowIpc,
 }
 
 /// Determines format of Binary column
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum HexOutput {
     /// Binary column won't be formatted as hex
+    #[default]
     NoEncode,
     /// Binary column would be formatted as prefixed hex i.e. 0xdeadbeef
     Prefixed,
     /// Binary column would be formatted as non prefixed hex i.e. deadbeef
     NonPrefixed,
 }
-
-impl Default for HexOutput {
-    fn default() -> Self {
-        Self::NoEncode
-    }
-}
@@ -1,22 +1,49 @@
+//! Decoding of logs into events.
+//!
+//! The ABI tables of a [`Decoder`] are immutable after construction and kept behind an `Arc`,
+//! and decoding only needs `&self` without any heap allocated scratch state.
+//! Stream workers that decode in parallel should each take a clone of the decoder, which costs a
+//! reference count increment, instead of sharing a single decoder behind a lock:
+//!
+//!     use hypersync_client::Decoder;
+//!     let decoder = Decoder::from_signatures(&[
+//!        "Transfer(address indexed from, address indexed to, uint amount)",
+//!     ]).unwrap();
+//!
+//!     let workers = (0..4)
+//!         .map(|_| {
+//!             let decoder = decoder.clone();
+//!             std::thread::spawn(move || {
+//!                 // decoder.decode_log(&log) for each log of this worker
+//!                 drop(decoder);
+//!             })
+//!         })
+//!         .collect::<Vec<_>>();
+//!     for worker in workers {
+//!         worker.join().unwrap();
+//!     }
+
 use crate::simple_types::Log;
 use alloy_dyn_abi::{DecodedEvent, DynSolEvent, Specifier};
 use anyhow::{Context, Result};
 use hypersync_format::LogArgument;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
 struct EventKey {
-    topic0: Vec<u8>,
+    topic0: [u8; 32],
     num_topics: usize,
 }
 
 type DecoderMap = HashMap<EventKey, DynSolEvent>;
 
 /// Decode logs parsing topics and log data.
-#[derive(Debug)]
+///
+/// Cloning is cheap since the ABI tables are shared between clones.
+#[derive(Debug, Clone)]
 pub struct Decoder {
     // A map of topic0 => Event decoder
-    map: DecoderMap,
+    map: Arc<DecoderMap>,
 }
 
 impl Decoder {
@@ -27,12 +54,32 @@ impl Decoder {
     ///        "Transfer(address indexed from, address indexed to, uint amount)",
     ///     ]).unwrap();
     pub fn from_signatures<S: AsRef<str>>(signatures: &[S]) -> Result<Self> {
-        let map: DecoderMap = signatures
+        let events = signatures
             .iter()
-            .map(|sig| {
-                let event =
-                    alloy_json_abi::Event::parse(sig.as_ref()).context("parse event signature")?;
-                let topic0 = event.selector().to_vec();
+            .map(|sig| alloy_json_abi::Event::parse(sig.as_ref()).context("parse event signature"))
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::from_events(&events)
+    }
+
+    /// Initialize decoder from the events of a contract ABI.
+    ///
+    /// Anonymous events are skipped since logs are matched to events by their topic0.
+    pub fn from_abi(abi: &alloy_json_abi::JsonAbi) -> Result<Self> {
+        let events = abi
+            .events()
+            .filter(|event| !event.anonymous)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Self::from_events(&events)
+    }
+
+    fn from_events(events: &[alloy_json_abi::Event]) -> Result<Self> {
+        let map: DecoderMap = events
+            .iter()
+            .map(|event| {
+                let topic0 = event.selector().0;
                 let num_topics = event.num_topics();
                 let event_key = EventKey { topic0, num_topics };
                 let event = event.resolve().context("resolve event")?;
@@ -41,7 +88,7 @@ impl Decoder {
             .collect::<Result<DecoderMap>>()
             .context("construct event decoder map")?;
 
-        Ok(Self { map })
+        Ok(Self { map: Arc::new(map) })
     }
 
     /// Parse log and return decoded event.
@@ -65,8 +112,12 @@ impl Decoder {
         topics: &[Option<LogArgument>],
         data: &[u8],
     ) -> Result<Option<DecodedEvent>> {
+        let topic0 = match topic0.try_into() {
+            Ok(topic0) => topic0,
+            Err(_) => return Ok(None),
+        };
         let event_key = EventKey {
-            topic0: topic0.into(),
+            topic0,
             num_topics: topics.iter().fold(
                 0,
                 |accum, topic| {
@@ -128,6 +179,50 @@ mod tests {
             }
         }
     }
+    #[test]
+    fn test_clone_shares_abi_tables() {
+        let decoder = Decoder::from_signatures(&[
+            "Transfer(address indexed from, address indexed to, uint amount)",
+        ])
+        .unwrap();
+        let clone = decoder.clone();
+        assert!(Arc::ptr_eq(&decoder.map, &clone.map));
+    }
+
+    #[test]
+    fn test_from_abi() {
+        let abi: alloy_json_abi::JsonAbi = serde_json::from_str(
+            r#"[
+                {"type": "function", "name": "transfer", "inputs": [], "outputs": []},
+                {"type": "event", "name": "Transfer", "anonymous": false, "inputs": [
+                    {"name": "from", "type": "address", "indexed": true},
+                    {"name": "to", "type": "address", "indexed": true},
+                    {"name": "amount", "type": "uint256", "indexed": false}
+                ]},
+                {"type": "event", "name": "Anon", "anonymous": true, "inputs": []}
+            ]"#,
+        )
+        .unwrap();
+        let decoder = Decoder::from_abi(&abi).unwrap();
+        assert_eq!(decoder.map.len(), 1);
+
+        let transfer = &abi.event("Transfer").unwrap()[0];
+        let topics = [
+            Some(LogArgument::from(transfer.selector().0)),
+            Some(LogArgument::from([1; 32])),
+            Some(LogArgument::from([2; 32])),
+        ];
+        let decoded = decoder
+            .decode(transfer.selector().as_slice(), &topics, &[0; 32])
+            .unwrap()
+            .unwrap();
+        assert_eq!(decoded.indexed.len(), 2);
+        assert_eq!(
+            decoded.body,
+            vec![DynSolValue::Uint(Default::default(), 256)]
+        );
+    }
+
     #[test]
     fn decodes_i24_event() {
         //https://basescan.org/tx/0x76aeccc2815612c23344557c07fff57aada63625f1977096d5e9c88f63c257a7#eventlog#176
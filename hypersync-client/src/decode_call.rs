@@ -2,19 +2,22 @@ use alloy_dyn_abi::{DynSolType, DynSolValue, JsonAbiExt};
 use alloy_json_abi::Function;
 use anyhow::{Context, Result};
 use hypersync_format::Data;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 #[derive(Debug, Hash, Eq, PartialEq)]
 struct FunctionKey {
-    signature: Vec<u8>,
+    signature: [u8; 4],
 }
 
 type DecoderMap = HashMap<FunctionKey, Function>;
 
 /// Decode input data parsing input data.
+///
+/// Cloning is cheap since the ABI tables are shared between clones.
+#[derive(Clone)]
 pub struct CallDecoder {
     // A map of signature => Function decoder
-    map: DecoderMap,
+    map: Arc<DecoderMap>,
 }
 
 impl CallDecoder {
@@ -29,14 +32,14 @@ impl CallDecoder {
             .iter()
             .map(|sig| {
                 let function = Function::parse(sig.as_ref()).context("parse event signature")?;
-                let signature = function.selector().to_vec();
+                let signature = function.selector().0;
                 let event_key = FunctionKey { signature };
                 Ok((event_key, function))
             })
             .collect::<Result<DecoderMap>>()
             .context("construct function decoder map")?;
 
-        Ok(Self { map })
+        Ok(Self { map: Arc::new(map) })
     }
 
     /// Parse input data and return result
@@ -44,7 +47,7 @@ impl CallDecoder {
     /// Returns Ok(None) if signature not found.
     pub fn decode_input(&self, data: &Data) -> Result<Option<Vec<DynSolValue>>> {
         let function_key = FunctionKey {
-            signature: data[0..4].to_vec(),
+            signature: data[0..4].try_into().unwrap(),
         };
         let function = match self.map.get(&function_key) {
             Some(function) => function,
@@ -62,7 +65,11 @@ impl CallDecoder {
     /// and returns the decoded values in a `Vec<DynSolValue>`. If the function
     /// signature is not found or the decoding fails, it returns `Ok(None)` as
     /// the result to match the behavior of `decode_input`
-    pub fn decode_output(&self, data: &Data, function_signature: &str) -> Result<Option<Vec<DynSolValue>>>{
+    pub fn decode_output(
+        &self,
+        data: &Data,
+        function_signature: &str,
+    ) -> Result<Option<Vec<DynSolValue>>> {
         // Parse the provided function signature into a Function object
         let function = Function::parse(function_signature).context("parsing function signature")?;
 
@@ -73,7 +80,7 @@ impl CallDecoder {
         let output_types: Vec<DynSolType> = output_types
             .into_iter()
             .map(|param| param.ty.parse::<DynSolType>())
-            .collect::<Result<_, _>>()  // Parse each type as DynSolType
+            .collect::<Result<_, _>>() // Parse each type as DynSolType
             .context("parsing output types")?;
 
         // Create a tuple type from the output parameters
@@ -97,7 +104,6 @@ impl CallDecoder {
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -132,7 +138,7 @@ mod tests {
             "transfer(address dst, uint256 wad)",
             "approve(address usr, uint256 wad)",
         ])
-            .unwrap();
+        .unwrap();
         let got = decoder.decode_input(&input).unwrap().unwrap();
 
         for (expected, got) in expected.iter().zip(got.iter()) {
@@ -153,10 +159,16 @@ mod tests {
         let function_signature = "balanceOf(address)(uint256)";
 
         let decoder = CallDecoder::from_signatures(&["balanceOf(address)"]).unwrap();
-        let result = decoder.decode_output(&output, function_signature).unwrap().unwrap();
+        let result = decoder
+            .decode_output(&output, function_signature)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(result.len(), 1, "Should return a single value");
-        assert!(matches!(result[0], DynSolValue::Uint(..)), "Should be a uint value");
+        assert!(
+            matches!(result[0], DynSolValue::Uint(..)),
+            "Should be a uint value"
+        );
     }
 
     #[test]
@@ -166,21 +178,32 @@ mod tests {
         let function_signature = "someFunction()(address,uint256)";
 
         let decoder = CallDecoder::from_signatures(&["someFunction()"]).unwrap();
-        let result = decoder.decode_output(&output, function_signature).unwrap().unwrap();
+        let result = decoder
+            .decode_output(&output, function_signature)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(result.len(), 2, "Should return two values");
-        assert!(matches!(result[0], DynSolValue::Address(..)), "First value should be an address");
-        assert!(matches!(result[1], DynSolValue::Uint(..)), "Second value should be a uint");
+        assert!(
+            matches!(result[0], DynSolValue::Address(..)),
+            "First value should be an address"
+        );
+        assert!(
+            matches!(result[1], DynSolValue::Uint(..)),
+            "Second value should be a uint"
+        );
     }
 
     #[test]
     fn test_decode_output_invalid_data() {
         let output = "invalid_data";
-        let output = Data::decode_hex(output).unwrap_err();
+        Data::decode_hex(output).unwrap_err();
         let function_signature = "balanceOf(address)(uint256)";
 
         let decoder = CallDecoder::from_signatures(&["balanceOf(address)"]).unwrap();
-        let result = decoder.decode_output(&Data::default(), function_signature).unwrap();
+        let result = decoder
+            .decode_output(&Data::default(), function_signature)
+            .unwrap();
 
         assert!(result.is_none(), "Should return None for invalid data");
     }
@@ -0,0 +1,180 @@
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use url::Url;
+
+use crate::config::LoadBalancing;
+
+/// The list of server urls a client can send requests to, along with the one currently in use.
+#[derive(Debug)]
+pub(crate) struct Endpoints {
+    urls: Vec<Url>,
+    active: AtomicUsize,
+    /// Moving average of successful request latency in microseconds for each endpoint, zero if
+    /// no request has succeeded yet.
+    latency_micros: Vec<AtomicU64>,
+    /// Number of consecutive failures on an endpoint after which the retry loop moves on to the
+    /// next endpoint.
+    failover_after: usize,
+}
+
+impl Endpoints {
+    pub(crate) fn new(urls: Vec<Url>, failover_after: usize) -> Self {
+        assert!(!urls.is_empty());
+
+        Self {
+            latency_micros: urls.iter().map(|_| AtomicU64::new(0)).collect(),
+            urls,
+            active: AtomicUsize::new(0),
+            failover_after: failover_after.max(1),
+        }
+    }
+
+    /// The primary url, the first one in the list.
+    pub(crate) fn primary(&self) -> &Url {
+        &self.urls[0]
+    }
+
+    /// Returns the index and url of the endpoint currently in use.
+    pub(crate) fn current(&self) -> (usize, &Url) {
+        let idx = self.active.load(Ordering::Relaxed);
+        (idx, &self.urls[idx])
+    }
+
+    /// Returns the url of the endpoint with the given index.
+    pub(crate) fn get(&self, idx: usize) -> &Url {
+        &self.urls[idx]
+    }
+
+    /// Picks the endpoint that the request with the given index should be sent to.
+    pub(crate) fn pick(&self, strategy: LoadBalancing, req_idx: usize) -> usize {
+        match strategy {
+            LoadBalancing::RoundRobin => req_idx % self.urls.len(),
+            LoadBalancing::LatencyWeighted => {
+                let latencies = self
+                    .latency_micros
+                    .iter()
+                    .map(|l| l.load(Ordering::Relaxed))
+                    .collect::<Vec<_>>();
+                pick_weighted(&latencies, rand::random())
+            }
+        }
+    }
+
+    /// Records the latency of a successful request to endpoint `idx`.
+    pub(crate) fn record_latency(&self, idx: usize, latency: Duration) {
+        let latency = u64::try_from(latency.as_micros())
+            .unwrap_or(u64::MAX)
+            .max(1);
+        self.latency_micros[idx]
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |avg| {
+                if avg == 0 {
+                    Some(latency)
+                } else {
+                    // exponential moving average with alpha = 1/4
+                    Some(avg - avg / 4 + latency / 4)
+                }
+            })
+            .ok();
+    }
+
+    /// Records that a request to endpoint `idx` failed `num_failures` times in a row.
+    ///
+    /// Moves to the next endpoint once the failure threshold is reached. Does nothing if another
+    /// request already moved away from `idx`, so concurrent failures on the same endpoint only
+    /// rotate once.
+    pub(crate) fn record_failures(&self, idx: usize, num_failures: usize) -> bool {
+        if self.urls.len() == 1 || num_failures < self.failover_after {
+            return false;
+        }
+
+        let next = (idx + 1) % self.urls.len();
+        let rotated = self
+            .active
+            .compare_exchange(idx, next, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok();
+        if rotated {
+            log::warn!(
+                "failing over from {} to {} after {} failed requests",
+                self.urls[idx],
+                self.urls[next],
+                num_failures
+            );
+        }
+
+        true
+    }
+}
+
+/// Picks an index with probability proportional to the inverse of its latency, `rand` being a
+/// uniformly distributed number in [0, 1).
+///
+/// Endpoints without a measurement are weighted like the fastest measured one so they get a
+/// chance to be measured.
+fn pick_weighted(latencies: &[u64], rand: f64) -> usize {
+    let fastest = latencies
+        .iter()
+        .copied()
+        .filter(|&l| l > 0)
+        .min()
+        .unwrap_or(1);
+    let weights = latencies
+        .iter()
+        .map(|&l| 1.0 / if l == 0 { fastest } else { l } as f64)
+        .collect::<Vec<_>>();
+
+    let mut target = rand * weights.iter().sum::<f64>();
+    for (idx, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return idx;
+        }
+        target -= weight;
+    }
+
+    weights.len() - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failover_rotation() {
+        let urls = vec![
+            "http://a".parse().unwrap(),
+            "http://b".parse().unwrap(),
+            "http://c".parse().unwrap(),
+        ];
+        let endpoints = Endpoints::new(urls, 2);
+
+        assert!(!endpoints.record_failures(0, 1));
+        assert_eq!(endpoints.current().0, 0);
+        assert!(endpoints.record_failures(0, 2));
+        assert_eq!(endpoints.current().0, 1);
+
+        // a stale failure on the previous endpoint doesn't rotate again
+        assert!(endpoints.record_failures(0, 2));
+        assert_eq!(endpoints.current().0, 1);
+
+        endpoints.record_failures(1, 2);
+        endpoints.record_failures(2, 2);
+        assert_eq!(endpoints.current().1.as_str(), "http://a/");
+    }
+
+    #[test]
+    fn test_pick_weighted() {
+        // 100us is picked 3 times as often as 300us
+        let latencies = [100, 300];
+        assert_eq!(pick_weighted(&latencies, 0.0), 0);
+        assert_eq!(pick_weighted(&latencies, 0.74), 0);
+        assert_eq!(pick_weighted(&latencies, 0.76), 1);
+
+        // unmeasured endpoints are weighted like the fastest one
+        let latencies = [0, 100, 200];
+        assert_eq!(pick_weighted(&latencies, 0.39), 0);
+        assert_eq!(pick_weighted(&latencies, 0.41), 1);
+        assert_eq!(pick_weighted(&latencies, 0.99), 2);
+    }
+}
@@ -0,0 +1,699 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+
+/// Returned when the server responds with a non-success status code.
+#[derive(Debug)]
+pub(crate) struct HttpStatusError {
+    pub status: StatusCode,
+    pub body: String,
+    /// Parsed `Retry-After` header of the response.
+    pub retry_after: Option<Duration>,
+}
+
+impl HttpStatusError {
+    /// Error for a non-success response whose body was already read.
+    #[cfg(feature = "websocket")]
+    pub(crate) fn new(status: StatusCode, headers: &HeaderMap, body: String) -> Self {
+        Self {
+            status,
+            body,
+            retry_after: parse_retry_after(headers, SystemTime::now()),
+        }
+    }
+
+    /// Reads the body of a non-success response into an error.
+    pub(crate) async fn from_response(res: reqwest::Response) -> anyhow::Error {
+        let status = res.status();
+        let retry_after = parse_retry_after(res.headers(), SystemTime::now());
+        match res.text().await.context("read text to see error") {
+            Ok(body) => Self {
+                status,
+                body,
+                retry_after,
+            }
+            .into(),
+            Err(e) => e,
+        }
+    }
+}
+
+impl fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "http response status code {}, err body: {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// Returns the error an [`Error`] was created from if it was converted back into an
+/// `anyhow::Error`, so the helpers below see its causes.
+fn unwrap_error(err: &anyhow::Error) -> &anyhow::Error {
+    match err.chain().find_map(|e| e.downcast_ref::<Error>()) {
+        Some(e) => e.as_anyhow(),
+        None => err,
+    }
+}
+
+/// Returns the status code if the error was caused by a non-success http response.
+pub(crate) fn http_status(err: &anyhow::Error) -> Option<StatusCode> {
+    unwrap_error(err)
+        .chain()
+        .find_map(|e| e.downcast_ref::<HttpStatusError>())
+        .map(|e| e.status)
+}
+
+/// Returns true if the server responded in a way that means it doesn't serve the arrow-ipc
+/// query endpoint at all, as opposed to failing this particular request.
+pub(crate) fn arrow_unavailable(err: &anyhow::Error) -> bool {
+    matches!(
+        http_status(err),
+        Some(
+            StatusCode::NOT_FOUND
+                | StatusCode::METHOD_NOT_ALLOWED
+                | StatusCode::NOT_ACCEPTABLE
+                | StatusCode::UNSUPPORTED_MEDIA_TYPE
+                | StatusCode::NOT_IMPLEMENTED
+        )
+    )
+}
+
+/// Returns true if the server rejected the request in a way that sending it again won't fix,
+/// like an invalid query (400) or missing permissions (401, 403). Timeouts (408) and throttling
+/// (429) are client errors too but are worth retrying.
+pub(crate) fn is_fatal_status(err: &anyhow::Error) -> bool {
+    match ErrorKind::of(err) {
+        ErrorKind::InvalidQuery { .. } => true,
+        ErrorKind::Http { status, .. } => {
+            status.is_client_error() && status != StatusCode::REQUEST_TIMEOUT
+        }
+        _ => false,
+    }
+}
+
+/// Returns the status code and the requested wait time if the server asked the client to slow
+/// down by responding with 429 or 503.
+pub(crate) fn throttle(err: &anyhow::Error) -> Option<(StatusCode, Option<Duration>)> {
+    unwrap_error(err)
+        .chain()
+        .find_map(|e| e.downcast_ref::<HttpStatusError>())
+        .filter(|e| {
+            e.status == StatusCode::TOO_MANY_REQUESTS || e.status == StatusCode::SERVICE_UNAVAILABLE
+        })
+        .map(|e| (e.status, e.retry_after))
+}
+
+/// Parses a `Retry-After` header given either as delay seconds or as an http date.
+fn parse_retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+/// Returned without contacting the server when the circuit breaker of every endpoint is open
+/// after too many consecutive failures.
+///
+/// Can be detected by downcasting the returned `anyhow::Error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitOpen {
+    /// Time until the circuit breaker lets requests through again.
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "circuit breaker is open after repeated failures, retry after {} ms",
+            self.retry_after.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+/// Returned when a stream used up its `StreamConfig::max_total_retry_duration_millis`.
+///
+/// Attached as context to the error of the last failed attempt, so it can be detected by
+/// downcasting the returned `anyhow::Error` while the chain still holds the cause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryDeadlineExceeded {
+    /// Time spent on failed attempts and waits between retries.
+    pub spent: Duration,
+    /// Configured retry budget.
+    pub limit: Duration,
+}
+
+impl fmt::Display for RetryDeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "spent {} ms retrying requests, exceeding the retry budget of {} ms",
+            self.spent.as_millis(),
+            self.limit.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for RetryDeadlineExceeded {}
+
+/// Returned by a stream after its `StreamConfig::cancellation_token` was cancelled.
+///
+/// Can be detected by downcasting the returned `anyhow::Error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("stream was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Context marking that a response from the server couldn't be parsed.
+#[derive(Debug)]
+pub(crate) struct ParseError(pub &'static str);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// Kind of failure behind an error returned by the client, so callers can decide whether to
+/// retry, alert or give up without matching on error messages.
+///
+/// Returned by [`Error::kind`]. Errors of stream responses and of the offline helpers are
+/// `anyhow::Error`s, their kind is recovered with `ErrorKind::of`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The server responded with a non-success status code not covered by a more specific kind.
+    Http {
+        /// Status code of the response.
+        status: StatusCode,
+        /// Body of the response.
+        body: String,
+    },
+    /// The server rejected the query with 400, retrying the same query won't help.
+    InvalidQuery {
+        /// Body of the response, usually explains what is wrong with the query.
+        body: String,
+    },
+    /// The server responded with 429 or 503 after the client ran out of retries.
+    RateLimited {
+        /// Wait time the server asked for in its `Retry-After` header.
+        retry_after: Option<Duration>,
+    },
+    /// The circuit breaker of every endpoint is open, see [`CircuitOpen`].
+    CircuitOpen {
+        /// Time until the circuit breaker lets requests through again.
+        retry_after: Duration,
+    },
+    /// Retries used up the retry budget of the stream, see [`RetryDeadlineExceeded`].
+    RetryDeadlineExceeded {
+        /// Time spent on failed attempts and waits between retries.
+        spent: Duration,
+    },
+    /// The stream was cancelled through `StreamConfig::cancellation_token`.
+    Cancelled,
+    /// A request timed out.
+    Timeout,
+    /// Couldn't connect to the server.
+    Connect,
+    /// A response couldn't be parsed.
+    Parse,
+    /// Anything else, e.g. invalid arguments or io errors while writing output.
+    Other,
+}
+
+impl ErrorKind {
+    /// Classifies an error returned by the client.
+    pub fn of(err: &anyhow::Error) -> Self {
+        if let Some(e) = err.chain().find_map(|e| e.downcast_ref::<Error>()) {
+            return e.kind();
+        }
+        if let Some(open) = err.downcast_ref::<CircuitOpen>() {
+            return Self::CircuitOpen {
+                retry_after: open.retry_after,
+            };
+        }
+        // attached as context, which only `anyhow::Error::downcast_ref` sees through
+        if let Some(e) = err.downcast_ref::<RetryDeadlineExceeded>() {
+            return Self::RetryDeadlineExceeded { spent: e.spent };
+        }
+        if err.downcast_ref::<Cancelled>().is_some() {
+            return Self::Cancelled;
+        }
+        if let Some(e) = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<HttpStatusError>())
+        {
+            return match e.status {
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                    Self::RateLimited {
+                        retry_after: e.retry_after,
+                    }
+                }
+                StatusCode::BAD_REQUEST => Self::InvalidQuery {
+                    body: e.body.clone(),
+                },
+                status => Self::Http {
+                    status,
+                    body: e.body.clone(),
+                },
+            };
+        }
+        for e in err.chain() {
+            if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+                if e.is_timeout() {
+                    return Self::Timeout;
+                }
+                if e.is_connect() {
+                    return Self::Connect;
+                }
+            }
+            if e.is::<serde_json::Error>() || e.is::<capnp::Error>() {
+                return Self::Parse;
+            }
+        }
+        if err.downcast_ref::<ParseError>().is_some() {
+            return Self::Parse;
+        }
+        Self::Other
+    }
+
+    /// Returns true for transient failures that may succeed if the request is sent again later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RateLimited { .. } | Self::CircuitOpen { .. } | Self::Timeout | Self::Connect => {
+                true
+            }
+            Self::Http { status, .. } => status.is_server_error(),
+            Self::InvalidQuery { .. }
+            | Self::RetryDeadlineExceeded { .. }
+            | Self::Cancelled
+            | Self::Parse
+            | Self::Other => false,
+        }
+    }
+}
+
+/// Error returned by [`Client`](crate::Client), [`blocking::Client`](crate::blocking::Client)
+/// and [`ClientPool`](crate::ClientPool).
+///
+/// The variant tells what kind of failure it was, see [`ErrorKind`] for what each of them means.
+/// Every variant keeps the `anyhow::Error` the client failed with in `source`, so the message,
+/// context and causes are shown as before and typed causes can still be found with
+/// [`Error::downcast_ref`]. The responses of streams carry `anyhow::Error`s, `Error::from`
+/// classifies them into the same variants.
+#[non_exhaustive]
+pub enum Error {
+    /// The server responded with a non-success status code not covered by a more specific
+    /// variant.
+    Http {
+        /// Status code of the response.
+        status: StatusCode,
+        /// Body of the response.
+        body: String,
+        /// The error the client failed with.
+        source: anyhow::Error,
+    },
+    /// The server rejected the query with 400, retrying the same query won't help.
+    InvalidQuery {
+        /// Body of the response, usually explains what is wrong with the query.
+        body: String,
+        /// The error the client failed with.
+        source: anyhow::Error,
+    },
+    /// The server responded with 429 or 503 after the client ran out of retries.
+    RateLimited {
+        /// Wait time the server asked for in its `Retry-After` header.
+        retry_after: Option<Duration>,
+        /// The error the client failed with.
+        source: anyhow::Error,
+    },
+    /// The circuit breaker of every endpoint is open, see [`CircuitOpen`].
+    CircuitOpen {
+        /// Time until the circuit breaker lets requests through again.
+        retry_after: Duration,
+        /// The error the client failed with.
+        source: anyhow::Error,
+    },
+    /// Retries used up the retry budget of the stream, see [`RetryDeadlineExceeded`].
+    RetryDeadlineExceeded {
+        /// Time spent on failed attempts and waits between retries.
+        spent: Duration,
+        /// The error the client failed with.
+        source: anyhow::Error,
+    },
+    /// The stream was cancelled through `StreamConfig::cancellation_token`.
+    Cancelled {
+        /// The error the client failed with.
+        source: anyhow::Error,
+    },
+    /// A request timed out.
+    Timeout {
+        /// The error the client failed with.
+        source: anyhow::Error,
+    },
+    /// Couldn't connect to the server.
+    Connect {
+        /// The error the client failed with.
+        source: anyhow::Error,
+    },
+    /// A response couldn't be parsed.
+    Parse {
+        /// The error the client failed with.
+        source: anyhow::Error,
+    },
+    /// Anything else, e.g. invalid arguments or io errors while writing output.
+    Other {
+        /// The error the client failed with.
+        source: anyhow::Error,
+    },
+}
+
+impl Error {
+    /// Kind of the failure, without the error it came from.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Http { status, body, .. } => ErrorKind::Http {
+                status: *status,
+                body: body.clone(),
+            },
+            Self::InvalidQuery { body, .. } => ErrorKind::InvalidQuery { body: body.clone() },
+            Self::RateLimited { retry_after, .. } => ErrorKind::RateLimited {
+                retry_after: *retry_after,
+            },
+            Self::CircuitOpen { retry_after, .. } => ErrorKind::CircuitOpen {
+                retry_after: *retry_after,
+            },
+            Self::RetryDeadlineExceeded { spent, .. } => {
+                ErrorKind::RetryDeadlineExceeded { spent: *spent }
+            }
+            Self::Cancelled { .. } => ErrorKind::Cancelled,
+            Self::Timeout { .. } => ErrorKind::Timeout,
+            Self::Connect { .. } => ErrorKind::Connect,
+            Self::Parse { .. } => ErrorKind::Parse,
+            Self::Other { .. } => ErrorKind::Other,
+        }
+    }
+
+    /// Returns true for transient failures that may succeed if the request is sent again later.
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+
+    /// The error the client failed with.
+    pub fn as_anyhow(&self) -> &anyhow::Error {
+        match self {
+            Self::Http { source, .. }
+            | Self::InvalidQuery { source, .. }
+            | Self::RateLimited { source, .. }
+            | Self::CircuitOpen { source, .. }
+            | Self::RetryDeadlineExceeded { source, .. }
+            | Self::Cancelled { source }
+            | Self::Timeout { source }
+            | Self::Connect { source }
+            | Self::Parse { source }
+            | Self::Other { source } => source,
+        }
+    }
+
+    /// Returns the error the client failed with.
+    pub fn into_anyhow(self) -> anyhow::Error {
+        match self {
+            Self::Http { source, .. }
+            | Self::InvalidQuery { source, .. }
+            | Self::RateLimited { source, .. }
+            | Self::CircuitOpen { source, .. }
+            | Self::RetryDeadlineExceeded { source, .. }
+            | Self::Cancelled { source }
+            | Self::Timeout { source }
+            | Self::Connect { source }
+            | Self::Parse { source }
+            | Self::Other { source } => source,
+        }
+    }
+
+    /// Looks for an error or context of type `E` in the error the client failed with, like
+    /// `anyhow::Error::downcast_ref`, also inside an `Error` it was wrapped around.
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    {
+        self.as_anyhow().downcast_ref::<E>().or_else(|| {
+            self.as_anyhow()
+                .chain()
+                .find_map(|e| e.downcast_ref::<Error>())
+                .and_then(|e| e.downcast_ref::<E>())
+        })
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(source: anyhow::Error) -> Self {
+        // only an `Error` without context on top is passed through, `downcast` would also find
+        // one below context and drop the context
+        let is_error = source
+            .chain()
+            .next()
+            .is_some_and(|e| e.downcast_ref::<Error>().is_some());
+        let source = match is_error {
+            true => match source.downcast::<Error>() {
+                Ok(e) => return e,
+                Err(source) => source,
+            },
+            false => source,
+        };
+        match ErrorKind::of(&source) {
+            ErrorKind::Http { status, body } => Self::Http {
+                status,
+                body,
+                source,
+            },
+            ErrorKind::InvalidQuery { body } => Self::InvalidQuery { body, source },
+            ErrorKind::RateLimited { retry_after } => Self::RateLimited {
+                retry_after,
+                source,
+            },
+            ErrorKind::CircuitOpen { retry_after } => Self::CircuitOpen {
+                retry_after,
+                source,
+            },
+            ErrorKind::RetryDeadlineExceeded { spent } => {
+                Self::RetryDeadlineExceeded { spent, source }
+            }
+            ErrorKind::Cancelled => Self::Cancelled { source },
+            ErrorKind::Timeout => Self::Timeout { source },
+            ErrorKind::Connect => Self::Connect { source },
+            ErrorKind::Parse => Self::Parse { source },
+            ErrorKind::Other => Self::Other { source },
+        }
+    }
+}
+
+// shows the error the client failed with, the variant can be read from `Error::kind`
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_anyhow(), f)
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_anyhow(), f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.as_anyhow().source()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers, now), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(
+            parse_retry_after(&headers, now),
+            Some(Duration::from_secs(120))
+        );
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:30 GMT"),
+        );
+        assert_eq!(
+            parse_retry_after(&headers, now),
+            Some(Duration::from_secs(30))
+        );
+
+        // dates in the past mean no wait
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:00:00 GMT"),
+        );
+        assert_eq!(parse_retry_after(&headers, now), Some(Duration::ZERO));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(parse_retry_after(&headers, now), None);
+    }
+
+    #[test]
+    fn test_is_fatal_status() {
+        let http = |status| {
+            anyhow::Error::from(HttpStatusError {
+                status,
+                body: String::new(),
+                retry_after: None,
+            })
+            .context("run query")
+        };
+
+        for status in [
+            StatusCode::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::FORBIDDEN,
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ] {
+            assert!(is_fatal_status(&http(status)), "{}", status);
+        }
+        for status in [
+            StatusCode::REQUEST_TIMEOUT,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::BAD_GATEWAY,
+        ] {
+            assert!(!is_fatal_status(&http(status)), "{}", status);
+        }
+        assert!(!is_fatal_status(&anyhow::anyhow!("connection reset")));
+    }
+
+    #[test]
+    fn test_error_kind() {
+        let http = |status| {
+            anyhow::Error::from(HttpStatusError {
+                status,
+                body: "bad".into(),
+                retry_after: Some(Duration::from_secs(3)),
+            })
+            .context("run query")
+        };
+
+        assert_eq!(
+            ErrorKind::of(&http(StatusCode::BAD_REQUEST)),
+            ErrorKind::InvalidQuery { body: "bad".into() }
+        );
+        assert_eq!(
+            ErrorKind::of(&http(StatusCode::TOO_MANY_REQUESTS)),
+            ErrorKind::RateLimited {
+                retry_after: Some(Duration::from_secs(3))
+            }
+        );
+        let server_error = ErrorKind::of(&http(StatusCode::BAD_GATEWAY));
+        assert!(
+            matches!(server_error, ErrorKind::Http { status, .. } if status == StatusCode::BAD_GATEWAY)
+        );
+        assert!(server_error.is_retryable());
+
+        let open = anyhow::Error::from(CircuitOpen {
+            retry_after: Duration::from_secs(1),
+        });
+        assert!(ErrorKind::of(&open).is_retryable());
+
+        let deadline = http(StatusCode::BAD_GATEWAY).context(RetryDeadlineExceeded {
+            spent: Duration::from_secs(61),
+            limit: Duration::from_secs(60),
+        });
+        assert_eq!(
+            ErrorKind::of(&deadline.context("get data")),
+            ErrorKind::RetryDeadlineExceeded {
+                spent: Duration::from_secs(61)
+            }
+        );
+
+        let parse = anyhow::anyhow!("truncated").context(ParseError("parse query response"));
+        assert_eq!(ErrorKind::of(&parse.context("get data")), ErrorKind::Parse);
+        let json = serde_json::from_str::<u64>("x").unwrap_err();
+        assert_eq!(ErrorKind::of(&json.into()), ErrorKind::Parse);
+
+        let cancelled = anyhow::Error::from(Cancelled).context("collect");
+        assert_eq!(ErrorKind::of(&cancelled), ErrorKind::Cancelled);
+        assert!(!ErrorKind::Cancelled.is_retryable());
+
+        assert_eq!(ErrorKind::of(&anyhow::anyhow!("oops")), ErrorKind::Other);
+    }
+
+    #[test]
+    fn test_error() {
+        let http = anyhow::Error::from(HttpStatusError {
+            status: StatusCode::BAD_REQUEST,
+            body: "bad field".into(),
+            retry_after: None,
+        })
+        .context("run query");
+
+        let err = Error::from(http);
+        assert!(matches!(&err, Error::InvalidQuery { body, .. } if body == "bad field"));
+        assert!(!err.is_retryable());
+        assert_eq!(err.to_string(), "run query");
+        assert!(err.downcast_ref::<HttpStatusError>().is_some());
+        assert!(std::error::Error::source(&err).is_some());
+
+        // the helpers see through an error converted back into anyhow
+        let wrapped = anyhow::Error::from(err).context("collect");
+        assert_eq!(http_status(&wrapped), Some(StatusCode::BAD_REQUEST));
+        assert_eq!(
+            ErrorKind::of(&wrapped),
+            ErrorKind::InvalidQuery {
+                body: "bad field".into()
+            }
+        );
+
+        // converting an anyhow holding an `Error` gives back that error
+        let err = Error::from(anyhow::Error::from(Error::from(anyhow::Error::from(
+            Cancelled,
+        ))));
+        assert!(matches!(err, Error::Cancelled { .. }));
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+
+        // context added on top of an `Error` is kept
+        let err = Error::from(
+            anyhow::Error::from(Error::from(anyhow::Error::from(Cancelled)))
+                .context("collect blocks"),
+        );
+        assert!(matches!(err, Error::Cancelled { .. }));
+        assert_eq!(err.to_string(), "collect blocks");
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+
+        let other = Error::from(anyhow::anyhow!("oops"));
+        assert_eq!(other.kind(), ErrorKind::Other);
+        assert!(format!("{:?}", other).starts_with("oops"));
+    }
+}
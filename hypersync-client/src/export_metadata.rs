@@ -0,0 +1,51 @@
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use hypersync_net_types::Query;
+
+use crate::Client;
+
+/// Key value metadata describing an export, so files found later can be traced back to the query
+/// that produced them.
+///
+/// Has the json encoded query under `hypersync.query`, the version of this crate under
+/// `hypersync.client_version`, the chain id of the server under `hypersync.chain_id` and the
+/// unix timestamp in seconds of the start of the export, or `export_timestamp` if it is set, under
+/// `hypersync.export_timestamp`. The chain id is left out if the server doesn't return it.
+pub(crate) async fn export_metadata(
+    client: &Client,
+    query: &Query,
+    export_timestamp: Option<u64>,
+) -> Result<BTreeMap<String, String>> {
+    let mut metadata = BTreeMap::new();
+    metadata.insert(
+        "hypersync.query".to_owned(),
+        serde_json::to_string(query).context("serialize query")?,
+    );
+    metadata.insert(
+        "hypersync.client_version".to_owned(),
+        env!("CARGO_PKG_VERSION").to_owned(),
+    );
+    match client.get_chain_id().await {
+        Ok(chain_id) => {
+            metadata.insert("hypersync.chain_id".to_owned(), chain_id.to_string());
+        }
+        Err(e) => log::warn!("leaving the chain id out of the export metadata: {:?}", e),
+    }
+    let timestamp = match export_timestamp {
+        Some(timestamp) => timestamp,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("get export timestamp")?
+            .as_secs(),
+    };
+    metadata.insert(
+        "hypersync.export_timestamp".to_owned(),
+        timestamp.to_string(),
+    );
+
+    Ok(metadata)
+}
@@ -0,0 +1,110 @@
+use std::{
+    num::NonZeroU64,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Artificial failures and latency injected at the HTTP layer.
+///
+/// Requests are numbered starting from 1 in the order the client issues them, so a given
+/// configuration produces the same sequence of failures on every run.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct FaultInjectionConfig {
+    /// Fail every Nth request before sending it to the server.
+    pub fail_every_nth_request: Option<NonZeroU64>,
+    /// Truncate the body of every Nth response to half of its length. Every message of the Nth
+    /// websocket connection is truncated.
+    pub truncate_every_nth_response: Option<NonZeroU64>,
+    /// Milliseconds of latency added to every request.
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Number of requests at the start of the client's lifetime that get `slow_start_latency_ms`
+    /// of additional latency.
+    #[serde(default)]
+    pub slow_start_num_requests: u64,
+    /// Milliseconds of additional latency for the first `slow_start_num_requests` requests.
+    #[serde(default)]
+    pub slow_start_latency_ms: u64,
+}
+
+#[derive(Debug)]
+pub(crate) struct FaultInjector {
+    cfg: FaultInjectionConfig,
+    num_requests: AtomicU64,
+}
+
+impl FaultInjector {
+    pub(crate) fn new(cfg: FaultInjectionConfig) -> Self {
+        Self {
+            cfg,
+            num_requests: AtomicU64::new(0),
+        }
+    }
+
+    /// Registers a new request, applies latency and returns its index or an injected error.
+    pub(crate) async fn before_request(&self) -> Result<u64> {
+        let idx = self.num_requests.fetch_add(1, Ordering::SeqCst) + 1;
+
+        let mut latency = self.cfg.latency_ms;
+        if idx <= self.cfg.slow_start_num_requests {
+            latency += self.cfg.slow_start_latency_ms;
+        }
+        if latency > 0 {
+            tokio::time::sleep(Duration::from_millis(latency)).await;
+        }
+
+        if is_nth(idx, self.cfg.fail_every_nth_request) {
+            return Err(anyhow!("injected failure for request {}", idx));
+        }
+
+        Ok(idx)
+    }
+
+    /// Truncates the response body of the request with the given index if configured.
+    pub(crate) fn response_body(&self, idx: u64, body: bytes::Bytes) -> bytes::Bytes {
+        if is_nth(idx, self.cfg.truncate_every_nth_response) {
+            body.slice(..body.len() / 2)
+        } else {
+            body
+        }
+    }
+}
+
+fn is_nth(idx: u64, n: Option<NonZeroU64>) -> bool {
+    n.map(|n| idx.is_multiple_of(n.get())).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fail_every_nth_request() {
+        let injector = FaultInjector::new(FaultInjectionConfig {
+            fail_every_nth_request: NonZeroU64::new(3),
+            ..Default::default()
+        });
+
+        let mut results = Vec::new();
+        for _ in 0..6 {
+            results.push(injector.before_request().await.is_ok());
+        }
+
+        assert_eq!(results, [true, true, false, true, true, false]);
+    }
+
+    #[test]
+    fn test_truncate_every_nth_response() {
+        let injector = FaultInjector::new(FaultInjectionConfig {
+            truncate_every_nth_response: NonZeroU64::new(2),
+            ..Default::default()
+        });
+
+        let body = bytes::Bytes::from_static(b"abcdef");
+        assert_eq!(injector.response_body(1, body.clone()).as_ref(), b"abcdef");
+        assert_eq!(injector.response_body(2, body).as_ref(), b"abc");
+    }
+}
@@ -1,9 +1,10 @@
 use arrayvec::ArrayVec;
 use polars_arrow::array::{
-    BinaryArray, BooleanArray, StaticArray, UInt64Array, UInt8Array, Utf8Array,
+    BinaryArray, BooleanArray, ListArray, StaticArray, UInt8Array, Utf8Array,
 };
 
 use crate::{
+    nested_columns::{read_access_list, read_hashes},
     simple_types::{Block, Log, Trace, Transaction},
     ArrowBatch,
 };
@@ -24,7 +25,7 @@ where
 
 impl FromArrow for Block {
     fn from_arrow(batch: &ArrowBatch) -> Vec<Self> {
-        let number = batch.column::<UInt64Array>("number").ok();
+        let number = batch.u64_column("number").ok();
         let hash = batch.column::<BinaryArray<i32>>("hash").ok();
         let parent_hash = batch.column::<BinaryArray<i32>>("parent_hash").ok();
         let nonce = batch.column::<BinaryArray<i32>>("nonce").ok();
@@ -50,14 +51,14 @@ impl FromArrow for Block {
             .ok();
         let withdrawals_root = batch.column::<BinaryArray<i32>>("withdrawals_root").ok();
         let withdrawals = batch.column::<BinaryArray<i32>>("withdrawals").ok();
-        let l1_block_number = batch.column::<UInt64Array>("l1_block_number").ok();
+        let l1_block_number = batch.u64_column("l1_block_number").ok();
         let send_count = batch.column::<BinaryArray<i32>>("send_count").ok();
         let send_root = batch.column::<BinaryArray<i32>>("send_root").ok();
         let mix_hash = batch.column::<BinaryArray<i32>>("mix_hash").ok();
 
         (0..batch.chunk.len())
             .map(|idx| Self {
-                number: number.and_then(|arr| arr.get(idx)),
+                number: number.as_deref().and_then(|arr| arr.get(idx)),
                 hash: map_binary(idx, hash),
                 parent_hash: map_binary(idx, parent_hash),
                 nonce: map_binary(idx, nonce),
@@ -88,7 +89,9 @@ impl FromArrow for Block {
                 withdrawals_root: map_binary(idx, withdrawals_root),
                 withdrawals: withdrawals
                     .and_then(|arr| arr.get(idx).map(|v| bincode::deserialize(v).unwrap())),
-                l1_block_number: l1_block_number.and_then(|arr| arr.get(idx).map(|v| v.into())),
+                l1_block_number: l1_block_number
+                    .as_deref()
+                    .and_then(|arr| arr.get(idx).map(|v| v.into())),
                 send_count: map_binary(idx, send_count),
                 send_root: map_binary(idx, send_root),
                 mix_hash: map_binary(idx, mix_hash),
@@ -100,7 +103,7 @@ impl FromArrow for Block {
 impl FromArrow for Transaction {
     fn from_arrow(batch: &ArrowBatch) -> Vec<Self> {
         let block_hash = batch.column::<BinaryArray<i32>>("block_hash").ok();
-        let block_number = batch.column::<UInt64Array>("block_number").ok();
+        let block_number = batch.u64_column("block_number").ok();
         let from = batch.column::<BinaryArray<i32>>("from").ok();
         let gas = batch.column::<BinaryArray<i32>>("gas").ok();
         let gas_price = batch.column::<BinaryArray<i32>>("gas_price").ok();
@@ -108,7 +111,7 @@ impl FromArrow for Transaction {
         let input = batch.column::<BinaryArray<i32>>("input").ok();
         let nonce = batch.column::<BinaryArray<i32>>("nonce").ok();
         let to = batch.column::<BinaryArray<i32>>("to").ok();
-        let transaction_index = batch.column::<UInt64Array>("transaction_index").ok();
+        let transaction_index = batch.u64_column("transaction_index").ok();
         let value = batch.column::<BinaryArray<i32>>("value").ok();
         let v = batch.column::<BinaryArray<i32>>("v").ok();
         let r = batch.column::<BinaryArray<i32>>("r").ok();
@@ -120,12 +123,15 @@ impl FromArrow for Transaction {
         let max_fee_per_gas = batch.column::<BinaryArray<i32>>("max_fee_per_gas").ok();
         let chain_id = batch.column::<BinaryArray<i32>>("chain_id").ok();
         let access_list = batch.column::<BinaryArray<i32>>("access_list").ok();
+        let nested_access_list = batch.column::<ListArray<i64>>("access_list").ok();
         let max_fee_per_blob_gas = batch
             .column::<BinaryArray<i32>>("max_fee_per_blob_gas")
             .ok();
         let blob_versioned_hashes = batch
             .column::<BinaryArray<i32>>("blob_versioned_hashes")
             .ok();
+        let nested_blob_versioned_hashes =
+            batch.column::<ListArray<i64>>("blob_versioned_hashes").ok();
         let cumulative_gas_used = batch.column::<BinaryArray<i32>>("cumulative_gas_used").ok();
         let effective_gas_price = batch.column::<BinaryArray<i32>>("effective_gas_price").ok();
         let gas_used = batch.column::<BinaryArray<i32>>("gas_used").ok();
@@ -143,7 +149,9 @@ impl FromArrow for Transaction {
         (0..batch.chunk.len())
             .map(|idx| Self {
                 block_hash: map_binary(idx, block_hash),
-                block_number: block_number.and_then(|arr| arr.get(idx).map(|v| v.into())),
+                block_number: block_number
+                    .as_deref()
+                    .and_then(|arr| arr.get(idx).map(|v| v.into())),
                 from: map_binary(idx, from),
                 gas: map_binary(idx, gas),
                 gas_price: map_binary(idx, gas_price),
@@ -151,7 +159,9 @@ impl FromArrow for Transaction {
                 input: map_binary(idx, input),
                 nonce: map_binary(idx, nonce),
                 to: map_binary(idx, to),
-                transaction_index: transaction_index.and_then(|arr| arr.get(idx).map(|v| v.into())),
+                transaction_index: transaction_index
+                    .as_deref()
+                    .and_then(|arr| arr.get(idx).map(|v| v.into())),
                 value: map_binary(idx, value),
                 v: map_binary(idx, v),
                 r: map_binary(idx, r),
@@ -161,15 +171,18 @@ impl FromArrow for Transaction {
                 max_fee_per_gas: map_binary(idx, max_fee_per_gas),
                 chain_id: map_binary(idx, chain_id),
                 access_list: access_list
-                    .and_then(|arr| arr.get(idx).map(|v| bincode::deserialize(v).unwrap())),
+                    .and_then(|arr| arr.get(idx).map(|v| bincode::deserialize(v).unwrap()))
+                    .or_else(|| nested_access_list.and_then(|arr| read_access_list(arr, idx))),
                 max_fee_per_blob_gas: map_binary(idx, max_fee_per_blob_gas),
-                blob_versioned_hashes: blob_versioned_hashes.and_then(|arr| {
-                    arr.get(idx).map(|v| {
-                        v.chunks(32)
-                            .map(|chunk| chunk.try_into().unwrap())
-                            .collect()
+                blob_versioned_hashes: blob_versioned_hashes
+                    .and_then(|arr| {
+                        arr.get(idx).map(|v| {
+                            v.chunks(32)
+                                .map(|chunk| chunk.try_into().unwrap())
+                                .collect()
+                        })
                     })
-                }),
+                    .or_else(|| nested_blob_versioned_hashes.and_then(|arr| read_hashes(arr, idx))),
                 cumulative_gas_used: map_binary(idx, cumulative_gas_used),
                 effective_gas_price: map_binary(idx, effective_gas_price),
                 gas_used: map_binary(idx, gas_used),
@@ -179,7 +192,7 @@ impl FromArrow for Transaction {
                 root: map_binary(idx, root),
                 status: status.and_then(|arr| {
                     arr.get(idx)
-                        .map(|v| hypersync_format::TransactionStatus::from_u8(v).unwrap())
+                        .and_then(|v| hypersync_format::TransactionStatus::from_u8(v).ok())
                 }),
                 l1_fee: map_binary(idx, l1_fee),
                 l1_gas_price: map_binary(idx, l1_gas_price),
@@ -197,11 +210,11 @@ impl FromArrow for Transaction {
 impl FromArrow for Log {
     fn from_arrow(batch: &ArrowBatch) -> Vec<Self> {
         let removed = batch.column::<BooleanArray>("removed").ok();
-        let log_index = batch.column::<UInt64Array>("log_index").ok();
-        let transaction_index = batch.column::<UInt64Array>("transaction_index").ok();
+        let log_index = batch.u64_column("log_index").ok();
+        let transaction_index = batch.u64_column("transaction_index").ok();
         let transaction_hash = batch.column::<BinaryArray<i32>>("transaction_hash").ok();
         let block_hash = batch.column::<BinaryArray<i32>>("block_hash").ok();
-        let block_number = batch.column::<UInt64Array>("block_number").ok();
+        let block_number = batch.u64_column("block_number").ok();
         let address = batch.column::<BinaryArray<i32>>("address").ok();
         let data = batch.column::<BinaryArray<i32>>("data").ok();
         let topic0 = batch.column::<BinaryArray<i32>>("topic0").ok();
@@ -212,11 +225,17 @@ impl FromArrow for Log {
         (0..batch.chunk.len())
             .map(|idx| Self {
                 removed: removed.and_then(|arr| arr.get(idx)),
-                log_index: log_index.and_then(|arr| arr.get(idx).map(|v| v.into())),
-                transaction_index: transaction_index.and_then(|arr| arr.get(idx).map(|v| v.into())),
+                log_index: log_index
+                    .as_deref()
+                    .and_then(|arr| arr.get(idx).map(|v| v.into())),
+                transaction_index: transaction_index
+                    .as_deref()
+                    .and_then(|arr| arr.get(idx).map(|v| v.into())),
                 transaction_hash: map_binary(idx, transaction_hash),
                 block_hash: map_binary(idx, block_hash),
-                block_number: block_number.and_then(|arr| arr.get(idx).map(|v| v.into())),
+                block_number: block_number
+                    .as_deref()
+                    .and_then(|arr| arr.get(idx).map(|v| v.into())),
                 address: map_binary(idx, address),
                 data: map_binary(idx, data),
                 topics: {
@@ -246,15 +265,15 @@ impl FromArrow for Trace {
         let author = batch.column::<BinaryArray<i32>>("author").ok();
         let reward_type = batch.column::<Utf8Array<i32>>("reward_type").ok();
         let block_hash = batch.column::<BinaryArray<i32>>("block_hash").ok();
-        let block_number = batch.column::<UInt64Array>("block_number").ok();
+        let block_number = batch.u64_column("block_number").ok();
         let address = batch.column::<BinaryArray<i32>>("address").ok();
         let code = batch.column::<BinaryArray<i32>>("code").ok();
         let gas_used = batch.column::<BinaryArray<i32>>("gas_used").ok();
         let output = batch.column::<BinaryArray<i32>>("output").ok();
-        let subtraces = batch.column::<UInt64Array>("subtraces").ok();
+        let subtraces = batch.u64_column("subtraces").ok();
         let trace_address = batch.column::<BinaryArray<i32>>("trace_address").ok();
         let transaction_hash = batch.column::<BinaryArray<i32>>("transaction_hash").ok();
-        let transaction_position = batch.column::<UInt64Array>("transaction_position").ok();
+        let transaction_position = batch.u64_column("transaction_position").ok();
         let kind = batch.column::<Utf8Array<i32>>("type").ok();
         let error = batch.column::<Utf8Array<i32>>("error").ok();
 
@@ -270,19 +289,73 @@ impl FromArrow for Trace {
                 author: map_binary(idx, author),
                 reward_type: reward_type.and_then(|arr| arr.get(idx).map(|v| v.to_owned())),
                 block_hash: map_binary(idx, block_hash),
-                block_number: block_number.and_then(|arr| arr.get(idx)),
+                block_number: block_number.as_deref().and_then(|arr| arr.get(idx)),
                 address: map_binary(idx, address),
                 code: map_binary(idx, code),
                 gas_used: map_binary(idx, gas_used),
                 output: map_binary(idx, output),
-                subtraces: subtraces.and_then(|arr| arr.get(idx)),
+                subtraces: subtraces.as_deref().and_then(|arr| arr.get(idx)),
                 trace_address: trace_address
                     .and_then(|arr| arr.get(idx).map(|v| bincode::deserialize(v).unwrap())),
                 transaction_hash: map_binary(idx, transaction_hash),
-                transaction_position: transaction_position.and_then(|arr| arr.get(idx)),
+                transaction_position: transaction_position.as_deref().and_then(|arr| arr.get(idx)),
                 kind: kind.and_then(|arr| arr.get(idx).map(|v| v.to_owned())),
                 error: error.and_then(|arr| arr.get(idx).map(|v| v.to_owned())),
             })
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use polars_arrow::{
+        array::Array,
+        datatypes::{ArrowDataType as DataType, ArrowSchema as Schema, Field},
+        record_batch::RecordBatchT as Chunk,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_transaction_receipt_columns() {
+        let gas_used = BinaryArray::<i32>::from([Some(&[0x52, 0x08][..]), None]);
+        let status = UInt8Array::from([Some(1), None]);
+        let effective_gas_price = BinaryArray::<i32>::from([Some(&[0x01][..]), None]);
+        let contract_address = BinaryArray::<i32>::from([None, Some(&[0xaa; 20][..])]);
+
+        let batch = ArrowBatch {
+            chunk: Arc::new(Chunk::new(vec![
+                gas_used.boxed(),
+                status.boxed(),
+                effective_gas_price.boxed(),
+                contract_address.boxed(),
+            ] as Vec<Box<dyn Array>>)),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("gas_used", DataType::Binary, true),
+                Field::new("status", DataType::UInt8, true),
+                Field::new("effective_gas_price", DataType::Binary, true),
+                Field::new("contract_address", DataType::Binary, true),
+            ])),
+        };
+
+        let txs = Transaction::from_arrow(&batch);
+
+        assert_eq!(txs[0].gas_used.as_ref().unwrap().as_ref(), &[0x52, 0x08]);
+        assert!(matches!(
+            txs[0].status,
+            Some(hypersync_format::TransactionStatus::Success)
+        ));
+        assert!(txs[0].effective_gas_price.is_some());
+        assert!(txs[0].contract_address.is_none());
+
+        assert!(txs[1].gas_used.is_none());
+        assert!(txs[1].status.is_none());
+        assert!(txs[1].effective_gas_price.is_none());
+        assert_eq!(
+            txs[1].contract_address.as_ref().unwrap().as_ref(),
+            &[0xaa; 20]
+        );
+    }
+}
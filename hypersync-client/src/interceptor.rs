@@ -0,0 +1,70 @@
+use std::{fmt, time::Duration};
+
+use reqwest::{header::HeaderMap, Method, StatusCode};
+use url::Url;
+
+/// Hooks that are called around every http request the client sends, including the ones issued
+/// by streams and the handshake of the websocket stream transport.
+///
+/// Interceptors are registered with `ClientConfig::interceptors` and called in the order they
+/// were given. They can be used to log requests, add signature headers or observe response
+/// metadata like rate limit headers.
+pub trait Interceptor: Send + Sync {
+    /// Called before a request is sent. The request can be modified in place.
+    fn on_request(&self, _req: &mut RequestParts) {}
+
+    /// Called when the response headers are received, before the body is read.
+    ///
+    /// Not called if the request fails before a response is received.
+    fn on_response(&self, _res: &ResponseMeta) {}
+
+    /// Called when the server responds with 429 or 503, before the client waits and retries.
+    fn on_throttle(&self, _throttle: &Throttle) {}
+}
+
+impl fmt::Debug for dyn Interceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Interceptor")
+    }
+}
+
+/// An outgoing request as seen by [`Interceptor::on_request`].
+#[derive(Debug, Clone)]
+pub struct RequestParts {
+    /// Http method of the request.
+    pub method: Method,
+    /// Url the request is sent to.
+    pub url: Url,
+    /// Request headers, including the authorization header if a bearer token is configured.
+    pub headers: HeaderMap,
+    /// Request body, if the request has one.
+    pub body: Option<bytes::Bytes>,
+}
+
+/// Metadata of a received response as seen by [`Interceptor::on_response`].
+#[derive(Debug, Clone)]
+pub struct ResponseMeta {
+    /// Http method of the request.
+    pub method: Method,
+    /// Url the request was sent to, after interceptors modified it.
+    pub url: Url,
+    /// Status code of the response.
+    pub status: StatusCode,
+    /// Response headers.
+    pub headers: HeaderMap,
+    /// Time between sending the request and receiving the response headers.
+    pub latency: Duration,
+}
+
+/// A throttled request as seen by [`Interceptor::on_throttle`].
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    /// Url of the endpoint that throttled the request.
+    pub url: Url,
+    /// Status code of the response, 429 or 503.
+    pub status: StatusCode,
+    /// Wait time requested by the server in the `Retry-After` header.
+    pub retry_after: Option<Duration>,
+    /// Time the client is going to wait before retrying.
+    pub wait: Duration,
+}
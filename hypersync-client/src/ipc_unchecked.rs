@@ -0,0 +1,452 @@
+use std::{collections::VecDeque, io::Read, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use bytemuck::Pod;
+use polars_arrow::{
+    array::{
+        Array, BinaryArray, BinaryViewArrayGeneric, BooleanArray, PrimitiveArray, Utf8Array, View,
+        ViewType,
+    },
+    bitmap::Bitmap,
+    buffer::Buffer,
+    datatypes::{ArrowDataType, ArrowSchema},
+    io::ipc::read::FileMetadata,
+    offset::{Offset, OffsetsBuffer},
+    types::NativeType,
+};
+use polars_arrow_format::ipc::{self as fb, planus::ReadAsRoot};
+
+use crate::ArrowChunk;
+
+const CONTINUATION_MARKER: [u8; 4] = [0xff; 4];
+
+/// Reads the record batches of the arrow IPC file in `bytes` without validating their arrays,
+/// for `ClientConfig::skip_ipc_validation`.
+///
+/// Offsets and views of binary and string columns aren't checked against their buffers and
+/// strings aren't checked to be utf8, so the file has to come from a trusted server. Only the
+/// column types the server returns are decoded, None is returned for files with any other
+/// column type so they can be read with the validating reader instead.
+pub(crate) fn read_chunks(
+    bytes: &[u8],
+    metadata: &FileMetadata,
+) -> Result<Option<Vec<ArrowChunk>>> {
+    let native_order = metadata.ipc_schema.is_little_endian == cfg!(target_endian = "little");
+    if !native_order
+        || !metadata
+            .schema
+            .fields
+            .iter()
+            .all(|f| is_supported(&f.data_type))
+    {
+        return Ok(None);
+    }
+
+    metadata
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| {
+            read_chunk(bytes, block, &metadata.schema).with_context(|| format!("read chunk {}", i))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
+
+fn is_supported(data_type: &ArrowDataType) -> bool {
+    matches!(
+        data_type,
+        ArrowDataType::UInt8
+            | ArrowDataType::UInt64
+            | ArrowDataType::Boolean
+            | ArrowDataType::Binary
+            | ArrowDataType::LargeBinary
+            | ArrowDataType::Utf8
+            | ArrowDataType::LargeUtf8
+            | ArrowDataType::BinaryView
+            | ArrowDataType::Utf8View
+    )
+}
+
+fn read_chunk(bytes: &[u8], block: &fb::Block, schema: &ArrowSchema) -> Result<ArrowChunk> {
+    let offset = usize::try_from(block.offset).context("negative block offset")?;
+    let meta_len = usize::try_from(block.meta_data_length).context("negative metadata length")?;
+    let body_len = usize::try_from(block.body_length).context("negative body length")?;
+
+    // the message is prefixed with its length, which follows a continuation marker since
+    // version 0.15 of the format
+    let meta = slice(bytes, offset, meta_len).context("read block metadata")?;
+    let meta = meta.strip_prefix(&CONTINUATION_MARKER).unwrap_or(meta);
+    let message_len = meta
+        .get(..4)
+        .context("read message length")?
+        .try_into()
+        .map(i32::from_le_bytes)
+        .unwrap();
+    let message_len = usize::try_from(message_len).context("negative message length")?;
+    let message = slice(meta, 4, message_len).context("read message")?;
+    let message = fb::MessageRef::read_as_root(message).context("parse message")?;
+    let batch = match message.header().context("parse message header")? {
+        Some(fb::MessageHeaderRef::RecordBatch(batch)) => batch,
+        _ => return Err(anyhow!("block doesn't hold a record batch")),
+    };
+
+    let mut reader = BatchReader {
+        body: slice(bytes, offset + meta_len, body_len).context("read block body")?,
+        nodes: batch
+            .nodes()
+            .context("parse field nodes")?
+            .context("missing field nodes")?
+            .iter()
+            .collect(),
+        buffers: batch
+            .buffers()
+            .context("parse buffers")?
+            .context("missing buffers")?
+            .iter()
+            .collect(),
+        variadic_buffer_counts: match batch
+            .variadic_buffer_counts()
+            .context("parse variadic buffer counts")?
+        {
+            Some(counts) => counts.iter().collect(),
+            None => VecDeque::new(),
+        },
+        compression: batch
+            .compression()
+            .context("parse compression")?
+            .map(|compression| compression.codec())
+            .transpose()
+            .context("parse compression codec")?,
+    };
+
+    let columns = schema
+        .fields
+        .iter()
+        .map(|field| {
+            reader
+                .read_array(&field.data_type)
+                .with_context(|| format!("read column {}", field.name))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    ArrowChunk::try_new(columns).context("create chunk")
+}
+
+fn slice(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| bytes.get(offset..end))
+        .context("out of bounds")
+}
+
+/// Reads the arrays of a record batch from its body, consuming its field nodes and buffers in
+/// the order the columns are laid out.
+struct BatchReader<'a> {
+    body: &'a [u8],
+    nodes: VecDeque<fb::FieldNodeRef<'a>>,
+    buffers: VecDeque<fb::BufferRef<'a>>,
+    variadic_buffer_counts: VecDeque<i64>,
+    compression: Option<fb::CompressionType>,
+}
+
+impl BatchReader<'_> {
+    fn read_array(&mut self, data_type: &ArrowDataType) -> Result<Box<dyn Array>> {
+        let node = self.nodes.pop_front().context("missing field node")?;
+        let len = usize::try_from(node.length()).context("negative array length")?;
+        let validity = if node.null_count() > 0 {
+            Some(self.read_bitmap(len).context("read validity")?)
+        } else {
+            self.buffers
+                .pop_front()
+                .context("missing validity buffer")?;
+            None
+        };
+
+        let data_type = data_type.clone();
+        let array = match data_type {
+            ArrowDataType::UInt8 => self.read_primitive::<u8>(data_type, len, validity)?,
+            ArrowDataType::UInt64 => self.read_primitive::<u64>(data_type, len, validity)?,
+            ArrowDataType::Boolean => {
+                let values = self.read_bitmap(len).context("read values")?;
+                BooleanArray::try_new(data_type, values, validity)?.boxed()
+            }
+            ArrowDataType::Binary => {
+                let (offsets, values) = self.read_offsets_and_values::<i32>(len)?;
+                // SAFETY: the server is trusted to send offsets that increase monotonically
+                unsafe { BinaryArray::new_unchecked(data_type, offsets, values, validity) }.boxed()
+            }
+            ArrowDataType::LargeBinary => {
+                let (offsets, values) = self.read_offsets_and_values::<i64>(len)?;
+                // SAFETY: the server is trusted to send offsets that increase monotonically
+                unsafe { BinaryArray::new_unchecked(data_type, offsets, values, validity) }.boxed()
+            }
+            ArrowDataType::Utf8 => {
+                let (offsets, values) = self.read_offsets_and_values::<i32>(len)?;
+                // SAFETY: the server is trusted to send monotonically increasing offsets of
+                // valid utf8 strings
+                unsafe { Utf8Array::new_unchecked(data_type, offsets, values, validity) }.boxed()
+            }
+            ArrowDataType::LargeUtf8 => {
+                let (offsets, values) = self.read_offsets_and_values::<i64>(len)?;
+                // SAFETY: the server is trusted to send monotonically increasing offsets of
+                // valid utf8 strings
+                unsafe { Utf8Array::new_unchecked(data_type, offsets, values, validity) }.boxed()
+            }
+            ArrowDataType::BinaryView => self.read_view_array::<[u8]>(data_type, len, validity)?,
+            ArrowDataType::Utf8View => self.read_view_array::<str>(data_type, len, validity)?,
+            data_type => return Err(anyhow!("unsupported data type {:?}", data_type)),
+        };
+
+        Ok(array)
+    }
+
+    fn read_primitive<T: NativeType>(
+        &mut self,
+        data_type: ArrowDataType,
+        len: usize,
+        validity: Option<Bitmap>,
+    ) -> Result<Box<dyn Array>> {
+        let values = self.read_buffer::<T>(Some(len)).context("read values")?;
+        Ok(PrimitiveArray::<T>::try_new(data_type, values.into(), validity)?.boxed())
+    }
+
+    fn read_offsets_and_values<O: Offset + Pod>(
+        &mut self,
+        len: usize,
+    ) -> Result<(OffsetsBuffer<O>, Buffer<u8>)> {
+        let offsets = self
+            .read_buffer::<O>(Some(len + 1))
+            .context("read offsets")?;
+        let values = self.read_buffer::<u8>(None).context("read values")?;
+        // checking that the strings end within the values is cheap, unlike checking all offsets
+        if offsets[len].to_usize() > values.len() {
+            return Err(anyhow!("offsets exceed the values"));
+        }
+        // SAFETY: the server is trusted to send offsets that increase monotonically
+        let offsets = unsafe { OffsetsBuffer::new_unchecked(offsets.into()) };
+
+        Ok((offsets, values.into()))
+    }
+
+    fn read_view_array<T: ViewType + ?Sized>(
+        &mut self,
+        data_type: ArrowDataType,
+        len: usize,
+        validity: Option<Bitmap>,
+    ) -> Result<Box<dyn Array>> {
+        let views = self.read_buffer::<View>(Some(len)).context("read views")?;
+        let num_buffers = self
+            .variadic_buffer_counts
+            .pop_front()
+            .context("missing variadic buffer count")?;
+        let num_buffers = usize::try_from(num_buffers).context("negative variadic buffer count")?;
+        let buffers = (0..num_buffers)
+            .map(|_| {
+                self.read_buffer::<u8>(None)
+                    .map(Buffer::from)
+                    .context("read data buffer")
+            })
+            .collect::<Result<Arc<[_]>>>()?;
+
+        // SAFETY: the server is trusted to send views that point into the buffers and valid utf8
+        // strings in string columns
+        let array = unsafe {
+            BinaryViewArrayGeneric::<T>::new_unchecked_unknown_md(
+                data_type,
+                views.into(),
+                buffers,
+                validity,
+                None,
+            )
+        };
+
+        Ok(array.boxed())
+    }
+
+    fn read_bitmap(&mut self, len: usize) -> Result<Bitmap> {
+        let bytes = self.read_buffer::<u8>(Some(len.div_ceil(8)))?;
+        Bitmap::try_new(bytes, len).context("create bitmap")
+    }
+
+    /// Reads the next buffer, decompressing it if the batch is compressed. Reads `len` values or
+    /// all values the buffer holds if it is None.
+    fn read_buffer<T: Pod>(&mut self, len: Option<usize>) -> Result<Vec<T>> {
+        let buffer = self.buffers.pop_front().context("missing buffer")?;
+        let offset = usize::try_from(buffer.offset()).context("negative buffer offset")?;
+        let buffer_len = usize::try_from(buffer.length()).context("negative buffer length")?;
+        let data = slice(self.body, offset, buffer_len).context("read buffer")?;
+
+        if len == Some(0) {
+            return Ok(Vec::new());
+        }
+
+        let value_size = std::mem::size_of::<T>();
+        let (data, codec) = match self.compression {
+            // a buffer of a compressed batch starts with its uncompressed length, which is -1
+            // if the buffer is stored uncompressed anyway
+            Some(codec) if !data.is_empty() => {
+                let (uncompressed_len, data) = data
+                    .split_first_chunk::<8>()
+                    .context("read uncompressed length")?;
+                match i64::from_le_bytes(*uncompressed_len) {
+                    -1 => (data, None),
+                    uncompressed_len => {
+                        let uncompressed_len = usize::try_from(uncompressed_len)
+                            .context("negative uncompressed length")?;
+                        (data, Some((codec, uncompressed_len)))
+                    }
+                }
+            }
+            _ => (data, None),
+        };
+        let available_len = match codec {
+            Some((_, uncompressed_len)) => uncompressed_len,
+            None => data.len(),
+        } / value_size;
+        let len = len.unwrap_or(available_len);
+        if len > available_len {
+            return Err(anyhow!(
+                "buffer holds {} values, expected {}",
+                available_len,
+                len
+            ));
+        }
+
+        let mut out = vec![T::zeroed(); len];
+        let out_bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut out);
+        match codec {
+            Some((fb::CompressionType::Lz4Frame, _)) => lz4::Decoder::new(data)
+                .and_then(|mut decoder| decoder.read_exact(out_bytes))
+                .context("decompress lz4")?,
+            Some((fb::CompressionType::Zstd, _)) => zstd::Decoder::new(data)
+                .and_then(|mut decoder| decoder.read_exact(out_bytes))
+                .context("decompress zstd")?,
+            None => out_bytes.copy_from_slice(&data[..out_bytes.len()]),
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use polars_arrow::{
+        array::{MutableBinaryViewArray, UInt64Array, Utf8ViewArray},
+        datatypes::Field,
+        io::ipc::{
+            read::{read_file_metadata, FileReader},
+            write::{Compression, FileWriter, WriteOptions},
+        },
+    };
+
+    use super::*;
+
+    fn ipc_file(
+        chunks: &[ArrowChunk],
+        schema: ArrowSchema,
+        compression: Option<Compression>,
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut writer = FileWriter::try_new(
+            &mut out,
+            Arc::new(schema),
+            None,
+            WriteOptions { compression },
+        )
+        .unwrap();
+        for chunk in chunks {
+            writer.write(chunk, None).unwrap();
+        }
+        writer.finish().unwrap();
+        out
+    }
+
+    fn read_validated(bytes: &[u8]) -> Result<Vec<ArrowChunk>> {
+        let mut reader = Cursor::new(bytes);
+        let metadata = read_file_metadata(&mut reader)?;
+        Ok(FileReader::new(reader, metadata, None, None).collect::<Result<Vec<_>, _>>()?)
+    }
+
+    fn read_unvalidated(bytes: &[u8]) -> Option<Vec<ArrowChunk>> {
+        let metadata = read_file_metadata(&mut Cursor::new(bytes)).unwrap();
+        read_chunks(bytes, &metadata).unwrap()
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let schema = ArrowSchema::from(vec![
+            Field::new("number", ArrowDataType::UInt64, true),
+            Field::new("status", ArrowDataType::UInt8, false),
+            Field::new("success", ArrowDataType::Boolean, true),
+            Field::new("hash", ArrowDataType::Binary, true),
+            Field::new("input", ArrowDataType::LargeBinary, false),
+            Field::new("name", ArrowDataType::Utf8, true),
+            Field::new("data", ArrowDataType::BinaryView, true),
+            Field::new("symbol", ArrowDataType::Utf8View, false),
+        ]);
+        let chunk = |from: u64, len: u64| {
+            let rows = from..from + len;
+            // values longer than 12 bytes are stored in the data buffers of view arrays
+            let data = rows
+                .clone()
+                .map(|i| (i % 3 != 0).then(|| vec![i as u8; i as usize % 20]));
+            ArrowChunk::try_new(vec![
+                UInt64Array::from_iter(rows.clone().map(|i| (i % 2 == 0).then_some(i))).boxed(),
+                PrimitiveArray::<u8>::from_vec(rows.clone().map(|i| i as u8).collect()).boxed(),
+                BooleanArray::from_iter(rows.clone().map(|i| (i % 5 != 0).then_some(i % 2 == 0)))
+                    .boxed(),
+                BinaryArray::<i32>::from_iter(rows.clone().map(|i| Some(i.to_be_bytes()))).boxed(),
+                BinaryArray::<i64>::from_iter(rows.clone().map(|i| Some(vec![1; i as usize % 7])))
+                    .boxed(),
+                Utf8Array::<i32>::from_iter(
+                    rows.clone().map(|i| (i % 4 != 0).then(|| i.to_string())),
+                )
+                .boxed(),
+                MutableBinaryViewArray::<[u8]>::from_iter(data)
+                    .freeze()
+                    .boxed(),
+                Utf8ViewArray::from_slice_values(
+                    rows.map(|i| format!("token number {}", i))
+                        .collect::<Vec<_>>(),
+                )
+                .boxed(),
+            ])
+            .unwrap()
+        };
+        let chunks = [chunk(0, 100), chunk(100, 0), chunk(100, 37)];
+
+        for compression in [None, Some(Compression::LZ4), Some(Compression::ZSTD)] {
+            let bytes = ipc_file(&chunks, schema.clone(), compression);
+            let unvalidated = read_unvalidated(&bytes).unwrap();
+            assert_eq!(unvalidated, read_validated(&bytes).unwrap());
+            assert_eq!(unvalidated, chunks);
+        }
+    }
+
+    #[test]
+    fn test_read_chunks_unsupported_type() {
+        let schema = ArrowSchema::from(vec![Field::new("value", ArrowDataType::Int32, false)]);
+        let chunk =
+            ArrowChunk::try_new(vec![PrimitiveArray::<i32>::from_vec(vec![1, 2]).boxed()]).unwrap();
+
+        assert!(read_unvalidated(&ipc_file(&[chunk], schema, None)).is_none());
+    }
+
+    #[test]
+    fn test_read_chunks_skips_validation() {
+        // a binary column that is declared as a string column in the schema, its values aren't
+        // valid utf8
+        let schema = ArrowSchema::from(vec![Field::new("name", ArrowDataType::Utf8, false)]);
+        let chunk =
+            ArrowChunk::try_new(vec![BinaryArray::<i32>::from_slice([[0xff, 0xfe]]).boxed()])
+                .unwrap();
+        let bytes = ipc_file(&[chunk], schema, None);
+
+        assert!(read_validated(&bytes).is_err());
+        let chunks = read_unvalidated(&bytes).unwrap();
+        assert_eq!(chunks[0].arrays()[0].len(), 1);
+    }
+}
@@ -1,45 +1,138 @@
 #![deny(missing_docs)]
 //! Hypersync client library for interacting with hypersync server.
-use std::{num::NonZeroU64, sync::Arc, time::Duration};
+use std::{
+    future::Future,
+    num::{NonZeroU64, NonZeroUsize},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::{anyhow, Context, Result};
-use hypersync_net_types::{ArchiveHeight, ChainId, Query};
-use polars_arrow::{array::Array, record_batch::RecordBatchT as Chunk};
-use reqwest::Method;
-
+use hypersync_net_types::{ArchiveHeight, ChainId, Query, RollbackGuard};
+use polars_arrow::{
+    array::Array,
+    datatypes::{ArrowDataType, ArrowSchema, Field},
+    record_batch::RecordBatchT as Chunk,
+};
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_ENCODING,
+        CONTENT_TYPE, USER_AGENT,
+    },
+    Method, StatusCode,
+};
+
+mod address_set;
+mod auth;
+pub mod blocking;
+mod bloom_prescan;
+mod checkpoint;
+mod circuit_breaker;
+mod client_pool;
 mod column_mapping;
+mod column_stats;
+mod concurrency;
 mod config;
 mod decode;
 mod decode_call;
+mod endpoints;
+mod error;
+mod export_metadata;
+#[cfg(feature = "test-util")]
+mod fault_injection;
 mod from_arrow;
+mod interceptor;
+mod ipc_unchecked;
+#[cfg(feature = "test-util")]
+mod mock_server;
+mod nested_columns;
+mod pagination;
+mod parquet_bloom;
 mod parquet_out;
 mod parse_response;
+pub mod prelude;
 pub mod preset_query;
+mod progress;
+mod query_validation;
+mod quota;
 mod rayon_async;
+mod registry;
+mod reorg;
+mod response_stream;
+mod response_transform;
+mod retention;
+mod retry_budget;
+#[cfg(feature = "root-verification")]
+mod root_verification;
 pub mod simple_types;
+mod spill;
+mod stop_condition;
 mod stream;
+mod stream_control;
+mod stream_metrics;
+mod time_range;
+#[cfg(feature = "alloy")]
+pub mod to_alloy;
 #[cfg(feature = "ethers")]
 pub mod to_ethers;
+mod trace_summary;
 mod types;
 mod util;
+mod writer_out;
 
 pub use from_arrow::FromArrow;
 pub use hypersync_format as format;
 pub use hypersync_net_types as net_types;
 pub use hypersync_schema as schema;
 
-use parse_response::parse_query_response;
+use parse_response::{parse_query_response, DecodeOptions};
 use simple_types::Event;
-use tokio::sync::mpsc;
+use tokio::{io::AsyncWrite, sync::mpsc};
 use types::{EventResponse, ResponseData};
 use url::Url;
 
+pub use address_set::AddressSet;
+pub use auth::TokenProvider;
+pub use checkpoint::{Checkpoint, CheckpointStore, MemoryCheckpoint};
+pub use client_pool::ClientPool;
 pub use column_mapping::{ColumnMapping, DataType};
+pub use column_stats::TableStats;
 pub use config::HexOutput;
-pub use config::{ClientConfig, StreamConfig};
+#[cfg(feature = "websocket")]
+pub use config::StreamTransport;
+pub use config::{
+    AddressShard, ApiKey, ChainKind, ClientConfig, ContentEncoding, DnsResolver, HeadModeConfig,
+    LoadBalancing, OutputFormat, ParquetConfig, ParquetEncoding, ProxyConfig, ResumePoint,
+    StreamConfig, TlsConfig,
+};
 pub use decode::Decoder;
 pub use decode_call::CallDecoder;
-pub use types::{ArrowBatch, ArrowResponse, ArrowResponseData, QueryResponse};
+pub use error::{Cancelled, CircuitOpen, Error, ErrorKind, RetryDeadlineExceeded};
+#[cfg(feature = "test-util")]
+pub use fault_injection::FaultInjectionConfig;
+pub use interceptor::{Interceptor, RequestParts, ResponseMeta, Throttle};
+#[cfg(feature = "test-util")]
+pub use mock_server::{MockBlock, MockChain, MockServer};
+pub use pagination::{EventCursor, EventPage};
+pub use parquet_out::ExportManifest;
+pub use progress::{Progress, ProgressListener};
+pub use query_validation::{QueryDiagnostic, QueryValidation, Severity};
+pub use quota::SharedQuota;
+pub use registry::{endpoint_for_chain, known_chain_ids};
+pub use response_stream::{IntoResponseStream, ResponseStream};
+pub use response_transform::ResponseTransform;
+pub use retention::{prune_exports, RetentionPolicy};
+#[cfg(feature = "root-verification")]
+pub use root_verification::{
+    verify_block_roots, verify_roots, RootKind, RootMismatch, RootVerification,
+};
+pub use stop_condition::StopCondition;
+pub use stream_control::StreamControl;
+pub use stream_metrics::{ExecutionReport, StreamMetrics};
+pub use types::{
+    ArrowBatch, ArrowResponse, ArrowResponseData, HealthReport, QueryResponse, Rollback,
+    ServerMetadata, TransferStats,
+};
 
 type ArrowChunk = Chunk<Box<dyn Array>>;
 
@@ -48,46 +141,140 @@ type ArrowChunk = Chunk<Box<dyn Array>>;
 pub struct Client {
     /// Initialized reqwest instance for client url.
     http_client: reqwest::Client,
-    /// HyperSync server URL.
-    url: Url,
+    /// HyperSync server URLs, the primary one followed by fallbacks.
+    endpoints: Arc<endpoints::Endpoints>,
     /// HyperSync server bearer token.
-    bearer_token: Option<String>,
+    bearer_token: Arc<auth::BearerToken>,
+    /// API key added to every request.
+    api_key: Option<ApiKey>,
+    /// Headers from `ClientConfig::default_headers` and `ClientConfig::user_agent`.
+    default_headers: HeaderMap,
     /// Number of retries to attempt before returning error.
     max_num_retries: usize,
+    /// Number of times a request is retried with a fresh token after a 401.
+    max_num_reauth_attempts: usize,
     /// Milliseconds that would be used for retry backoff increasing.
     retry_backoff_ms: u64,
     /// Initial wait time for request backoff.
     retry_base_ms: u64,
     /// Ceiling time for request backoff.
     retry_ceiling_ms: u64,
+    /// `Accept-Encoding` header sent with query requests, None to get uncompressed responses.
+    accept_encoding: Option<HeaderValue>,
+    /// Circuit breaker of each endpoint, None if disabled.
+    circuit_breakers: Option<Arc<Vec<circuit_breaker::CircuitBreaker>>>,
+    /// Hooks called around every http request.
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    /// Rate limit shared with other clients.
+    shared_quota: Option<Arc<quota::SharedQuota>>,
+    /// Slots for requests in flight, None if the number of connections isn't limited.
+    connection_limit: Option<(NonZeroUsize, Arc<tokio::sync::Semaphore>)>,
+    /// Time retries may take in total, set for the client of a stream.
+    retry_budget: Option<Arc<retry_budget::RetryBudget>>,
+    /// Set once the server turned out not to serve arrow-ipc, None if json fallback is disabled.
+    arrow_unavailable: Option<Arc<std::sync::atomic::AtomicBool>>,
+    /// Height returned by the last `get_height_cached` refresh and when it was fetched.
+    cached_height: Arc<tokio::sync::Mutex<Option<(u64, std::time::Instant)>>>,
+    /// Schema fingerprint that every query response is expected to have.
+    pin_schema_fingerprint: Option<u64>,
+    /// How the tables of responses are decoded.
+    ipc_decode: DecodeOptions,
+    /// Artificial failures and latency applied to every request.
+    #[cfg(feature = "test-util")]
+    fault_injector: Option<Arc<fault_injection::FaultInjector>>,
 }
 
 impl Client {
     /// Creates a new client with the given configuration.
-    pub fn new(cfg: ClientConfig) -> Result<Self> {
-        let timeout = cfg
-            .http_req_timeout_millis
-            .unwrap_or(NonZeroU64::new(30_000).unwrap());
-
-        let http_client = reqwest::Client::builder()
-            .no_gzip()
-            .timeout(Duration::from_millis(timeout.get()))
-            .build()
-            .unwrap();
+    pub fn new(cfg: ClientConfig) -> Result<Self, Error> {
+        let http_client = match &cfg.http_client {
+            Some(http_client) => http_client.clone(),
+            None => build_http_client(&cfg)?,
+        };
+        let default_headers = build_default_headers(&cfg)?;
+
+        let url = match cfg.url {
+            Some(url) => url,
+            None => "https://eth.hypersync.xyz".parse().context("parse url")?,
+        };
+        let urls = std::iter::once(url)
+            .chain(cfg.fallback_urls)
+            .collect::<Vec<_>>();
+
+        let circuit_breakers = cfg.circuit_breaker_threshold.map(|threshold| {
+            let cooldown = Duration::from_millis(cfg.circuit_breaker_cooldown_ms.unwrap_or(30_000));
+            Arc::new(
+                urls.iter()
+                    .map(|_| circuit_breaker::CircuitBreaker::new(threshold.get(), cooldown))
+                    .collect(),
+            )
+        });
+
+        let accept_encoding = if cfg.accept_encodings.is_empty() {
+            None
+        } else {
+            let value = cfg
+                .accept_encodings
+                .iter()
+                .map(|e| e.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            Some(HeaderValue::from_str(&value).context("build accept-encoding header")?)
+        };
 
         Ok(Self {
             http_client,
-            url: cfg
-                .url
-                .unwrap_or("https://eth.hypersync.xyz".parse().context("parse url")?),
-            bearer_token: cfg.bearer_token,
+            accept_encoding,
+            default_headers,
+            endpoints: Arc::new(endpoints::Endpoints::new(
+                urls,
+                cfg.failover_after_num_failures.unwrap_or(3),
+            )),
+            bearer_token: Arc::new(auth::BearerToken::new(cfg.bearer_token, cfg.token_provider)),
+            api_key: cfg.api_key,
             max_num_retries: cfg.max_num_retries.unwrap_or(12),
+            max_num_reauth_attempts: cfg.max_num_reauth_attempts.unwrap_or(1),
             retry_backoff_ms: cfg.retry_backoff_ms.unwrap_or(500),
             retry_base_ms: cfg.retry_base_ms.unwrap_or(200),
             retry_ceiling_ms: cfg.retry_ceiling_ms.unwrap_or(5_000),
+            circuit_breakers,
+            interceptors: cfg.interceptors,
+            shared_quota: cfg.shared_quota,
+            connection_limit: cfg
+                .max_connections
+                .map(|max| (max, Arc::new(tokio::sync::Semaphore::new(max.get())))),
+            retry_budget: None,
+            cached_height: Arc::new(tokio::sync::Mutex::new(None)),
+            arrow_unavailable: cfg
+                .json_fallback
+                .then(|| Arc::new(std::sync::atomic::AtomicBool::new(false))),
+            pin_schema_fingerprint: cfg.pin_schema_fingerprint,
+            ipc_decode: DecodeOptions {
+                parallel_tables: cfg.parallel_ipc_decode,
+                skip_validation: cfg.skip_ipc_validation,
+            },
+            #[cfg(feature = "test-util")]
+            fault_injector: cfg
+                .fault_injection
+                .map(|cfg| Arc::new(fault_injection::FaultInjector::new(cfg))),
         })
     }
 
+    /// Creates a client for the public hypersync.xyz endpoint of the given chain.
+    ///
+    /// `cfg.url` is replaced with the endpoint from the built in registry. Fails if the chain
+    /// isn't in the registry, in which case the url has to be given explicitly.
+    pub fn for_chain(chain_id: u64, mut cfg: ClientConfig) -> Result<Self, Error> {
+        let url = endpoint_for_chain(chain_id).with_context(|| {
+            format!(
+                "no known hypersync endpoint for chain id {}, set ClientConfig::url instead",
+                chain_id
+            )
+        })?;
+        cfg.url = Some(url);
+        Self::new(cfg)
+    }
+
     /// Retrieves blocks, transactions, traces, and logs through a stream using the provided
     /// query and stream configuration.
     ///
@@ -100,21 +287,34 @@ impl Client {
         self: Arc<Self>,
         query: Query,
         config: StreamConfig,
-    ) -> Result<QueryResponse> {
+    ) -> Result<QueryResponse, Error> {
         check_simple_stream_params(&config)?;
+        check_collect_params(&config)?;
+        check_in_memory_collect_params(&config)?;
 
+        let metrics = config.metrics.clone();
+        let parallel_conversion = config.parallel_conversion;
+        let event_signature = config.event_signature.clone();
+        let unordered = config.unordered;
         let mut recv = stream::stream_arrow(self, query, config)
             .await
             .context("start stream")?;
 
         let mut data = ResponseData::default();
         let mut archive_height = None;
+        let mut rollback_guard = None;
         let mut next_block = 0;
         let mut total_execution_time = 0;
+        let mut transfer = TransferStats::default();
 
         while let Some(res) = recv.recv().await {
             let res = res.context("get response")?;
-            let res: QueryResponse = QueryResponse::from(&res);
+            let start = std::time::Instant::now();
+            let res =
+                convert_response(res, parallel_conversion, event_signature.as_deref()).await?;
+            if let Some(metrics) = metrics.as_ref() {
+                metrics.record_convert(start.elapsed());
+            }
 
             for batch in res.data.blocks {
                 data.blocks.push(batch);
@@ -128,10 +328,15 @@ impl Client {
             for batch in res.data.traces {
                 data.traces.push(batch);
             }
+            for decoded in res.data.decoded_logs {
+                data.decoded_logs.push(decoded);
+            }
 
             archive_height = res.archive_height;
-            next_block = res.next_block;
-            total_execution_time += res.total_execution_time
+            keep_latest_rollback_guard(&mut rollback_guard, res.rollback_guard);
+            next_block = end_of_responses(next_block, res.next_block, unordered);
+            total_execution_time += res.total_execution_time;
+            transfer.add(&res.transfer);
         }
 
         Ok(QueryResponse {
@@ -139,39 +344,64 @@ impl Client {
             next_block,
             total_execution_time,
             data,
-            rollback_guard: None,
+            rollback_guard,
+            rollback: None,
+            transfer,
         })
     }
 
+    /// Same as [`Client::collect`] but also returns a report of the requests and time it took.
+    ///
+    /// The report is read from `config.metrics`, which is created if it isn't set.
+    pub async fn collect_with_report(
+        self: Arc<Self>,
+        query: Query,
+        mut config: StreamConfig,
+    ) -> Result<(QueryResponse, ExecutionReport), Error> {
+        let metrics = config.metrics.get_or_insert_with(Default::default).clone();
+        let res = self.collect(query, config).await?;
+        Ok((res, metrics.report()))
+    }
+
     /// Retrieves events through a stream using the provided query and stream configuration.
     pub async fn collect_events(
         self: Arc<Self>,
         mut query: Query,
         config: StreamConfig,
-    ) -> Result<EventResponse> {
+    ) -> Result<EventResponse, Error> {
         check_simple_stream_params(&config)?;
+        check_collect_params(&config)?;
+        check_in_memory_collect_params(&config)?;
 
         add_event_join_fields_to_selection(&mut query);
 
+        let parallel_conversion = config.parallel_conversion;
+        let event_signature = config.event_signature.clone();
+        let unordered = config.unordered;
         let mut recv = stream::stream_arrow(self, query, config)
             .await
             .context("start stream")?;
 
         let mut data = Vec::new();
         let mut archive_height = None;
+        let mut rollback_guard = None;
         let mut next_block = 0;
         let mut total_execution_time = 0;
+        let mut transfer = TransferStats::default();
 
         while let Some(res) = recv.recv().await {
             let res = res.context("get response")?;
-            let res: QueryResponse = QueryResponse::from(&res);
+            let res =
+                convert_response(res, parallel_conversion, event_signature.as_deref()).await?;
             let events: Vec<Event> = res.data.into();
 
             data.push(events);
 
             archive_height = res.archive_height;
-            next_block = res.next_block;
-            total_execution_time += res.total_execution_time
+            keep_latest_rollback_guard(&mut rollback_guard, res.rollback_guard);
+            next_block = end_of_responses(next_block, res.next_block, unordered);
+            total_execution_time += res.total_execution_time;
+            transfer.add(&res.transfer);
         }
 
         Ok(EventResponse {
@@ -179,7 +409,9 @@ impl Client {
             next_block,
             total_execution_time,
             data,
-            rollback_guard: None,
+            rollback_guard,
+            rollback: None,
+            transfer,
         })
     }
 
@@ -189,15 +421,21 @@ impl Client {
         self: Arc<Self>,
         query: Query,
         config: StreamConfig,
-    ) -> Result<ArrowResponse> {
+    ) -> Result<ArrowResponse, Error> {
+        check_collect_params(&config)?;
+        check_in_memory_collect_params(&config)?;
+
+        let unordered = config.unordered;
         let mut recv = stream::stream_arrow(self, query, config)
             .await
             .context("start stream")?;
 
         let mut data = ArrowResponseData::default();
         let mut archive_height = None;
+        let mut rollback_guard = None;
         let mut next_block = 0;
         let mut total_execution_time = 0;
+        let mut transfer = TransferStats::default();
 
         while let Some(res) = recv.recv().await {
             let res = res.context("get response")?;
@@ -217,10 +455,18 @@ impl Client {
             for batch in res.data.decoded_logs {
                 data.decoded_logs.push(batch);
             }
+            for batch in res.data.trace_summary {
+                data.trace_summary.push(batch);
+            }
+            for (name, batches) in res.data.decoded_events {
+                data.decoded_events.entry(name).or_default().extend(batches);
+            }
 
             archive_height = res.archive_height;
-            next_block = res.next_block;
-            total_execution_time += res.total_execution_time
+            keep_latest_rollback_guard(&mut rollback_guard, res.rollback_guard);
+            next_block = end_of_responses(next_block, res.next_block, unordered);
+            total_execution_time += res.total_execution_time;
+            transfer.add(&res.transfer);
         }
 
         Ok(ArrowResponse {
@@ -228,120 +474,491 @@ impl Client {
             next_block,
             total_execution_time,
             data,
-            rollback_guard: None,
+            rollback_guard,
+            rollback: None,
+            transfer,
         })
     }
 
     /// Writes parquet file getting data through a stream using the provided path, query,
     /// and stream configuration.
+    ///
+    /// Each file carries the json encoded query under the `hypersync.query` key value metadata
+    /// key, along with `hypersync.client_version`, `hypersync.chain_id` and
+    /// `hypersync.export_timestamp` (unix seconds), so it can be traced back to the export that
+    /// produced it.
+    ///
+    /// Row groups are cut at fixed row counts, so exporting the same blocks again writes byte
+    /// identical files whatever the `concurrency` and batch size of the stream, as long as
+    /// `config.export_timestamp` is set. This doesn't hold with `config.unordered`, which writes
+    /// the rows in the order the responses arrive.
     pub async fn collect_parquet(
         self: Arc<Self>,
         path: &str,
         query: Query,
         config: StreamConfig,
-    ) -> Result<()> {
-        parquet_out::collect_parquet(self, path, query, config).await
+    ) -> Result<(), Error> {
+        check_collect_params(&config)?;
+
+        parquet_out::collect_parquet(self, path, query, config)
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Streams the query response into the given writer, e.g. `tokio::io::stdout()`, in the
+    /// given format.
+    ///
+    /// Rows of every table in the response are written to the same writer, tagged with the name
+    /// of their table. The writer is flushed but not shut down when the stream ends.
+    pub async fn collect_to_writer<W: AsyncWrite + Unpin>(
+        self: Arc<Self>,
+        query: Query,
+        config: StreamConfig,
+        writer: W,
+        format: OutputFormat,
+    ) -> Result<(), Error> {
+        check_collect_params(&config)?;
+
+        writer_out::collect_to_writer(self, query, config, writer, format)
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Collects a compact (number, timestamp, hash) table of every block in the range
+    /// [from_block, to_block) and returns it as a single Arrow batch.
+    ///
+    /// The timestamp column is mapped to UInt64. Batch sizing defaults are tuned for this
+    /// narrow, uniform shape and only apply to values that aren't set in config.
+    pub async fn collect_block_index(
+        self: Arc<Self>,
+        from_block: u64,
+        to_block: Option<u64>,
+        config: StreamConfig,
+    ) -> Result<ArrowBatch, Error> {
+        let config = block_index_stream_config(config);
+        let hex_output = config.hex_output;
+
+        let res = self
+            .collect_arrow(preset_query::block_index(from_block, to_block), config)
+            .await
+            .context("collect blocks")?;
+
+        let schema = match res.data.blocks.first() {
+            Some(batch) => batch.schema.clone(),
+            None => {
+                let hash_dt = match hex_output {
+                    HexOutput::NoEncode => ArrowDataType::Binary,
+                    HexOutput::Prefixed | HexOutput::NonPrefixed => ArrowDataType::Utf8,
+                };
+                let schema = ArrowSchema::from(vec![
+                    Field::new("number", ArrowDataType::UInt64, false),
+                    Field::new("timestamp", ArrowDataType::UInt64, false),
+                    Field::new("hash", hash_dt, false),
+                ]);
+
+                return Ok(ArrowBatch {
+                    chunk: Arc::new(schema::empty_chunk(&schema)),
+                    schema: Arc::new(schema),
+                });
+            }
+        };
+
+        let chunks = res
+            .data
+            .blocks
+            .into_iter()
+            .map(|b| b.chunk)
+            .collect::<Vec<_>>();
+        let chunk = schema::concat_chunks(&chunks).context("concat block chunks")?;
+
+        Ok(ArrowBatch {
+            chunk: Arc::new(chunk),
+            schema,
+        })
+    }
+
+    /// Writes a compact (number, timestamp, hash) table of every block in the range
+    /// [from_block, to_block) to `blocks.parquet` under the given path.
+    ///
+    /// Uses the same field selection and pacing as [`Client::collect_block_index`].
+    pub async fn collect_block_index_parquet(
+        self: Arc<Self>,
+        path: &str,
+        from_block: u64,
+        to_block: Option<u64>,
+        config: StreamConfig,
+    ) -> Result<(), Error> {
+        self.collect_parquet(
+            path,
+            preset_query::block_index(from_block, to_block),
+            block_index_stream_config(config),
+        )
+        .await
     }
 
     /// Internal implementation of getting chain_id from server
-    async fn get_chain_id_impl(&self) -> Result<u64> {
-        let mut url = self.url.clone();
+    async fn get_chain_id_impl(&self, mut url: Url) -> Result<u64> {
         let mut segments = url.path_segments_mut().ok().context("get path segments")?;
         segments.push("chain_id");
         std::mem::drop(segments);
-        let mut req = self.http_client.request(Method::GET, url);
 
-        if let Some(bearer_token) = &self.bearer_token {
-            req = req.bearer_auth(bearer_token);
-        }
+        #[cfg(feature = "test-util")]
+        let fault_idx = self.inject_request_faults().await?;
 
-        let res = req.send().await.context("execute http req")?;
+        let res = self
+            .send_request(Method::GET, url, None, HeaderMap::new(), None)
+            .await?;
 
-        let status = res.status();
-        if !status.is_success() {
-            return Err(anyhow!("http response status code {}", status));
+        if !res.status().is_success() {
+            return Err(error::HttpStatusError::from_response(res).await);
         }
 
-        let chain_id: ChainId = res.json().await.context("read response body json")?;
+        let bytes = res.bytes().await.context("read response body bytes")?;
+        #[cfg(feature = "test-util")]
+        let bytes = self.inject_response_faults(fault_idx, bytes);
+
+        let chain_id: ChainId =
+            serde_json::from_slice(&bytes).context("read response body json")?;
 
         Ok(chain_id.chain_id)
     }
 
+    /// Internal implementation of sending a raw request to the server
+    async fn request_raw_impl(
+        &self,
+        method: Method,
+        mut url: Url,
+        path_segments: &[&str],
+        body: Option<bytes::Bytes>,
+    ) -> Result<bytes::Bytes> {
+        let mut segments = url.path_segments_mut().ok().context("get path segments")?;
+        segments.extend(path_segments);
+        std::mem::drop(segments);
+
+        #[cfg(feature = "test-util")]
+        let fault_idx = self.inject_request_faults().await?;
+
+        let res = self
+            .send_request(method, url, body, HeaderMap::new(), None)
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(error::HttpStatusError::from_response(res).await);
+        }
+
+        let bytes = res.bytes().await.context("read response body bytes")?;
+        #[cfg(feature = "test-util")]
+        let bytes = self.inject_response_faults(fault_idx, bytes);
+
+        Ok(bytes)
+    }
+
     /// Internal implementation of getting height from server
-    async fn get_height_impl(&self, http_timeout_override: Option<Duration>) -> Result<u64> {
-        let mut url = self.url.clone();
+    async fn get_height_impl(
+        &self,
+        mut url: Url,
+        http_timeout_override: Option<Duration>,
+    ) -> Result<u64> {
         let mut segments = url.path_segments_mut().ok().context("get path segments")?;
         segments.push("height");
         std::mem::drop(segments);
-        let mut req = self.http_client.request(Method::GET, url);
 
-        if let Some(bearer_token) = &self.bearer_token {
-            req = req.bearer_auth(bearer_token);
+        #[cfg(feature = "test-util")]
+        let fault_idx = self.inject_request_faults().await?;
+
+        let res = self
+            .send_request(
+                Method::GET,
+                url,
+                None,
+                HeaderMap::new(),
+                http_timeout_override,
+            )
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(error::HttpStatusError::from_response(res).await);
         }
 
+        let bytes = res.bytes().await.context("read response body bytes")?;
+        #[cfg(feature = "test-util")]
+        let bytes = self.inject_response_faults(fault_idx, bytes);
+
+        let height: ArchiveHeight =
+            serde_json::from_slice(&bytes).context("read response body json")?;
+
+        Ok(height.height.unwrap_or(0))
+    }
+
+    /// Sends a single request with the given headers, running the configured interceptors around
+    /// it.
+    ///
+    /// The body is sent as json if given.
+    async fn send_request(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<bytes::Bytes>,
+        headers: HeaderMap,
+        http_timeout_override: Option<Duration>,
+    ) -> Result<reqwest::Response> {
+        let RequestParts {
+            method,
+            url,
+            headers,
+            body,
+        } = self.request_parts(method, url, body, headers).await?;
+
+        let mut req = self
+            .http_client
+            .request(method.clone(), url.clone())
+            .headers(headers);
+        if let Some(body) = body {
+            req = req.body(body);
+        }
         if let Some(http_timeout_override) = http_timeout_override {
             req = req.timeout(http_timeout_override);
         }
 
-        let res = req.send().await.context("execute http req")?;
+        if let Some(quota) = self.shared_quota.as_ref() {
+            let wait = quota.reserve(std::time::Instant::now());
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        metrics::counter!("hypersync_requests_total").increment(1);
+        #[cfg(feature = "metrics")]
+        let inflight = InflightGuard::new();
+        let start = std::time::Instant::now();
+        let res = req.send().await;
+        #[cfg(feature = "metrics")]
+        drop(inflight);
+        let res = res.context("execute http req")?;
+
+        self.intercept_response(method, url, res.status(), res.headers(), start.elapsed());
+
+        Ok(res)
+    }
 
-        let status = res.status();
-        if !status.is_success() {
-            return Err(anyhow!("http response status code {}", status));
+    /// Builds a request with the default headers, the bearer token and the api key, then lets
+    /// the configured interceptors modify it.
+    async fn request_parts(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<bytes::Bytes>,
+        headers: HeaderMap,
+    ) -> Result<RequestParts> {
+        let mut parts = RequestParts {
+            method,
+            url,
+            headers: self.default_headers.clone(),
+            body,
+        };
+        parts.headers.extend(headers);
+        if let Some(bearer_token) = self.bearer_token.get().await? {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", bearer_token))
+                .context("build authorization header")?;
+            value.set_sensitive(true);
+            parts.headers.insert(AUTHORIZATION, value);
+        }
+        if let Some(api_key) = self.api_key.as_ref() {
+            api_key.apply(&mut parts.url, &mut parts.headers)?;
+        }
+        if parts.body.is_some() {
+            parts
+                .headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
         }
 
-        let height: ArchiveHeight = res.json().await.context("read response body json")?;
+        for interceptor in self.interceptors.iter() {
+            interceptor.on_request(&mut parts);
+        }
 
-        Ok(height.height.unwrap_or(0))
+        Ok(parts)
+    }
+
+    /// Passes the metadata of a received response to the configured interceptors.
+    fn intercept_response(
+        &self,
+        method: Method,
+        url: Url,
+        status: StatusCode,
+        headers: &HeaderMap,
+        latency: Duration,
+    ) {
+        if self.interceptors.is_empty() {
+            return;
+        }
+        let meta = ResponseMeta {
+            method,
+            url,
+            status,
+            headers: headers.clone(),
+            latency,
+        };
+        for interceptor in self.interceptors.iter() {
+            interceptor.on_response(&meta);
+        }
     }
 
     /// Get the chain_id from the server with retries.
-    pub async fn get_chain_id(&self) -> Result<u64> {
-        let mut base = self.retry_base_ms;
+    pub async fn get_chain_id(&self) -> Result<u64, Error> {
+        self.with_retries("get chain_id", None, |url| self.get_chain_id_impl(url))
+            .await
+            .map_err(Error::from)
+    }
 
-        let mut err = anyhow!("");
+    /// Get the height of from server with retries.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn get_height(&self) -> Result<u64, Error> {
+        self.with_retries("get height", None, |url| self.get_height_impl(url, None))
+            .await
+            .map_err(Error::from)
+    }
 
-        for _ in 0..self.max_num_retries + 1 {
-            match self.get_chain_id_impl().await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    log::error!(
-                        "failed to get chain_id from server, retrying... The error was: {:?}",
-                        e
-                    );
-                    err = err.context(format!("{:?}", e));
-                }
+    /// Returns the first block with a timestamp at or after the given unix timestamp in seconds,
+    /// or the block after the height of the server if no block is that recent yet.
+    ///
+    /// Binary searches over the headers of single blocks, so it takes a few dozen requests.
+    /// `StreamConfig::from_timestamp` and `to_timestamp` use this to bound streams by time.
+    pub async fn get_block_at_timestamp(&self, timestamp: u64) -> Result<u64, Error> {
+        time_range::block_at_timestamp(self, timestamp)
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Get the height of the server, reusing the last fetched height if it is younger than `ttl`.
+    ///
+    /// Meant for clients shared by many concurrent tasks. Only one of them fetches the height
+    /// when it expires, the others wait for it and get the same value.
+    pub async fn get_height_cached(&self, ttl: Duration) -> Result<u64, Error> {
+        let mut cached = self.cached_height.lock().await;
+        if let Some((height, fetched_at)) = *cached {
+            if fetched_at.elapsed() < ttl {
+                return Ok(height);
             }
+        }
+        let height = self.get_height().await?;
+        *cached = Some((height, std::time::Instant::now()));
+        Ok(height)
+    }
 
-            let base_ms = Duration::from_millis(base);
-            let jitter = Duration::from_millis(fastrange_rs::fastrange_64(
-                rand::random(),
-                self.retry_backoff_ms,
-            ));
+    /// Fetches the height of the server with retries and replaces the height cached by
+    /// `get_height_cached` with it.
+    pub async fn refresh_height(&self) -> Result<u64, Error> {
+        let mut cached = self.cached_height.lock().await;
+        let height = self.get_height().await?;
+        *cached = Some((height, std::time::Instant::now()));
+        Ok(height)
+    }
 
-            tokio::time::sleep(base_ms + jitter).await;
+    /// Get the chain id and height of the server with retries.
+    ///
+    /// Useful for checking that the client is pointed at the expected network before starting
+    /// a stream.
+    pub async fn get_server_metadata(&self) -> Result<ServerMetadata, Error> {
+        let (chain_id, height) = futures::future::try_join(self.get_chain_id(), self.get_height())
+            .await
+            .context("get server metadata")?;
 
-            base = std::cmp::min(base + self.retry_backoff_ms, self.retry_ceiling_ms);
-        }
+        Ok(ServerMetadata {
+            chain_id,
+            height,
+            chain: ChainKind::from_chain_id(chain_id),
+        })
+    }
 
-        Err(err)
+    /// Sends a request to the given path under the endpoint url with retries and returns the
+    /// raw response body.
+    ///
+    /// Uses the same authentication, retry, failover and error handling as the typed methods, so
+    /// endpoints the client doesn't know about yet can be called. The body is sent as json if
+    /// given, the response can be parsed with `serde_json::from_slice` if it is json.
+    pub async fn request_raw(
+        &self,
+        method: Method,
+        path_segments: &[&str],
+        body: Option<bytes::Bytes>,
+    ) -> Result<bytes::Bytes, Error> {
+        let what = format!("{} /{}", method, path_segments.join("/"));
+        self.with_retries(&what, None, |url| {
+            self.request_raw_impl(method.clone(), url, path_segments, body.clone())
+        })
+        .await
+        .map_err(Error::from)
     }
 
-    /// Get the height of from server with retries.
-    pub async fn get_height(&self) -> Result<u64> {
+    /// Runs the given request with retries, moving on to the next endpoint when the current one
+    /// keeps failing.
+    ///
+    /// If `preferred_endpoint` is given, requests are sent to it until it fails over.
+    async fn with_retries<T, F, Fut>(
+        &self,
+        what: &str,
+        mut preferred_endpoint: Option<usize>,
+        f: F,
+    ) -> Result<T>
+    where
+        F: Fn(Url) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
         let mut base = self.retry_base_ms;
 
-        let mut err = anyhow!("");
-
-        for _ in 0..self.max_num_retries + 1 {
-            match self.get_height_impl(None).await {
-                Ok(res) => return Ok(res),
+        let mut last_failure = None;
+        let mut num_failures = 0;
+
+        for attempt in 0..self.max_num_retries + 1 {
+            let (endpoint_idx, url) = self.pick_endpoint(preferred_endpoint)?;
+
+            let permit = match self.connection_limit.as_ref() {
+                Some((_, semaphore)) => Some(
+                    semaphore
+                        .acquire()
+                        .await
+                        .context("acquire connection slot")?,
+                ),
+                None => None,
+            };
+            let start = std::time::Instant::now();
+            let mut throttle = None;
+            let last_err;
+            let res = self.call_with_reauth(&f, &url).await;
+            drop(permit);
+            match res {
+                Ok(res) => {
+                    self.endpoints.record_latency(endpoint_idx, start.elapsed());
+                    if let Some(breakers) = self.circuit_breakers.as_ref() {
+                        breakers[endpoint_idx].record_success();
+                    }
+                    return Ok(res);
+                }
+                // Retrying won't help if the response is too large, the caller should shrink the
+                // query, or if the server rejected the request itself, e.g. an invalid query.
+                Err(e) if error::is_fatal_status(&e) => return Err(e),
+                // The server is up but asks us to slow down, this doesn't count as an endpoint failure.
+                Err(e) if error::throttle(&e).is_some() => {
+                    log::warn!("server throttled request to {}: {:?}", what, e);
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(what, attempt, url = %url, error = ?e, "request throttled");
+                    throttle = error::throttle(&e);
+                    last_err = e;
+                }
                 Err(e) => {
+                    if let Some(breakers) = self.circuit_breakers.as_ref() {
+                        breakers[endpoint_idx].record_failure(std::time::Instant::now());
+                    }
                     log::error!(
-                        "failed to get height from server, retrying... The error was: {:?}",
+                        "failed to {} from server on attempt {}, retrying... The error was: {:?}",
+                        what,
+                        attempt + 1,
                         e
                     );
-                    err = err.context(format!("{:?}", e));
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(what, attempt, url = %url, error = ?e, "request failed");
+                    last_err = e;
                 }
             }
 
@@ -350,105 +967,548 @@ impl Client {
                 rand::random(),
                 self.retry_backoff_ms,
             ));
+            let mut wait = base_ms + jitter;
+
+            // only attempts after the first count as retries, the last failure isn't retried
+            #[cfg(feature = "metrics")]
+            if attempt < self.max_num_retries {
+                metrics::counter!(
+                    "hypersync_retries_total",
+                    "reason" => if throttle.is_some() { "throttled" } else { "failed" }
+                )
+                .increment(1);
+            }
 
-            tokio::time::sleep(base_ms + jitter).await;
+            match throttle {
+                Some((status, retry_after)) => {
+                    if let Some(retry_after) = retry_after {
+                        wait = retry_after;
+                    }
+                    let throttle = Throttle {
+                        url,
+                        status,
+                        retry_after,
+                        wait,
+                    };
+                    for interceptor in self.interceptors.iter() {
+                        interceptor.on_throttle(&throttle);
+                    }
+                    if let Some(quota) = self.shared_quota.as_ref() {
+                        quota.pause_until(std::time::Instant::now() + throttle.wait);
+                    }
+                }
+                None => {
+                    num_failures += 1;
+                    if self.endpoints.record_failures(endpoint_idx, num_failures) {
+                        num_failures = 0;
+                        preferred_endpoint = None;
+                    }
+                }
+            }
+
+            if let Some(budget) = self.retry_budget.as_ref() {
+                if let Err(deadline) = budget.spend(start.elapsed() + wait) {
+                    return Err(last_err.context(deadline));
+                }
+            }
+            last_failure = Some(last_err);
+
+            tokio::time::sleep(wait).await;
 
             base = std::cmp::min(base + self.retry_backoff_ms, self.retry_ceiling_ms);
         }
 
-        Err(err)
+        // the error of the last attempt is kept as is so `ErrorKind::of` can classify it, the
+        // earlier ones were logged
+        let num_attempts = self.max_num_retries + 1;
+        Err(match last_failure {
+            Some(e) => e.context(format!(
+                "failed to {} after {} attempts",
+                what, num_attempts
+            )),
+            None => anyhow!("failed to {}", what),
+        })
+    }
+
+    /// Runs a single attempt, retrying it with a fresh token from the token provider if the
+    /// server rejects the current one.
+    async fn call_with_reauth<T, F, Fut>(&self, f: &F, url: &Url) -> Result<T>
+    where
+        F: Fn(Url) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut num_reauths = 0;
+        loop {
+            // the first token is fetched here so the attempt is rejected with its generation
+            self.bearer_token.get().await?;
+            let generation = self.bearer_token.generation();
+            match f(url.clone()).await {
+                Err(e)
+                    if error::http_status(&e) == Some(StatusCode::UNAUTHORIZED)
+                        && self.bearer_token.has_provider()
+                        && num_reauths < self.max_num_reauth_attempts =>
+                {
+                    log::warn!("server rejected bearer token, fetching a new one");
+                    num_reauths += 1;
+                    self.bearer_token.refresh(generation).await?;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Returns the endpoint the next attempt should be sent to.
+    ///
+    /// Skips over endpoints with an open circuit breaker, failing with `CircuitOpen` if the
+    /// circuit of every endpoint is open.
+    fn pick_endpoint(&self, preferred_endpoint: Option<usize>) -> Result<(usize, Url)> {
+        let (idx, url) = match preferred_endpoint {
+            Some(idx) => (idx, self.endpoints.get(idx)),
+            None => self.endpoints.current(),
+        };
+
+        let breakers = match self.circuit_breakers.as_ref() {
+            Some(breakers) => breakers,
+            None => return Ok((idx, url.clone())),
+        };
+
+        let now = std::time::Instant::now();
+        let mut retry_after = match breakers[idx].check(now) {
+            Ok(()) => return Ok((idx, url.clone())),
+            Err(open) => open.retry_after,
+        };
+
+        for offset in 1..breakers.len() {
+            let other = (idx + offset) % breakers.len();
+            match breakers[other].check(now) {
+                Ok(()) => {
+                    self.endpoints.record_failures(idx, usize::MAX);
+                    return Ok((other, self.endpoints.get(other).clone()));
+                }
+                Err(open) => retry_after = retry_after.min(open.retry_after),
+            }
+        }
+
+        Err(CircuitOpen { retry_after }.into())
+    }
+
+    /// Establishes a connection to the server by making a height request without retries,
+    /// so the TCP connection and TLS session are ready before latency sensitive requests are made.
+    ///
+    /// Returns the latency of the request.
+    pub async fn warm_up(&self) -> Result<Duration, Error> {
+        let start = std::time::Instant::now();
+        let url = self.endpoints.current().1.clone();
+        self.get_height_impl(url, None)
+            .await
+            .context("get height")?;
+        Ok(start.elapsed())
     }
 
     /// Get the height of the Client instance for health checks.
     /// Doesn't do any retries and the `http_req_timeout` parameter will override the http timeout config set when creating the client.
-    pub async fn health_check(&self, http_req_timeout: Option<Duration>) -> Result<u64> {
-        self.get_height_impl(http_req_timeout).await
+    pub async fn health_check(&self, http_req_timeout: Option<Duration>) -> Result<u64, Error> {
+        let url = self.endpoints.current().1.clone();
+        self.get_height_impl(url, http_req_timeout)
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Checks that the server is reachable by requesting its height once, without retries.
+    ///
+    /// Never fails, errors are reported in the returned `HealthReport` so it can be served
+    /// directly from a readiness probe.
+    pub async fn health(&self) -> HealthReport {
+        let url = self.endpoints.current().1.clone();
+        let start = std::time::Instant::now();
+        let res = self.get_height_impl(url.clone(), None).await;
+        let latency = start.elapsed();
+
+        match res {
+            Ok(height) => HealthReport {
+                url,
+                height: Some(height),
+                latency,
+                error: None,
+                error_kind: None,
+            },
+            Err(e) => HealthReport {
+                url,
+                height: None,
+                latency,
+                error_kind: Some(ErrorKind::of(&e)),
+                error: Some(format!("{:?}", e)),
+            },
+        }
     }
 
     /// Executes query with retries and returns the response.
-    pub async fn get(&self, query: &Query) -> Result<QueryResponse> {
-        let arrow_response = self.get_arrow(query).await.context("get data")?;
-        Ok(QueryResponse::from(&arrow_response))
+    ///
+    /// Falls back to the json query endpoint if `ClientConfig::json_fallback` is set.
+    pub async fn get(&self, query: &Query) -> Result<QueryResponse, Error> {
+        let arrow_unavailable = match self.arrow_unavailable.as_ref() {
+            Some(arrow_unavailable) => arrow_unavailable,
+            None => {
+                let arrow_response = self.get_arrow(query).await.context("get data")?;
+                return Ok(QueryResponse::from(&arrow_response));
+            }
+        };
+
+        let (res, fingerprint) = self
+            .with_retries("get data", None, |url| async move {
+                if !arrow_unavailable.load(std::sync::atomic::Ordering::Relaxed) {
+                    match self.get_arrow_impl(url.clone(), query, None).await {
+                        Ok((res, _, fingerprint)) => {
+                            return Ok((QueryResponse::from(&res), Some(fingerprint)))
+                        }
+                        Err(e) if !error::arrow_unavailable(&e) => return Err(e),
+                        Err(e) => {
+                            log::warn!(
+                                "server doesn't serve arrow-ipc, falling back to json: {:?}",
+                                e
+                            );
+                            arrow_unavailable.store(true, std::sync::atomic::Ordering::Relaxed);
+                        }
+                    }
+                }
+                self.get_json_impl(url, query).await.map(|res| (res, None))
+            })
+            .await
+            .context("get data")?;
+
+        if let Some(fingerprint) = fingerprint {
+            self.check_schema_fingerprint(fingerprint)?;
+        }
+
+        Ok(res)
     }
 
     /// Add block, transaction and log fields selection to the query, executes it with retries
     /// and returns the response.
-    pub async fn get_events(&self, mut query: Query) -> Result<EventResponse> {
+    pub async fn get_events(&self, mut query: Query) -> Result<EventResponse, Error> {
         add_event_join_fields_to_selection(&mut query);
-        let arrow_response = self.get_arrow(&query).await.context("get data")?;
-        Ok(EventResponse::from(&arrow_response))
+        let res = self.get(&query).await?;
+        Ok(EventResponse {
+            archive_height: res.archive_height,
+            next_block: res.next_block,
+            total_execution_time: res.total_execution_time,
+            data: vec![res.data.into()],
+            rollback_guard: res.rollback_guard,
+            rollback: res.rollback,
+            transfer: res.transfer,
+        })
     }
 
-    /// Executes query once and returns the result in (Arrow, size) format.
-    async fn get_arrow_impl(&self, query: &Query) -> Result<(ArrowResponse, u64)> {
-        let mut url = self.url.clone();
+    /// Executes query once against the json query endpoint.
+    async fn get_json_impl(&self, mut url: Url, query: &Query) -> Result<QueryResponse> {
         let mut segments = url.path_segments_mut().ok().context("get path segments")?;
         segments.push("query");
-        segments.push("arrow-ipc");
         std::mem::drop(segments);
-        let mut req = self.http_client.request(Method::POST, url);
 
-        if let Some(bearer_token) = &self.bearer_token {
-            req = req.bearer_auth(bearer_token);
-        }
+        let body = serde_json::to_vec(query).context("serialize query")?;
 
-        let res = req.json(&query).send().await.context("execute http req")?;
+        #[cfg(feature = "test-util")]
+        let fault_idx = self.inject_request_faults().await?;
 
-        let status = res.status();
-        if !status.is_success() {
-            let text = res.text().await.context("read text to see error")?;
+        let mut headers = HeaderMap::new();
+        if let Some(accept_encoding) = self.accept_encoding.clone() {
+            headers.insert(ACCEPT_ENCODING, accept_encoding);
+        }
 
-            return Err(anyhow!(
-                "http response status code {}, err body: {}",
-                status,
-                text
-            ));
+        let start = std::time::Instant::now();
+        let res = self
+            .send_request(Method::POST, url, Some(body.into()), headers, None)
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(error::HttpStatusError::from_response(res).await);
         }
 
+        let content_encoding = res.headers().get(CONTENT_ENCODING).cloned();
         let bytes = res.bytes().await.context("read response body bytes")?;
-
-        let res = tokio::task::block_in_place(|| {
-            parse_query_response(&bytes).context("parse query response")
+        #[cfg(feature = "test-util")]
+        let bytes = self.inject_response_faults(fault_idx, bytes);
+        let latency = start.elapsed();
+        let wire_bytes = u64::try_from(bytes.len()).unwrap();
+
+        let (bytes, mut res) = tokio::task::block_in_place(|| {
+            let bytes = decompress(content_encoding.as_ref(), bytes)?;
+            let res = parse_response::parse_json_query_response(&bytes)
+                .context(error::ParseError("parse json query response"))?;
+            Ok::<_, anyhow::Error>((bytes, res))
         })?;
-
-        Ok((res, bytes.len().try_into().unwrap()))
+        res.transfer = TransferStats {
+            wire_bytes,
+            decompressed_bytes: bytes.len().try_into().unwrap(),
+            server_execution_time: Duration::from_millis(res.total_execution_time),
+            latency,
+        };
+
+        Ok(res)
     }
 
-    /// Executes query with retries and returns the response in Arrow format.
-    pub async fn get_arrow(&self, query: &Query) -> Result<ArrowResponse> {
-        self.get_arrow_with_size(query).await.map(|res| res.0)
+    /// Returns at most `limit` events of the query ordered by block number and log index,
+    /// starting at `cursor` or at `query.from_block` if no cursor is given.
+    ///
+    /// Makes as many requests as needed to fill the page. `EventPage::next_cursor` is passed
+    /// back with the same query to get the next page, so an http api can hand out the cursor
+    /// string instead of keeping track of block numbers and log indices itself.
+    pub async fn get_events_page(
+        &self,
+        mut query: Query,
+        cursor: Option<&EventCursor>,
+        limit: usize,
+    ) -> Result<EventPage, Error> {
+        if limit == 0 {
+            return Err(anyhow!("limit must be greater than zero").into());
+        }
+
+        // fields used for ordering the events
+        query.field_selection.log.insert("block_number".to_owned());
+        query.field_selection.log.insert("log_index".to_owned());
+
+        let start = match cursor {
+            Some(cursor) => {
+                query.from_block = query.from_block.max(cursor.block_number());
+                *cursor
+            }
+            None => EventCursor::new(query.from_block, 0),
+        };
+
+        let mut events = Vec::new();
+        let mut archive_height = None;
+
+        loop {
+            if query
+                .to_block
+                .is_some_and(|to_block| query.from_block >= to_block)
+            {
+                return Ok(EventPage {
+                    events,
+                    next_cursor: None,
+                    archive_height,
+                });
+            }
+
+            let res = self.get_events(query.clone()).await.context("get events")?;
+            archive_height = res.archive_height;
+
+            let next =
+                pagination::fill_page(&mut events, res.data.into_iter().flatten(), start, limit)?;
+            if next.is_some() {
+                return Ok(EventPage {
+                    events,
+                    next_cursor: next,
+                    archive_height,
+                });
+            }
+
+            query.from_block = res.next_block;
+
+            // reached the height of the server, continue from here on the next page
+            if archive_height.is_none_or(|height| res.next_block > height) {
+                return Ok(EventPage {
+                    events,
+                    next_cursor: Some(EventCursor::new(res.next_block, 0)),
+                    archive_height,
+                });
+            }
+        }
     }
 
-    /// Internal implementation for get_arrow.
-    async fn get_arrow_with_size(&self, query: &Query) -> Result<(ArrowResponse, u64)> {
-        let mut base = self.retry_base_ms;
+    /// Executes query with retries and returns the fingerprint of the response schema.
+    ///
+    /// The fingerprint can be pinned using `ClientConfig::pin_schema_fingerprint` so the client
+    /// fails if the server starts returning a different schema for the same query.
+    pub async fn get_schema_fingerprint(&self, query: &Query) -> Result<u64, Error> {
+        self.get_arrow_with_retries(query, None, None)
+            .await
+            .map(|res| res.2)
+            .map_err(Error::from)
+    }
 
-        let mut err = anyhow!("");
+    /// Checks the query for mistakes without running it over its whole range.
+    ///
+    /// Field names are checked against the schema, with suggestions for typos, and selections,
+    /// block range and limits are checked for values that would make the query fail or return
+    /// nothing. If `on_server` is set and no errors were found locally, the query is also sent
+    /// to the server once, limited to its first block since the server has no dry-run endpoint,
+    /// and a rejection is returned as a diagnostic at `server`. Other request errors are
+    /// returned as errors.
+    pub async fn validate_query(
+        &self,
+        query: &Query,
+        on_server: bool,
+    ) -> Result<QueryValidation, Error> {
+        let mut res = query_validation::validate(query);
+        if !on_server || !res.is_valid() {
+            return Ok(res);
+        }
 
-        for _ in 0..self.max_num_retries + 1 {
-            match self.get_arrow_impl(query).await {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    log::error!(
-                        "failed to get arrow data from server, retrying... The error was: {:?}",
-                        e
-                    );
-                    err = err.context(format!("{:?}", e));
+        let mut probe = query.clone();
+        probe.to_block = Some(query.from_block + 1);
+        probe.max_num_blocks = Some(1);
+        let url = self.endpoints.current().1.clone();
+        match self
+            .call_with_reauth(&|url| self.get_arrow_impl(url, &probe, None), &url)
+            .await
+        {
+            Ok(_) => Ok(res),
+            Err(e) => match ErrorKind::of(&e) {
+                ErrorKind::InvalidQuery { body } => {
+                    res.diagnostics.push(QueryDiagnostic {
+                        severity: Severity::Error,
+                        path: "server".into(),
+                        message: body,
+                    });
+                    Ok(res)
                 }
-            }
+                _ => Err(e.context("send query to server for validation").into()),
+            },
+        }
+    }
 
-            let base_ms = Duration::from_millis(base);
-            let jitter = Duration::from_millis(fastrange_rs::fastrange_64(
-                rand::random(),
-                self.retry_backoff_ms,
-            ));
+    /// Executes query once and returns the result in (Arrow, size, schema fingerprint) format.
+    ///
+    /// Records the request into `metrics` if given.
+    async fn get_arrow_impl(
+        &self,
+        mut url: Url,
+        query: &Query,
+        metrics: Option<&StreamMetrics>,
+    ) -> Result<(ArrowResponse, u64, u64)> {
+        let mut segments = url.path_segments_mut().ok().context("get path segments")?;
+        segments.push("query");
+        segments.push("arrow-ipc");
+        std::mem::drop(segments);
 
-            tokio::time::sleep(base_ms + jitter).await;
+        let body = serde_json::to_vec(query).context("serialize query")?;
 
-            base = std::cmp::min(base + self.retry_backoff_ms, self.retry_ceiling_ms);
+        #[cfg(feature = "test-util")]
+        let fault_idx = self.inject_request_faults().await?;
+
+        let mut headers = HeaderMap::new();
+        if let Some(accept_encoding) = self.accept_encoding.clone() {
+            headers.insert(ACCEPT_ENCODING, accept_encoding);
         }
 
-        Err(err)
+        let start = std::time::Instant::now();
+        let res = self
+            .send_request(Method::POST, url, Some(body.into()), headers, None)
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(error::HttpStatusError::from_response(res).await);
+        }
+
+        let content_encoding = res.headers().get(CONTENT_ENCODING).cloned();
+        let bytes = res.bytes().await.context("read response body bytes")?;
+        #[cfg(feature = "test-util")]
+        let bytes = self.inject_response_faults(fault_idx, bytes);
+        let latency = start.elapsed();
+        let wire_bytes = u64::try_from(bytes.len()).unwrap();
+        if let Some(metrics) = metrics {
+            metrics.record_fetch(wire_bytes, latency);
+        }
+        #[cfg(feature = "metrics")]
+        metrics::counter!("hypersync_bytes_downloaded").increment(wire_bytes);
+
+        let start = std::time::Instant::now();
+        let (bytes, mut res, fingerprint) = tokio::task::block_in_place(|| {
+            let bytes = decompress(content_encoding.as_ref(), bytes)?;
+            let (res, fingerprint) = parse_query_response(&bytes, self.ipc_decode)
+                .context(error::ParseError("parse query response"))?;
+            Ok::<_, anyhow::Error>((bytes, res, fingerprint))
+        })?;
+        res.transfer = TransferStats {
+            wire_bytes,
+            decompressed_bytes: bytes.len().try_into().unwrap(),
+            server_execution_time: Duration::from_millis(res.total_execution_time),
+            latency,
+        };
+        #[cfg(feature = "metrics")]
+        metrics::histogram!("hypersync_batch_parse_seconds").record(start.elapsed().as_secs_f64());
+        if let Some(metrics) = metrics {
+            metrics.record_parse(start.elapsed());
+            metrics.record_transfer(&res.transfer);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            wire_bytes = res.transfer.wire_bytes,
+            decompressed_bytes = res.transfer.decompressed_bytes,
+            latency_ms = u64::try_from(res.transfer.latency.as_millis()).unwrap_or(u64::MAX),
+            next_block = res.next_block,
+            "received query response"
+        );
+
+        Ok((res, bytes.len().try_into().unwrap(), fingerprint))
+    }
+
+    /// Executes query with retries and returns the response in Arrow format.
+    pub async fn get_arrow(&self, query: &Query) -> Result<ArrowResponse, Error> {
+        self.get_arrow_with_size(query, None, None)
+            .await
+            .map(|res| res.0)
+            .map_err(Error::from)
+    }
+
+    /// Internal implementation for get_arrow.
+    async fn get_arrow_with_size(
+        &self,
+        query: &Query,
+        preferred_endpoint: Option<usize>,
+        metrics: Option<&StreamMetrics>,
+    ) -> Result<(ArrowResponse, u64)> {
+        let (res, size, fingerprint) = self
+            .get_arrow_with_retries(query, preferred_endpoint, metrics)
+            .await?;
+
+        // Checked outside of the retry loop since retrying won't change the server's schema.
+        self.check_schema_fingerprint(fingerprint)?;
+
+        Ok((res, size))
+    }
+
+    /// Returns an error if a schema fingerprint is pinned and the given one doesn't match it.
+    fn check_schema_fingerprint(&self, fingerprint: u64) -> Result<()> {
+        if let Some(pinned) = self.pin_schema_fingerprint {
+            if fingerprint != pinned {
+                return Err(anyhow!(
+                    "response schema fingerprint {} doesn't match the pinned fingerprint {}",
+                    fingerprint,
+                    pinned
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Executes query with retries and returns (Arrow, size, schema fingerprint).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "get_arrow",
+            level = "debug",
+            skip_all,
+            fields(from_block = query.from_block, to_block = ?query.to_block)
+        )
+    )]
+    async fn get_arrow_with_retries(
+        &self,
+        query: &Query,
+        preferred_endpoint: Option<usize>,
+        metrics: Option<&StreamMetrics>,
+    ) -> Result<(ArrowResponse, u64, u64)> {
+        let num_attempts = std::sync::atomic::AtomicUsize::new(0);
+        self.with_retries("get arrow data", preferred_endpoint, |url| {
+            if let Some(metrics) = metrics {
+                let attempt = num_attempts.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                metrics.record_request(attempt > 0);
+            }
+            self.get_arrow_impl(url, query, metrics)
+        })
+        .await
     }
 
     /// Spawns task to execute query and return data via a channel.
@@ -456,26 +1516,29 @@ impl Client {
         self: Arc<Self>,
         query: Query,
         config: StreamConfig,
-    ) -> Result<mpsc::Receiver<Result<QueryResponse>>> {
+    ) -> Result<mpsc::Receiver<Result<QueryResponse, Error>>, Error> {
         check_simple_stream_params(&config)?;
 
-        let (tx, rx): (_, mpsc::Receiver<Result<QueryResponse>>) =
+        let (tx, rx): (_, mpsc::Receiver<Result<QueryResponse, Error>>) =
             mpsc::channel(config.concurrency.unwrap_or(10));
 
+        let parallel_conversion = config.parallel_conversion;
+        let event_signature = config.event_signature.clone();
         let mut inner_rx = self
             .stream_arrow(query, config)
             .await
             .context("start inner stream")?;
 
-        tokio::spawn(async move {
+        stream::spawn_until_closed(tx.clone(), async move {
             while let Some(resp) = inner_rx.recv().await {
+                let resp = match resp {
+                    Ok(r) => convert_response(r, parallel_conversion, event_signature.as_deref())
+                        .await
+                        .map_err(Error::from),
+                    Err(e) => Err(e),
+                };
                 let is_err = resp.is_err();
-                if tx
-                    .send(resp.map(|r| QueryResponse::from(&r)))
-                    .await
-                    .is_err()
-                    || is_err
-                {
+                if tx.send(resp).await.is_err() || is_err {
                     return;
                 }
             }
@@ -490,28 +1553,104 @@ impl Client {
         self: Arc<Self>,
         mut query: Query,
         config: StreamConfig,
-    ) -> Result<mpsc::Receiver<Result<EventResponse>>> {
+    ) -> Result<mpsc::Receiver<Result<EventResponse, Error>>, Error> {
         check_simple_stream_params(&config)?;
 
         add_event_join_fields_to_selection(&mut query);
 
-        let (tx, rx): (_, mpsc::Receiver<Result<EventResponse>>) =
+        let (tx, rx): (_, mpsc::Receiver<Result<EventResponse, Error>>) =
             mpsc::channel(config.concurrency.unwrap_or(10));
 
+        let parallel_conversion = config.parallel_conversion;
+        let event_signature = config.event_signature.clone();
         let mut inner_rx = self
             .stream_arrow(query, config)
             .await
             .context("start inner stream")?;
 
-        tokio::spawn(async move {
+        stream::spawn_until_closed(tx.clone(), async move {
             while let Some(resp) = inner_rx.recv().await {
+                let resp = match resp {
+                    Ok(r) => convert_response(r, parallel_conversion, event_signature.as_deref())
+                        .await
+                        .map(EventResponse::from)
+                        .map_err(Error::from),
+                    Err(e) => Err(e),
+                };
                 let is_err = resp.is_err();
-                if tx
-                    .send(resp.map(|r| EventResponse::from(&r)))
-                    .await
-                    .is_err()
-                    || is_err
-                {
+                if tx.send(resp).await.is_err() || is_err {
+                    return;
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    /// Streams the events of a contract from `from_block` on and keeps following the chain
+    /// once it reaches the height of the server.
+    ///
+    /// Logs of the contract are queried with the log fields of `preset_query::logs` and the
+    /// `number`, `hash`, `parent_hash` and `timestamp` of their blocks, and decoded into
+    /// `Event::decoded` with the events of `abi`. Logs of events that aren't in the ABI are
+    /// skipped unless it has anonymous events, which are passed on undecoded.
+    ///
+    /// Responses come in block order. After a reorg the first response on the new fork has
+    /// `QueryResponse::rollback` set and events returned before from the replaced blocks have
+    /// to be dropped. Set `config.checkpoint` to resume from where an earlier watch stopped,
+    /// `config.follow` is always set.
+    pub async fn watch_contract(
+        self: Arc<Self>,
+        address: hypersync_format::Address,
+        abi: &alloy_json_abi::JsonAbi,
+        from_block: u64,
+        mut config: StreamConfig,
+    ) -> Result<mpsc::Receiver<Result<EventResponse, Error>>, Error> {
+        if config.event_signature.is_some() {
+            return Err(anyhow!(
+                "config.event_signature can't be passed to watch_contract, events are decoded \
+                 with the ABI"
+            )
+            .into());
+        }
+        if config.unordered {
+            return Err(anyhow!("config.unordered can't be passed to watch_contract").into());
+        }
+        if abi.events().next().is_none() {
+            return Err(anyhow!("the ABI passed to watch_contract has no events").into());
+        }
+        config.follow = true;
+
+        let decoder = Decoder::from_abi(abi).context("create decoder from ABI")?;
+        let mut query = preset_query::logs(from_block, None, address);
+        if abi.events().all(|event| !event.anonymous) {
+            query.logs[0].topics.push(
+                abi.events()
+                    .map(|event| event.selector().0.into())
+                    .collect(),
+            );
+        }
+        query.field_selection.block = ["number", "hash", "parent_hash", "timestamp"]
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+
+        let (tx, rx) = mpsc::channel(config.concurrency.unwrap_or(10));
+        let mut inner_rx = self
+            .stream_events(query, config)
+            .await
+            .context("start inner stream")?;
+
+        stream::spawn_until_closed(tx.clone(), async move {
+            while let Some(resp) = inner_rx.recv().await {
+                let resp = resp.map(|mut resp| {
+                    for event in resp.data.iter_mut().flatten() {
+                        event.decoded = decoder.decode_log(&event.log).ok().flatten();
+                    }
+                    resp
+                });
+                let is_err = resp.is_err();
+                if tx.send(resp).await.is_err() || is_err {
                     return;
                 }
             }
@@ -525,19 +1664,334 @@ impl Client {
         self: Arc<Self>,
         query: Query,
         config: StreamConfig,
-    ) -> Result<mpsc::Receiver<Result<ArrowResponse>>> {
-        stream::stream_arrow(self, query, config).await
+    ) -> Result<mpsc::Receiver<Result<ArrowResponse, Error>>, Error> {
+        stream::stream_arrow(self, query, config)
+            .await
+            .map_err(Error::from)
     }
 
-    /// Getter for url field.
+    /// Getter for the primary url.
     pub fn url(&self) -> &Url {
-        &self.url
+        self.endpoints.primary()
+    }
+
+    /// Returns a copy of the client whose requests share a retry budget of the given duration.
+    pub(crate) fn with_retry_budget(&self, limit: Duration) -> Self {
+        Self {
+            retry_budget: Some(Arc::new(retry_budget::RetryBudget::new(limit))),
+            ..self.clone()
+        }
+    }
+
+    /// Maximum number of requests in flight from `ClientConfig::max_connections`.
+    pub(crate) fn max_connections(&self) -> Option<usize> {
+        self.connection_limit.as_ref().map(|(max, _)| max.get())
+    }
+
+    /// Time to wait before a failed stream range is started again for the `num_failures`th time,
+    /// growing with every failure like the wait between the retries of a request.
+    pub(crate) fn range_retry_wait(&self, num_failures: usize) -> Duration {
+        Duration::from_millis(std::cmp::min(
+            self.retry_base_ms
+                .saturating_mul(u64::try_from(num_failures).unwrap()),
+            self.retry_ceiling_ms,
+        ))
+    }
+
+    /// Applies configured latency and failures before a request is sent.
+    #[cfg(feature = "test-util")]
+    async fn inject_request_faults(&self) -> Result<u64> {
+        match &self.fault_injector {
+            Some(injector) => injector.before_request().await,
+            None => Ok(0),
+        }
+    }
+
+    /// Applies configured truncation to the body of a response.
+    #[cfg(feature = "test-util")]
+    fn inject_response_faults(&self, idx: u64, bytes: bytes::Bytes) -> bytes::Bytes {
+        match &self.fault_injector {
+            Some(injector) => injector.response_body(idx, bytes),
+            None => bytes,
+        }
+    }
+}
+
+/// Counts a request in `hypersync_inflight_requests` until it is dropped, so requests whose
+/// future is dropped before the response arrives are taken out of the gauge too.
+#[cfg(feature = "metrics")]
+struct InflightGuard;
+
+#[cfg(feature = "metrics")]
+impl InflightGuard {
+    fn new() -> Self {
+        metrics::gauge!("hypersync_inflight_requests").increment(1.0);
+        Self
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        metrics::gauge!("hypersync_inflight_requests").decrement(1.0);
+    }
+}
+
+fn build_http_client(cfg: &ClientConfig) -> Result<reqwest::Client> {
+    let timeout = cfg
+        .http_req_timeout_millis
+        .unwrap_or(NonZeroU64::new(30_000).unwrap());
+
+    let mut http_client = reqwest::Client::builder()
+        .no_gzip()
+        .timeout(Duration::from_millis(timeout.get()))
+        .tcp_nodelay(cfg.tcp_nodelay.unwrap_or(true));
+
+    if let Some(interval) = cfg.http2_keep_alive_interval_millis {
+        http_client = http_client.http2_keep_alive_interval(Duration::from_millis(interval.get()));
+    }
+    if let Some(timeout) = cfg.http2_keep_alive_timeout_millis {
+        http_client = http_client.http2_keep_alive_timeout(Duration::from_millis(timeout.get()));
+    }
+    if let Some(while_idle) = cfg.http2_keep_alive_while_idle {
+        http_client = http_client.http2_keep_alive_while_idle(while_idle);
+    }
+    if let Some(idle_timeout) = cfg.pool_idle_timeout_millis {
+        http_client = http_client.pool_idle_timeout(Duration::from_millis(idle_timeout));
+    }
+    if let Some(max_idle) = cfg
+        .pool_max_idle_per_host
+        .or(cfg.max_connections.map(NonZeroUsize::get))
+    {
+        http_client = http_client.pool_max_idle_per_host(max_idle);
+    }
+    if let Some(proxy_cfg) = cfg.proxy.as_ref() {
+        let mut proxy = reqwest::Proxy::all(proxy_cfg.url.clone()).context("parse proxy url")?;
+        if let Some(username) = proxy_cfg.username.as_deref() {
+            proxy = proxy.basic_auth(username, proxy_cfg.password.as_deref().unwrap_or(""));
+        }
+        if !proxy_cfg.no_proxy.is_empty() {
+            proxy = proxy.no_proxy(reqwest::NoProxy::from_string(&proxy_cfg.no_proxy.join(",")));
+        }
+        http_client = http_client.proxy(proxy);
+    }
+    if let Some(tls) = cfg.tls.as_ref() {
+        #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+        {
+            http_client = configure_tls(http_client, tls)?;
+        }
+        #[cfg(not(any(feature = "rustls-tls", feature = "native-tls")))]
+        {
+            let _ = tls;
+            return Err(anyhow!(
+                "tls config given but the client was built without the rustls-tls or native-tls feature"
+            ));
+        }
+    }
+    if let Some(resolver) = cfg.dns_resolver.clone() {
+        http_client = http_client.dns_resolver(Arc::new(resolver));
+    }
+    for (host, ips) in cfg.pinned_ips.iter() {
+        // port 0 makes reqwest use the port of the url
+        let addrs = ips
+            .iter()
+            .map(|ip| std::net::SocketAddr::new(*ip, 0))
+            .collect::<Vec<_>>();
+        http_client = http_client.resolve_to_addrs(host, &addrs);
+    }
+
+    http_client.build().context("build http client")
+}
+
+/// Converts the response into simple types, on the rayon thread pool if `parallel` is set.
+async fn convert_response(
+    res: ArrowResponse,
+    parallel: bool,
+    event_signature: Option<&str>,
+) -> Result<QueryResponse> {
+    let event_signature = event_signature.map(str::to_owned);
+    let convert = move || {
+        let mut converted = if parallel {
+            res.to_query_response_parallel()
+        } else {
+            QueryResponse::from(&res)
+        };
+        if let Some(sig) = event_signature.as_deref() {
+            converted.data.decoded_logs = res
+                .data
+                .decoded_logs
+                .iter()
+                .map(|batch| util::decoded_events_from_batch(sig, batch))
+                .collect::<Result<_>>()
+                .context("convert decoded logs")?;
+        }
+        Ok(converted)
+    };
+
+    if parallel {
+        rayon_async::spawn(convert)
+            .await
+            .context("convert response")?
+    } else {
+        convert()
     }
 }
 
+/// Decompresses a response body according to its `Content-Encoding` header.
+fn decompress(content_encoding: Option<&HeaderValue>, bytes: bytes::Bytes) -> Result<bytes::Bytes> {
+    let encoding = match content_encoding {
+        Some(encoding) => encoding.to_str().context("read content-encoding header")?,
+        None => return Ok(bytes),
+    };
+
+    let mut out = Vec::new();
+    match encoding.trim() {
+        "identity" | "" => return Ok(bytes),
+        "gzip" => {
+            std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(&*bytes), &mut out)
+                .context("decompress gzip response")?;
+        }
+        "zstd" => {
+            out = zstd::stream::decode_all(&*bytes).context("decompress zstd response")?;
+        }
+        other => return Err(anyhow!("unsupported content-encoding '{}'", other)),
+    }
+
+    Ok(out.into())
+}
+
+#[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+fn configure_tls(
+    mut http_client: reqwest::ClientBuilder,
+    tls: &TlsConfig,
+) -> Result<reqwest::ClientBuilder> {
+    let read = |path: &std::path::Path| {
+        std::fs::read(path).with_context(|| format!("read {}", path.display()))
+    };
+
+    for path in tls.ca_cert_paths.iter() {
+        let certs = reqwest::Certificate::from_pem_bundle(&read(path)?)
+            .with_context(|| format!("parse CA certificates in {}", path.display()))?;
+        for cert in certs {
+            http_client = http_client.add_root_certificate(cert);
+        }
+    }
+    if tls.disable_built_in_roots {
+        http_client = http_client.tls_built_in_root_certs(false);
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    if let Some(cert_path) = tls.client_cert_path.as_deref() {
+        let mut pem = read(cert_path)?;
+        if let Some(key_path) = tls.client_key_path.as_deref() {
+            pem.push(b'\n');
+            pem.extend_from_slice(&read(key_path)?);
+        }
+        let identity = reqwest::Identity::from_pem(&pem).context("parse client certificate")?;
+        http_client = http_client.identity(identity);
+    }
+    #[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+    if let Some(cert_path) = tls.client_cert_path.as_deref() {
+        let key_path = tls
+            .client_key_path
+            .as_deref()
+            .context("native-tls needs the client key in a separate client_key_path file")?;
+        let identity = reqwest::Identity::from_pkcs8_pem(&read(cert_path)?, &read(key_path)?)
+            .context("parse client certificate")?;
+        http_client = http_client.identity(identity);
+    }
+
+    Ok(http_client)
+}
+
+fn block_index_stream_config(mut config: StreamConfig) -> StreamConfig {
+    // Index rows are a few dozen bytes per block, so a single request can cover far more blocks
+    // than the generic defaults allow before hitting the response size ceiling.
+    config.batch_size.get_or_insert(100_000);
+    config.max_batch_size.get_or_insert(2_000_000);
+    config.min_batch_size.get_or_insert(10_000);
+    config.response_bytes_ceiling.get_or_insert(4_000_000);
+    config.response_bytes_floor.get_or_insert(2_000_000);
+
+    let column_mapping = config.column_mapping.get_or_insert_with(Default::default);
+    column_mapping
+        .block
+        .entry("timestamp".to_owned())
+        .or_insert(DataType::UInt64);
+
+    config
+}
+
+/// Builds the headers sent with every request from the config.
+fn build_default_headers(cfg: &ClientConfig) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in cfg.default_headers.iter() {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("parse default header name {:?}", name))?;
+        let value = HeaderValue::from_str(value)
+            .with_context(|| format!("build default header {}", name))?;
+        headers.insert(name, value);
+    }
+    if let Some(user_agent) = cfg.user_agent.as_ref() {
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_str(user_agent).context("build user-agent header")?,
+        );
+    }
+    Ok(headers)
+}
+
+/// Keeps the guard of the latest block out of the guards of the collected responses, which is
+/// the last response of a forward stream and the first one of a reverse stream.
+fn keep_latest_rollback_guard(current: &mut Option<RollbackGuard>, guard: Option<RollbackGuard>) {
+    if let Some(guard) = guard {
+        if current
+            .as_ref()
+            .is_none_or(|current| current.block_number <= guard.block_number)
+        {
+            *current = Some(guard);
+        }
+    }
+}
+
+/// Block the collected responses end at. Streams in order end at the `next_block` of their last
+/// response, unordered streams at the highest one since the last range can finish early. Collect
+/// functions don't let unordered streams stop early, so every range below it was collected.
+fn end_of_responses(current: u64, next_block: u64, unordered: bool) -> u64 {
+    if unordered {
+        current.max(next_block)
+    } else {
+        next_block
+    }
+}
+
+fn check_collect_params(config: &StreamConfig) -> Result<()> {
+    if config.follow && !stop_condition::has_stop_condition(config) {
+        return Err(anyhow!("config.follow can't be passed to collect functions since the stream never ends. Use the stream functions or set a stop condition like config.max_num_logs or config.stop_condition instead."));
+    }
+    if config.unordered && stop_condition::has_stop_condition(config) {
+        return Err(anyhow!("config.unordered can't be combined with config.max_num_*, config.max_bytes or config.stop_condition in collect functions since the stream would stop with ranges below the collected ones missing. Use the stream functions instead."));
+    }
+
+    Ok(())
+}
+
+/// Fails if progress is checkpointed by a collect function that keeps the responses in memory,
+/// since the checkpoint would move past responses that are lost if the call fails later on.
+fn check_in_memory_collect_params(config: &StreamConfig) -> Result<()> {
+    if config.checkpoint.is_some() {
+        return Err(anyhow!("config.checkpoint can't be passed to in-memory collect functions since the collected data is lost if the call fails. Use collect_parquet or the stream functions instead."));
+    }
+
+    Ok(())
+}
+
 fn check_simple_stream_params(config: &StreamConfig) -> Result<()> {
-    if config.event_signature.is_some() {
-        return Err(anyhow!("config.event_signature can't be passed to simple type function. User is expected to decode the logs using Decoder."));
+    if config.event_signature.is_some() && !matches!(config.hex_output, HexOutput::NoEncode) {
+        return Err(anyhow!("config.hex_output can't be combined with config.event_signature in simple type function. Decoded logs are converted from binary columns."));
+    }
+    if !config.event_routes.is_empty() {
+        return Err(anyhow!("config.event_routes can't be passed to simple type function. User is expected to decode the logs using Decoder."));
     }
     if config.column_mapping.is_some() {
         return Err(anyhow!("config.column_mapping can't be passed to single type function. User is expected to map values manually."));
@@ -571,3 +2025,747 @@ fn add_event_join_fields_to_selection(query: &mut Query) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::mock_server::{mock_client, mock_logs, transfers_query, MockChain, MockServer};
+
+    /// Serves a single http request on a local port, answering with the given json body.
+    pub(crate) fn serve_once(
+        body: &'static str,
+        check_request: impl FnOnce(&str) + Send + 'static,
+    ) -> (u16, std::thread::JoinHandle<()>) {
+        serve_once_with_status("200 OK", body, check_request)
+    }
+
+    /// Like `serve_once`, but responds with the given status line.
+    pub(crate) fn serve_once_with_status(
+        status: &'static str,
+        body: &'static str,
+        check_request: impl FnOnce(&str) + Send + 'static,
+    ) -> (u16, std::thread::JoinHandle<()>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            check_request(std::str::from_utf8(&buf[..n]).unwrap());
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\ncontent-length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+        (port, server)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_request_raw() {
+        let (port, server) = serve_once(r#"{"tables":["blocks"]}"#, |req| {
+            assert!(req.starts_with("POST /experimental/tables HTTP/1.1"));
+            assert!(req.contains("content-type: application/json"));
+        });
+
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            max_num_retries: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let res = client
+            .request_raw(
+                Method::POST,
+                &["experimental", "tables"],
+                Some(bytes::Bytes::from_static(br#"{"verbose":true}"#)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(&res[..], br#"{"tables":["blocks"]}"#);
+
+        server.join().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_api_key() {
+        let (port, server) = serve_once(r#"{"height":100}"#, |req| {
+            assert!(req.starts_with("GET /height?key=secret HTTP/1.1"));
+        });
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            api_key: Some(ApiKey::QueryParam {
+                name: "key".into(),
+                value: "secret".into(),
+            }),
+            max_num_retries: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(client.get_height().await.unwrap(), 100);
+        server.join().unwrap();
+
+        let (port, server) = serve_once(r#"{"height":100}"#, |req| {
+            assert!(req.contains("x-api-key: secret\r\n"));
+            assert!(!req.contains("authorization"));
+        });
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            api_key: Some(ApiKey::Header {
+                name: "x-api-key".into(),
+                value: "secret".into(),
+            }),
+            max_num_retries: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(client.get_height().await.unwrap(), 100);
+        server.join().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_default_headers() {
+        let (port, server) = serve_once(r#"{"height":100}"#, |req| {
+            assert!(req.contains("user-agent: my-indexer/1.0\r\n"));
+            assert!(req.contains("x-team: data\r\n"));
+            assert!(req.contains("authorization: Bearer token\r\n"));
+        });
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            bearer_token: Some("token".into()),
+            default_headers: [
+                ("x-team".to_owned(), "data".to_owned()),
+                ("authorization".to_owned(), "ignored".to_owned()),
+            ]
+            .into_iter()
+            .collect(),
+            user_agent: Some("my-indexer/1.0".into()),
+            max_num_retries: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(client.get_height().await.unwrap(), 100);
+        server.join().unwrap();
+
+        assert!(Client::new(ClientConfig {
+            default_headers: [("bad header".to_owned(), "x".to_owned())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_health() {
+        let (port, server) = serve_once(r#"{"height":100}"#, |_| {});
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            ..Default::default()
+        })
+        .unwrap();
+        let report = client.health().await;
+        assert!(report.is_healthy());
+        assert_eq!(report.height, Some(100));
+        server.join().unwrap();
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            ..Default::default()
+        })
+        .unwrap();
+        // nothing listens on the port anymore
+        let report = client.health().await;
+        assert!(!report.is_healthy());
+        assert_eq!(report.height, None);
+        assert_eq!(report.error_kind, Some(ErrorKind::Connect));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_get_height_cached() {
+        let (port, server) = serve_once(r#"{"height":100}"#, |req| {
+            assert!(req.starts_with("GET /height HTTP/1.1"));
+        });
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            max_num_retries: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let ttl = Duration::from_secs(60);
+        assert_eq!(client.get_height_cached(ttl).await.unwrap(), 100);
+        server.join().unwrap();
+        // the server only answers once, so these have to come from the cache
+        assert_eq!(client.get_height_cached(ttl).await.unwrap(), 100);
+        assert_eq!(client.clone().get_height_cached(ttl).await.unwrap(), 100);
+        // an expired height is fetched again
+        assert!(client.get_height_cached(Duration::ZERO).await.is_err());
+        assert!(client.refresh_height().await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_validate_query_on_server() {
+        let (port, server) = serve_once_with_status("400 Bad Request", "unknown field", |req| {
+            assert!(req.starts_with("POST /query/arrow-ipc HTTP/1.1"));
+        });
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            ..Default::default()
+        })
+        .unwrap();
+        let query: Query = serde_json::from_value(serde_json::json!({
+            "from_block": 0,
+            "logs": [{}],
+            "field_selection": {"log": ["address"]}
+        }))
+        .unwrap();
+
+        let res = client.validate_query(&query, true).await.unwrap();
+        server.join().unwrap();
+        assert!(!res.is_valid());
+        let diagnostic = res.errors().next().unwrap();
+        assert_eq!(diagnostic.path, "server");
+        assert_eq!(diagnostic.message, "unknown field");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_json_fallback() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut paths = Vec::new();
+            for _ in 0..3 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                let req = std::str::from_utf8(&buf[..n]).unwrap();
+                let path = req.split(' ').nth(1).unwrap().to_owned();
+                let (status, body) = if path == "/query/arrow-ipc" {
+                    ("404 Not Found", "")
+                } else {
+                    (
+                        "200 OK",
+                        r#"{"data":[{"blocks":[{"number":5}]}],"archive_height":10,"next_block":6,"total_execution_time":2}"#,
+                    )
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+                paths.push(path);
+            }
+            paths
+        });
+
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            json_fallback: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let query: Query = serde_json::from_value(serde_json::json!({
+            "from_block": 5,
+            "include_all_blocks": true,
+            "field_selection": {"block": ["number"]}
+        }))
+        .unwrap();
+
+        let res = client.get(&query).await.unwrap();
+        assert_eq!(res.next_block, 6);
+        assert_eq!(res.archive_height, Some(10));
+        assert_eq!(res.data.blocks[0][0].number, Some(5));
+        // the client remembers that arrow-ipc isn't served
+        client.get(&query).await.unwrap();
+
+        assert_eq!(
+            server.join().unwrap(),
+            ["/query/arrow-ipc", "/query", "/query"]
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_json_fallback_fault_injection() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut paths = Vec::new();
+            for _ in 0..3 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                let req = std::str::from_utf8(&buf[..n]).unwrap();
+                let path = req.split(' ').nth(1).unwrap().to_owned();
+                let (status, body) = if path == "/query/arrow-ipc" {
+                    ("404 Not Found", "")
+                } else {
+                    (
+                        "200 OK",
+                        r#"{"data":[{"blocks":[{"number":5}]}],"archive_height":10,"next_block":6,"total_execution_time":2}"#,
+                    )
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+                paths.push(path);
+            }
+            paths
+        });
+
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            json_fallback: true,
+            retry_base_ms: Some(1),
+            retry_backoff_ms: Some(1),
+            fault_injection: Some(FaultInjectionConfig {
+                truncate_every_nth_response: NonZeroU64::new(2),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        let query: Query = serde_json::from_value(serde_json::json!({
+            "from_block": 5,
+            "include_all_blocks": true,
+            "field_selection": {"block": ["number"]}
+        }))
+        .unwrap();
+
+        // the body of the first json response is truncated and the request is retried
+        let res = client.get(&query).await.unwrap();
+        assert_eq!(res.next_block, 6);
+        assert_eq!(
+            server.join().unwrap(),
+            ["/query/arrow-ipc", "/query", "/query"]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_get_server_metadata() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                let body = if buf[..n].starts_with(b"GET /chain_id") {
+                    r#"{"chain_id":8453}"#
+                } else {
+                    r#"{"height":20000000}"#
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            max_num_retries: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let metadata = client.get_server_metadata().await.unwrap();
+        assert_eq!(
+            metadata,
+            ServerMetadata {
+                chain_id: 8453,
+                height: 20_000_000,
+                chain: Some(ChainKind::Base),
+            }
+        );
+        server.join().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_invalid_query_is_not_retried() {
+        let (port, server) = serve_once_with_status("400 Bad Request", "bad field", |req| {
+            assert!(req.starts_with("POST /query/arrow-ipc HTTP/1.1"));
+        });
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            max_num_retries: Some(12),
+            ..Default::default()
+        })
+        .unwrap();
+        let query: Query = serde_json::from_value(serde_json::json!({
+            "from_block": 0,
+            "logs": [{}],
+            "field_selection": {"log": ["address"]}
+        }))
+        .unwrap();
+
+        // a retry would fail to connect since the server only answers once
+        let err = client.get_arrow(&query).await.unwrap_err();
+        server.join().unwrap();
+        assert_eq!(
+            err.kind(),
+            ErrorKind::InvalidQuery {
+                body: "bad field".into()
+            }
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_retry_deadline() {
+        let (port, server) = serve_once_with_status("502 Bad Gateway", "upstream down", |req| {
+            assert!(req.starts_with("POST /query/arrow-ipc HTTP/1.1"));
+        });
+        let client = Arc::new(
+            Client::new(ClientConfig {
+                url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+                max_num_retries: Some(100),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let query: Query = serde_json::from_value(serde_json::json!({
+            "from_block": 0,
+            "to_block": 10,
+            "logs": [{}],
+            "field_selection": {"log": ["address"]}
+        }))
+        .unwrap();
+
+        // the wait before the first retry already exceeds the budget
+        let mut rx = client
+            .stream_arrow(
+                query,
+                StreamConfig {
+                    max_total_retry_duration_millis: Some(1),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let err = rx.recv().await.unwrap().unwrap_err();
+        server.join().unwrap();
+
+        let deadline = err.downcast_ref::<RetryDeadlineExceeded>().unwrap();
+        assert_eq!(deadline.limit, Duration::from_millis(1));
+        // the error ending the stream can be matched on
+        assert!(matches!(err, Error::RetryDeadlineExceeded { .. }));
+        assert!(format!("{:?}", err).contains("upstream down"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_max_connections() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            for i in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                assert!(buf[..n].starts_with(b"GET /height"));
+                if i == 0 {
+                    // the second request has to wait until the first one is answered
+                    std::thread::sleep(Duration::from_millis(200));
+                    listener.set_nonblocking(true).unwrap();
+                    assert_eq!(
+                        listener.accept().unwrap_err().kind(),
+                        std::io::ErrorKind::WouldBlock
+                    );
+                    listener.set_nonblocking(false).unwrap();
+                }
+                let body = r#"{"height":7}"#;
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        let client = Client::new(ClientConfig {
+            url: Some(format!("http://127.0.0.1:{}", port).parse().unwrap()),
+            max_num_retries: Some(0),
+            max_connections: NonZeroUsize::new(1),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(client.max_connections(), Some(1));
+
+        let (a, b) = tokio::join!(client.get_height(), client.get_height());
+        assert_eq!(a.unwrap(), 7);
+        assert_eq!(b.unwrap(), 7);
+        server.join().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pinned_ips() {
+        let (port, server) = serve_once(r#"{"height":123}"#, |req| {
+            assert!(req.starts_with("GET /height"));
+        });
+
+        let client = Client::new(ClientConfig {
+            url: Some(
+                format!("http://hypersync.invalid:{}", port)
+                    .parse()
+                    .unwrap(),
+            ),
+            pinned_ips: [(
+                "hypersync.invalid".to_owned(),
+                vec!["127.0.0.1".parse().unwrap()],
+            )]
+            .into_iter()
+            .collect(),
+            max_num_retries: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(client.get_height().await.unwrap(), 123);
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    #[test]
+    fn test_tls_config_errors() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        let ca_path = dir.join("ca.pem");
+        std::fs::write(&ca_path, "not a certificate").unwrap();
+
+        let new_client = |tls| {
+            Client::new(ClientConfig {
+                tls: Some(tls),
+                ..Default::default()
+            })
+        };
+
+        let err = new_client(TlsConfig {
+            ca_cert_paths: vec![dir.join("missing.pem")],
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(format!("{:?}", err).contains("missing.pem"));
+
+        // a file without any PEM blocks or key can't be used as a client identity
+        let err = new_client(TlsConfig {
+            client_cert_path: Some(ca_path),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(format!("{:?}", err).contains("parse client certificate"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_decompress_response() {
+        let body = b"arrow ipc bytes".repeat(100);
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&body).unwrap();
+        let gzip = encoder.finish().unwrap();
+        let zstd = zstd::stream::encode_all(&body[..], 1).unwrap();
+
+        for (encoding, compressed) in [("gzip", gzip), ("zstd", zstd)] {
+            let out =
+                decompress(Some(&HeaderValue::from_static(encoding)), compressed.into()).unwrap();
+            assert_eq!(out, body);
+        }
+
+        let plain = decompress(None, body.clone().into()).unwrap();
+        assert_eq!(plain, body);
+        assert!(decompress(Some(&HeaderValue::from_static("br")), body.into()).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_http_proxy() {
+        let (port, server) = serve_once(r#"{"height":7}"#, |req| {
+            // proxied requests carry the absolute url and the proxy credentials
+            assert!(req.starts_with("GET http://hypersync.invalid/height"));
+            assert!(req
+                .to_lowercase()
+                .contains("proxy-authorization: basic dxnlcjpwyxnz"));
+        });
+
+        let client = Client::new(ClientConfig {
+            url: Some("http://hypersync.invalid".parse().unwrap()),
+            proxy: Some(ProxyConfig {
+                url: format!("http://127.0.0.1:{}", port).parse().unwrap(),
+                username: Some("user".to_owned()),
+                password: Some("pass".to_owned()),
+                no_proxy: vec!["internal.example".to_owned()],
+            }),
+            max_num_retries: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(client.get_height().await.unwrap(), 7);
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_watch_contract() {
+        let server = MockServer::start(MockChain::new(20)).unwrap();
+        let client = mock_client(&server);
+        let abi: alloy_json_abi::JsonAbi = serde_json::from_str(
+            r#"[{"type": "event", "name": "Transfer", "anonymous": false, "inputs": [
+                {"name": "from", "type": "address", "indexed": true},
+                {"name": "to", "type": "address", "indexed": true},
+                {"name": "amount", "type": "uint256", "indexed": false}
+            ]}]"#,
+        )
+        .unwrap();
+        let address = hypersync_format::Address::from([1; 20]);
+
+        let err = client
+            .clone()
+            .watch_contract(
+                address.clone(),
+                &abi,
+                0,
+                StreamConfig {
+                    event_signature: Some("Transfer(address,address,uint256)".into()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("event_signature"));
+
+        let checkpoint = Arc::new(MemoryCheckpoint::new(Some(10)));
+        let mut rx = client
+            .watch_contract(
+                address,
+                &abi,
+                0,
+                StreamConfig {
+                    follow_poll_interval_millis: Some(10),
+                    checkpoint: Some(checkpoint.clone()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut next_block = 10;
+        while next_block < 19 {
+            let res = rx.recv().await.unwrap().unwrap();
+            assert!(res.next_block > next_block);
+            next_block = res.next_block;
+        }
+
+        // the watch keeps going once the chain grows
+        server.update_chain(|chain| chain.push_blocks(10));
+        while next_block < 29 {
+            next_block = rx.recv().await.unwrap().unwrap().next_block;
+        }
+        // the responses before the last one taken are checkpointed
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(rx);
+        assert!(checkpoint.next_block().unwrap() >= 19);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_deterministic_collect() {
+        let server = MockServer::start(MockChain::new(3_000)).unwrap();
+        server.set_max_blocks_per_response(250);
+        let client = mock_client(&server);
+
+        let mut outputs = Vec::new();
+        // responses end at different blocks with each config
+        for (concurrency, batch_size) in [(1, 70), (4, 130), (16, 700)] {
+            let res = client
+                .clone()
+                .collect(
+                    transfers_query(0, 2_000),
+                    StreamConfig {
+                        concurrency: Some(concurrency),
+                        batch_size: Some(batch_size),
+                        max_batch_size: Some(batch_size),
+                        event_signature: Some(
+                            "Transfer(address indexed from, address indexed to, uint256 value)"
+                                .to_owned(),
+                        ),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+            assert_eq!(res.next_block, 2_000);
+            let logs = res.data.logs.concat();
+            let decoded_logs = res.data.decoded_logs.concat();
+            assert_eq!(logs.len(), mock_logs(0..2_000).len());
+            assert_eq!(decoded_logs.len(), logs.len());
+            // block 1 has a single log with a value of 4
+            assert_eq!(
+                decoded_logs[0].as_ref().unwrap().body,
+                vec![alloy_dyn_abi::DynSolValue::Uint(
+                    alloy_primitives::U256::from(4),
+                    256
+                )]
+            );
+            outputs.push(format!(
+                "{:?}",
+                (
+                    res.data.blocks.concat(),
+                    res.data.transactions.concat(),
+                    logs,
+                    decoded_logs
+                )
+            ));
+        }
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_collect_block_index_parquet_checks_params() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        let client = mock_client(&server);
+        let path = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+
+        let err = client
+            .collect_block_index_parquet(
+                path.to_str().unwrap(),
+                0,
+                None,
+                StreamConfig {
+                    follow: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("config.follow"));
+        // rejected before anything is written
+        assert!(!path.exists());
+    }
+}
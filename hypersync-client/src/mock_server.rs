@@ -0,0 +1,809 @@
+#![cfg(feature = "test-util")]
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use hypersync_format::Hash;
+use hypersync_net_types::{hypersync_net_types_capnp, Query, RollbackGuard};
+use polars_arrow::{
+    array::{Array, BinaryArray, UInt64Array},
+    datatypes::{ArrowSchema, Field},
+    io::ipc::write::{FileWriter, WriteOptions},
+};
+use url::Url;
+
+use crate::ArrowChunk;
+
+/// Block header of a [`MockChain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockBlock {
+    /// Number of the block.
+    pub number: u64,
+    /// Hash of the block, different for every fork the block was created on.
+    pub hash: Hash,
+    /// Hash of the previous block.
+    pub parent_hash: Hash,
+    /// Unix timestamp of the block, 12 seconds after the previous one.
+    pub timestamp: u64,
+}
+
+/// A simulated chain of block headers that can be reorganized, served by [`MockServer`].
+///
+/// Block hashes encode the block number and the fork the block was created on, so after a reorg
+/// the replaced heights have different hashes while the blocks below them keep theirs.
+#[derive(Debug, Clone)]
+pub struct MockChain {
+    blocks: Vec<MockBlock>,
+    fork: u64,
+    unfinalized_depth: u64,
+}
+
+impl MockChain {
+    /// Creates a chain with blocks `0..num_blocks` on the initial fork.
+    pub fn new(num_blocks: u64) -> Self {
+        let mut chain = Self {
+            blocks: Vec::new(),
+            fork: 0,
+            unfinalized_depth: 64,
+        };
+        chain.push_blocks(num_blocks);
+        chain
+    }
+
+    /// Sets the number of most recent blocks that aren't final yet and are covered by the
+    /// rollback guard of responses. Defaults to 64.
+    pub fn with_unfinalized_depth(mut self, depth: u64) -> Self {
+        self.unfinalized_depth = depth;
+        self
+    }
+
+    /// All blocks of the chain, ordered by number.
+    pub fn blocks(&self) -> &[MockBlock] {
+        &self.blocks
+    }
+
+    /// Returns the block with the given number if the chain reached it.
+    pub fn block(&self, number: u64) -> Option<&MockBlock> {
+        self.blocks.get(usize::try_from(number).ok()?)
+    }
+
+    /// Number of the latest block, None if the chain is empty.
+    pub fn latest(&self) -> Option<u64> {
+        self.blocks.last().map(|b| b.number)
+    }
+
+    /// Appends blocks to the current fork.
+    pub fn push_blocks(&mut self, num_blocks: u64) {
+        for _ in 0..num_blocks {
+            let number = self.blocks.len() as u64;
+            let parent_hash = self
+                .blocks
+                .last()
+                .map(|b| b.hash.clone())
+                .unwrap_or_default();
+            self.blocks.push(MockBlock {
+                number,
+                hash: block_hash(number, self.fork),
+                parent_hash,
+                timestamp: 1_700_000_000 + number * 12,
+            });
+        }
+    }
+
+    /// Replaces the latest `depth` blocks with blocks of a new fork at the same heights.
+    ///
+    /// Combine with [`MockChain::push_blocks`] for reorgs onto a longer fork, or with
+    /// [`MockChain::truncate`] first for a shorter one.
+    pub fn reorg(&mut self, depth: u64) {
+        let depth = depth.min(self.blocks.len() as u64);
+        let keep = self.blocks.len() as u64 - depth;
+        self.truncate(keep);
+        self.fork += 1;
+        self.push_blocks(depth);
+    }
+
+    /// Drops every block with a number of `num_blocks` or higher.
+    pub fn truncate(&mut self, num_blocks: u64) {
+        self.blocks
+            .truncate(usize::try_from(num_blocks).unwrap_or(usize::MAX));
+    }
+
+    /// Rollback guard the server returns with its responses, covering the unfinalized blocks.
+    pub fn rollback_guard(&self) -> Option<RollbackGuard> {
+        let last = self.blocks.last()?;
+        let first_idx =
+            self.blocks.len() - (self.unfinalized_depth.max(1) as usize).min(self.blocks.len());
+        let first = &self.blocks[first_idx];
+        Some(RollbackGuard {
+            block_number: last.number,
+            timestamp: last.timestamp as i64,
+            hash: last.hash.clone(),
+            first_block_number: first.number,
+            first_parent_hash: first.parent_hash.clone(),
+        })
+    }
+}
+
+fn block_hash(number: u64, fork: u64) -> Hash {
+    let mut hash = [0; 32];
+    hash[..8].copy_from_slice(&fork.to_be_bytes());
+    hash[24..].copy_from_slice(&number.to_be_bytes());
+    hash.into()
+}
+
+#[derive(Debug)]
+struct State {
+    chain: MockChain,
+    num_queries: u64,
+    num_height_requests: u64,
+    max_blocks_per_response: u64,
+    /// Reorgs to apply once the given number of queries was answered, as `(num_queries, depth)`.
+    scheduled_reorgs: Vec<(u64, u64)>,
+    /// Time to wait before answering queries, keyed by their `from_block`.
+    query_delays: BTreeMap<u64, Duration>,
+    /// Number of queries starting at the given block to fail before answering them.
+    query_failures: BTreeMap<u64, u64>,
+    /// Time until which queries starting at the given block fail.
+    query_outages: BTreeMap<u64, Instant>,
+    /// Bearer token requests have to carry, others are rejected with 401.
+    bearer_token: Option<String>,
+}
+
+/// HyperSync server serving the block headers of a [`MockChain`] on a local port, for testing
+/// reorg handling against the real client.
+///
+/// Answers `GET /height` and `POST /query/arrow-ipc`, and `GET /query/arrow-ipc/ws` with the
+/// `websocket` feature, where the responses to the query sent by the client are pushed until
+/// its `to_block` or the latest block is reached. Queries return the `number`, `hash`,
+/// `parent_hash` and `timestamp` columns of the selected block fields for every block in the
+/// range if `include_all_blocks` is set. If the query has any transaction or log selection, the
+/// `number % 3` transactions and `number % 4` `Transfer` logs of every block in the range are
+/// returned too, see [`mock_transactions`] and [`mock_logs`]. The selections themselves and
+/// other columns are ignored and traces are always empty. Every response carries the rollback
+/// guard of the chain at the time it was answered.
+///
+/// The server stops when it is dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Starts serving the given chain on a free local port.
+    pub fn start(chain: MockChain) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").context("bind listener")?;
+        let addr = listener.local_addr().context("get local address")?;
+        let state = Arc::new(Mutex::new(State {
+            chain,
+            num_queries: 0,
+            num_height_requests: 0,
+            max_blocks_per_response: 100,
+            scheduled_reorgs: Vec::new(),
+            query_delays: BTreeMap::new(),
+            query_failures: BTreeMap::new(),
+            query_outages: BTreeMap::new(),
+            bearer_token: None,
+        }));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(_) => continue,
+                    };
+                    let state = state.clone();
+                    std::thread::spawn(move || {
+                        if let Err(e) = handle_connection(stream, &state) {
+                            log::debug!("mock server failed to handle a request: {:?}", e);
+                        }
+                    });
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            state,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Url to pass to `ClientConfig::url`.
+    pub fn url(&self) -> Url {
+        format!("http://{}", self.addr).parse().unwrap()
+    }
+
+    /// Runs the given function on the served chain, e.g. to add blocks or reorg it.
+    pub fn update_chain<R>(&self, f: impl FnOnce(&mut MockChain) -> R) -> R {
+        f(&mut self.state.lock().unwrap().chain)
+    }
+
+    /// Returns a copy of the served chain.
+    pub fn chain(&self) -> MockChain {
+        self.state.lock().unwrap().chain.clone()
+    }
+
+    /// Reorgs the latest `depth` blocks right after the server answered `num_queries` queries in
+    /// total, so a reorg can hit a running stream at a known point.
+    pub fn reorg_after_queries(&self, num_queries: u64, depth: u64) {
+        self.state
+            .lock()
+            .unwrap()
+            .scheduled_reorgs
+            .push((num_queries, depth));
+    }
+
+    /// Sets the maximum number of blocks a single query response covers. Defaults to 100.
+    pub fn set_max_blocks_per_response(&self, num_blocks: u64) {
+        self.state.lock().unwrap().max_blocks_per_response = num_blocks.max(1);
+    }
+
+    /// Waits for `delay` before answering queries starting at `from_block`, e.g. to make one range
+    /// of a stream finish after the ones after it.
+    pub fn delay_queries_from(&self, from_block: u64, delay: Duration) {
+        self.state
+            .lock()
+            .unwrap()
+            .query_delays
+            .insert(from_block, delay);
+    }
+
+    /// Fails the next `num_failures` queries starting at `from_block` with a 500 response, e.g.
+    /// to make one range of a stream run out of retries.
+    pub fn fail_queries_from(&self, from_block: u64, num_failures: u64) {
+        self.state
+            .lock()
+            .unwrap()
+            .query_failures
+            .insert(from_block, num_failures);
+    }
+
+    /// Fails all queries starting at `from_block` with a 500 response for the given duration
+    /// from now on, e.g. to make one range of a stream recover only after a while.
+    pub fn fail_queries_from_for(&self, from_block: u64, duration: Duration) {
+        self.state
+            .lock()
+            .unwrap()
+            .query_outages
+            .insert(from_block, Instant::now() + duration);
+    }
+
+    /// Rejects requests that don't carry the given bearer token with 401, or accepts all
+    /// requests again if it is None.
+    pub fn require_bearer_token(&self, token: Option<&str>) {
+        self.state.lock().unwrap().bearer_token = token.map(str::to_owned);
+    }
+
+    /// Number of queries answered so far.
+    pub fn num_queries(&self) -> u64 {
+        self.state.lock().unwrap().num_queries
+    }
+
+    /// Number of height requests answered so far.
+    pub fn num_height_requests(&self) -> u64 {
+        self.state.lock().unwrap().num_height_requests
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // wake up the accept loop so it sees the shutdown flag
+        TcpStream::connect(self.addr).ok();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, state: &Mutex<State>) -> Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).context("read request")?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = std::str::from_utf8(&buf[..header_end]).context("parse request head")?;
+    let mut lines = head.lines();
+    let request_line = lines.next().context("read request line")?;
+    let mut parts = request_line.split(' ');
+    let method = parts.next().unwrap_or_default().to_owned();
+    let path = parts.next().unwrap_or_default().to_owned();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_owned()))
+        .collect::<BTreeMap<_, _>>();
+    let content_length = headers
+        .get("content-length")
+        .map(|value| value.parse::<usize>())
+        .transpose()
+        .context("parse content-length")?
+        .unwrap_or(0);
+
+    while buf.len() < header_end + content_length {
+        let n = stream.read(&mut chunk).context("read request body")?;
+        if n == 0 {
+            return Err(anyhow!(
+                "connection closed before the request body was read"
+            ));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body = &buf[header_end..header_end + content_length];
+
+    let bearer_token = state.lock().unwrap().bearer_token.clone();
+    let authorized = bearer_token
+        .is_none_or(|token| headers.get("authorization") == Some(&format!("Bearer {}", token)));
+
+    let (status, response) = match (method.as_str(), path.split('?').next().unwrap_or_default()) {
+        _ if !authorized => ("401 Unauthorized", b"invalid bearer token".to_vec()),
+        ("GET", "/height") => {
+            let mut state = state.lock().unwrap();
+            state.num_height_requests += 1;
+            let height = state.chain.latest().unwrap_or(0);
+            ("200 OK", format!(r#"{{"height":{}}}"#, height).into_bytes())
+        }
+        ("POST", "/query/arrow-ipc") if take_query_failure(body, state) => {
+            ("500 Internal Server Error", b"injected failure".to_vec())
+        }
+        ("POST", "/query/arrow-ipc") => {
+            match parse_query(body).and_then(|q| answer_query(&q, state)) {
+                Ok((res, _)) => ("200 OK", res),
+                Err(e) => ("400 Bad Request", format!("{:?}", e).into_bytes()),
+            }
+        }
+        #[cfg(feature = "websocket")]
+        ("GET", "/query/arrow-ipc/ws") => {
+            let key = headers
+                .get("sec-websocket-key")
+                .context("read sec-websocket-key")?;
+            return serve_websocket(stream, key, state);
+        }
+        _ => ("404 Not Found", Vec::new()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nconnection: close\r\ncontent-length: {}\r\n\r\n",
+        status,
+        response.len()
+    )
+    .context("write response head")?;
+    stream.write_all(&response).context("write response body")?;
+    Ok(())
+}
+
+/// Returns true if the query should fail because of `MockServer::fail_queries_from` or
+/// `MockServer::fail_queries_from_for`.
+fn take_query_failure(body: &[u8], state: &Mutex<State>) -> bool {
+    let Ok(query) = serde_json::from_slice::<Query>(body) else {
+        return false;
+    };
+    let mut state = state.lock().unwrap();
+    if state
+        .query_outages
+        .get(&query.from_block)
+        .is_some_and(|until| Instant::now() < *until)
+    {
+        return true;
+    }
+    match state.query_failures.get_mut(&query.from_block) {
+        Some(num_failures) if *num_failures > 0 => {
+            *num_failures -= 1;
+            true
+        }
+        _ => false,
+    }
+}
+
+fn parse_query(body: &[u8]) -> Result<Query> {
+    serde_json::from_slice(body).context("parse query")
+}
+
+/// Accepts a websocket connection, reads the query sent by the client and pushes the responses
+/// to it one after another.
+#[cfg(feature = "websocket")]
+fn serve_websocket(mut stream: TcpStream, key: &str, state: &Mutex<State>) -> Result<()> {
+    use tokio_tungstenite::tungstenite::{
+        handshake::derive_accept_key, protocol::Role, Message, WebSocket,
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nconnection: Upgrade\r\nupgrade: websocket\r\nsec-websocket-accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    )
+    .context("write handshake response")?;
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+    let mut query = loop {
+        match socket.read().context("read query")? {
+            Message::Text(text) => break parse_query(text.as_bytes())?,
+            _ => continue,
+        }
+    };
+    loop {
+        let (res, next_block) = answer_query(&query, state)?;
+        socket.send(Message::Binary(res)).context("send response")?;
+        let done = query
+            .to_block
+            .is_some_and(|to_block| next_block >= to_block);
+        if done || next_block == query.from_block {
+            break;
+        }
+        query.from_block = next_block;
+    }
+    socket.close(None).ok();
+    // the client answers the close frame before the connection is dropped
+    while socket.read().is_ok() {}
+    Ok(())
+}
+
+/// Answers the query, returning the response and its `next_block`.
+fn answer_query(query: &Query, state: &Mutex<State>) -> Result<(Vec<u8>, u64)> {
+    let delay = state
+        .lock()
+        .unwrap()
+        .query_delays
+        .get(&query.from_block)
+        .copied();
+    if let Some(delay) = delay {
+        std::thread::sleep(delay);
+    }
+
+    let mut state = state.lock().unwrap();
+    let chain = &state.chain;
+    let end = chain.blocks.len() as u64;
+    let to_block = query.to_block.unwrap_or(u64::MAX).min(end).min(
+        query
+            .from_block
+            .saturating_add(state.max_blocks_per_response),
+    );
+    let next_block = to_block.max(query.from_block);
+    let blocks = if query.include_all_blocks && query.from_block < to_block {
+        &chain.blocks[query.from_block as usize..to_block as usize]
+    } else {
+        &[]
+    };
+
+    let range = query.from_block..to_block.max(query.from_block);
+    let transactions = if query.transactions.is_empty() {
+        Vec::new()
+    } else {
+        mock_transactions(range.clone())
+    };
+    let logs = if query.logs.is_empty() {
+        Vec::new()
+    } else {
+        mock_logs(range)
+    };
+
+    let blocks = blocks_ipc(blocks, query).context("write blocks")?;
+    let transactions = transactions_ipc(&transactions, query).context("write transactions")?;
+    let logs = logs_ipc(&logs, query).context("write logs")?;
+    let empty = ipc_file(ArrowSchema::default(), None).context("write empty table")?;
+
+    let mut message = capnp::message::Builder::new_default();
+    let mut res = message.init_root::<hypersync_net_types_capnp::query_response::Builder>();
+    res.set_archive_height(chain.latest().map(|h| h as i64).unwrap_or(-1));
+    res.set_next_block(next_block);
+    res.set_total_execution_time(1);
+    {
+        let mut data = res.reborrow().init_data();
+        data.set_blocks(&blocks);
+        data.set_transactions(&transactions);
+        data.set_logs(&logs);
+        data.set_traces(&empty);
+    }
+    if let Some(guard) = chain.rollback_guard() {
+        let mut rg = res.reborrow().init_rollback_guard();
+        rg.set_hash(guard.hash.as_slice());
+        rg.set_block_number(guard.block_number);
+        rg.set_timestamp(guard.timestamp);
+        rg.set_first_block_number(guard.first_block_number);
+        rg.set_first_parent_hash(guard.first_parent_hash.as_slice());
+    }
+    let mut out = Vec::new();
+    capnp::serialize_packed::write_message(&mut out, &message).context("write response")?;
+
+    state.num_queries += 1;
+    let num_queries = state.num_queries;
+    let mut reorgs = Vec::new();
+    state.scheduled_reorgs.retain(|&(after, depth)| {
+        if after <= num_queries {
+            reorgs.push(depth);
+            false
+        } else {
+            true
+        }
+    });
+    for depth in reorgs {
+        state.chain.reorg(depth);
+    }
+
+    Ok((out, next_block))
+}
+
+/// Transactions of the mock blocks in `blocks`, as `(block_number, transaction_index)`.
+///
+/// Block `n` has `n % 3` transactions.
+pub fn mock_transactions(blocks: std::ops::Range<u64>) -> Vec<(u64, u64)> {
+    blocks
+        .flat_map(|n| (0..n % 3).map(move |i| (n, i)))
+        .collect()
+}
+
+/// Logs of the mock blocks in `blocks`, as `(block_number, log_index)`.
+///
+/// Block `n` has `n % 4` logs. Log `i` of the block is emitted by the contract with all address
+/// bytes set to `n % 5 + 1` in transaction `i / 2`, and decodes with
+/// `Transfer(address indexed from, address indexed to, uint256 value)` to a transfer from the
+/// address with the low bytes `n` to the one with `n + i` of `4 * n + i`.
+pub fn mock_logs(blocks: std::ops::Range<u64>) -> Vec<(u64, u64)> {
+    blocks
+        .flat_map(|n| (0..n % 4).map(move |i| (n, i)))
+        .collect()
+}
+
+/// Writes the selected block columns that the mock chain has as an arrow ipc file.
+fn blocks_ipc(blocks: &[MockBlock], query: &Query) -> Result<Vec<u8>> {
+    columns_ipc(&query.field_selection.block, |name| {
+        Some(match name {
+            "number" => UInt64Array::from_iter(blocks.iter().map(|b| Some(b.number))).boxed(),
+            "hash" => BinaryArray::<i32>::from_iter(blocks.iter().map(|b| Some(b.hash.as_slice())))
+                .boxed(),
+            "parent_hash" => {
+                BinaryArray::<i32>::from_iter(blocks.iter().map(|b| Some(b.parent_hash.as_slice())))
+                    .boxed()
+            }
+            "timestamp" => {
+                BinaryArray::<i32>::from_iter(blocks.iter().map(|b| Some(quantity(b.timestamp))))
+                    .boxed()
+            }
+            _ => return None,
+        })
+    })
+}
+
+/// Writes the selected transaction columns of [`mock_transactions`] as an arrow ipc file.
+fn transactions_ipc(txs: &[(u64, u64)], query: &Query) -> Result<Vec<u8>> {
+    columns_ipc(&query.field_selection.transaction, |name| {
+        Some(match name {
+            "block_number" => UInt64Array::from_iter(txs.iter().map(|&(n, _)| Some(n))).boxed(),
+            "transaction_index" => {
+                UInt64Array::from_iter(txs.iter().map(|&(_, i)| Some(i))).boxed()
+            }
+            "hash" => {
+                BinaryArray::<i32>::from_iter(txs.iter().map(|&(n, i)| Some(word((n << 8) | i))))
+                    .boxed()
+            }
+            _ => return None,
+        })
+    })
+}
+
+/// Writes the selected log columns of [`mock_logs`] as an arrow ipc file.
+fn logs_ipc(logs: &[(u64, u64)], query: &Query) -> Result<Vec<u8>> {
+    let binary = |f: &dyn Fn(u64, u64) -> Vec<u8>| {
+        BinaryArray::<i32>::from_iter(logs.iter().map(|&(n, i)| Some(f(n, i)))).boxed()
+    };
+    columns_ipc(&query.field_selection.log, |name| {
+        Some(match name {
+            "block_number" => UInt64Array::from_iter(logs.iter().map(|&(n, _)| Some(n))).boxed(),
+            "log_index" => UInt64Array::from_iter(logs.iter().map(|&(_, i)| Some(i))).boxed(),
+            "transaction_index" => {
+                UInt64Array::from_iter(logs.iter().map(|&(_, i)| Some(i / 2))).boxed()
+            }
+            "transaction_hash" => binary(&|n, i| word((n << 8) | (i / 2))),
+            "address" => binary(&|n, _| vec![(n % 5 + 1) as u8; 20]),
+            "topic0" => binary(&|_, _| TRANSFER_TOPIC.to_vec()),
+            "topic1" => binary(&|n, _| word(n)),
+            "topic2" => binary(&|n, i| word(n + i)),
+            "data" => binary(&|n, i| word(4 * n + i)),
+            _ => return None,
+        })
+    })
+}
+
+/// Keccak hash of `Transfer(address,address,uint256)`.
+const TRANSFER_TOPIC: [u8; 32] = [
+    0xdd, 0xf2, 0x52, 0xad, 0x1b, 0xe2, 0xc8, 0x9b, 0x69, 0xc2, 0xb0, 0x68, 0xfc, 0x37, 0x8d, 0xaa,
+    0x95, 0x2b, 0xa7, 0xf1, 0x63, 0xc4, 0xa1, 0x16, 0x28, 0xf5, 0x5a, 0x4d, 0xf5, 0x23, 0xb3, 0xef,
+];
+
+/// Writes the columns `col` returns for the selected fields as an arrow ipc file, skipping the
+/// fields it doesn't know.
+fn columns_ipc(
+    selection: &BTreeSet<String>,
+    col: impl Fn(&str) -> Option<Box<dyn Array>>,
+) -> Result<Vec<u8>> {
+    let mut fields = Vec::new();
+    let mut cols = Vec::new();
+    for name in selection.iter() {
+        if let Some(col) = col(name) {
+            fields.push(Field::new(name.as_str(), col.data_type().clone(), false));
+            cols.push(col);
+        }
+    }
+
+    // tables without columns have no rows either
+    let chunk = if cols.is_empty() {
+        None
+    } else {
+        Some(ArrowChunk::try_new(cols).context("create chunk")?)
+    };
+    ipc_file(ArrowSchema::from(fields), chunk)
+}
+
+fn ipc_file(schema: ArrowSchema, chunk: Option<ArrowChunk>) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut writer = FileWriter::try_new(
+        &mut out,
+        Arc::new(schema),
+        None,
+        WriteOptions { compression: None },
+    )
+    .context("create writer")?;
+    if let Some(chunk) = chunk {
+        writer.write(&chunk, None).context("write chunk")?;
+    }
+    writer.finish().context("finish file")?;
+    Ok(out)
+}
+
+/// 32 byte big endian encoding, like an abi encoded uint or an address in a topic.
+fn word(value: u64) -> Vec<u8> {
+    let mut word = vec![0; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// Big endian encoding without leading zeros, like quantities returned by the server.
+fn quantity(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    bytes[start..].to_vec()
+}
+
+/// Client for `server` that doesn't retry failed requests.
+#[cfg(test)]
+pub(crate) fn mock_client(server: &MockServer) -> Arc<crate::Client> {
+    Arc::new(
+        crate::Client::new(crate::ClientConfig {
+            url: Some(server.url()),
+            max_num_retries: Some(0),
+            ..Default::default()
+        })
+        .unwrap(),
+    )
+}
+
+/// Query for the headers of the blocks in `[from_block, to_block)`.
+#[cfg(test)]
+pub(crate) fn blocks_query(from_block: u64, to_block: u64) -> Query {
+    serde_json::from_value(serde_json::json!({
+        "from_block": from_block,
+        "to_block": to_block,
+        "include_all_blocks": true,
+        "field_selection": {"block": ["number", "hash", "parent_hash"]}
+    }))
+    .unwrap()
+}
+
+/// Query for the headers, transactions and `Transfer` logs of the blocks in
+/// `[from_block, to_block)`.
+#[cfg(test)]
+pub(crate) fn transfers_query(from_block: u64, to_block: u64) -> Query {
+    serde_json::from_value(serde_json::json!({
+        "from_block": from_block,
+        "to_block": to_block,
+        "include_all_blocks": true,
+        "transactions": [{}],
+        "logs": [{}],
+        "field_selection": {
+            "block": ["number", "hash", "timestamp"],
+            "transaction": ["block_number", "transaction_index", "hash"],
+            "log": [
+                "block_number", "log_index", "transaction_index", "address", "topic0", "topic1",
+                "topic2", "data"
+            ]
+        }
+    }))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reorg() {
+        let mut chain = MockChain::new(10).with_unfinalized_depth(4);
+        let before = chain.clone();
+        chain.reorg(3);
+
+        assert_eq!(chain.latest(), Some(9));
+        assert_eq!(chain.blocks()[..7], before.blocks()[..7]);
+        for number in 7..10 {
+            let (old, new) = (before.block(number).unwrap(), chain.block(number).unwrap());
+            assert_ne!(old.hash, new.hash);
+        }
+        assert_eq!(
+            chain.block(7).unwrap().parent_hash,
+            before.block(6).unwrap().hash
+        );
+
+        let guard = chain.rollback_guard().unwrap();
+        assert_eq!(guard.block_number, 9);
+        assert_eq!(guard.first_block_number, 6);
+        assert_eq!(guard.first_parent_hash, chain.block(5).unwrap().hash);
+
+        chain.truncate(8);
+        chain.push_blocks(4);
+        assert_eq!(chain.latest(), Some(11));
+        assert_eq!(
+            chain.block(8).unwrap().parent_hash,
+            chain.block(7).unwrap().hash
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_client_sees_reorg() {
+        let server = MockServer::start(MockChain::new(50).with_unfinalized_depth(10)).unwrap();
+        let client = mock_client(&server);
+
+        assert_eq!(client.get_height().await.unwrap(), 49);
+
+        let res = client.get(&blocks_query(40, 50)).await.unwrap();
+        let blocks = res.data.blocks.concat();
+        assert_eq!(blocks.len(), 10);
+        let guard = res.rollback_guard.unwrap();
+        assert_eq!(
+            guard.hash.as_slice(),
+            blocks[9].hash.as_ref().unwrap().as_slice()
+        );
+
+        server.update_chain(|chain| chain.reorg(5));
+
+        let res = client.get(&blocks_query(40, 50)).await.unwrap();
+        let reorged = res.data.blocks.concat();
+        assert_eq!(reorged[..5], blocks[..5]);
+        assert!(reorged[5..]
+            .iter()
+            .zip(blocks[5..].iter())
+            .all(|(new, old)| new.number == old.number && new.hash != old.hash));
+        // the guard no longer matches the hash the client saw for the latest block
+        assert_ne!(
+            res.rollback_guard.unwrap().hash.as_slice(),
+            blocks[9].hash.as_ref().unwrap().as_slice()
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use hypersync_format::{AccessList, Hash};
+use polars_arrow::{
+    array::{Array, BinaryArray, ListArray, StructArray},
+    datatypes::{ArrowDataType as DataType, ArrowSchema as Schema, Field},
+    offset::OffsetsBuffer,
+};
+
+use crate::{ArrowBatch, ArrowChunk};
+
+/// Data type of the nested `access_list` column.
+pub fn access_list_dt() -> DataType {
+    DataType::LargeList(Box::new(Field::new(
+        "item",
+        DataType::Struct(vec![
+            Field::new("address", DataType::Binary, true),
+            Field::new("storage_keys", hash_list_dt(), true),
+        ]),
+        true,
+    )))
+}
+
+/// Data type of the nested `blob_versioned_hashes` column.
+pub fn hash_list_dt() -> DataType {
+    DataType::LargeList(Box::new(Field::new("item", DataType::Binary, true)))
+}
+
+/// Converts the binary encoded `access_list` and `blob_versioned_hashes` columns of a transaction
+/// batch into list columns, so their contents are preserved as structured data in outputs like
+/// parquet.
+pub fn nest_transaction_list_columns(batch: ArrowBatch) -> Result<ArrowBatch> {
+    let (fields, cols) = batch
+        .schema
+        .fields
+        .iter()
+        .zip(batch.chunk.columns().iter())
+        .map(|(field, col)| {
+            let col = match (field.name.as_str(), col.data_type()) {
+                ("access_list", DataType::Binary) => {
+                    nest_access_list(binary(&**col)).context("nest access_list")?
+                }
+                ("blob_versioned_hashes", DataType::Binary) => {
+                    nest_hashes(binary(&**col)).context("nest blob_versioned_hashes")?
+                }
+                _ => col.clone(),
+            };
+
+            Ok((
+                Field::new(
+                    field.name.clone(),
+                    col.data_type().clone(),
+                    field.is_nullable,
+                ),
+                col,
+            ))
+        })
+        .collect::<Result<(Vec<_>, Vec<_>)>>()?;
+
+    Ok(ArrowBatch {
+        chunk: Arc::new(ArrowChunk::try_new(cols).context("create arrow chunk")?),
+        schema: Arc::new(Schema::from(fields)),
+    })
+}
+
+fn binary(col: &dyn Array) -> &BinaryArray<i32> {
+    col.as_any().downcast_ref().unwrap()
+}
+
+fn nest_access_list(col: &BinaryArray<i32>) -> Result<Box<dyn Array>> {
+    let mut offsets = vec![0i64];
+    let mut addresses = Vec::new();
+    let mut key_offsets = vec![0i64];
+    let mut key_validity = Vec::new();
+    let mut keys = Vec::new();
+
+    for bytes in col.iter() {
+        if let Some(bytes) = bytes {
+            let access_list: Vec<AccessList> =
+                bincode::deserialize(bytes).context("deserialize access list")?;
+
+            for item in access_list {
+                addresses.push(item.address.map(|a| a.as_ref().to_vec()));
+                key_validity.push(item.storage_keys.is_some());
+                for key in item.storage_keys.unwrap_or_default() {
+                    keys.push(Some(key.as_ref().to_vec()));
+                }
+                key_offsets.push(keys.len() as i64);
+            }
+        }
+        offsets.push(addresses.len() as i64);
+    }
+
+    let keys = ListArray::<i64>::try_new(
+        hash_list_dt(),
+        OffsetsBuffer::try_from(key_offsets).context("create storage_keys offsets")?,
+        BinaryArray::<i32>::from(keys).boxed(),
+        Some(key_validity.into()),
+    )
+    .context("create storage_keys array")?;
+
+    let DataType::LargeList(item) = access_list_dt() else {
+        unreachable!()
+    };
+    let items = StructArray::try_new(
+        item.data_type,
+        vec![BinaryArray::<i32>::from(addresses).boxed(), keys.boxed()],
+        None,
+    )
+    .context("create access list item array")?;
+
+    let arr = ListArray::<i64>::try_new(
+        access_list_dt(),
+        OffsetsBuffer::try_from(offsets).context("create access_list offsets")?,
+        items.boxed(),
+        col.validity().cloned(),
+    )
+    .context("create access_list array")?;
+
+    Ok(arr.boxed())
+}
+
+fn nest_hashes(col: &BinaryArray<i32>) -> Result<Box<dyn Array>> {
+    let mut offsets = vec![0i64];
+    let mut hashes = Vec::new();
+
+    for bytes in col.iter() {
+        let bytes = bytes.unwrap_or_default();
+        if bytes.len() % 32 != 0 {
+            return Err(anyhow!(
+                "expected a multiple of 32 bytes but got {}",
+                bytes.len()
+            ));
+        }
+        hashes.extend(bytes.chunks(32).map(Some));
+        offsets.push(hashes.len() as i64);
+    }
+
+    let arr = ListArray::<i64>::try_new(
+        hash_list_dt(),
+        OffsetsBuffer::try_from(offsets).context("create offsets")?,
+        BinaryArray::<i32>::from(hashes).boxed(),
+        col.validity().cloned(),
+    )
+    .context("create array")?;
+
+    Ok(arr.boxed())
+}
+
+/// Reads the access list at the given row of a nested `access_list` column.
+pub fn read_access_list(col: &ListArray<i64>, idx: usize) -> Option<Vec<AccessList>> {
+    if !col.is_valid(idx) {
+        return None;
+    }
+
+    let items = col.value(idx);
+    let items = items.as_any().downcast_ref::<StructArray>().unwrap();
+    let addresses = binary(&*items.values()[0]);
+    let keys = items.values()[1]
+        .as_any()
+        .downcast_ref::<ListArray<i64>>()
+        .unwrap();
+
+    Some(
+        (0..items.len())
+            .map(|i| AccessList {
+                address: addresses.get(i).map(|a| a.try_into().unwrap()),
+                storage_keys: read_hashes(keys, i),
+            })
+            .collect(),
+    )
+}
+
+/// Reads the hashes at the given row of a nested hash list column.
+pub fn read_hashes(col: &ListArray<i64>, idx: usize) -> Option<Vec<Hash>> {
+    if !col.is_valid(idx) {
+        return None;
+    }
+
+    let hashes = col.value(idx);
+    Some(
+        binary(&*hashes)
+            .iter()
+            .flatten()
+            .map(|h| h.try_into().unwrap())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nest_transaction_list_columns() {
+        let access_list = vec![
+            AccessList {
+                address: Some([1u8; 20].into()),
+                storage_keys: Some(vec![[2u8; 32].into(), [3u8; 32].into()]),
+            },
+            AccessList {
+                address: None,
+                storage_keys: None,
+            },
+        ];
+        let encoded = bincode::serialize(&access_list).unwrap();
+        let hashes = [[4u8; 32], [5u8; 32]].concat();
+
+        let batch = ArrowBatch {
+            chunk: Arc::new(ArrowChunk::new(vec![
+                BinaryArray::<i32>::from([Some(encoded.as_slice()), None]).boxed(),
+                BinaryArray::<i32>::from([None, Some(hashes.as_slice())]).boxed(),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("access_list", DataType::Binary, true),
+                Field::new("blob_versioned_hashes", DataType::Binary, true),
+            ])),
+        };
+
+        let nested = nest_transaction_list_columns(batch).unwrap();
+        assert_eq!(nested.schema.fields[0].data_type, access_list_dt());
+        assert_eq!(nested.schema.fields[1].data_type, hash_list_dt());
+
+        let access_list_col = nested.column::<ListArray<i64>>("access_list").unwrap();
+        assert_eq!(read_access_list(access_list_col, 0), Some(access_list));
+        assert_eq!(read_access_list(access_list_col, 1), None);
+
+        let hashes_col = nested
+            .column::<ListArray<i64>>("blob_versioned_hashes")
+            .unwrap();
+        assert_eq!(read_hashes(hashes_col, 0), None);
+        assert_eq!(
+            read_hashes(hashes_col, 1),
+            Some(vec![[4u8; 32].into(), [5u8; 32].into()])
+        );
+    }
+}
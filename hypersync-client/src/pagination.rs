@@ -0,0 +1,161 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::simple_types::Event;
+
+/// Opaque position in the ordered event sequence of a query, returned by
+/// `Client::get_events_page` to continue from where the previous page stopped.
+///
+/// The string form can be handed to API clients and parsed back with `FromStr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EventCursor {
+    block_number: u64,
+    log_index: u64,
+}
+
+impl EventCursor {
+    pub(crate) fn new(block_number: u64, log_index: u64) -> Self {
+        Self {
+            block_number,
+            log_index,
+        }
+    }
+
+    /// Block the next page starts at.
+    pub fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn of(event: &Event) -> Result<Self> {
+        let block_number = event
+            .block_number()
+            .context("log.block_number missing from event")?;
+        let log_index = event
+            .log_index()
+            .context("log.log_index missing from event")?;
+        Ok(Self::new(block_number, log_index))
+    }
+}
+
+impl fmt::Display for EventCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}{:016x}", self.block_number, self.log_index)
+    }
+}
+
+impl FromStr for EventCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.len() != 32 || !s.is_ascii() {
+            return Err(anyhow!("invalid event cursor {:?}", s));
+        }
+        let block_number = u64::from_str_radix(&s[..16], 16).context("parse block number")?;
+        let log_index = u64::from_str_radix(&s[16..], 16).context("parse log index")?;
+        Ok(Self::new(block_number, log_index))
+    }
+}
+
+/// A page of events returned by `Client::get_events_page`.
+#[derive(Debug, Clone)]
+pub struct EventPage {
+    /// Events ordered by block number and log index, at most `limit` of them.
+    pub events: Vec<Event>,
+    /// Cursor to pass to get the next page. None if the end of the query range was reached.
+    ///
+    /// A cursor is also returned when the page stopped at the height of the server, so
+    /// following it later returns events from newer blocks.
+    pub next_cursor: Option<EventCursor>,
+    /// Height of the source hypersync instance when the last request of this page was served.
+    pub archive_height: Option<u64>,
+}
+
+/// Appends the events at or after `start` to the page in order.
+///
+/// Returns the cursor of the first event that didn't fit if the page got more than `limit`
+/// events, the page is truncated to `limit` in that case.
+pub(crate) fn fill_page(
+    page: &mut Vec<Event>,
+    events: impl IntoIterator<Item = Event>,
+    start: EventCursor,
+    limit: usize,
+) -> Result<Option<EventCursor>> {
+    let mut events = events
+        .into_iter()
+        .map(|e| Ok((EventCursor::of(&e)?, e)))
+        .collect::<Result<Vec<_>>>()?;
+    events.retain(|(pos, _)| *pos >= start);
+    events.sort_by_key(|(pos, _)| *pos);
+
+    let remaining = limit - page.len();
+    let next = events.get(remaining).map(|(pos, _)| *pos);
+    page.extend(events.into_iter().take(remaining).map(|(_, e)| e));
+
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(block_number: u64, log_index: u64) -> Event {
+        let mut event = Event::default();
+        event.log.block_number = Some(block_number.into());
+        event.log.log_index = Some(log_index.into());
+        event
+    }
+
+    fn positions(events: &[Event]) -> Vec<EventCursor> {
+        events.iter().map(|e| EventCursor::of(e).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let cursor = EventCursor::new(19_000_000, 42);
+        assert_eq!(cursor.to_string().parse::<EventCursor>().unwrap(), cursor);
+        assert!("12".parse::<EventCursor>().is_err());
+        assert!("zz00000000000000000000000000000z"
+            .parse::<EventCursor>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_fill_page() {
+        let start = EventCursor::new(10, 1);
+        let mut page = Vec::new();
+
+        // events before the cursor are dropped and the rest is ordered
+        let next = fill_page(
+            &mut page,
+            vec![event(11, 0), event(10, 0), event(10, 2), event(10, 1)],
+            start,
+            3,
+        )
+        .unwrap();
+        assert_eq!(
+            positions(&page),
+            vec![
+                EventCursor::new(10, 1),
+                EventCursor::new(10, 2),
+                EventCursor::new(11, 0)
+            ]
+        );
+        assert_eq!(next, None);
+
+        // continuing fills the page and points at the first event that didn't fit, even if it
+        // is in the middle of a block
+        let mut page = vec![event(10, 1)];
+        let next = fill_page(
+            &mut page,
+            vec![event(12, 0), event(12, 1), event(12, 2)],
+            start,
+            3,
+        )
+        .unwrap();
+        assert_eq!(page.len(), 3);
+        assert_eq!(next, Some(EventCursor::new(12, 2)));
+
+        assert!(fill_page(&mut Vec::new(), vec![Event::default()], start, 3).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::OpenOptions,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use parquet_format_safe::{
+    thrift::protocol::{TCompactInputProtocol, TCompactOutputProtocol},
+    BloomFilterAlgorithm, BloomFilterCompression, BloomFilterHash, BloomFilterHeader, FileMetaData,
+    SplitBlockAlgorithm, Uncompressed, XxHash,
+};
+use polars_arrow::array::{BinaryViewArray, Utf8ViewArray};
+use polars_parquet::parquet::bloom_filter::{hash_byte, insert};
+
+use crate::ArrowBatch;
+
+/// Smallest bitset allowed by the parquet spec, one block of the split block bloom filter.
+const MIN_NUM_BYTES: usize = 32;
+/// Largest bitset written, the default limit of parquet-mr.
+const MAX_NUM_BYTES: usize = 128 * 1024 * 1024;
+
+const PARQUET_MAGIC: &[u8; 4] = b"PAR1";
+
+/// Bloom filters of the columns of a row group as `(column name, bitset)`.
+pub(crate) type RowGroupFilters = Vec<(String, Vec<u8>)>;
+
+/// Checks that the false positive probabilities of the configured columns are usable.
+pub(crate) fn check_config(columns: &BTreeMap<String, f64>) -> Result<()> {
+    for (name, fpp) in columns.iter() {
+        if !(*fpp > 0.0 && *fpp < 1.0) {
+            return Err(anyhow!(
+                "false positive probability of the bloom filter of column '{}' has to be \
+                 between 0 and 1, got {}",
+                name,
+                fpp
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Builds the bloom filters of the configured columns of a row group.
+///
+/// The batch has to be converted with `map_batch_to_binary_view`. Columns that aren't binary or
+/// string columns are skipped. Bitsets are sized for the number of distinct values in the row
+/// group.
+pub(crate) fn build_filters(
+    batch: &ArrowBatch,
+    columns: &BTreeMap<String, f64>,
+) -> RowGroupFilters {
+    let mut filters = Vec::new();
+    for (field, col) in batch.schema.fields.iter().zip(batch.chunk.columns()) {
+        let Some(fpp) = columns.get(&field.name) else {
+            continue;
+        };
+        let any = col.as_any();
+        let hashes = if let Some(col) = any.downcast_ref::<BinaryViewArray>() {
+            col.iter().flatten().map(hash_byte).collect::<HashSet<_>>()
+        } else if let Some(col) = any.downcast_ref::<Utf8ViewArray>() {
+            col.iter().flatten().map(hash_byte).collect::<HashSet<_>>()
+        } else {
+            continue;
+        };
+
+        let mut bitset = vec![0; optimal_num_bytes(hashes.len(), *fpp)];
+        for hash in hashes {
+            insert(&mut bitset, hash);
+        }
+        filters.push((field.name.clone(), bitset));
+    }
+    filters
+}
+
+/// Size of the bitset that keeps the false positive rate at `fpp` for `ndv` distinct values,
+/// with the formula of the parquet spec rounded up to a power of two.
+fn optimal_num_bytes(ndv: usize, fpp: f64) -> usize {
+    let num_bits = -8.0 * ndv as f64 / (1.0 - fpp.powf(1.0 / 8.0)).ln();
+    let num_bytes = (num_bits / 8.0).ceil() as usize;
+    num_bytes
+        .clamp(MIN_NUM_BYTES, MAX_NUM_BYTES)
+        .next_power_of_two()
+}
+
+/// Adds bloom filters to a finished parquet file, one list of filters per row group in the
+/// order the row groups were written.
+///
+/// The parquet writer can't write bloom filters, so the footer is read back, the filters are
+/// written in its place and a new footer pointing at them is appended.
+pub(crate) fn append_to_file(path: &Path, row_groups: &[RowGroupFilters]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .context("open parquet file")?;
+
+    let file_len = file.metadata().context("get file metadata")?.len();
+    let mut tail = [0; 8];
+    file.seek(SeekFrom::End(-8)).context("seek to footer")?;
+    file.read_exact(&mut tail).context("read footer length")?;
+    if &tail[4..] != PARQUET_MAGIC {
+        return Err(anyhow!("parquet file doesn't end with the magic bytes"));
+    }
+    let footer_len = u32::from_le_bytes(tail[..4].try_into().unwrap()) as u64;
+    let footer_start = file_len
+        .checked_sub(8 + footer_len)
+        .context("footer length is larger than the file")?;
+
+    let mut footer = vec![0; footer_len as usize];
+    file.seek(SeekFrom::Start(footer_start))
+        .context("seek to footer")?;
+    file.read_exact(&mut footer).context("read footer")?;
+    let mut prot = TCompactInputProtocol::new(footer.as_slice(), footer.len() * 2 + 1024);
+    let mut metadata = FileMetaData::read_from_in_protocol(&mut prot).context("parse footer")?;
+
+    if metadata.row_groups.len() != row_groups.len() {
+        return Err(anyhow!(
+            "parquet file has {} row groups but bloom filters were built for {}",
+            metadata.row_groups.len(),
+            row_groups.len()
+        ));
+    }
+
+    let mut out = Vec::new();
+    for (row_group, filters) in metadata.row_groups.iter_mut().zip(row_groups) {
+        for (name, bitset) in filters {
+            let column = row_group
+                .columns
+                .iter_mut()
+                .find(|c| {
+                    c.meta_data
+                        .as_ref()
+                        .is_some_and(|m| m.path_in_schema == [name.as_str()])
+                })
+                .with_context(|| format!("find column chunk of {}", name))?;
+
+            let header = BloomFilterHeader::new(
+                bitset.len().try_into().unwrap(),
+                BloomFilterAlgorithm::BLOCK(SplitBlockAlgorithm::new()),
+                BloomFilterHash::XXHASH(XxHash::new()),
+                BloomFilterCompression::UNCOMPRESSED(Uncompressed::new()),
+            );
+            let offset = footer_start + out.len() as u64;
+            header
+                .write_to_out_protocol(&mut TCompactOutputProtocol::new(&mut out))
+                .context("write bloom filter header")?;
+            out.extend_from_slice(bitset);
+            column.meta_data.as_mut().unwrap().bloom_filter_offset =
+                Some(offset.try_into().unwrap());
+        }
+    }
+
+    let mut new_footer = Vec::new();
+    metadata
+        .write_to_out_protocol(&mut TCompactOutputProtocol::new(&mut new_footer))
+        .context("write footer")?;
+    out.extend_from_slice(&new_footer);
+    out.extend_from_slice(&u32::try_from(new_footer.len()).unwrap().to_le_bytes());
+    out.extend_from_slice(PARQUET_MAGIC);
+
+    file.set_len(footer_start).context("truncate footer")?;
+    file.seek(SeekFrom::Start(footer_start))
+        .context("seek to end of row groups")?;
+    file.write_all(&out).context("write bloom filters")?;
+    file.sync_all().context("sync parquet file")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optimal_num_bytes() {
+        assert_eq!(optimal_num_bytes(0, 0.01), MIN_NUM_BYTES);
+        // ~9.6 bits per value at a 1% false positive rate
+        assert_eq!(optimal_num_bytes(10_000, 0.01), 16 * 1024);
+        assert!(optimal_num_bytes(10_000, 0.001) > optimal_num_bytes(10_000, 0.01));
+        assert_eq!(optimal_num_bytes(usize::MAX / 64, 0.01), MAX_NUM_BYTES);
+    }
+
+    #[test]
+    fn test_check_config() {
+        check_config(&BTreeMap::from([("address".to_owned(), 0.01)])).unwrap();
+        assert!(check_config(&BTreeMap::from([("address".to_owned(), 0.0)])).is_err());
+        assert!(check_config(&BTreeMap::from([("address".to_owned(), 1.0)])).is_err());
+    }
+}
@@ -1,10 +1,18 @@
-use std::{collections::VecDeque, path::PathBuf, sync::Arc, time::Instant};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+    time::Instant,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use hypersync_net_types::Query;
 use hypersync_schema::concat_chunks;
-use polars_arrow::{datatypes::ArrowSchema as Schema, legacy::error::PolarsError};
-use polars_parquet::parquet::write::FileStreamer;
+use polars_arrow::{
+    datatypes::{ArrowSchema as Schema, Field},
+    legacy::error::PolarsError,
+};
+use polars_parquet::parquet::{metadata::KeyValue, write::FileStreamer};
 use polars_parquet::write::StatisticsOptions;
 use polars_parquet::{
     read::ParquetError,
@@ -14,44 +22,155 @@ use polars_parquet::{
         RowGroupIterColumns as RowGroupIter, WriteOptions,
     },
 };
+use serde::{Deserialize, Serialize};
 use tokio::{sync::mpsc, task::JoinHandle};
 use tokio_util::compat::TokioAsyncReadCompatExt;
 
 use crate::{
-    config::StreamConfig, rayon_async, util::map_batch_to_binary_view, ArrowBatch, Client,
+    checkpoint::{resume_query, CheckpointStore},
+    column_stats::{StatsCollector, TableStats},
+    config::{ParquetConfig, ParquetEncoding, StreamConfig},
+    export_metadata::export_metadata,
+    parquet_bloom, rayon_async, stream,
+    time_range::resolve_time_range,
+    util::{group_rows_by_address, map_batch_to_binary_view, take_rows},
+    ArrowBatch, ArrowChunk, Client,
 };
 
+/// Written as `manifest.json` into the output directory once a parquet export has finished.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportManifest {
+    /// First block of the export.
+    pub from_block: u64,
+    /// Block the export stopped at, exclusive.
+    pub next_block: u64,
+    /// Names of the written files.
+    pub files: Vec<String>,
+    /// Unix timestamp in seconds of the start of the export.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export_timestamp: Option<u64>,
+    /// Statistics of each table, if `StreamConfig::collect_column_stats` was set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column_stats: Option<BTreeMap<String, TableStats>>,
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(path = %path, from_block = query.from_block, to_block = ?query.to_block)
+    )
+)]
 pub async fn collect_parquet(
     client: Arc<Client>,
     path: &str,
-    query: Query,
-    config: StreamConfig,
+    mut query: Query,
+    mut config: StreamConfig,
 ) -> Result<()> {
+    // resolved here since the manifest records the block range
+    resolve_time_range(&client, &mut query, &mut config)
+        .await
+        .context("resolve time range")?;
+
+    stream::check_skip_failed_ranges(&config)?;
+
+    // the stream would store every response but the files are only committed once finished
+    let checkpoint = config.checkpoint.take();
+    if let Some(checkpoint) = checkpoint.as_deref() {
+        if !resume_query(&mut query, checkpoint, config.reverse.unwrap_or_default()).await? {
+            log::info!("checkpoint is already at the end of the query, nothing to export");
+            return Ok(());
+        }
+    }
+
+    parquet_bloom::check_config(&config.parquet.bloom_filters)?;
+    if config.parquet.partition_by_address && !query.field_selection.log.contains("address") {
+        return Err(anyhow!(
+            "config.parquet.partition_by_address needs the address field of logs to be selected"
+        ));
+    }
+
     let path = PathBuf::from(path);
 
     tokio::fs::create_dir_all(&path)
         .await
         .context("create parquet dir")?;
 
+    let parquet_cfg = Arc::new(config.parquet.clone());
+    let metadata = Arc::new(
+        export_metadata(&client, &query, config.export_timestamp)
+            .await
+            .context("get export metadata")?,
+    );
+
     let mut blocks_path = path.clone();
     blocks_path.push("blocks.parquet");
-    let (mut blocks_sender, blocks_join) = spawn_writer(blocks_path)?;
+    let (mut blocks_sender, blocks_join) =
+        spawn_writer(blocks_path, parquet_cfg.clone(), metadata.clone())?;
 
     let mut transactions_path = path.clone();
     transactions_path.push("transactions.parquet");
-    let (mut transactions_sender, transactions_join) = spawn_writer(transactions_path)?;
+    let (mut transactions_sender, transactions_join) =
+        spawn_writer(transactions_path, parquet_cfg.clone(), metadata.clone())?;
 
     let mut logs_path = path.clone();
     logs_path.push("logs.parquet");
-    let (mut logs_sender, logs_join) = spawn_writer(logs_path)?;
+    let (mut logs_sender, logs_join) =
+        spawn_writer(logs_path, parquet_cfg.clone(), metadata.clone())?;
 
     let mut traces_path = path.clone();
     traces_path.push("traces.parquet");
-    let (mut traces_sender, traces_join) = spawn_writer(traces_path)?;
+    let (mut traces_sender, traces_join) =
+        spawn_writer(traces_path, parquet_cfg.clone(), metadata.clone())?;
 
     let mut decoded_logs_path = path.clone();
     decoded_logs_path.push("decoded_logs.parquet");
-    let (mut decoded_logs_sender, decoded_logs_join) = spawn_writer(decoded_logs_path)?;
+    let (mut decoded_logs_sender, decoded_logs_join) =
+        spawn_writer(decoded_logs_path, parquet_cfg.clone(), metadata.clone())?;
+
+    let trace_summary_writer = if config.trace_summary {
+        let mut trace_summary_path = path.clone();
+        trace_summary_path.push("trace_summary.parquet");
+        Some(spawn_writer(
+            trace_summary_path,
+            parquet_cfg.clone(),
+            metadata.clone(),
+        )?)
+    } else {
+        None
+    };
+
+    let mut address_partitions = config
+        .parquet
+        .partition_by_address
+        .then(|| AddressPartitions {
+            path: path.clone(),
+            parquet_cfg: parquet_cfg.clone(),
+            metadata: metadata.clone(),
+            max_partitions: config
+                .parquet
+                .max_address_partitions
+                .unwrap_or(DEFAULT_MAX_ADDRESS_PARTITIONS),
+            has_decoded_logs: config.event_signature.is_some(),
+            writers: BTreeMap::new(),
+        });
+
+    let mut route_writers = Vec::with_capacity(config.event_routes.len());
+    for name in config.event_routes.keys() {
+        check_route_name(name)?;
+
+        let mut route_path = path.clone();
+        route_path.push(format!("{}.parquet", name));
+        let (sender, join) = spawn_writer(route_path, parquet_cfg.clone(), metadata.clone())?;
+        route_writers.push((name.clone(), sender, join));
+    }
+
+    let metrics = config.metrics.clone();
+    let unordered = config.unordered;
+    let from_block = query.from_block;
+    let mut next_block = from_block;
+    let mut stats = config.collect_column_stats.then(StatsCollector::default);
 
     let mut rx = client
         .stream_arrow(query, config)
@@ -59,9 +178,43 @@ pub async fn collect_parquet(
         .context("start stream")?;
 
     while let Some(resp) = rx.recv().await {
-        let resp = resp.context("get query response")?;
+        let mut resp = resp.context("get query response")?;
 
         log::trace!("got data up to block {}", resp.next_block);
+        next_block = crate::end_of_responses(next_block, resp.next_block, unordered);
+        if let Some(stats) = stats.as_mut() {
+            let data = &resp.data;
+            let tables = [
+                ("blocks", &data.blocks),
+                ("transactions", &data.transactions),
+                ("logs", &data.logs),
+                ("traces", &data.traces),
+                ("decoded_logs", &data.decoded_logs),
+                ("trace_summary", &data.trace_summary),
+            ];
+            for (table, batches) in tables.into_iter().chain(
+                data.decoded_events
+                    .iter()
+                    .map(|(name, b)| (name.as_str(), b)),
+            ) {
+                for batch in batches {
+                    stats.observe(table, batch);
+                }
+            }
+        }
+        if let Some(progress) = metrics.as_ref().and_then(|m| m.progress()) {
+            log::debug!("{}", progress);
+        }
+
+        if let Some(partitions) = address_partitions.as_mut() {
+            // the top level writers get nothing so they don't create their files
+            let logs = std::mem::take(&mut resp.data.logs);
+            let decoded_logs = std::mem::take(&mut resp.data.decoded_logs);
+            partitions
+                .write(&logs, &decoded_logs)
+                .await
+                .context("write address partitions")?;
+        }
 
         let blocks_fut = async move {
             for batch in resp.data.blocks {
@@ -121,6 +274,9 @@ pub async fn collect_parquet(
             Ok::<_, anyhow::Error>(decoded_logs_sender)
         };
 
+        let decoded_events = resp.data.decoded_events;
+        let trace_summary = resp.data.trace_summary;
+
         let start = Instant::now();
 
         (
@@ -133,7 +289,31 @@ pub async fn collect_parquet(
             .await
             .context("write to parquet")?;
 
+        if let Some((sender, _)) = trace_summary_writer.as_ref() {
+            for batch in trace_summary {
+                sender
+                    .send(batch)
+                    .await
+                    .context("write trace_summary chunk to parquet")?;
+            }
+        }
+
+        for (name, sender, _) in route_writers.iter() {
+            for batch in decoded_events.get(name).into_iter().flatten() {
+                sender
+                    .send(batch.clone())
+                    .await
+                    .with_context(|| format!("write {} chunk to parquet", name))?;
+            }
+        }
+
         log::trace!("wrote to parquet in {} ms", start.elapsed().as_millis());
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            next_block,
+            elapsed_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+            "wrote response to parquet"
+        );
     }
 
     std::mem::drop(blocks_sender);
@@ -163,14 +343,192 @@ pub async fn collect_parquet(
         .context("join decoded_logs task")?
         .context("finish decoded_logs file")?;
 
+    let has_trace_summary = trace_summary_writer.is_some();
+    if let Some((sender, join)) = trace_summary_writer {
+        std::mem::drop(sender);
+        join.await
+            .context("join trace_summary task")?
+            .context("finish trace_summary file")?;
+    }
+
+    let route_names = route_writers
+        .iter()
+        .map(|(name, _, _)| name.clone())
+        .collect::<Vec<_>>();
+    for (name, sender, join) in route_writers {
+        std::mem::drop(sender);
+        join.await
+            .with_context(|| format!("join {} task", name))?
+            .with_context(|| format!("finish {} file", name))?;
+    }
+
+    let partition_files = match address_partitions {
+        Some(partitions) => Some(partitions.finish().await?),
+        None => None,
+    };
+
+    let mut files = ["blocks", "transactions", "logs", "traces", "decoded_logs"]
+        .into_iter()
+        .filter(|name| partition_files.is_none() || !["logs", "decoded_logs"].contains(name))
+        .map(|name| format!("{}.parquet", name))
+        .collect::<Vec<_>>();
+    files.extend(partition_files.into_iter().flatten());
+    if has_trace_summary {
+        files.push("trace_summary.parquet".to_owned());
+    }
+    files.extend(route_names.iter().map(|name| format!("{}.parquet", name)));
+
+    let manifest = ExportManifest {
+        from_block,
+        next_block,
+        files,
+        export_timestamp: metadata
+            .get("hypersync.export_timestamp")
+            .and_then(|timestamp| timestamp.parse().ok()),
+        column_stats: stats.map(StatsCollector::finish),
+    };
+    CheckpointStore::new(path.join("manifest.json"))
+        .save(&manifest)
+        .await
+        .context("write manifest")?;
+    if let Some(checkpoint) = checkpoint {
+        checkpoint
+            .save_next_block(next_block)
+            .await
+            .context("save checkpoint")?;
+    }
+
     Ok(())
 }
 
-fn spawn_writer(path: PathBuf) -> Result<(mpsc::Sender<ArrowBatch>, JoinHandle<Result<()>>)> {
+const DEFAULT_MAX_ADDRESS_PARTITIONS: usize = 1000;
+
+type Writer = (mpsc::Sender<ArrowBatch>, JoinHandle<Result<()>>);
+
+/// Writers of the logs and decoded_logs of an export partitioned by contract address, opened as
+/// new addresses show up.
+struct AddressPartitions {
+    path: PathBuf,
+    parquet_cfg: Arc<ParquetConfig>,
+    metadata: Arc<BTreeMap<String, String>>,
+    max_partitions: usize,
+    has_decoded_logs: bool,
+    /// Writers of logs and decoded_logs keyed by address.
+    writers: BTreeMap<String, (Writer, Option<Writer>)>,
+}
+
+impl AddressPartitions {
+    async fn write(&mut self, logs: &[ArrowBatch], decoded_logs: &[ArrowBatch]) -> Result<()> {
+        for (i, batch) in logs.iter().enumerate() {
+            let decoded = decoded_logs.get(i);
+            if decoded.is_some_and(|decoded| decoded.chunk.len() != batch.chunk.len()) {
+                return Err(anyhow!("decoded_logs don't line up with logs"));
+            }
+
+            for (address, rows) in group_rows_by_address(batch)? {
+                let (logs_writer, decoded_writer) = self.writer(&address).await?;
+                logs_writer
+                    .0
+                    .send(take_rows(batch, &rows)?)
+                    .await
+                    .context("write logs chunk to parquet")?;
+                if let (Some(decoded), Some(decoded_writer)) = (decoded, decoded_writer) {
+                    decoded_writer
+                        .0
+                        .send(take_rows(decoded, &rows)?)
+                        .await
+                        .context("write decoded_logs chunk to parquet")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn writer(&mut self, address: &str) -> Result<&(Writer, Option<Writer>)> {
+        if !self.writers.contains_key(address) {
+            if self.writers.len() >= self.max_partitions {
+                return Err(anyhow!(
+                    "export has more than {} addresses, raise \
+                     config.parquet.max_address_partitions to write them all",
+                    self.max_partitions
+                ));
+            }
+            let logs = self.spawn_writer("logs", address).await?;
+            let decoded_logs = if self.has_decoded_logs {
+                Some(self.spawn_writer("decoded_logs", address).await?)
+            } else {
+                None
+            };
+            self.writers
+                .insert(address.to_owned(), (logs, decoded_logs));
+        }
+        Ok(&self.writers[address])
+    }
+
+    async fn spawn_writer(&self, table: &str, address: &str) -> Result<Writer> {
+        let dir = self.path.join(table).join(format!("address={}", address));
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .context("create partition dir")?;
+        spawn_writer(
+            dir.join("data.parquet"),
+            self.parquet_cfg.clone(),
+            self.metadata.clone(),
+        )
+    }
+
+    /// Finishes the files of all partitions and returns their paths relative to the export.
+    async fn finish(self) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+        for (address, (logs, decoded_logs)) in self.writers {
+            for (table, (sender, join)) in [("logs", Some(logs)), ("decoded_logs", decoded_logs)]
+                .into_iter()
+                .filter_map(|(table, writer)| Some((table, writer?)))
+            {
+                std::mem::drop(sender);
+                join.await
+                    .with_context(|| format!("join {} task of {}", table, address))?
+                    .with_context(|| format!("finish {} file of {}", table, address))?;
+                files.push(format!("{}/address={}/data.parquet", table, address));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+fn check_route_name(name: &str) -> Result<()> {
+    const RESERVED: &[&str] = &[
+        "blocks",
+        "transactions",
+        "logs",
+        "traces",
+        "decoded_logs",
+        "trace_summary",
+    ];
+
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err(anyhow!("invalid event route name '{}'", name));
+    }
+    if RESERVED.contains(&name) {
+        return Err(anyhow!(
+            "event route name '{}' conflicts with a built-in table",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+fn spawn_writer(
+    path: PathBuf,
+    parquet_cfg: Arc<ParquetConfig>,
+    metadata: Arc<BTreeMap<String, String>>,
+) -> Result<(mpsc::Sender<ArrowBatch>, JoinHandle<Result<()>>)> {
     let (tx, rx) = mpsc::channel(64);
 
     let handle = tokio::task::spawn(async move {
-        match run_writer(rx, path).await {
+        match run_writer(rx, path, parquet_cfg, metadata).await {
             Ok(v) => Ok(v),
             Err(e) => {
                 log::error!("failed to run parquet writer: {:?}", e);
@@ -182,7 +540,17 @@ fn spawn_writer(path: PathBuf) -> Result<(mpsc::Sender<ArrowBatch>, JoinHandle<R
     Ok((tx, handle))
 }
 
-async fn run_writer(mut rx: mpsc::Receiver<ArrowBatch>, path: PathBuf) -> Result<()> {
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(path = %path.display()))
+)]
+async fn run_writer(
+    mut rx: mpsc::Receiver<ArrowBatch>,
+    path: PathBuf,
+    parquet_cfg: Arc<ParquetConfig>,
+    metadata: Arc<BTreeMap<String, String>>,
+) -> Result<()> {
+    let file_path = path.clone();
     let make_writer = move |schema: &Schema| {
         let schema = schema.clone();
         let path = path.clone();
@@ -212,6 +580,7 @@ async fn run_writer(mut rx: mpsc::Receiver<ArrowBatch>, path: PathBuf) -> Result
     let num_cpus = num_cpus::get();
     let mut encode_jobs = VecDeque::<EncodeFut>::with_capacity(num_cpus);
 
+    let mut bloom_filters = Vec::new();
     let mut data = Vec::new();
     let mut total_rows = 0;
     loop {
@@ -223,11 +592,10 @@ async fn run_writer(mut rx: mpsc::Receiver<ArrowBatch>, path: PathBuf) -> Result
             stop = true;
         }
 
-        if !data.is_empty() && (stop || total_rows >= ROW_GROUP_MAX_ROWS) {
-            let batches = std::mem::take(&mut data);
+        while !data.is_empty() && (stop || total_rows >= ROW_GROUP_MAX_ROWS) {
             if encode_jobs.len() >= num_cpus {
                 let fut = encode_jobs.pop_front().unwrap();
-                let (rg, schema) = fut
+                let (rg, schema, filters) = fut
                     .await
                     .context("join prepare task")?
                     .context("prepare row group")?;
@@ -240,21 +608,19 @@ async fn run_writer(mut rx: mpsc::Receiver<ArrowBatch>, path: PathBuf) -> Result
                     .write(rg)
                     .await
                     .context("write encoded row group to file")?;
+                bloom_filters.push(filters);
             }
 
-            total_rows = 0;
-            let schema = batches[0].schema.clone();
-            let chunks = batches.into_iter().map(|b| b.chunk).collect::<Vec<_>>();
-            let chunk = concat_chunks(chunks.as_slice()).context("concat chunks")?;
-            let batch = ArrowBatch {
-                chunk: Arc::new(chunk),
-                schema: schema.clone(),
-            };
+            let batch = take_row_group(&mut data, &mut total_rows)?;
+            let schema = batch.schema.clone();
             let batch = map_batch_to_binary_view(batch);
 
+            let parquet_cfg = parquet_cfg.clone();
             let fut = rayon_async::spawn(move || {
+                let filters = parquet_bloom::build_filters(&batch, &parquet_cfg.bloom_filters);
                 let rg = encode_row_group(
                     batch,
+                    &parquet_cfg,
                     WriteOptions {
                         statistics: StatisticsOptions::default(),
                         version: polars_parquet::write::Version::V2,
@@ -264,7 +630,7 @@ async fn run_writer(mut rx: mpsc::Receiver<ArrowBatch>, path: PathBuf) -> Result
                 )
                 .context("encode row group")?;
 
-                Ok((rg, schema))
+                Ok((rg, schema, filters))
             });
 
             encode_jobs.push_back(fut);
@@ -276,7 +642,7 @@ async fn run_writer(mut rx: mpsc::Receiver<ArrowBatch>, path: PathBuf) -> Result
     }
 
     while let Some(fut) = encode_jobs.pop_front() {
-        let (rg, schema) = fut
+        let (rg, schema, filters) = fut
             .await
             .context("join prepare task")?
             .context("prepare row group")?;
@@ -289,15 +655,85 @@ async fn run_writer(mut rx: mpsc::Receiver<ArrowBatch>, path: PathBuf) -> Result
             .write(rg)
             .await
             .context("write encoded row group to file")?;
+        bloom_filters.push(filters);
     }
 
     if let Some(writer) = writer.as_mut() {
-        let _size = writer.end(None).await.context("write footer")?;
+        let key_value_metadata = metadata
+            .iter()
+            .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+            .collect();
+        let _size = writer
+            .end(Some(key_value_metadata))
+            .await
+            .context("write footer")?;
+
+        if bloom_filters.iter().any(|filters| !filters.is_empty()) {
+            tokio::task::spawn_blocking(move || {
+                parquet_bloom::append_to_file(&file_path, &bloom_filters)
+            })
+            .await
+            .context("join bloom filter task")?
+            .context("append bloom filters")?;
+        }
     }
 
     Ok(())
 }
 
+/// Takes the first `ROW_GROUP_MAX_ROWS` buffered rows as one batch and keeps the rest buffered.
+///
+/// Row groups are cut at fixed row counts instead of at response boundaries, since the block
+/// ranges of the responses change with the batch size adjustments and concurrency of the stream.
+/// This keeps the files of the same export identical.
+fn take_row_group(data: &mut Vec<ArrowBatch>, total_rows: &mut usize) -> Result<ArrowBatch> {
+    let schema = data[0].schema.clone();
+    let slice = |chunk: &ArrowChunk, offset: usize, len: usize| {
+        Arc::new(ArrowChunk::new(
+            chunk
+                .arrays()
+                .iter()
+                .map(|col| col.sliced(offset, len))
+                .collect(),
+        ))
+    };
+
+    // only the rows of the row group are concatenated, the rest stay buffered as they are so
+    // each row is copied once however many row groups are buffered
+    let mut chunks = Vec::new();
+    let mut num_rows = 0;
+    for batch in data.iter() {
+        if num_rows == ROW_GROUP_MAX_ROWS {
+            break;
+        }
+        let len = batch.chunk.len().min(ROW_GROUP_MAX_ROWS - num_rows);
+        chunks.push(if len == batch.chunk.len() {
+            batch.chunk.clone()
+        } else {
+            slice(&batch.chunk, 0, len)
+        });
+        num_rows += len;
+    }
+    let num_batches = chunks.len();
+    let last = &data[num_batches - 1];
+    let taken_of_last = chunks[num_batches - 1].len();
+    let rest = (taken_of_last < last.chunk.len()).then(|| ArrowBatch {
+        chunk: slice(&last.chunk, taken_of_last, last.chunk.len() - taken_of_last),
+        schema: last.schema.clone(),
+    });
+    data.drain(..num_batches);
+    if let Some(rest) = rest {
+        data.insert(0, rest);
+    }
+    *total_rows -= num_rows;
+
+    let chunk = concat_chunks(chunks.as_slice()).context("concat chunks")?;
+    Ok(ArrowBatch {
+        chunk: Arc::new(chunk),
+        schema,
+    })
+}
+
 type EncodeFut = tokio::sync::oneshot::Receiver<
     Result<(
         DynIter<
@@ -308,11 +744,13 @@ type EncodeFut = tokio::sync::oneshot::Receiver<
             >,
         >,
         Arc<Schema>,
+        parquet_bloom::RowGroupFilters,
     )>,
 >;
 
 fn encode_row_group(
     batch: ArrowBatch,
+    parquet_cfg: &ParquetConfig,
     write_options: WriteOptions,
 ) -> Result<RowGroupIter<'static, PolarsError>> {
     let fields = batch
@@ -325,7 +763,10 @@ fn encode_row_group(
         .schema
         .fields
         .iter()
-        .map(|f| transverse(&f.data_type, |_| Encoding::Plain))
+        .map(|f| match column_encoding(parquet_cfg, f) {
+            Some(encoding) => vec![encoding],
+            None => transverse(&f.data_type, |_| Encoding::Plain),
+        })
         .collect::<Vec<_>>();
 
     let data = batch
@@ -366,6 +807,59 @@ fn encode_row_group(
     Ok(DynIter::new(data.into_iter()))
 }
 
+/// Integer columns that increase monotonically within a row group and so are delta encoded by
+/// default.
+const SEQUENCE_COLUMNS: &[&str] = &[
+    "block_number",
+    "number",
+    "log_index",
+    "transaction_index",
+    "transaction_position",
+];
+
+/// Returns the encoding to write a non-nested column with, None for nested columns.
+fn column_encoding(parquet_cfg: &ParquetConfig, field: &Field) -> Option<Encoding> {
+    use polars_arrow::datatypes::ArrowDataType as DT;
+
+    let data_type = field.data_type.to_logical_type();
+    if let DT::Dictionary(..) = data_type {
+        return Some(Encoding::RleDictionary);
+    }
+    let is_integer = matches!(
+        data_type,
+        DT::Int8
+            | DT::Int16
+            | DT::Int32
+            | DT::Int64
+            | DT::UInt8
+            | DT::UInt16
+            | DT::UInt32
+            | DT::UInt64
+    );
+    let is_binary = matches!(
+        data_type,
+        DT::Binary | DT::LargeBinary | DT::BinaryView | DT::Utf8 | DT::LargeUtf8 | DT::Utf8View
+    );
+    if !is_integer && !is_binary && !matches!(data_type, DT::Boolean | DT::Float32 | DT::Float64) {
+        return None;
+    }
+
+    let requested = match parquet_cfg.column_encodings.get(&field.name) {
+        Some(encoding) => *encoding,
+        None if is_integer && SEQUENCE_COLUMNS.contains(&field.name.as_str()) => {
+            ParquetEncoding::DeltaBinaryPacked
+        }
+        None => ParquetEncoding::Plain,
+    };
+
+    Some(match requested {
+        ParquetEncoding::DeltaBinaryPacked if is_integer => Encoding::DeltaBinaryPacked,
+        ParquetEncoding::DeltaLengthByteArray if is_binary => Encoding::DeltaLengthByteArray,
+        ParquetEncoding::Dictionary if is_integer || is_binary => Encoding::RleDictionary,
+        _ => Encoding::Plain,
+    })
+}
+
 struct CompressedPageIter {
     data: std::vec::IntoIter<std::result::Result<CompressedPage, PolarsError>>,
     current: Option<CompressedPage>,
@@ -389,3 +883,372 @@ impl FallibleStreamingIterator for CompressedPageIter {
 }
 
 const ROW_GROUP_MAX_ROWS: usize = 10_000;
+
+#[cfg(test)]
+mod tests {
+    use polars_arrow::{
+        array::{
+            BinaryArray, BinaryViewArray, DictionaryArray, UInt64Array, Utf8Array, Utf8ViewArray,
+        },
+        datatypes::ArrowDataType as DataType,
+        record_batch::RecordBatchT as Chunk,
+    };
+
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::mock_server::{mock_client, mock_logs, transfers_query, MockChain, MockServer};
+
+    /// Block numbers of logs, several logs per block with some blocks having none.
+    fn log_block_numbers() -> Vec<u64> {
+        let mut state = 1u64;
+        let mut block_number = 18_000_000;
+        (0..50_000)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                block_number += (state >> 62) % 3;
+                block_number
+            })
+            .collect()
+    }
+
+    async fn write_block_numbers(path: PathBuf, parquet_cfg: ParquetConfig) -> u64 {
+        let metadata = BTreeMap::from([("hypersync.chain_id".to_owned(), "1".to_owned())]);
+        let (tx, join) =
+            spawn_writer(path.clone(), Arc::new(parquet_cfg), Arc::new(metadata)).unwrap();
+        let col = UInt64Array::from_vec(log_block_numbers());
+        tx.send(ArrowBatch {
+            chunk: Arc::new(Chunk::new(vec![col.boxed()])),
+            schema: Arc::new(Schema::from(vec![Field::new(
+                "block_number",
+                DataType::UInt64,
+                false,
+            )])),
+        })
+        .await
+        .unwrap();
+        drop(tx);
+        join.await.unwrap().unwrap();
+        std::fs::metadata(&path).unwrap().len()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_delta_encoded_block_numbers() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let delta_path = dir.join("delta.parquet");
+        let delta_size = write_block_numbers(delta_path.clone(), ParquetConfig::default()).await;
+        let plain_size = write_block_numbers(
+            dir.join("plain.parquet"),
+            ParquetConfig {
+                column_encodings: [("block_number".to_owned(), ParquetEncoding::Plain)]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(delta_size * 4 < plain_size);
+
+        let mut file = std::fs::File::open(&delta_path).unwrap();
+        let metadata = polars_parquet::read::read_metadata(&mut file).unwrap();
+        assert_eq!(
+            metadata.key_value_metadata,
+            Some(vec![KeyValue::new(
+                "hypersync.chain_id".to_owned(),
+                "1".to_owned()
+            )])
+        );
+        let schema = polars_parquet::read::infer_schema(&metadata).unwrap();
+        let reader = polars_parquet::read::FileReader::new(file, metadata.row_groups, schema, None);
+        let mut values = Vec::new();
+        for chunk in reader {
+            let chunk = chunk.unwrap();
+            let col = chunk.columns()[0]
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .unwrap();
+            values.extend(col.values_iter().copied());
+        }
+        assert_eq!(values, log_block_numbers());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dictionary_columns() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        let call_types = ["call", "delegatecall", "staticcall"];
+        let traces = ArrowBatch {
+            chunk: Arc::new(Chunk::new(vec![Utf8Array::<i32>::from_iter_values(
+                (0..30_000).map(|i| call_types[i % 7 % 3]),
+            )
+            .boxed()])),
+            schema: Arc::new(Schema::from(vec![Field::new(
+                "call_type",
+                DataType::Utf8,
+                true,
+            )])),
+        };
+        let mapping = BTreeMap::from([("call_type".to_owned(), crate::DataType::Dictionary)]);
+        let dictionary = crate::column_mapping::apply_to_batch(&traces, &mapping).unwrap();
+        let col = dictionary.chunk.columns()[0]
+            .as_any()
+            .downcast_ref::<DictionaryArray<u32>>()
+            .unwrap();
+        assert_eq!(col.values().len(), 3);
+
+        let mut sizes = Vec::new();
+        for (name, batch) in [("plain", traces), ("dictionary", dictionary)] {
+            let path = dir.join(format!("{}.parquet", name));
+            let (tx, join) =
+                spawn_writer(path.clone(), Default::default(), Default::default()).unwrap();
+            // the two batches are concatenated into row groups
+            tx.send(batch.clone()).await.unwrap();
+            tx.send(batch).await.unwrap();
+            drop(tx);
+            join.await.unwrap().unwrap();
+            sizes.push(std::fs::metadata(&path).unwrap().len());
+
+            let mut file = std::fs::File::open(&path).unwrap();
+            let metadata = polars_parquet::read::read_metadata(&mut file).unwrap();
+            let schema = polars_parquet::read::infer_schema(&metadata).unwrap();
+            let reader =
+                polars_parquet::read::FileReader::new(file, metadata.row_groups, schema, None);
+            let mut values = Vec::new();
+            for chunk in reader {
+                let col = polars_arrow::compute::cast::cast(
+                    &*chunk.unwrap().columns()[0],
+                    &DataType::Utf8View,
+                    Default::default(),
+                )
+                .unwrap();
+                let col = col.as_any().downcast_ref::<Utf8ViewArray>().unwrap();
+                values.extend(col.values_iter().map(str::to_owned));
+            }
+            assert_eq!(values.len(), 60_000);
+            assert!(values
+                .iter()
+                .enumerate()
+                .all(|(i, value)| value == call_types[i % 30_000 % 7 % 3]));
+        }
+        assert!(sizes[1] < sizes[0]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_address_partitions() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let addresses = [[1u8; 20], [2; 20], [1; 20], [3; 20]];
+        let logs = ArrowBatch {
+            chunk: Arc::new(Chunk::new(vec![
+                UInt64Array::from_slice([10, 11, 12, 13]).boxed(),
+                BinaryArray::<i32>::from_iter_values(addresses.iter()).boxed(),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("block_number", DataType::UInt64, false),
+                Field::new("address", DataType::Binary, false),
+            ])),
+        };
+        let decoded_logs = ArrowBatch {
+            chunk: Arc::new(Chunk::new(vec![
+                UInt64Array::from_slice([0, 1, 2, 3]).boxed()
+            ])),
+            schema: Arc::new(Schema::from(vec![Field::new(
+                "value",
+                DataType::UInt64,
+                true,
+            )])),
+        };
+        let partitions = |path: PathBuf, max_partitions| AddressPartitions {
+            path,
+            parquet_cfg: Default::default(),
+            metadata: Default::default(),
+            max_partitions,
+            has_decoded_logs: true,
+            writers: BTreeMap::new(),
+        };
+
+        let mut limited = partitions(dir.join("limited"), 2);
+        assert!(limited
+            .write(
+                std::slice::from_ref(&logs),
+                std::slice::from_ref(&decoded_logs)
+            )
+            .await
+            .is_err());
+
+        let mut partitions = partitions(dir.clone(), 3);
+        partitions.write(&[logs], &[decoded_logs]).await.unwrap();
+        let files = partitions.finish().await.unwrap();
+        let first = format!("address={}", crate::util::hex_encode_prefixed(&[1; 20]));
+        assert_eq!(files.len(), 6);
+        assert_eq!(files[0], format!("decoded_logs/{}/data.parquet", first));
+        assert_eq!(files[3], format!("logs/{}/data.parquet", first));
+
+        let read_u64s = |file: &str| {
+            let mut file = std::fs::File::open(dir.join(file)).unwrap();
+            let metadata = polars_parquet::read::read_metadata(&mut file).unwrap();
+            let schema = polars_parquet::read::infer_schema(&metadata).unwrap();
+            polars_parquet::read::FileReader::new(file, metadata.row_groups, schema, None)
+                .flat_map(|chunk| {
+                    let chunk = chunk.unwrap();
+                    let col = chunk.columns()[0]
+                        .as_any()
+                        .downcast_ref::<UInt64Array>()
+                        .unwrap()
+                        .clone();
+                    col.values_iter().copied().collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(read_u64s(&files[3]), vec![10, 12]);
+        assert_eq!(read_u64s(&files[0]), vec![0, 2]);
+        assert_eq!(read_u64s(&files[5]), vec![13]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_bloom_filters() {
+        use polars_parquet::parquet::bloom_filter::{hash_byte, is_in_set};
+
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("logs.parquet");
+
+        let address = |i: u64| {
+            let mut addr = vec![0; 20];
+            addr[12..].copy_from_slice(&i.to_be_bytes());
+            addr
+        };
+        let num_rows = 25_000;
+        let addresses = (0..num_rows).map(|i| address(i % 500)).collect::<Vec<_>>();
+
+        let parquet_cfg = ParquetConfig {
+            bloom_filters: BTreeMap::from([
+                ("address".to_owned(), 0.01),
+                ("block_number".to_owned(), 0.01),
+            ]),
+            // the reader can't decode delta encoded pages with a constant delta
+            column_encodings: BTreeMap::from([("block_number".to_owned(), ParquetEncoding::Plain)]),
+            ..Default::default()
+        };
+        let (tx, join) =
+            spawn_writer(path.clone(), Arc::new(parquet_cfg), Default::default()).unwrap();
+        // one row group per batch
+        for (i, chunk) in addresses.chunks(ROW_GROUP_MAX_ROWS).enumerate() {
+            let first_block = (i * ROW_GROUP_MAX_ROWS) as u64;
+            tx.send(ArrowBatch {
+                chunk: Arc::new(Chunk::new(vec![
+                    UInt64Array::from_vec(
+                        (first_block..first_block + chunk.len() as u64).collect(),
+                    )
+                    .boxed(),
+                    BinaryArray::<i32>::from_iter_values(chunk.iter()).boxed(),
+                ])),
+                schema: Arc::new(Schema::from(vec![
+                    Field::new("block_number", DataType::UInt64, false),
+                    Field::new("address", DataType::Binary, false),
+                ])),
+            })
+            .await
+            .unwrap();
+        }
+        drop(tx);
+        join.await.unwrap().unwrap();
+
+        let mut file = std::fs::File::open(&path).unwrap();
+        let metadata = polars_parquet::read::read_metadata(&mut file).unwrap();
+        assert_eq!(metadata.row_groups.len(), 3);
+        let mut bitset = Vec::new();
+        for row_group in metadata.row_groups.iter() {
+            // not a binary column
+            polars_parquet::parquet::bloom_filter::read(
+                &row_group.columns()[0],
+                &mut file,
+                &mut bitset,
+            )
+            .unwrap();
+            assert!(bitset.is_empty());
+
+            polars_parquet::parquet::bloom_filter::read(
+                &row_group.columns()[1],
+                &mut file,
+                &mut bitset,
+            )
+            .unwrap();
+            assert!(!bitset.is_empty());
+            for i in 0..500 {
+                assert!(is_in_set(&bitset, hash_byte(address(i))));
+            }
+            let false_positives = (500..10_500)
+                .filter(|i| is_in_set(&bitset, hash_byte(address(*i))))
+                .count();
+            assert!(false_positives < 300, "{}", false_positives);
+        }
+
+        let schema = polars_parquet::read::infer_schema(&metadata).unwrap();
+        let reader = polars_parquet::read::FileReader::new(file, metadata.row_groups, schema, None);
+        let mut read_addresses = Vec::new();
+        for chunk in reader {
+            let chunk = chunk.unwrap();
+            let col = chunk.columns()[1]
+                .as_any()
+                .downcast_ref::<BinaryViewArray>()
+                .unwrap();
+            read_addresses.extend(col.values_iter().map(|a| a.to_vec()));
+        }
+        assert_eq!(read_addresses, addresses);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_deterministic_output() {
+        let server = MockServer::start(MockChain::new(25_000)).unwrap();
+        server.set_max_blocks_per_response(2_500);
+        let client = mock_client(&server);
+
+        let mut outputs = Vec::new();
+        // responses end at different blocks with each config
+        for (concurrency, batch_size) in [(1, 700), (4, 1_300), (16, 7_000)] {
+            let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+            let config = StreamConfig {
+                concurrency: Some(concurrency),
+                batch_size: Some(batch_size),
+                max_batch_size: Some(batch_size),
+                export_timestamp: Some(1_700_000_000),
+                event_signature: Some(
+                    "Transfer(address indexed from, address indexed to, uint256 value)".to_owned(),
+                ),
+                ..Default::default()
+            };
+            client
+                .clone()
+                .collect_parquet(dir.to_str().unwrap(), transfers_query(0, 25_000), config)
+                .await
+                .unwrap();
+
+            let mut file = std::fs::File::open(dir.join("decoded_logs.parquet")).unwrap();
+            let metadata = polars_parquet::read::read_metadata(&mut file).unwrap();
+            assert_eq!(metadata.num_rows, mock_logs(0..25_000).len());
+            assert!(metadata.row_groups.len() > 1);
+
+            let files = [
+                "blocks.parquet",
+                "transactions.parquet",
+                "logs.parquet",
+                "decoded_logs.parquet",
+                "manifest.json",
+            ]
+            .map(|name| std::fs::read(dir.join(name)).unwrap());
+            outputs.push(files);
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+        assert!(outputs.iter().all(|files| *files == outputs[0]));
+    }
+}
@@ -1,32 +1,87 @@
 use std::{io::Cursor, sync::Arc};
 
-use crate::{types::ArrowResponse, ArrowBatch, ArrowResponseData, QueryResponse};
+use crate::{
+    ipc_unchecked,
+    simple_types::{Block, Log, Trace, Transaction},
+    types::ArrowResponse,
+    ArrowBatch, ArrowResponseData, QueryResponse, ResponseData,
+};
 use anyhow::{Context, Result};
 use hypersync_net_types::{hypersync_net_types_capnp, RollbackGuard};
-use polars_arrow::io::ipc;
+use polars_arrow::{datatypes::ArrowSchema, io::ipc};
+use serde::Deserialize;
+use xxhash_rust::xxh3::Xxh3;
 
-fn read_chunks(bytes: &[u8]) -> Result<Vec<ArrowBatch>> {
+/// How the arrow IPC tables of a response are decoded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodeOptions {
+    /// Decode the tables in parallel on the rayon thread pool instead of one after another on the
+    /// calling thread.
+    pub parallel_tables: bool,
+    /// Decode the tables without validating their arrays, see
+    /// `ClientConfig::skip_ipc_validation`.
+    pub skip_validation: bool,
+}
+
+pub(crate) fn read_chunks(
+    bytes: &[u8],
+    skip_validation: bool,
+) -> Result<(Vec<ArrowBatch>, Arc<ArrowSchema>)> {
     let mut reader = Cursor::new(bytes);
 
     let metadata = ipc::read::read_file_metadata(&mut reader).context("read metadata")?;
 
     let schema = metadata.schema.clone();
+    let file_schema = schema.clone();
+    let batch = |chunk| ArrowBatch {
+        chunk: Arc::new(chunk),
+        schema: schema.clone(),
+    };
+
+    // tables with column types the unvalidated reader doesn't handle are validated anyway
+    if skip_validation {
+        if let Some(chunks) = ipc_unchecked::read_chunks(bytes, &metadata)? {
+            return Ok((chunks.into_iter().map(batch).collect(), file_schema));
+        }
+    }
 
     let reader = ipc::read::FileReader::new(reader, metadata, None, None);
 
     let chunks = reader
-        .map(|chunk| {
-            chunk.context("read chunk").map(|chunk| ArrowBatch {
-                chunk: Arc::new(chunk),
-                schema: schema.clone(),
-            })
-        })
+        .map(|chunk| chunk.context("read chunk").map(batch))
         .collect::<Result<Vec<ArrowBatch>>>()?;
 
-    Ok(chunks)
+    Ok((chunks, file_schema))
 }
 
-pub fn parse_query_response(bytes: &[u8]) -> Result<ArrowResponse> {
+/// Hashes the name, data type and nullability of every column of every table in the response.
+///
+/// Tables that are missing from the response hash differently from tables that have no columns.
+fn schema_fingerprint(schemas: &[(&str, Option<&ArrowSchema>)]) -> u64 {
+    let mut hasher = Xxh3::new();
+
+    for (table, schema) in schemas {
+        hasher.update(table.as_bytes());
+        match schema {
+            Some(schema) => {
+                for field in schema.fields.iter() {
+                    let desc = format!(
+                        "|{}:{:?}:{}",
+                        field.name, field.data_type, field.is_nullable
+                    );
+                    hasher.update(desc.as_bytes());
+                }
+            }
+            None => hasher.update(b"|<missing>"),
+        }
+        hasher.update(b";");
+    }
+
+    hasher.digest()
+}
+
+/// Parses the response and returns it alongside the fingerprint of its schema.
+pub fn parse_query_response(bytes: &[u8], decode: DecodeOptions) -> Result<(ArrowResponse, u64)> {
     let mut opts = capnp::message::ReaderOptions::new();
     opts.nesting_limit(i32::MAX).traversal_limit_in_words(None);
     let message_reader =
@@ -70,17 +125,54 @@ pub fn parse_query_response(bytes: &[u8]) -> Result<ArrowResponse> {
 
     let data = query_response.get_data().context("read data")?;
 
-    let blocks = read_chunks(data.get_blocks().context("get data")?).context("parse block data")?;
-    let transactions =
-        read_chunks(data.get_transactions().context("get data")?).context("parse tx data")?;
-    let logs = read_chunks(data.get_logs().context("get data")?).context("parse log data")?;
+    let blocks = data.get_blocks().context("get data")?;
+    let transactions = data.get_transactions().context("get data")?;
+    let logs = data.get_logs().context("get data")?;
     let traces = if data.has_traces() {
-        read_chunks(data.get_traces().context("get data")?).context("parse traces data")?
+        Some(data.get_traces().context("get data")?)
+    } else {
+        None
+    };
+
+    let skip_validation = decode.skip_validation;
+    let read_blocks = || read_chunks(blocks, skip_validation).context("parse block data");
+    let read_transactions = || read_chunks(transactions, skip_validation).context("parse tx data");
+    let read_logs = || read_chunks(logs, skip_validation).context("parse log data");
+    let read_traces = || {
+        traces
+            .map(|traces| read_chunks(traces, skip_validation).context("parse traces data"))
+            .transpose()
+    };
+    let (blocks, transactions, logs, traces) = if decode.parallel_tables {
+        let ((blocks, transactions), (logs, traces)) = rayon::join(
+            || rayon::join(read_blocks, read_transactions),
+            || rayon::join(read_logs, read_traces),
+        );
+        (blocks, transactions, logs, traces)
     } else {
-        Vec::new()
+        (
+            read_blocks(),
+            read_transactions(),
+            read_logs(),
+            read_traces(),
+        )
+    };
+    let (blocks, blocks_schema) = blocks?;
+    let (transactions, transactions_schema) = transactions?;
+    let (logs, logs_schema) = logs?;
+    let (traces, traces_schema) = match traces? {
+        Some((traces, schema)) => (traces, Some(schema)),
+        None => (Vec::new(), None),
     };
 
-    Ok(QueryResponse {
+    let fingerprint = schema_fingerprint(&[
+        ("blocks", Some(&blocks_schema)),
+        ("transactions", Some(&transactions_schema)),
+        ("logs", Some(&logs_schema)),
+        ("traces", traces_schema.as_deref()),
+    ]);
+
+    let res = QueryResponse {
         archive_height,
         next_block: query_response.get_next_block(),
         total_execution_time: query_response.get_total_execution_time(),
@@ -90,7 +182,160 @@ pub fn parse_query_response(bytes: &[u8]) -> Result<ArrowResponse> {
             logs,
             traces,
             decoded_logs: Vec::new(),
+            decoded_events: Default::default(),
+            trace_summary: Vec::new(),
         },
         rollback_guard,
+        rollback: None,
+        transfer: Default::default(),
+    };
+
+    Ok((res, fingerprint))
+}
+
+#[derive(Deserialize)]
+struct JsonQueryResponse {
+    archive_height: Option<u64>,
+    next_block: u64,
+    total_execution_time: u64,
+    #[serde(default)]
+    data: Vec<JsonResponseData>,
+    rollback_guard: Option<RollbackGuard>,
+}
+
+#[derive(Deserialize)]
+struct JsonResponseData {
+    #[serde(default)]
+    blocks: Vec<Block>,
+    #[serde(default)]
+    transactions: Vec<Transaction>,
+    #[serde(default)]
+    logs: Vec<Log>,
+    #[serde(default)]
+    traces: Vec<Trace>,
+}
+
+/// Parses a response of the json query endpoint.
+pub fn parse_json_query_response(bytes: &[u8]) -> Result<QueryResponse> {
+    let res: JsonQueryResponse = serde_json::from_slice(bytes).context("parse json")?;
+
+    let mut data = ResponseData::default();
+    for batch in res.data {
+        data.blocks.push(batch.blocks);
+        data.transactions.push(batch.transactions);
+        data.logs.push(batch.logs);
+        data.traces.push(batch.traces);
+    }
+
+    Ok(QueryResponse {
+        archive_height: res.archive_height,
+        next_block: res.next_block,
+        total_execution_time: res.total_execution_time,
+        data,
+        rollback_guard: res.rollback_guard,
+        rollback: None,
+        transfer: Default::default(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars_arrow::{
+        array::{BinaryArray, UInt64Array},
+        datatypes::{ArrowDataType, Field},
+        io::ipc::write::{FileWriter, WriteOptions},
+    };
+
+    use crate::ArrowChunk;
+
+    fn ipc_file(name: &str, num_rows: u64) -> Vec<u8> {
+        let schema = ArrowSchema::from(vec![
+            Field::new(name, ArrowDataType::UInt64, false),
+            Field::new("hash", ArrowDataType::Binary, false),
+        ]);
+        let chunk = ArrowChunk::try_new(vec![
+            UInt64Array::from_vec((0..num_rows).collect()).boxed(),
+            BinaryArray::<i32>::from_iter((0..num_rows).map(|i| Some(i.to_be_bytes()))).boxed(),
+        ])
+        .unwrap();
+        let mut out = Vec::new();
+        let mut writer = FileWriter::try_new(
+            &mut out,
+            Arc::new(schema),
+            None,
+            WriteOptions { compression: None },
+        )
+        .unwrap();
+        writer.write(&chunk, None).unwrap();
+        writer.finish().unwrap();
+        out
+    }
+
+    #[test]
+    fn test_parse_tables_in_parallel() {
+        let mut message = capnp::message::Builder::new_default();
+        let mut res = message.init_root::<hypersync_net_types_capnp::query_response::Builder>();
+        res.set_archive_height(99);
+        res.set_next_block(40);
+        {
+            let mut data = res.reborrow().init_data();
+            data.set_blocks(&ipc_file("number", 10));
+            data.set_transactions(&ipc_file("block_number", 20));
+            data.set_logs(&ipc_file("block_number", 30));
+        }
+        let mut bytes = Vec::new();
+        capnp::serialize_packed::write_message(&mut bytes, &message).unwrap();
+
+        let (sequential, sequential_fp) =
+            parse_query_response(&bytes, DecodeOptions::default()).unwrap();
+        let (parallel, parallel_fp) = parse_query_response(
+            &bytes,
+            DecodeOptions {
+                parallel_tables: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (unvalidated, unvalidated_fp) = parse_query_response(
+            &bytes,
+            DecodeOptions {
+                parallel_tables: true,
+                skip_validation: true,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(sequential_fp, parallel_fp);
+        assert_eq!(sequential_fp, unvalidated_fp);
+        for res in [&sequential, &parallel, &unvalidated] {
+            assert_eq!(res.next_block, 40);
+            assert_eq!(res.archive_height, Some(99));
+            let rows = |batches: &[ArrowBatch]| batches.iter().map(|b| b.num_rows()).sum::<usize>();
+            assert_eq!(rows(&res.data.blocks), 10);
+            assert_eq!(rows(&res.data.transactions), 20);
+            assert_eq!(rows(&res.data.logs), 30);
+            assert!(res.data.traces.is_empty());
+        }
+        for res in [&parallel, &unvalidated] {
+            assert_eq!(
+                res.data.logs[0].u64_column("block_number").unwrap(),
+                sequential.data.logs[0].u64_column("block_number").unwrap()
+            );
+            assert_eq!(res.data.blocks[0].chunk, sequential.data.blocks[0].chunk);
+        }
+    }
+
+    #[test]
+    fn test_schema_fingerprint() {
+        let a = ArrowSchema::from(vec![Field::new("number", ArrowDataType::UInt64, false)]);
+        let b = ArrowSchema::from(vec![Field::new("number", ArrowDataType::UInt64, true)]);
+        let empty = ArrowSchema::from(Vec::new());
+
+        let fp = |schema| schema_fingerprint(&[("blocks", schema)]);
+
+        assert_eq!(fp(Some(&a)), fp(Some(&a)));
+        assert_ne!(fp(Some(&a)), fp(Some(&b)));
+        assert_ne!(fp(Some(&empty)), fp(None));
+    }
+}
@@ -0,0 +1,55 @@
+//! The commonly used types of the client, for glob imports with
+//! `use hypersync_client::prelude::*;`.
+//!
+//! # Stability
+//!
+//! Everything exported here follows semver: items are only removed or changed incompatibly in a
+//! major release. New items may be added in minor releases, so avoid defining types with the same
+//! names as the exported ones next to a glob import of the prelude. Items that are only available
+//! at the crate root or in other modules are public but may be moved between minor releases while
+//! the crate is below 1.0.
+
+pub use crate::{
+    simple_types::{Block, Event, Log, Trace, Transaction},
+    ArrowBatch, ArrowResponse, Client, ClientConfig, Decoder, ErrorKind, HexOutput, QueryResponse,
+    StreamConfig,
+};
+pub use hypersync_format::{
+    Address, BlockNumber, Data, FixedSizeData, Hash, Hex, LogArgument, LogIndex, Quantity,
+    TransactionIndex,
+};
+pub use hypersync_net_types::{
+    BlockSelection, FieldSelection, JoinMode, LogSelection, Query, TraceSelection,
+    TransactionSelection,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_import_is_unambiguous() {
+        let query = Query {
+            logs: vec![LogSelection::default()],
+            field_selection: FieldSelection::default(),
+            ..Default::default()
+        };
+        let block = Block {
+            number: Some(query.from_block),
+            hash: Some(Hash::from([0; 32])),
+            ..Default::default()
+        };
+        let log = Log {
+            address: Some(Address::from([0; 20])),
+            ..Default::default()
+        };
+        let event = Event {
+            block: Some(block.into()),
+            log,
+            ..Default::default()
+        };
+        assert!(event.transaction.is_none());
+        assert!(Client::new(ClientConfig::default()).is_ok());
+        let _ = StreamConfig::default();
+    }
+}
@@ -67,6 +67,28 @@ pub fn blocks_and_transaction_hashes(from_block: u64, to_block: Option<u64>) ->
     }
 }
 
+/// Returns a query for the number, timestamp and hash of every block within the block range
+/// [from_block, to_block).  Uses include_all_blocks so blocks are returned regardless of their
+/// contents, which makes it suitable for maintaining a block-time lookup table.
+/// If to_block is None then query runs to the head of the chain.
+pub fn block_index(from_block: u64, to_block: Option<u64>) -> Query {
+    let mut block_field_selection = BTreeSet::new();
+    block_field_selection.insert("number".to_owned());
+    block_field_selection.insert("timestamp".to_owned());
+    block_field_selection.insert("hash".to_owned());
+
+    Query {
+        from_block,
+        to_block,
+        include_all_blocks: true,
+        field_selection: FieldSelection {
+            block: block_field_selection,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
 /// Returns a query object for all Logs within the block range (from_block, to_block] from
 /// the given address.  If to_block is None then query runs to the head of the chain.
 /// Note: this is only for quickstart purposes.  For the best performance, create a custom query
@@ -181,3 +203,47 @@ pub fn transactions_from_address(
         ..Default::default()
     }
 }
+
+/// Returns a query object for all transactions within the block range (from_block, to_block]
+/// with the fields needed for fee analytics, including the ones that come from the transaction
+/// receipt like gas_used, effective_gas_price, status and contract_address.
+/// If to_block is None then query runs to the head of the chain.
+/// Receipt fields are null on chains or blocks where the receipt isn't available.
+pub fn transactions_with_receipts(from_block: u64, to_block: Option<u64>) -> Query {
+    let fields: BTreeSet<String> = [
+        "block_number",
+        "transaction_index",
+        "hash",
+        "from",
+        "to",
+        "type",
+        "gas",
+        "gas_price",
+        "max_fee_per_gas",
+        "max_priority_fee_per_gas",
+        "cumulative_gas_used",
+        "effective_gas_price",
+        "gas_used",
+        "contract_address",
+        "status",
+        "l1_fee",
+        "l1_gas_price",
+        "l1_gas_used",
+        "blob_gas_price",
+        "blob_gas_used",
+    ]
+    .into_iter()
+    .map(|x| x.to_owned())
+    .collect();
+
+    Query {
+        from_block,
+        to_block,
+        transactions: vec![TransactionSelection::default()],
+        field_selection: FieldSelection {
+            transaction: fields,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
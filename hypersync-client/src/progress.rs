@@ -0,0 +1,244 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Receives the progress of a stream after every response it sends, registered with
+/// `StreamConfig::on_progress`.
+///
+/// Called from the task running the stream, so it should return quickly, e.g. by updating a
+/// progress bar or storing the snapshot. Implemented for closures taking a `&Progress`.
+pub trait ProgressListener: Send + Sync {
+    /// Called with the progress of the stream after a response was sent.
+    fn on_progress(&self, progress: &Progress);
+}
+
+impl<F: Fn(&Progress) + Send + Sync> ProgressListener for F {
+    fn on_progress(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+impl fmt::Debug for dyn ProgressListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressListener")
+    }
+}
+
+/// Snapshot of how far a stream has progressed through its block range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// First block of the stream.
+    pub from_block: u64,
+    /// Block the stream ends at, exclusive.
+    pub to_block: u64,
+    /// Block the stream will continue from, every block before it has been sent.
+    pub next_block: u64,
+    /// Smoothed number of blocks processed per second, weighted towards recent responses.
+    pub blocks_per_second: f64,
+    /// Smoothed number of response bytes received per second, before decompression.
+    pub bytes_per_second: f64,
+    /// Estimated time until the stream reaches `to_block`.
+    ///
+    /// None until a rate has been measured.
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    /// Number of blocks of the range that have been processed.
+    pub fn blocks_completed(&self) -> u64 {
+        self.next_block.saturating_sub(self.from_block)
+    }
+
+    /// Fraction of the block range that has been processed, in [0, 1].
+    pub fn ratio(&self) -> f64 {
+        let total = self.to_block.saturating_sub(self.from_block);
+        if total == 0 {
+            return 1.0;
+        }
+        let done = self.next_block.saturating_sub(self.from_block).min(total);
+        done as f64 / total as f64
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block {}/{} ({:.1}%), {:.1} blocks/s, {:.1} MB/s",
+            self.next_block,
+            self.to_block,
+            self.ratio() * 100.0,
+            self.blocks_per_second,
+            self.bytes_per_second / 1_000_000.0
+        )?;
+        match self.eta {
+            Some(eta) => {
+                let secs = eta.as_secs();
+                write!(
+                    f,
+                    ", eta {}h{:02}m{:02}s",
+                    secs / 3600,
+                    secs / 60 % 60,
+                    secs % 60
+                )
+            }
+            None => write!(f, ", eta unknown"),
+        }
+    }
+}
+
+/// Estimates the remaining time of a stream from an exponentially smoothed block rate, smoothing
+/// the byte rate the same way.
+///
+/// Block density varies a lot over a chain's history, so the rate is smoothed over a short time
+/// window instead of averaging over the whole run, which would keep predicting the speed of the
+/// early, sparse blocks long after the stream has reached dense ones.
+#[derive(Debug)]
+pub(crate) struct EtaEstimator {
+    from_block: u64,
+    to_block: u64,
+    last: (Instant, u64, u64),
+    rate: Option<f64>,
+    byte_rate: f64,
+}
+
+impl EtaEstimator {
+    /// Time constant of the smoothing, an update `SMOOTHING_WINDOW` after the previous one
+    /// carries ~63% of the weight.
+    const SMOOTHING_WINDOW: Duration = Duration::from_secs(30);
+
+    pub(crate) fn new(from_block: u64, to_block: u64, start: Instant) -> Self {
+        Self {
+            from_block,
+            to_block,
+            last: (start, from_block, 0),
+            rate: None,
+            byte_rate: 0.0,
+        }
+    }
+
+    /// Records that the stream reached `next_block` with `bytes` received in total.
+    pub(crate) fn update(&mut self, now: Instant, next_block: u64, bytes: u64) {
+        let (last_time, last_block, last_bytes) = self.last;
+        let elapsed = now.saturating_duration_since(last_time).as_secs_f64();
+        if elapsed <= 0.0 || next_block <= last_block {
+            return;
+        }
+
+        let rate = (next_block - last_block) as f64 / elapsed;
+        let byte_rate = bytes.saturating_sub(last_bytes) as f64 / elapsed;
+        match self.rate {
+            Some(prev) => {
+                let alpha = 1.0 - (-elapsed / Self::SMOOTHING_WINDOW.as_secs_f64()).exp();
+                self.rate = Some(prev + alpha * (rate - prev));
+                self.byte_rate += alpha * (byte_rate - self.byte_rate);
+            }
+            None => {
+                self.rate = Some(rate);
+                self.byte_rate = byte_rate;
+            }
+        }
+        self.last = (now, next_block, bytes);
+    }
+
+    pub(crate) fn progress(&self) -> Progress {
+        let next_block = self.last.1;
+        let rate = self.rate.unwrap_or(0.0);
+        let eta = self.rate.filter(|&r| r > 0.0).map(|r| {
+            let remaining = self.to_block.saturating_sub(next_block);
+            Duration::from_secs_f64(remaining as f64 / r)
+        });
+
+        Progress {
+            from_block: self.from_block,
+            to_block: self.to_block,
+            next_block,
+            blocks_per_second: rate,
+            bytes_per_second: self.byte_rate,
+            eta,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "test-util")]
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::mock_server::{blocks_query, mock_client, MockChain, MockServer};
+    #[cfg(feature = "test-util")]
+    use crate::StreamConfig;
+
+    #[test]
+    fn test_eta_follows_recent_rate() {
+        let start = Instant::now();
+        let mut est = EtaEstimator::new(0, 100_000, start);
+        assert_eq!(est.progress().eta, None);
+
+        // 1000 blocks/s for the first minute
+        for i in 1..=60 {
+            est.update(start + Duration::from_secs(i), i * 1000, i * 50_000);
+        }
+        let progress = est.progress();
+        assert!((progress.blocks_per_second - 1000.0).abs() < 1e-6);
+        assert!((progress.bytes_per_second - 50_000.0).abs() < 1e-6);
+        assert_eq!(progress.blocks_completed(), 60_000);
+        assert_eq!(progress.eta, Some(Duration::from_secs(40)));
+
+        // then blocks get dense and the rate drops to 100 blocks/s
+        for i in 1..=240 {
+            est.update(
+                start + Duration::from_secs(60 + i),
+                60_000 + i * 100,
+                3_000_000 + i * 50_000,
+            );
+        }
+        let progress = est.progress();
+        assert!((progress.blocks_per_second - 100.0).abs() < 1.0);
+        assert!((progress.bytes_per_second - 50_000.0).abs() < 1e-6);
+        assert_eq!(progress.next_block, 84_000);
+        assert!(progress
+            .to_string()
+            .starts_with("block 84000/100000 (84.0%)"));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_on_progress() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+        let reports = Arc::new(Mutex::new(Vec::<Progress>::new()));
+        let on_progress = {
+            let reports = reports.clone();
+            move |progress: &Progress| reports.lock().unwrap().push(*progress)
+        };
+
+        let res = client
+            .collect(
+                blocks_query(20, 100),
+                StreamConfig {
+                    concurrency: Some(2),
+                    batch_size: Some(10),
+                    on_progress: Some(Arc::new(on_progress)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.data.blocks.concat().len(), 80);
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 8);
+        assert!(reports
+            .windows(2)
+            .all(|w| w[0].next_block < w[1].next_block));
+        let last = reports.last().unwrap();
+        assert_eq!((last.from_block, last.to_block), (20, 100));
+        assert_eq!(last.blocks_completed(), 80);
+        assert_eq!(last.ratio(), 1.0);
+    }
+}
@@ -0,0 +1,255 @@
+use std::{collections::BTreeSet, fmt};
+
+use hypersync_net_types::Query;
+
+use crate::column_mapping::unknown_column_message;
+
+/// How bad a problem found by [`crate::Client::validate_query`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The query would be rejected by the server or can't return what it asks for.
+    Error,
+    /// The query is valid but probably doesn't do what was intended.
+    Warning,
+}
+
+/// A single problem found in a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryDiagnostic {
+    /// How bad the problem is.
+    pub severity: Severity,
+    /// Part of the query the problem is in, e.g. `field_selection.log` or `transactions[1]`.
+    pub path: String,
+    /// Description of the problem.
+    pub message: String,
+}
+
+/// Result of [`crate::Client::validate_query`].
+#[derive(Debug, Clone, Default)]
+pub struct QueryValidation {
+    /// Every problem that was found, in the order of the query fields.
+    pub diagnostics: Vec<QueryDiagnostic>,
+}
+
+impl QueryValidation {
+    /// Returns true if no errors were found, warnings are allowed.
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// Returns the diagnostics with `Severity::Error`.
+    pub fn errors(&self) -> impl Iterator<Item = &QueryDiagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+    }
+
+    fn push(&mut self, severity: Severity, path: impl Into<String>, message: impl Into<String>) {
+        self.diagnostics.push(QueryDiagnostic {
+            severity,
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+}
+
+impl fmt::Display for QueryValidation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, d) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let severity = match d.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            write!(f, "{}: {}: {}", severity, d.path, d.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the query locally, against the schema in `hypersync_schema`.
+pub(crate) fn validate(query: &Query) -> QueryValidation {
+    let mut res = QueryValidation::default();
+
+    if let Some(to_block) = query.to_block {
+        if to_block <= query.from_block {
+            res.push(
+                Severity::Error,
+                "to_block",
+                format!(
+                    "to_block ({}) has to be greater than from_block ({}), it is exclusive",
+                    to_block, query.from_block
+                ),
+            );
+        }
+    }
+
+    let tables = [
+        (
+            "block",
+            &query.field_selection.block,
+            hypersync_schema::block_header(),
+        ),
+        (
+            "transaction",
+            &query.field_selection.transaction,
+            hypersync_schema::transaction(),
+        ),
+        ("log", &query.field_selection.log, hypersync_schema::log()),
+        (
+            "trace",
+            &query.field_selection.trace,
+            hypersync_schema::trace(),
+        ),
+    ];
+    for (table, selection, schema) in tables {
+        let columns = schema
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<BTreeSet<_>>();
+        for name in selection {
+            if !columns.contains(name.as_str()) {
+                res.push(
+                    Severity::Error,
+                    format!("field_selection.{}", table),
+                    unknown_column_message(table, name, columns.iter().copied()),
+                );
+            }
+        }
+    }
+
+    for (i, selection) in query.transactions.iter().enumerate() {
+        if let Some(status) = selection.status {
+            if status > 1 {
+                res.push(
+                    Severity::Error,
+                    format!("transactions[{}].status", i),
+                    format!("status has to be 0 or 1, got {}", status),
+                );
+            }
+        }
+        if !selection.contract_address.is_empty() && !selection.to.is_empty() {
+            res.push(
+                Severity::Warning,
+                format!("transactions[{}]", i),
+                "contract creations don't have a to address, selecting both contract_address \
+                 and to matches nothing",
+            );
+        }
+    }
+
+    let limits = [
+        ("max_num_blocks", query.max_num_blocks),
+        ("max_num_transactions", query.max_num_transactions),
+        ("max_num_logs", query.max_num_logs),
+        ("max_num_traces", query.max_num_traces),
+    ];
+    for (name, limit) in limits {
+        if limit == Some(0) {
+            res.push(
+                Severity::Error,
+                name,
+                format!("{} is 0, the query can't make progress", name),
+            );
+        }
+    }
+
+    let has_selections = !query.logs.is_empty()
+        || !query.transactions.is_empty()
+        || !query.traces.is_empty()
+        || !query.blocks.is_empty();
+    if !has_selections && !query.include_all_blocks {
+        res.push(
+            Severity::Warning,
+            "query",
+            "query has no selections and include_all_blocks isn't set, it won't return any data",
+        );
+    }
+
+    let selected_tables = [
+        (
+            "logs",
+            "log",
+            !query.logs.is_empty(),
+            &query.field_selection.log,
+        ),
+        (
+            "transactions",
+            "transaction",
+            !query.transactions.is_empty(),
+            &query.field_selection.transaction,
+        ),
+        (
+            "traces",
+            "trace",
+            !query.traces.is_empty(),
+            &query.field_selection.trace,
+        ),
+    ];
+    for (selection, table, has_selection, fields) in selected_tables {
+        if has_selection && fields.is_empty() {
+            res.push(
+                Severity::Warning,
+                selection,
+                format!(
+                    "{} are selected but field_selection.{} is empty, no {} columns will be \
+                     returned",
+                    selection, table, table
+                ),
+            );
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let query: Query = serde_json::from_value(serde_json::json!({
+            "from_block": 10,
+            "to_block": 10,
+            "logs": [{}],
+            "transactions": [{"status": 2}],
+            "field_selection": {
+                "log": ["adress", "data"],
+                "transaction": ["hash"]
+            },
+            "max_num_logs": 0
+        }))
+        .unwrap();
+
+        let res = validate(&query);
+        assert!(!res.is_valid());
+        let paths = res.errors().map(|d| d.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "to_block",
+                "field_selection.log",
+                "transactions[0].status",
+                "max_num_logs"
+            ]
+        );
+        assert!(res.diagnostics[1]
+            .message
+            .contains("did you mean log.address?"));
+
+        let query: Query = serde_json::from_value(serde_json::json!({
+            "from_block": 0,
+            "logs": [{}],
+        }))
+        .unwrap();
+        let res = validate(&query);
+        assert!(res.is_valid());
+        assert_eq!(res.diagnostics.len(), 1);
+        assert_eq!(res.diagnostics[0].severity, Severity::Warning);
+        assert_eq!(res.diagnostics[0].path, "logs");
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    num::NonZeroU32,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Request rate limit shared between clients.
+///
+/// Every client the quota is passed to through `ClientConfig::shared_quota` takes a slot from it
+/// before sending a request, so several clients using one API key, e.g. one per chain in a
+/// multi-chain service, stay under the key's global rate limit together. When the server
+/// throttles one of them, all of them wait until the throttle has passed.
+#[derive(Debug)]
+pub struct SharedQuota {
+    interval: Duration,
+    burst: u32,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Time at which the bucket is empty again if no more requests arrive.
+    next_free: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl SharedQuota {
+    /// Creates a quota that lets through `requests_per_second` requests on average and up to
+    /// `burst` requests at once.
+    pub fn new(requests_per_second: NonZeroU32, burst: NonZeroU32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / requests_per_second.get(),
+            burst: burst.get(),
+            state: Mutex::new(State {
+                next_free: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Takes a slot for a request at the given time and returns how long to wait before sending
+    /// it.
+    pub(crate) fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let mut next_free = state.next_free.max(now);
+        if let Some(paused_until) = state.paused_until {
+            next_free = next_free.max(paused_until);
+        }
+        let lead = self.interval * (self.burst - 1);
+        let start = next_free
+            .checked_sub(lead)
+            .map_or(now, |start| start.max(now));
+        state.next_free = next_free + self.interval;
+        start - now
+    }
+
+    /// Holds back requests of every client until the given time.
+    pub(crate) fn pause_until(&self, until: Instant) {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .paused_until
+            .is_none_or(|paused_until| paused_until < until)
+        {
+            state.paused_until = Some(until);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota(requests_per_second: u32, burst: u32) -> SharedQuota {
+        SharedQuota::new(
+            NonZeroU32::new(requests_per_second).unwrap(),
+            NonZeroU32::new(burst).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_burst_then_rate() {
+        let quota = quota(10, 3);
+        let now = Instant::now();
+        assert_eq!(quota.reserve(now), Duration::ZERO);
+        assert_eq!(quota.reserve(now), Duration::ZERO);
+        assert_eq!(quota.reserve(now), Duration::ZERO);
+        assert_eq!(quota.reserve(now), Duration::from_millis(100));
+        assert_eq!(quota.reserve(now), Duration::from_millis(200));
+
+        // the bucket refills while idle
+        let later = now + Duration::from_secs(10);
+        assert_eq!(quota.reserve(later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_pause() {
+        let quota = quota(100, 1);
+        let now = Instant::now();
+        quota.pause_until(now + Duration::from_secs(2));
+        // an earlier pause doesn't shorten the current one
+        quota.pause_until(now + Duration::from_secs(1));
+        assert_eq!(quota.reserve(now), Duration::from_secs(2));
+        assert_eq!(
+            quota.reserve(now),
+            Duration::from_secs(2) + Duration::from_millis(10)
+        );
+    }
+}
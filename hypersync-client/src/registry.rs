@@ -0,0 +1,53 @@
+use url::Url;
+
+/// Chain ids of the networks with a public hypersync.xyz endpoint, keyed to their subdomain.
+const ENDPOINTS: &[(u64, &str)] = &[
+    (1, "eth"),
+    (10, "optimism"),
+    (56, "bsc"),
+    (100, "gnosis"),
+    (137, "polygon"),
+    (250, "fantom"),
+    (324, "zksync"),
+    (8453, "base"),
+    (17000, "holesky"),
+    (42161, "arbitrum"),
+    (43114, "avalanche"),
+    (59144, "linea"),
+    (81457, "blast"),
+    (534352, "scroll"),
+    (11155111, "sepolia"),
+];
+
+/// Returns the url of the public hypersync.xyz endpoint for the given chain id, None if the
+/// chain isn't in the built in registry.
+pub fn endpoint_for_chain(chain_id: u64) -> Option<Url> {
+    ENDPOINTS
+        .iter()
+        .find(|(id, _)| *id == chain_id)
+        .map(|(_, name)| {
+            format!("https://{}.hypersync.xyz", name)
+                .parse()
+                .expect("registry urls are valid")
+        })
+}
+
+/// Chain ids that have an endpoint in the built in registry.
+pub fn known_chain_ids() -> impl Iterator<Item = u64> {
+    ENDPOINTS.iter().map(|(id, _)| *id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_for_chain() {
+        assert_eq!(
+            endpoint_for_chain(8453).unwrap().as_str(),
+            "https://base.hypersync.xyz/"
+        );
+        assert!(endpoint_for_chain(999_999_999).is_none());
+        assert!(known_chain_ids().all(|id| endpoint_for_chain(id).is_some()));
+    }
+}
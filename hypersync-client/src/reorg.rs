@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use polars_arrow::array::BinaryArray;
+
+use crate::ArrowResponse;
+
+/// Most block hashes kept if the server doesn't send rollback guards to prune them with.
+const MAX_KNOWN_HASHES: usize = 4096;
+
+/// Detects reorgs by comparing the block hashes reported by consecutive responses of a stream.
+///
+/// Hashes are taken from the rollback guard of each response and from the `number`, `hash` and
+/// `parent_hash` block columns if they are selected and binary. A response that reports a
+/// different hash for a height than an earlier one means the blocks from that height on were
+/// replaced.
+#[derive(Debug, Default)]
+pub(crate) struct ReorgDetector {
+    hashes: BTreeMap<u64, Vec<u8>>,
+}
+
+impl ReorgDetector {
+    /// Checks the response against the hashes of the earlier responses. Returns the first block
+    /// that may have been replaced if the response is from a different fork, the hashes of the
+    /// response are only recorded if it isn't.
+    pub(crate) fn observe(&mut self, resp: &ArrowResponse) -> Option<u64> {
+        let reported = reported_hashes(resp);
+
+        let mismatch = reported
+            .iter()
+            .filter(|(number, hash)| self.hashes.get(number).is_some_and(|h| h != hash))
+            .map(|(number, _)| *number)
+            .min();
+
+        if let Some(mismatch) = mismatch {
+            // everything above the highest height that still matches may have been replaced,
+            // falling back to the start of the unfinalized blocks if none is known
+            let from_block = reported
+                .iter()
+                .filter(|(number, hash)| {
+                    *number < mismatch && self.hashes.get(number) == Some(hash)
+                })
+                .map(|(number, _)| number + 1)
+                .max()
+                .or_else(|| {
+                    resp.rollback_guard
+                        .as_ref()
+                        .map(|guard| guard.first_block_number)
+                })
+                .map_or(mismatch, |from_block| from_block.min(mismatch));
+
+            self.hashes.split_off(&from_block);
+            return Some(from_block);
+        }
+
+        self.hashes.extend(reported);
+        match resp.rollback_guard.as_ref() {
+            Some(guard) => {
+                // blocks below the guard are final and can't be replaced anymore
+                self.hashes = self
+                    .hashes
+                    .split_off(&guard.first_block_number.saturating_sub(1));
+            }
+            None => {
+                while self.hashes.len() > MAX_KNOWN_HASHES {
+                    self.hashes.pop_first();
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn reported_hashes(resp: &ArrowResponse) -> Vec<(u64, Vec<u8>)> {
+    let mut hashes = Vec::new();
+
+    if let Some(guard) = resp.rollback_guard.as_ref() {
+        hashes.push((guard.block_number, guard.hash.as_slice().to_vec()));
+        if let Some(parent) = guard.first_block_number.checked_sub(1) {
+            hashes.push((parent, guard.first_parent_hash.as_slice().to_vec()));
+        }
+    }
+
+    for batch in resp.data.blocks.iter() {
+        let number = match batch.u64_column("number") {
+            Ok(number) => number,
+            Err(_) => continue,
+        };
+        let hash = batch.column::<BinaryArray<i32>>("hash").ok();
+        let parent_hash = batch.column::<BinaryArray<i32>>("parent_hash").ok();
+
+        for (i, number) in number.values_iter().enumerate() {
+            if let Some(hash) = hash.and_then(|col| col.get(i)) {
+                hashes.push((*number, hash.to_vec()));
+            }
+            if let (Some(parent), Some(parent_hash)) = (
+                number.checked_sub(1),
+                parent_hash.and_then(|col| col.get(i)),
+            ) {
+                hashes.push((parent, parent_hash.to_vec()));
+            }
+        }
+    }
+
+    hashes
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use crate::mock_server::{blocks_query, mock_client, MockChain, MockServer};
+    use crate::{Rollback, StreamConfig};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_follow_rollback() {
+        let server = MockServer::start(MockChain::new(100).with_unfinalized_depth(10)).unwrap();
+        let client = mock_client(&server);
+
+        let mut query = blocks_query(0, 0);
+        query.to_block = None;
+        let mut rx = client
+            .stream(
+                query,
+                StreamConfig {
+                    follow: true,
+                    follow_poll_interval_millis: Some(10),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let res = rx.recv().await.unwrap().unwrap();
+        assert_eq!(res.next_block, 99);
+        assert_eq!(res.rollback, None);
+
+        server.update_chain(|chain| {
+            chain.reorg(3);
+            chain.push_blocks(2);
+        });
+
+        // blocks from the start of the unfinalized range are fetched again from the new fork
+        let res = rx.recv().await.unwrap().unwrap();
+        assert_eq!(res.rollback, Some(Rollback { from_block: 92 }));
+        let blocks = res.data.blocks.concat();
+        assert_eq!(blocks[0].number, Some(92));
+        assert_eq!(res.next_block, 101);
+        let chain = server.chain();
+        for block in blocks.iter() {
+            let expected = chain.block(block.number.unwrap()).unwrap();
+            assert_eq!(block.hash.as_ref(), Some(&expected.hash));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_follow_rollback_behind_unfinalized() {
+        let server = MockServer::start(MockChain::new(100).with_unfinalized_depth(10)).unwrap();
+        server.set_max_blocks_per_response(10);
+        // the tip changes while the stream is still far below the unfinalized blocks
+        server.reorg_after_queries(1, 3);
+        let client = mock_client(&server);
+
+        let mut query = blocks_query(0, 0);
+        query.to_block = None;
+        let mut rx = client
+            .stream(
+                query,
+                StreamConfig {
+                    follow: true,
+                    follow_poll_interval_millis: Some(10),
+                    concurrency: Some(1),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let res = rx.recv().await.unwrap().unwrap();
+        assert_eq!(res.next_block, 10);
+        assert_eq!(res.rollback, None);
+
+        // nothing is skipped, the stream goes on from where it was
+        let res = rx.recv().await.unwrap().unwrap();
+        assert_eq!(res.rollback, Some(Rollback { from_block: 10 }));
+        let mut blocks = res.data.blocks.concat();
+        let mut next_block = res.next_block;
+        while next_block < 99 {
+            let res = rx.recv().await.unwrap().unwrap();
+            assert_eq!(res.rollback, None);
+            blocks.extend(res.data.blocks.concat());
+            next_block = res.next_block;
+        }
+
+        let chain = server.chain();
+        let numbers = blocks.iter().map(|b| b.number.unwrap()).collect::<Vec<_>>();
+        assert_eq!(numbers, (10..99).collect::<Vec<_>>());
+        for block in blocks.iter() {
+            let expected = chain.block(block.number.unwrap()).unwrap();
+            assert_eq!(block.hash.as_ref(), Some(&expected.hash));
+        }
+    }
+}
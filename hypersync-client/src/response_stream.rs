@@ -0,0 +1,182 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{stream::BoxStream, Stream, StreamExt};
+use tokio::{sync::mpsc, time::Instant};
+
+use crate::Error;
+
+/// The receiver of a stream as a [`futures::Stream`], so `StreamExt` and `TryStreamExt`
+/// combinators can be used on the responses instead of a `recv` loop.
+///
+/// Wraps the receiver returned by `Client::stream`, `Client::stream_events`,
+/// `Client::stream_arrow` and the other stream functions:
+///
+///     use futures::TryStreamExt;
+///     use hypersync_client::{Client, Error, ResponseStream, StreamConfig, net_types::Query};
+///
+///     async fn count_logs(client: std::sync::Arc<Client>, query: Query) -> Result<usize, Error> {
+///         let rx = client.stream(query, StreamConfig::default()).await?;
+///         ResponseStream::new(rx)
+///             .try_fold(0, |num_logs, res| async move {
+///                 Ok(num_logs + res.data.logs.iter().map(Vec::len).sum::<usize>())
+///             })
+///             .await
+///     }
+///
+/// Like the receiver, dropping it stops the stream.
+#[derive(Debug)]
+pub struct ResponseStream<T> {
+    rx: mpsc::Receiver<Result<T, Error>>,
+}
+
+impl<T> ResponseStream<T> {
+    /// Wraps the receiver of a stream.
+    pub fn new(rx: mpsc::Receiver<Result<T, Error>>) -> Self {
+        Self { rx }
+    }
+
+    /// Receives the next response, None once the stream has ended.
+    pub async fn recv(&mut self) -> Option<Result<T, Error>> {
+        self.rx.recv().await
+    }
+
+    /// Returns the wrapped receiver.
+    pub fn into_inner(self) -> mpsc::Receiver<Result<T, Error>> {
+        self.rx
+    }
+}
+
+impl<T: Send + 'static> ResponseStream<T> {
+    /// Groups the responses into chunks of up to `max_len`, so a sink can write them in batches.
+    ///
+    /// A chunk is passed on once it is full or `timeout` after its first response arrived,
+    /// whichever comes first, so a slow live stream still flushes regularly. Chunks are never
+    /// empty. An error ends the current chunk and is passed on after it:
+    ///
+    ///     use std::time::Duration;
+    ///     use futures::StreamExt;
+    ///     use hypersync_client::{Client, IntoResponseStream, StreamConfig, net_types::Query};
+    ///
+    ///     async fn write_batches(client: std::sync::Arc<Client>, query: Query) -> anyhow::Result<()> {
+    ///         let rx = client.stream_events(query, StreamConfig::default()).await?;
+    ///         let mut chunks = rx.into_stream().chunks_timeout(100, Duration::from_secs(1));
+    ///         while let Some(chunk) = chunks.next().await {
+    ///             println!("writing {} responses", chunk?.len());
+    ///         }
+    ///         Ok(())
+    ///     }
+    pub fn chunks_timeout(
+        self,
+        max_len: usize,
+        timeout: Duration,
+    ) -> BoxStream<'static, Result<Vec<T>, Error>> {
+        let max_len = max_len.max(1);
+        futures::stream::unfold((self, None), move |(mut stream, err)| async move {
+            if let Some(err) = err {
+                return Some((Err(err), (stream, None)));
+            }
+            let mut chunk = match stream.recv().await? {
+                Ok(first) => vec![first],
+                Err(e) => return Some((Err(e), (stream, None))),
+            };
+
+            let deadline = Instant::now() + timeout;
+            let mut err = None;
+            while chunk.len() < max_len {
+                match tokio::time::timeout_at(deadline, stream.recv()).await {
+                    Ok(Some(Ok(resp))) => chunk.push(resp),
+                    Ok(Some(Err(e))) => {
+                        err = Some(e);
+                        break;
+                    }
+                    // the stream ended or the chunk timed out
+                    Ok(None) | Err(_) => break,
+                }
+            }
+            Some((Ok(chunk), (stream, err)))
+        })
+        .boxed()
+    }
+}
+
+/// Turns the receiver of a stream into a [`ResponseStream`] with `rx.into_stream()`.
+pub trait IntoResponseStream<T> {
+    /// Wraps the receiver in a [`ResponseStream`].
+    fn into_stream(self) -> ResponseStream<T>;
+}
+
+impl<T> IntoResponseStream<T> for mpsc::Receiver<Result<T, Error>> {
+    fn into_stream(self) -> ResponseStream<T> {
+        ResponseStream::new(self)
+    }
+}
+
+impl<T> From<mpsc::Receiver<Result<T, Error>>> for ResponseStream<T> {
+    fn from(rx: mpsc::Receiver<Result<T, Error>>) -> Self {
+        Self::new(rx)
+    }
+}
+
+impl<T> Stream for ResponseStream<T> {
+    type Item = Result<T, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use futures::{StreamExt, TryStreamExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_response_stream() {
+        let (tx, rx) = mpsc::channel(4);
+        for i in 0..3 {
+            tx.send(Ok(i)).await.unwrap();
+        }
+        drop(tx);
+        let doubled = ResponseStream::new(rx)
+            .map_ok(|i| i * 2)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(doubled, vec![0, 2, 4]);
+
+        let (tx, rx) = mpsc::channel(4);
+        tx.send(Ok(1)).await.unwrap();
+        tx.send(Err(anyhow!("failed").into())).await.unwrap();
+        drop(tx);
+        let results = ResponseStream::from(rx).collect::<Vec<_>>().await;
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_chunks_timeout() {
+        let (tx, rx) = mpsc::channel(16);
+        let mut chunks = rx.into_stream().chunks_timeout(3, Duration::from_secs(1));
+        for i in 0..4 {
+            tx.send(Ok(i)).await.unwrap();
+        }
+        // full chunks are passed on right away, the rest after the timeout
+        assert_eq!(chunks.next().await.unwrap().unwrap(), vec![0, 1, 2]);
+        let start = Instant::now();
+        assert_eq!(chunks.next().await.unwrap().unwrap(), vec![3]);
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        tx.send(Ok(4)).await.unwrap();
+        tx.send(Err(anyhow!("failed").into())).await.unwrap();
+        drop(tx);
+        assert_eq!(chunks.next().await.unwrap().unwrap(), vec![4]);
+        assert!(chunks.next().await.unwrap().is_err());
+        assert!(chunks.next().await.is_none());
+    }
+}
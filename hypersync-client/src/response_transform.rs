@@ -0,0 +1,98 @@
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::ArrowResponse;
+
+/// Transforms every response of a stream before it is passed on, registered with
+/// `StreamConfig::transform`.
+///
+/// Runs on the rayon pool after the response is decoded and mapped, so heavy work like
+/// filtering rows or computing derived columns stays off the task consuming the stream. The
+/// `max_num_*` limits count the rows of the transformed responses. An error fails the stream.
+/// Implemented for closures taking and returning an `ArrowResponse`:
+///
+///     use std::sync::Arc;
+///     use hypersync_client::{ArrowResponse, StreamConfig};
+///
+///     // only keep the first batch of logs of each response
+///     let config = StreamConfig {
+///         transform: Some(Arc::new(|mut resp: ArrowResponse| {
+///             resp.data.logs.truncate(1);
+///             Ok(resp)
+///         })),
+///         ..Default::default()
+///     };
+pub trait ResponseTransform: Send + Sync {
+    /// Returns the response to pass on in place of `resp`.
+    fn transform(&self, resp: ArrowResponse) -> Result<ArrowResponse>;
+}
+
+impl<F: Fn(ArrowResponse) -> Result<ArrowResponse> + Send + Sync> ResponseTransform for F {
+    fn transform(&self, resp: ArrowResponse) -> Result<ArrowResponse> {
+        self(resp)
+    }
+}
+
+impl fmt::Debug for dyn ResponseTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResponseTransform")
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mock_server::{blocks_query, mock_client, MockChain, MockServer};
+    use crate::StreamConfig;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_transform() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+        // keeps the first block of every response
+        let config = StreamConfig {
+            batch_size: Some(10),
+            max_batch_size: Some(10),
+            transform: Some(Arc::new(|mut resp: ArrowResponse| {
+                resp.data.blocks = resp
+                    .data
+                    .blocks
+                    .iter()
+                    .map(|batch| batch.slice(0, batch.num_rows().min(1)))
+                    .collect::<anyhow::Result<_>>()?;
+                Ok(resp)
+            })),
+            ..Default::default()
+        };
+
+        let res = client
+            .clone()
+            .collect(blocks_query(0, 100), config.clone())
+            .await
+            .unwrap();
+        let numbers = res
+            .data
+            .blocks
+            .concat()
+            .iter()
+            .map(|block| block.number.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, (0..10).map(|i| i * 10).collect::<Vec<_>>());
+
+        let config = StreamConfig {
+            transform: Some(Arc::new(|_: ArrowResponse| -> anyhow::Result<_> {
+                Err(anyhow::anyhow!("rejected"))
+            })),
+            ..config
+        };
+        let err = client
+            .collect_arrow(blocks_query(0, 100), config)
+            .await
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains("rejected"));
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{Checkpoint, CheckpointStore, ExportManifest};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Which partitions of a rolling export [`prune_exports`] keeps.
+///
+/// A partition is deleted once it is outside of any of the limits. Nothing is deleted if no
+/// limit is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Keep partitions that were exported within this many days.
+    pub keep_days: Option<u64>,
+    /// Keep partitions that have blocks within this many blocks of the end of the newest
+    /// partition.
+    pub keep_blocks: Option<u64>,
+}
+
+/// Deletes the partitions of a rolling export that are outside of the retention policy and
+/// returns their paths.
+///
+/// Partitions are the directories right below `root` that `collect_parquet` finished an export
+/// into, identified by their `manifest.json`. Directories without a manifest, e.g. exports that
+/// are still running or failed, are never deleted. The age of a partition is taken from
+/// `ExportManifest::export_timestamp`, or the modification time of the manifest for exports
+/// written before it was recorded.
+///
+/// If `checkpoint` is given, partitions that end at or after the stored block are kept even if
+/// the policy would delete them. The checkpoint says that everything before it was exported, so
+/// deleting the partition it resumes after would leave a gap that resuming never fills.
+pub async fn prune_exports(
+    root: &Path,
+    policy: &RetentionPolicy,
+    checkpoint: Option<&dyn Checkpoint>,
+) -> Result<Vec<PathBuf>> {
+    let checkpoint_block = match checkpoint {
+        Some(checkpoint) => checkpoint
+            .load_next_block()
+            .await
+            .context("load checkpoint")?,
+        None => None,
+    };
+
+    let mut partitions = Vec::new();
+    let mut entries = tokio::fs::read_dir(root).await.context("read export dir")?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .context("read export dir entry")?
+    {
+        let path = entry.path();
+        if !entry.file_type().await.context("get file type")?.is_dir() {
+            continue;
+        }
+        let manifest_path = path.join("manifest.json");
+        let manifest: ExportManifest = match CheckpointStore::new(&manifest_path)
+            .load()
+            .await
+            .with_context(|| format!("load manifest of {}", path.display()))?
+        {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+        let exported_at = match manifest.export_timestamp {
+            Some(timestamp) => UNIX_EPOCH + Duration::from_secs(timestamp),
+            None => tokio::fs::metadata(&manifest_path)
+                .await
+                .and_then(|metadata| metadata.modified())
+                .context("get manifest modification time")?,
+        };
+        partitions.push((path, manifest, exported_at));
+    }
+
+    let newest_block = partitions
+        .iter()
+        .map(|(_, manifest, _)| manifest.next_block)
+        .max();
+    let now = SystemTime::now();
+
+    let mut removed = Vec::new();
+    for (path, manifest, exported_at) in partitions {
+        let too_old = policy.keep_days.is_some_and(|days| {
+            let age = now.duration_since(exported_at).unwrap_or_default();
+            age > Duration::from_secs(days.saturating_mul(SECONDS_PER_DAY))
+        });
+        let too_far_behind = match (policy.keep_blocks, newest_block) {
+            (Some(blocks), Some(newest)) => manifest.next_block <= newest.saturating_sub(blocks),
+            _ => false,
+        };
+        let needed_by_checkpoint =
+            checkpoint_block.is_some_and(|block| manifest.next_block >= block);
+        if !(too_old || too_far_behind) || needed_by_checkpoint {
+            continue;
+        }
+
+        log::info!(
+            "deleting export of blocks [{}, {}) at {}",
+            manifest.from_block,
+            manifest.next_block,
+            path.display()
+        );
+        tokio::fs::remove_dir_all(&path)
+            .await
+            .with_context(|| format!("delete {}", path.display()))?;
+        removed.push(path);
+    }
+
+    removed.sort();
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryCheckpoint;
+
+    async fn write_partition(root: &Path, from_block: u64, next_block: u64, age_days: u64) {
+        let dir = root.join(format!("{}-{}", from_block, next_block));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let exported_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - age_days * SECONDS_PER_DAY;
+        let manifest = ExportManifest {
+            from_block,
+            next_block,
+            files: Vec::new(),
+            export_timestamp: Some(exported_at),
+            column_stats: None,
+        };
+        CheckpointStore::new(dir.join("manifest.json"))
+            .save(&manifest)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_prune_exports() {
+        let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        write_partition(&root, 0, 100, 10).await;
+        write_partition(&root, 100, 200, 5).await;
+        write_partition(&root, 200, 300, 1).await;
+        write_partition(&root, 300, 400, 0).await;
+        // an export that is still running
+        tokio::fs::create_dir_all(root.join("400-500"))
+            .await
+            .unwrap();
+
+        let removed = prune_exports(&root, &RetentionPolicy::default(), None)
+            .await
+            .unwrap();
+        assert!(removed.is_empty());
+
+        let policy = RetentionPolicy {
+            keep_days: Some(7),
+            keep_blocks: None,
+        };
+        let removed = prune_exports(&root, &policy, None).await.unwrap();
+        assert_eq!(removed, vec![root.join("0-100")]);
+
+        let policy = RetentionPolicy {
+            keep_days: None,
+            keep_blocks: Some(150),
+        };
+        let removed = prune_exports(&root, &policy, None).await.unwrap();
+        assert_eq!(removed, vec![root.join("100-200")]);
+
+        // the checkpoint resumes after the partition ending at 400, so it is kept
+        let policy = RetentionPolicy {
+            keep_days: Some(0),
+            keep_blocks: None,
+        };
+        let checkpoint = MemoryCheckpoint::new(Some(400));
+        let removed = prune_exports(&root, &policy, Some(&checkpoint))
+            .await
+            .unwrap();
+        assert_eq!(removed, vec![root.join("200-300")]);
+        assert!(root.join("300-400").join("manifest.json").exists());
+        assert!(root.join("400-500").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
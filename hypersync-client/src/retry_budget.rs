@@ -0,0 +1,51 @@
+use std::{sync::Mutex, time::Duration};
+
+use crate::error::RetryDeadlineExceeded;
+
+/// Total time the requests of a stream may spend on retries.
+///
+/// Failed attempts and the waits after them are added up over every request sharing the
+/// budget, so retries of requests running in parallel all count in full.
+#[derive(Debug)]
+pub(crate) struct RetryBudget {
+    limit: Duration,
+    spent: Mutex<Duration>,
+}
+
+impl RetryBudget {
+    pub(crate) fn new(limit: Duration) -> Self {
+        Self {
+            limit,
+            spent: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Takes the time of a failed attempt plus the wait before the next one from the budget,
+    /// returning an error instead of retrying if that exceeds the limit.
+    pub(crate) fn spend(&self, retry: Duration) -> Result<(), RetryDeadlineExceeded> {
+        let mut spent = self.spent.lock().unwrap();
+        *spent += retry;
+        if *spent > self.limit {
+            return Err(RetryDeadlineExceeded {
+                spent: *spent,
+                limit: self.limit,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spend() {
+        let budget = RetryBudget::new(Duration::from_secs(10));
+        assert!(budget.spend(Duration::from_secs(6)).is_ok());
+        assert!(budget.spend(Duration::from_secs(4)).is_ok());
+        let err = budget.spend(Duration::from_millis(1)).unwrap_err();
+        assert_eq!(err.spent, Duration::from_millis(10_001));
+        assert_eq!(err.limit, Duration::from_secs(10));
+    }
+}
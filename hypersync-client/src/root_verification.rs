@@ -0,0 +1,537 @@
+#![cfg(feature = "root-verification")]
+
+use std::collections::BTreeMap;
+
+use alloy_consensus::{Receipt, ReceiptEnvelope, ReceiptWithBloom, TxEnvelope};
+use alloy_eips::eip2718::Encodable2718;
+use alloy_primitives::B256;
+use alloy_trie::{HashBuilder, Nibbles};
+
+use crate::{
+    simple_types::{Block, Log, Transaction},
+    to_alloy, ResponseData,
+};
+
+/// Root of a block header that is recomputed from the data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootKind {
+    /// `transactions_root`, computed from the transactions.
+    Transactions,
+    /// `receipts_root`, computed from the receipt fields of the transactions and their logs.
+    Receipts,
+}
+
+/// A root that doesn't match the one in the block header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootMismatch {
+    /// Number of the block.
+    pub block_number: u64,
+    /// Which root doesn't match.
+    pub root: RootKind,
+    /// Root in the block header.
+    pub expected: B256,
+    /// Root computed from the received data.
+    pub computed: B256,
+}
+
+/// Result of [`verify_roots`].
+#[derive(Debug, Clone, Default)]
+pub struct RootVerification {
+    /// Number of roots that matched their block header.
+    pub num_verified: u64,
+    /// Roots that couldn't be computed, with the reason, because fields are missing from the
+    /// selection or a transaction type isn't supported, e.g. op stack deposits.
+    pub skipped: Vec<(u64, RootKind, String)>,
+    /// Roots that didn't match their block header.
+    pub mismatches: Vec<RootMismatch>,
+}
+
+impl RootVerification {
+    /// Returns true if no root mismatched. Skipped roots are allowed.
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Recomputes the transactions and receipts roots of the blocks in the response and compares
+/// them against the block headers.
+///
+/// Roots can only be verified for fully selected blocks: the query has to return every
+/// transaction of the block, e.g. through an empty `TransactionSelection`, and every log for
+/// the receipts root, with all fields that go into the encoding. The header needs `number`,
+/// `transactions_root` and `receipts_root`, the transactions need the fields listed on
+/// [`to_alloy::AlloyEvent`] plus the signature and fee fields of their type, and the logs
+/// need `block_number`, `transaction_index`, `log_index`, `address`, `data` and the topics.
+/// Missing transactions or logs show up as mismatches.
+pub fn verify_roots(data: &ResponseData) -> RootVerification {
+    let mut transactions = BTreeMap::<u64, Vec<&Transaction>>::new();
+    for tx in data.transactions.iter().flatten() {
+        if let Some(block_number) = tx.block_number {
+            transactions
+                .entry(block_number.into())
+                .or_default()
+                .push(tx);
+        }
+    }
+    let mut logs = BTreeMap::<(u64, u64), Vec<&Log>>::new();
+    for log in data.logs.iter().flatten() {
+        if let (Some(block_number), Some(tx_index)) = (log.block_number, log.transaction_index) {
+            logs.entry((block_number.into(), tx_index.into()))
+                .or_default()
+                .push(log);
+        }
+    }
+
+    let mut res = RootVerification::default();
+    for block in data.blocks.iter().flatten() {
+        let block_number = match block.number {
+            Some(block_number) => block_number,
+            None => continue,
+        };
+        let mut txs = transactions.remove(&block_number).unwrap_or_default();
+        txs.sort_by_key(|tx| tx.transaction_index.map(u64::from));
+
+        let checks = [
+            (
+                RootKind::Transactions,
+                block.transactions_root.as_ref(),
+                transactions_root(&txs),
+            ),
+            (
+                RootKind::Receipts,
+                block.receipts_root.as_ref(),
+                receipts_root(block_number, &txs, &logs),
+            ),
+        ];
+        for (kind, expected, computed) in checks {
+            match (expected, computed) {
+                (None, _) => res.skipped.push((
+                    block_number,
+                    kind,
+                    "root isn't selected in the block header".into(),
+                )),
+                (Some(_), Err(e)) => res.skipped.push((block_number, kind, e)),
+                (Some(expected), Ok(computed)) => {
+                    let expected = B256::from_slice(expected.as_ref());
+                    if expected == computed {
+                        res.num_verified += 1;
+                    } else {
+                        res.mismatches.push(RootMismatch {
+                            block_number,
+                            root: kind,
+                            expected,
+                            computed,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// Convenience wrapper around [`verify_roots`] for a single block.
+pub fn verify_block_roots(
+    block: &Block,
+    transactions: &[Transaction],
+    logs: &[Log],
+) -> RootVerification {
+    verify_roots(&ResponseData {
+        blocks: vec![vec![block.clone()]],
+        transactions: vec![transactions.to_vec()],
+        logs: vec![logs.to_vec()],
+        traces: Vec::new(),
+        decoded_logs: Vec::new(),
+    })
+}
+
+fn transactions_root(txs: &[&Transaction]) -> Result<B256, String> {
+    let encoded = txs
+        .iter()
+        .map(|&tx| {
+            let tx = alloy_rpc_types_eth::Transaction::try_from(tx.clone())
+                .map_err(|e| e.to_string())?;
+            let tx = TxEnvelope::try_from(tx).map_err(|e| e.to_string())?;
+            Ok(tx.encoded_2718())
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(ordered_trie_root(&encoded))
+}
+
+fn receipts_root(
+    block_number: u64,
+    txs: &[&Transaction],
+    logs: &BTreeMap<(u64, u64), Vec<&Log>>,
+) -> Result<B256, String> {
+    let encoded = txs
+        .iter()
+        .map(|&tx| {
+            let tx_index = tx
+                .transaction_index
+                .map(u64::from)
+                .ok_or("Field `transaction_index` is null")?;
+            let mut tx_logs = logs
+                .get(&(block_number, tx_index))
+                .cloned()
+                .unwrap_or_default();
+            tx_logs.sort_by_key(|log| log.log_index.map(u64::from));
+            let tx_logs = tx_logs
+                .into_iter()
+                .map(|log| alloy_rpc_types_eth::Log::try_from(log.clone()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+
+            let receipt = to_alloy::receipt(tx, tx_logs).map_err(|e| e.to_string())?;
+            Ok(into_consensus_envelope(receipt.inner)?.encoded_2718())
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(ordered_trie_root(&encoded))
+}
+
+/// Strips the rpc metadata from the logs of the receipt, leaving the consensus encoding.
+fn into_consensus_envelope(
+    envelope: ReceiptEnvelope<alloy_rpc_types_eth::Log>,
+) -> Result<ReceiptEnvelope, String> {
+    let strip = |r: ReceiptWithBloom<alloy_rpc_types_eth::Log>| ReceiptWithBloom {
+        receipt: Receipt {
+            status: r.receipt.status,
+            cumulative_gas_used: r.receipt.cumulative_gas_used,
+            logs: r.receipt.logs.into_iter().map(|log| log.inner).collect(),
+        },
+        logs_bloom: r.logs_bloom,
+    };
+    Ok(match envelope {
+        ReceiptEnvelope::Legacy(r) => ReceiptEnvelope::Legacy(strip(r)),
+        ReceiptEnvelope::Eip2930(r) => ReceiptEnvelope::Eip2930(strip(r)),
+        ReceiptEnvelope::Eip1559(r) => ReceiptEnvelope::Eip1559(strip(r)),
+        ReceiptEnvelope::Eip4844(r) => ReceiptEnvelope::Eip4844(strip(r)),
+        ReceiptEnvelope::Eip7702(r) => ReceiptEnvelope::Eip7702(strip(r)),
+        other => return Err(format!("unsupported receipt type {}", other.tx_type())),
+    })
+}
+
+/// Computes the root of a trie keyed by the rlp encoded index of each value, like the
+/// transactions and receipts tries of a block.
+fn ordered_trie_root(values: &[Vec<u8>]) -> B256 {
+    let mut leaves = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| (alloy_rlp::encode(i), value))
+        .collect::<Vec<_>>();
+    // leaves have to be added in key order
+    leaves.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut builder = HashBuilder::default();
+    for (key, value) in leaves {
+        builder.add_leaf(Nibbles::unpack(key), value);
+    }
+    builder.root()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{keccak256, Bytes};
+    use alloy_trie::EMPTY_ROOT_HASH;
+    use hypersync_format::{Address, Hash, Hex, Quantity, TransactionStatus, TransactionType};
+
+    use super::*;
+
+    fn block(number: u64, transactions_root: B256, receipts_root: B256) -> Block {
+        Block {
+            number: Some(number),
+            transactions_root: Some(Hash::from(transactions_root.0)),
+            receipts_root: Some(Hash::from(receipts_root.0)),
+            ..Default::default()
+        }
+    }
+
+    fn legacy_tx() -> Transaction {
+        let address = Address::decode_hex(&format!("0x{}", "11".repeat(20))).unwrap();
+        Transaction {
+            block_number: Some(5.into()),
+            transaction_index: Some(0.into()),
+            hash: Some(Hash::decode_hex(&format!("0x{}", "ab".repeat(32))).unwrap()),
+            from: Some(address.clone()),
+            to: Some(address),
+            nonce: Some(vec![1].into()),
+            gas: Some(vec![0x52, 0x08].into()),
+            gas_price: Some(vec![10].into()),
+            value: Some(vec![1].into()),
+            input: Some(vec![].into()),
+            v: Some(vec![27].into()),
+            r: Some(vec![1].into()),
+            s: Some(vec![2].into()),
+            kind: Some(TransactionType(0)),
+            gas_used: Some(vec![0x52, 0x08].into()),
+            cumulative_gas_used: Some(vec![0x52, 0x08].into()),
+            effective_gas_price: Some(vec![10].into()),
+            logs_bloom: Some(vec![0; 256].into()),
+            status: Some(TransactionStatus::Success),
+            ..Default::default()
+        }
+    }
+
+    fn quantity(value: u64) -> Quantity {
+        let bytes = value.to_be_bytes();
+        let start = bytes
+            .iter()
+            .position(|&b| b != 0)
+            .unwrap_or(bytes.len() - 1);
+        bytes[start..].into()
+    }
+
+    /// Root of a trie with a single leaf at key `rlp(0)`, computed by hand.
+    fn single_leaf_root(value: &[u8]) -> B256 {
+        // hex prefix encoding of the even length leaf path [8, 0]
+        let path = Bytes::from_static(&[0x20, 0x80]);
+        let mut node = Vec::new();
+        alloy_rlp::encode_list::<_, [u8]>(&[&path[..], value], &mut node);
+        keccak256(node)
+    }
+
+    #[test]
+    fn test_verify_roots() {
+        let tx = legacy_tx();
+
+        let encoded_tx =
+            TxEnvelope::try_from(alloy_rpc_types_eth::Transaction::try_from(tx.clone()).unwrap())
+                .unwrap()
+                .encoded_2718();
+        let receipt = to_alloy::receipt(&tx, Vec::new()).unwrap();
+        let encoded_receipt = into_consensus_envelope(receipt.inner)
+            .unwrap()
+            .encoded_2718();
+
+        let good = block(
+            5,
+            single_leaf_root(&encoded_tx),
+            single_leaf_root(&encoded_receipt),
+        );
+        let res = verify_block_roots(&good, std::slice::from_ref(&tx), &[]);
+        assert!(res.is_ok(), "{:?}", res);
+        assert_eq!(res.num_verified, 2);
+
+        // a block without transactions has the empty trie as roots
+        let empty = block(6, EMPTY_ROOT_HASH, EMPTY_ROOT_HASH);
+        assert_eq!(verify_block_roots(&empty, &[], &[]).num_verified, 2);
+
+        // a missing transaction doesn't match
+        let res = verify_block_roots(&good, &[], &[]);
+        assert_eq!(res.mismatches.len(), 2);
+        assert_eq!(res.mismatches[0].root, RootKind::Transactions);
+        assert_eq!(res.mismatches[0].computed, EMPTY_ROOT_HASH);
+
+        // missing receipt fields skip the receipts root only
+        let mut no_receipt = tx;
+        no_receipt.gas_used = None;
+        let res = verify_block_roots(&good, &[no_receipt], &[]);
+        assert_eq!(res.num_verified, 1);
+        assert_eq!(res.skipped.len(), 1);
+        assert_eq!(res.skipped[0].1, RootKind::Receipts);
+    }
+
+    /// Root of the trie with the given keys and values, built node by node like the yellow paper
+    /// describes instead of through `HashBuilder`.
+    fn reference_root(items: &[(Vec<u8>, Vec<u8>)]) -> B256 {
+        let items = items
+            .iter()
+            .map(|(key, value)| {
+                let path = key
+                    .iter()
+                    .flat_map(|b| [b >> 4, b & 0xf])
+                    .collect::<Vec<_>>();
+                (path, value.as_slice())
+            })
+            .collect::<Vec<_>>();
+        keccak256(reference_node(&items))
+    }
+
+    /// Rlp encoding of the node holding the given paths.
+    fn reference_node(items: &[(Vec<u8>, &[u8])]) -> Vec<u8> {
+        let list = |parts: Vec<Vec<u8>>| {
+            let mut out = Vec::new();
+            alloy_rlp::Header {
+                list: true,
+                payload_length: parts.iter().map(Vec::len).sum(),
+            }
+            .encode(&mut out);
+            out.extend(parts.concat());
+            out
+        };
+        // nodes shorter than a hash are embedded into their parent
+        let child = |node: Vec<u8>| match node.len() {
+            0..=31 => node,
+            _ => alloy_rlp::encode(keccak256(node)),
+        };
+        let hex_prefix = |path: &[u8], leaf: bool| {
+            let flag = if leaf { 2 } else { 0 };
+            let (mut out, rest) = match path.len() % 2 {
+                1 => (vec![((flag + 1) << 4) | path[0]], &path[1..]),
+                _ => (vec![flag << 4], path),
+            };
+            out.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+            alloy_rlp::encode(out.as_slice())
+        };
+
+        match items {
+            [] => alloy_rlp::encode(&[][..]),
+            [(path, value)] => list(vec![hex_prefix(path, true), alloy_rlp::encode(*value)]),
+            _ => {
+                let common = (0..)
+                    .take_while(|&i| {
+                        items
+                            .iter()
+                            .all(|(path, _)| path.len() > i && path[i] == items[0].0[i])
+                    })
+                    .count();
+                if common > 0 {
+                    let rest = items
+                        .iter()
+                        .map(|(path, value)| (path[common..].to_vec(), *value))
+                        .collect::<Vec<_>>();
+                    return list(vec![
+                        hex_prefix(&items[0].0[..common], false),
+                        child(reference_node(&rest)),
+                    ]);
+                }
+                let mut parts = (0..16)
+                    .map(|nibble| {
+                        let branch = items
+                            .iter()
+                            .filter(|(path, _)| path.first() == Some(&nibble))
+                            .map(|(path, value)| (path[1..].to_vec(), *value))
+                            .collect::<Vec<_>>();
+                        match branch.is_empty() {
+                            true => alloy_rlp::encode(&[][..]),
+                            false => child(reference_node(&branch)),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let value = items.iter().find(|(path, _)| path.is_empty());
+                parts.push(alloy_rlp::encode(
+                    value.map_or(&[][..], |(_, value)| *value),
+                ));
+                list(parts)
+            }
+        }
+    }
+
+    #[test]
+    fn test_reference_root() {
+        // "anyorder" vector of the ethereum trie tests
+        let items = [
+            ("do", "verb"),
+            ("horse", "stallion"),
+            ("doge", "coin"),
+            ("dog", "puppy"),
+        ]
+        .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()));
+        assert_eq!(
+            reference_root(&items),
+            "0x5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"
+                .parse::<B256>()
+                .unwrap()
+        );
+        assert_eq!(reference_root(&[]), EMPTY_ROOT_HASH);
+    }
+
+    #[test]
+    fn test_verify_roots_many_transactions() {
+        // more than 128 transactions so the rlp encoded indexes go from one to two bytes
+        let num_txs = 130u64;
+        let mut txs = Vec::new();
+        let mut logs = Vec::new();
+        for i in 0..num_txs {
+            let mut tx = legacy_tx();
+            tx.block_number = Some(7.into());
+            tx.transaction_index = Some(i.into());
+            tx.nonce = Some(quantity(i));
+            tx.hash = Some(Hash::from(keccak256(i.to_be_bytes()).0));
+            tx.cumulative_gas_used = Some(quantity(21_000 * (i + 1)));
+            if i % 2 == 1 {
+                tx.kind = Some(TransactionType(2));
+                tx.gas_price = None;
+                tx.chain_id = Some(vec![1].into());
+                tx.max_fee_per_gas = Some(vec![20].into());
+                tx.max_priority_fee_per_gas = Some(vec![2].into());
+                tx.access_list = Some(Vec::new());
+                tx.v = Some(vec![1].into());
+                tx.y_parity = Some(vec![1].into());
+            }
+            if i % 5 == 0 {
+                tx.status = Some(TransactionStatus::Failure);
+            }
+            // logs of a transaction are received out of order
+            for log_index in (0..i % 3).rev() {
+                logs.push(Log {
+                    block_number: Some(7.into()),
+                    transaction_index: Some(i.into()),
+                    log_index: Some((i * 3 + log_index).into()),
+                    address: tx.to.clone(),
+                    data: Some(vec![i as u8; log_index as usize].into()),
+                    topics: [Some(Hash::from(keccak256([log_index as u8]).0))]
+                        .into_iter()
+                        .collect(),
+                    ..Default::default()
+                });
+            }
+            txs.push(tx);
+        }
+
+        // leaves are encoded like the verification does, the trie is built independently
+        let key = |i: u64| alloy_rlp::encode(i);
+        let tx_leaves = txs
+            .iter()
+            .enumerate()
+            .map(|(i, tx)| {
+                let tx = alloy_rpc_types_eth::Transaction::try_from(tx.clone()).unwrap();
+                (
+                    key(i as u64),
+                    TxEnvelope::try_from(tx).unwrap().encoded_2718(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let receipt_leaves = txs
+            .iter()
+            .enumerate()
+            .map(|(i, tx)| {
+                let mut tx_logs = logs
+                    .iter()
+                    .filter(|log| log.transaction_index == tx.transaction_index)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                tx_logs.sort_by_key(|log| log.log_index.map(u64::from));
+                let tx_logs = tx_logs
+                    .into_iter()
+                    .map(|log| alloy_rpc_types_eth::Log::try_from(log).unwrap())
+                    .collect();
+                let receipt = to_alloy::receipt(tx, tx_logs).unwrap();
+                let encoded = into_consensus_envelope(receipt.inner)
+                    .unwrap()
+                    .encoded_2718();
+                (key(i as u64), encoded)
+            })
+            .collect::<Vec<_>>();
+
+        let good = block(
+            7,
+            reference_root(&tx_leaves),
+            reference_root(&receipt_leaves),
+        );
+        // transactions are received out of order too
+        txs.reverse();
+        let res = verify_block_roots(&good, &txs, &logs);
+        assert!(res.is_ok(), "{:?}", res);
+        assert_eq!(res.num_verified, 2);
+        assert!(res.skipped.is_empty());
+
+        // a missing log only changes the receipts root
+        let res = verify_block_roots(&good, &txs, &logs[1..]);
+        assert_eq!(res.num_verified, 1);
+        assert_eq!(res.mismatches.len(), 1);
+        assert_eq!(res.mismatches[0].root, RootKind::Receipts);
+    }
+}
@@ -1,6 +1,7 @@
 //! Base object types for the Hypersync client.
 use std::{collections::HashMap, sync::Arc};
 
+use alloy_dyn_abi::DecodedEvent;
 use arrayvec::ArrayVec;
 use hypersync_format::{
     AccessList, Address, BlockNumber, BloomFilter, Data, Hash, LogArgument, LogIndex, Nonce,
@@ -21,6 +22,37 @@ pub struct Event {
     pub block: Option<Arc<Block>>,
     /// An Ethereum event log object.
     pub log: Log,
+    /// The log decoded with `StreamConfig::event_signature`, None if no signature was given or
+    /// the log couldn't be decoded.
+    pub decoded: Option<DecodedEvent>,
+}
+
+impl Event {
+    /// Block number of the event, taken from the log or the joined block.
+    pub fn block_number(&self) -> Option<u64> {
+        self.log
+            .block_number
+            .map(u64::from)
+            .or_else(|| self.block.as_ref().and_then(|b| b.number))
+    }
+
+    /// Index of the log within its block.
+    pub fn log_index(&self) -> Option<u64> {
+        self.log.log_index.map(u64::from)
+    }
+
+    /// Index of the transaction that emitted the log within its block, taken from the log or
+    /// the joined transaction.
+    pub fn transaction_index(&self) -> Option<u64> {
+        self.log
+            .transaction_index
+            .or_else(|| {
+                self.transaction
+                    .as_ref()
+                    .and_then(|tx| tx.transaction_index)
+            })
+            .map(u64::from)
+    }
 }
 
 impl From<ResponseData> for Vec<Event> {
@@ -44,10 +76,13 @@ impl From<ResponseData> for Vec<Event> {
             })
             .collect::<HashMap<_, _, Xxh3Builder>>();
 
+        let (blocks, transactions) = (&blocks, &transactions);
+        let mut decoded_logs = data.decoded_logs.into_iter();
         data.logs
             .into_iter()
             .flat_map(|logs| {
-                logs.into_iter().map(|log| {
+                let mut decoded = decoded_logs.next().unwrap_or_default().into_iter();
+                logs.into_iter().map(move |log| {
                     let block = blocks.get(&log.block_number.unwrap().into()).cloned();
                     let transaction = transactions
                         .get(log.transaction_hash.as_ref().unwrap())
@@ -57,6 +92,7 @@ impl From<ResponseData> for Vec<Event> {
                         transaction,
                         block,
                         log,
+                        decoded: decoded.next().flatten(),
                     }
                 })
             })
@@ -0,0 +1,155 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use polars_arrow::io::ipc::write::{Compression, FileWriter, WriteOptions};
+
+use crate::{parse_response, types::ArrowResponse, ArrowBatch, StreamMetrics};
+
+/// Numbers the spill files of all streams in this process.
+static NEXT_FILE: AtomicU64 = AtomicU64::new(0);
+
+/// Responses of a range request that were moved to a file in `StreamConfig::spill_dir` until it
+/// is their turn to be passed on.
+///
+/// Every batch of their tables is written to the file as an lz4 compressed arrow IPC file, the
+/// rest of the responses stays in memory. The file is removed when this is dropped.
+pub(crate) struct SpilledResponses {
+    path: PathBuf,
+    /// The responses with their blocks, transactions, logs and traces taken out.
+    resps: Vec<ArrowResponse>,
+    /// Byte ranges of the batches of the four tables of every response in the file.
+    tables: Vec<[Vec<(usize, usize)>; 4]>,
+    size: u64,
+}
+
+impl SpilledResponses {
+    /// Writes the tables of the responses and their size, as returned by a range request, to a
+    /// new file in `dir`.
+    pub(crate) fn write(
+        dir: &Path,
+        (resps, size): (Vec<ArrowResponse>, u64),
+        metrics: Option<&StreamMetrics>,
+    ) -> Result<Self> {
+        let start = Instant::now();
+        let name = format!(
+            "hypersync-spill-{}-{}",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut spilled = Self {
+            path: dir.join(name),
+            resps: Vec::with_capacity(resps.len()),
+            tables: Vec::with_capacity(resps.len()),
+            size,
+        };
+
+        let file = File::create(&spilled.path).context("create spill file")?;
+        let mut writer = BufWriter::new(file);
+        let mut buf = Vec::new();
+        let mut file_len = 0;
+        let mut decompressed_bytes = 0;
+        for mut resp in resps {
+            decompressed_bytes += resp.transfer.decompressed_bytes;
+            let mut ranges: [Vec<(usize, usize)>; 4] = Default::default();
+            for (batches, ranges) in tables(&mut resp).into_iter().zip(ranges.iter_mut()) {
+                for batch in std::mem::take(batches) {
+                    buf.clear();
+                    write_batch(&mut buf, &batch)?;
+                    writer.write_all(&buf).context("write spill file")?;
+                    ranges.push((file_len, buf.len()));
+                    file_len += buf.len();
+                }
+            }
+            spilled.resps.push(resp);
+            spilled.tables.push(ranges);
+        }
+        writer.flush().context("write spill file")?;
+
+        if let Some(metrics) = metrics {
+            metrics.record_spill(
+                u64::try_from(spilled.resps.len()).unwrap(),
+                decompressed_bytes,
+                u64::try_from(file_len).unwrap(),
+                start.elapsed(),
+            );
+        }
+
+        Ok(spilled)
+    }
+
+    /// Reads the responses back from the memory mapped file and removes it.
+    pub(crate) fn read(
+        mut self,
+        metrics: Option<&StreamMetrics>,
+    ) -> Result<(Vec<ArrowResponse>, u64)> {
+        let start = Instant::now();
+        let file = File::open(&self.path).context("open spill file")?;
+        // empty files can't be mapped, they are left when none of the responses had any rows
+        let mmap = match file.metadata().context("read spill file metadata")?.len() {
+            0 => None,
+            // SAFETY: the file is only written by this stream, before it is mapped
+            _ => Some(unsafe { memmap2::Mmap::map(&file) }.context("map spill file")?),
+        };
+        let bytes = mmap.as_deref().unwrap_or_default();
+
+        let mut resps = std::mem::take(&mut self.resps);
+        for (resp, ranges) in resps.iter_mut().zip(&self.tables) {
+            for (batches, ranges) in tables(resp).into_iter().zip(ranges) {
+                for &(offset, len) in ranges {
+                    let bytes = bytes
+                        .get(offset..offset + len)
+                        .context("spill file is truncated")?;
+                    // the file was written by this stream so it doesn't need to be validated
+                    let (chunks, _) =
+                        parse_response::read_chunks(bytes, true).context("read spilled batch")?;
+                    batches.extend(chunks);
+                }
+            }
+        }
+
+        if let Some(metrics) = metrics {
+            metrics.record_spill_read(start.elapsed());
+        }
+
+        Ok((resps, self.size))
+    }
+}
+
+impl Drop for SpilledResponses {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("failed to remove spill file {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+fn tables(resp: &mut ArrowResponse) -> [&mut Vec<ArrowBatch>; 4] {
+    [
+        &mut resp.data.blocks,
+        &mut resp.data.transactions,
+        &mut resp.data.logs,
+        &mut resp.data.traces,
+    ]
+}
+
+fn write_batch(out: &mut Vec<u8>, batch: &ArrowBatch) -> Result<()> {
+    let mut writer = FileWriter::try_new(
+        out,
+        batch.schema.clone(),
+        None,
+        WriteOptions {
+            compression: Some(Compression::LZ4),
+        },
+    )
+    .context("create ipc writer")?;
+    writer.write(&batch.chunk, None).context("write batch")?;
+    writer.finish().context("finish ipc file")
+}
@@ -0,0 +1,153 @@
+use std::{fmt, sync::Arc};
+
+use tokio::sync::mpsc;
+
+use crate::{stream::spawn_until_closed, ArrowResponse, StreamConfig};
+
+/// Decides when a stream ends early, registered with `StreamConfig::stop_condition`.
+///
+/// Called with every response of the stream before it is passed on. Once it returns true, that
+/// response is the last one the stream sends. Implemented for closures taking an
+/// `&ArrowResponse`:
+///
+///     use std::sync::Arc;
+///     use hypersync_client::{ArrowResponse, StreamConfig};
+///
+///     // stop at the first response that has a log
+///     let config = StreamConfig {
+///         stop_condition: Some(Arc::new(|resp: &ArrowResponse| {
+///             resp.data.logs.iter().any(|batch| batch.num_rows() > 0)
+///         })),
+///         ..Default::default()
+///     };
+pub trait StopCondition: Send + Sync {
+    /// Returns true if the stream should end after this response.
+    fn should_stop(&self, resp: &ArrowResponse) -> bool;
+}
+
+impl<F: Fn(&ArrowResponse) -> bool + Send + Sync> StopCondition for F {
+    fn should_stop(&self, resp: &ArrowResponse) -> bool {
+        self(resp)
+    }
+}
+
+impl fmt::Debug for dyn StopCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StopCondition")
+    }
+}
+
+/// Returns true if the stream ends on its own through one of the stop conditions of the config.
+pub(crate) fn has_stop_condition(config: &StreamConfig) -> bool {
+    config.max_num_blocks.is_some()
+        || config.max_num_transactions.is_some()
+        || config.max_num_logs.is_some()
+        || config.max_num_traces.is_some()
+        || config.max_bytes.is_some()
+        || config.stop_condition.is_some()
+}
+
+/// Ends the stream after the response that reaches `max_bytes` or fulfills the condition, and
+/// drops the inner receiver so the requests still running are stopped.
+pub(crate) fn stop_when(
+    mut inner_rx: mpsc::Receiver<anyhow::Result<ArrowResponse>>,
+    max_bytes: Option<u64>,
+    condition: Option<Arc<dyn StopCondition>>,
+) -> mpsc::Receiver<anyhow::Result<ArrowResponse>> {
+    let (tx, rx) = mpsc::channel(1);
+
+    spawn_until_closed(tx.clone(), async move {
+        let mut num_bytes = 0;
+        while let Some(resp) = inner_rx.recv().await {
+            let stop = match &resp {
+                Ok(resp) => {
+                    num_bytes += resp.transfer.decompressed_bytes;
+                    max_bytes.is_some_and(|max| num_bytes >= max)
+                        || condition.as_ref().is_some_and(|c| c.should_stop(resp))
+                }
+                Err(_) => true,
+            };
+            if tx.send(resp).await.is_err() || stop {
+                return;
+            }
+        }
+    });
+
+    rx
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::mock_server::{blocks_query, mock_client, MockChain, MockServer};
+    use crate::{ArrowResponseData, QueryResponse};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stop_conditions() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+        let config = StreamConfig {
+            batch_size: Some(10),
+            max_batch_size: Some(10),
+            ..Default::default()
+        };
+        let num_blocks = |res: &QueryResponse<ArrowResponseData>| {
+            res.data.blocks.iter().map(|b| b.num_rows()).sum::<usize>()
+        };
+
+        let res = client
+            .clone()
+            .collect_arrow(
+                blocks_query(0, 100),
+                StreamConfig {
+                    stop_condition: Some(Arc::new(|resp: &ArrowResponse| resp.next_block >= 30)),
+                    ..config.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.next_block, 30);
+        assert_eq!(num_blocks(&res), 30);
+
+        let res = client
+            .clone()
+            .collect_arrow(
+                blocks_query(0, 100),
+                StreamConfig {
+                    max_bytes: Some(1),
+                    ..config.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.next_block, 10);
+
+        // a followed stream can be collected once it ends on its own
+        let mut query = blocks_query(0, 0);
+        query.to_block = None;
+        let follow_config = StreamConfig {
+            follow: true,
+            follow_poll_interval_millis: Some(10),
+            ..config.clone()
+        };
+        assert!(client
+            .clone()
+            .collect_arrow(query.clone(), follow_config.clone())
+            .await
+            .is_err());
+        let res = client
+            .clone()
+            .collect_arrow(
+                query,
+                StreamConfig {
+                    max_num_blocks: Some(25),
+                    ..follow_config
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.next_block, 30);
+        assert_eq!(num_blocks(&res), 30);
+    }
+}
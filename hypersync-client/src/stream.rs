@@ -1,57 +1,799 @@
 use std::{
     cmp,
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    future::Future,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context, Result};
-use hypersync_net_types::Query;
+use hypersync_net_types::{FieldSelection, Query};
 use polars_arrow::{
-    array::{Array, BinaryArray, BooleanArray, UInt64Array, UInt8Array, Utf8Array},
+    array::{
+        new_empty_array, Array, BinaryArray, BooleanArray, ListArray, UInt64Array, UInt8Array,
+        Utf8Array,
+    },
+    bitmap::Bitmap,
+    compute::concatenate::concatenate,
     datatypes::ArrowDataType,
+    offset::{Offset, Offsets},
     record_batch::RecordBatch,
 };
+use reqwest::StatusCode;
 use tokio::sync::mpsc;
 use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
+    bloom_prescan::{
+        check_query as check_bloom_prescan_query, may_match as bloom_may_match, CandidateRanges,
+    },
+    checkpoint::resume_query,
+    concurrency::AimdConcurrency,
     config::HexOutput,
+    error::{self, Cancelled, ErrorKind},
+    nested_columns::nest_transaction_list_columns,
     rayon_async,
-    types::ArrowResponse,
-    util::{decode_logs_batch, hex_encode_batch, hex_encode_prefixed},
-    ArrowBatch, ArrowResponseData, StreamConfig,
+    reorg::ReorgDetector,
+    spill::SpilledResponses,
+    stop_condition,
+    time_range::resolve_time_range,
+    trace_summary::{check_field_selection as check_trace_summary_fields, summarize_traces},
+    types::{ArrowResponse, Rollback},
+    util::{
+        decode_event_logs_batch, decode_logs_batch, decoded_log_column_names, dedup_transactions,
+        drop_rows_until, filter_logs_by_address_shard, hex_encode_batch, hex_encode_prefixed,
+    },
+    ArrowBatch, ArrowResponseData, Checkpoint, Error, ResumePoint, StreamConfig, StreamMetrics,
 };
 
+#[cfg(feature = "websocket")]
+mod ws;
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(from_block = query.from_block, to_block = ?query.to_block)
+    )
+)]
 pub async fn stream_arrow(
+    client: Arc<crate::Client>,
+    mut query: Query,
+    mut config: StreamConfig,
+) -> Result<mpsc::Receiver<Result<ArrowResponse, Error>>> {
+    resolve_time_range(&client, &mut query, &mut config)
+        .await
+        .context("resolve time range")?;
+
+    if let Some(mapping) = config.column_mapping.as_ref() {
+        validate_column_mapping(mapping, &query, &config)?;
+    }
+    if let Some(shard) = config.address_shard {
+        if shard.index >= shard.num_shards.get() {
+            return Err(anyhow!(
+                "address shard index {} is out of range for {} shards",
+                shard.index,
+                shard.num_shards
+            ));
+        }
+    }
+
+    if config.follow {
+        if query.to_block.is_some() {
+            return Err(anyhow!(
+                "config.follow can't be combined with query.to_block"
+            ));
+        }
+        if config.reverse.unwrap_or_default() {
+            return Err(anyhow!(
+                "config.follow can't be combined with config.reverse"
+            ));
+        }
+        #[cfg(feature = "websocket")]
+        if config.transport == crate::StreamTransport::WebSocket {
+            return Err(anyhow!(
+                "config.follow can't be combined with the websocket transport, which already \
+                 follows the tip of the chain"
+            ));
+        }
+    }
+
+    if config.bloom_prescan {
+        check_bloom_prescan_query(&query)?;
+        if config.follow {
+            return Err(anyhow!(
+                "config.bloom_prescan can't be combined with config.follow"
+            ));
+        }
+        if config.reverse.unwrap_or_default() {
+            return Err(anyhow!(
+                "config.bloom_prescan can't be combined with config.reverse"
+            ));
+        }
+        #[cfg(feature = "websocket")]
+        if config.transport == crate::StreamTransport::WebSocket {
+            return Err(anyhow!(
+                "config.bloom_prescan can't be combined with the websocket transport"
+            ));
+        }
+    }
+
+    if config.unordered {
+        check_unordered_config(&config)?;
+    }
+
+    if config.resume_after.is_some() && config.reverse.unwrap_or_default() {
+        return Err(anyhow!(
+            "config.resume_after can't be combined with config.reverse"
+        ));
+    }
+
+    check_skip_failed_ranges(&config)?;
+
+    if let Some(spill_dir) = config.spill_dir.as_ref() {
+        if config.max_buffered_bytes.is_none() {
+            return Err(anyhow!(
+                "config.spill_dir can't be used without config.max_buffered_bytes"
+            ));
+        }
+        #[cfg(feature = "websocket")]
+        if config.transport == crate::StreamTransport::WebSocket {
+            return Err(anyhow!(
+                "config.spill_dir can't be combined with the websocket transport"
+            ));
+        }
+        std::fs::create_dir_all(spill_dir).context("create spill dir")?;
+    }
+
+    if config.trace_summary {
+        check_trace_summary_fields(&query.field_selection)?;
+    }
+
+    let client = match config.max_total_retry_duration_millis {
+        Some(limit) => Arc::new(client.with_retry_budget(std::time::Duration::from_millis(limit))),
+        None => client,
+    };
+
+    let checkpoint = config.checkpoint.clone();
+    if let Some(checkpoint) = checkpoint.as_deref() {
+        if !resume_query(&mut query, checkpoint, config.reverse.unwrap_or_default()).await? {
+            let (_, rx) = mpsc::channel(1);
+            return Ok(rx);
+        }
+    }
+
+    let cancellation_token = config.cancellation_token.clone();
+    if cancellation_token
+        .as_ref()
+        .is_some_and(|token| token.is_cancelled())
+    {
+        return Err(Cancelled.into());
+    }
+    let error_on_empty = config.error_on_empty;
+    let max_bytes = config.max_bytes;
+    let stop_condition = config.stop_condition.clone();
+    let rx = if config.follow {
+        follow(client, query, config)
+    } else if config.bloom_prescan {
+        bloom_prescan(client, query, config)
+    } else {
+        run_stream(client, query, config).await?
+    };
+    let rx = if max_bytes.is_some() || stop_condition.is_some() {
+        stop_condition::stop_when(rx, max_bytes, stop_condition)
+    } else {
+        rx
+    };
+    let rx = if error_on_empty {
+        fail_if_empty(rx)
+    } else {
+        rx
+    };
+
+    let rx = match checkpoint {
+        Some(checkpoint) => save_checkpoints(rx, checkpoint),
+        None => typed_errors(rx),
+    };
+
+    Ok(match cancellation_token {
+        Some(token) => cancel_on(rx, token),
+        None => rx,
+    })
+}
+
+/// Spawns a task of a stream that is dropped as soon as the receiver of `tx` is, so the requests
+/// it waits on and the tasks it owns are aborted right away instead of running until it next
+/// tries to send. `tx` is a clone of the sender the task sends its responses on.
+pub(crate) fn spawn_until_closed<T: Send + 'static>(
+    tx: mpsc::Sender<T>,
+    task: impl Future<Output = ()> + Send + 'static,
+) {
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = task => {}
+            _ = tx.closed() => {}
+        }
+    });
+}
+
+/// Forwards the responses of the stream with their errors converted into the [`Error`] returned
+/// by the public API.
+fn typed_errors(
+    mut inner_rx: mpsc::Receiver<Result<ArrowResponse>>,
+) -> mpsc::Receiver<Result<ArrowResponse, Error>> {
+    let (tx, rx) = mpsc::channel(1);
+
+    spawn_until_closed(tx.clone(), async move {
+        while let Some(resp) = inner_rx.recv().await {
+            if tx.send(resp.map_err(Error::from)).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Forwards the responses of the stream until the token is cancelled, then drops the stream and
+/// sends a `Cancelled` error.
+fn cancel_on(
+    mut inner_rx: mpsc::Receiver<Result<ArrowResponse, Error>>,
+    token: CancellationToken,
+) -> mpsc::Receiver<Result<ArrowResponse, Error>> {
+    let (tx, rx) = mpsc::channel(1);
+
+    spawn_until_closed(tx.clone(), async move {
+        let cancelled = tokio::select! {
+            _ = async {
+                while let Some(resp) = inner_rx.recv().await {
+                    if tx.send(resp).await.is_err() {
+                        return;
+                    }
+                }
+            } => false,
+            _ = token.cancelled() => true,
+        };
+        if cancelled {
+            drop(inner_rx);
+            tx.send(Err(anyhow::Error::from(Cancelled).into()))
+                .await
+                .ok();
+        }
+    });
+
+    rx
+}
+
+/// Holds back the responses of the stream until one of them has a row, sending an error instead
+/// of them if the stream ends before that.
+///
+/// The empty responses are merged into the one held back, so a long stretch without rows
+/// doesn't pile up in memory.
+fn fail_if_empty(
+    mut inner_rx: mpsc::Receiver<Result<ArrowResponse>>,
+) -> mpsc::Receiver<Result<ArrowResponse>> {
+    let (tx, rx) = mpsc::channel(1);
+
+    spawn_until_closed(tx.clone(), async move {
+        let mut held_back: Option<ArrowResponse> = None;
+        while let Some(resp) = inner_rx.recv().await {
+            match resp {
+                Ok(resp) if !has_rows(&resp) => {
+                    held_back = Some(match held_back.take() {
+                        Some(prev) => merge_empty(prev, resp),
+                        None => resp,
+                    });
+                }
+                resp => {
+                    let found_rows = resp.is_ok();
+                    if let Some(held_back) = held_back.take() {
+                        if tx.send(Ok(held_back)).await.is_err() {
+                            return;
+                        }
+                    }
+                    if tx.send(resp).await.is_err() || !found_rows {
+                        return;
+                    }
+                    while let Some(resp) = inner_rx.recv().await {
+                        if tx.send(resp).await.is_err() {
+                            return;
+                        }
+                    }
+                    return;
+                }
+            }
+        }
+
+        tx.send(Err(anyhow!(
+            "stream ended without returning any rows and config.error_on_empty is set"
+        )))
+        .await
+        .ok();
+    });
+
+    rx
+}
+
+/// Merges two consecutive responses without rows into one that covers both.
+fn merge_empty(prev: ArrowResponse, mut resp: ArrowResponse) -> ArrowResponse {
+    resp.total_execution_time += prev.total_execution_time;
+    resp.transfer.add(&prev.transfer);
+    resp.rollback = match (prev.rollback, resp.rollback) {
+        (Some(a), Some(b)) => Some(Rollback {
+            from_block: cmp::min(a.from_block, b.from_block),
+        }),
+        (a, b) => a.or(b),
+    };
+    resp
+}
+
+fn has_rows(resp: &ArrowResponse) -> bool {
+    let data = &resp.data;
+    [&data.blocks, &data.transactions, &data.logs, &data.traces]
+        .into_iter()
+        .flatten()
+        .any(|batch| batch.num_rows() > 0)
+}
+
+/// Forwards the responses of the stream, storing the `next_block` of each one in the checkpoint
+/// after passing it on. Errors are converted into the [`Error`] returned by the public API.
+fn save_checkpoints(
+    mut inner_rx: mpsc::Receiver<Result<ArrowResponse>>,
+    checkpoint: Arc<dyn Checkpoint>,
+) -> mpsc::Receiver<Result<ArrowResponse, Error>> {
+    let (tx, rx) = mpsc::channel(1);
+
+    spawn_until_closed(tx.clone(), async move {
+        let Ok(mut permit) = tx.reserve().await else {
+            return;
+        };
+        // next block of the response the receiver took last
+        let mut taken = None;
+        while let Some(resp) = inner_rx.recv().await {
+            let next_block = match &resp {
+                Ok(resp) => resp.next_block,
+                Err(_) => {
+                    permit.send(resp.map_err(Error::from));
+                    return;
+                }
+            };
+            permit.send(resp.map_err(Error::from));
+            // the channel only has room again once the receiver took the response, so it is
+            // done with the one before
+            permit = match tx.reserve().await {
+                Ok(permit) => permit,
+                Err(_) => return,
+            };
+            if let Some(done) = taken.replace(next_block) {
+                if let Err(e) = checkpoint.save_next_block(done).await {
+                    permit.send(Err(e.context("save checkpoint").into()));
+                    return;
+                }
+            }
+        }
+        if let Some(done) = taken {
+            if let Err(e) = checkpoint.save_next_block(done).await {
+                permit.send(Err(e.context("save checkpoint").into()));
+            }
+        }
+    });
+
+    rx
+}
+
+/// Streams up to the current height, then waits for the chain to grow past it and streams the
+/// new blocks, until the receiver is dropped or a request fails.
+fn follow(
+    client: Arc<crate::Client>,
+    mut query: Query,
+    config: StreamConfig,
+) -> mpsc::Receiver<Result<ArrowResponse>> {
+    let head_mode = config.head_mode;
+    let poll_interval = match head_mode {
+        Some(head_mode) => head_mode.poll_interval_millis.unwrap_or(100),
+        None => config.follow_poll_interval_millis.unwrap_or(1000),
+    };
+    let poll_interval = Duration::from_millis(poll_interval);
+    let keep_warm = config.keep_warm_interval_millis.map(Duration::from_millis);
+    let (tx, rx) = mpsc::channel(config.concurrency.unwrap_or(10));
+
+    spawn_until_closed(tx.clone(), async move {
+        let start_block = query.from_block;
+        let mut reorgs = ReorgDetector::default();
+        let mut rollback = None;
+        let mut at_head = false;
+        let mut num_blocks = 0;
+        let mut num_transactions = 0;
+        let mut num_logs = 0;
+        let mut num_traces = 0;
+
+        'follow: loop {
+            if check_entity_limit(num_blocks, config.max_num_blocks)
+                || check_entity_limit(num_transactions, config.max_num_transactions)
+                || check_entity_limit(num_logs, config.max_num_logs)
+                || check_entity_limit(num_traces, config.max_num_traces)
+            {
+                return;
+            }
+            if let Some(control) = config.control.as_ref() {
+                control.wait_while_paused().await;
+            }
+
+            let height = match client.get_height().await.context("get height") {
+                Ok(height) => height,
+                Err(e) => {
+                    tx.send(Err(e)).await.ok();
+                    return;
+                }
+            };
+            if height <= query.from_block {
+                if tx.is_closed() {
+                    return;
+                }
+                wait_for_poll(&client, poll_interval, keep_warm).await;
+                continue;
+            }
+
+            let head_batch_size = head_mode
+                .filter(|head_mode| height - query.from_block <= head_mode.distance)
+                .map(|head_mode| head_mode.batch_size.unwrap_or(1).max(1));
+            if head_batch_size.is_some() != at_head {
+                at_head = head_batch_size.is_some();
+                log::debug!(
+                    "{} head mode at block {} with height {}",
+                    if at_head { "entering" } else { "leaving" },
+                    query.from_block,
+                    height
+                );
+            }
+            query.to_block = Some(match head_batch_size {
+                Some(batch_size) => cmp::min(height, query.from_block.saturating_add(batch_size)),
+                None => height,
+            });
+            // the limits are for the whole stream so each catch up only gets what is left
+            let run_config = StreamConfig {
+                max_num_blocks: config.max_num_blocks.map(|limit| limit - num_blocks),
+                max_num_transactions: config
+                    .max_num_transactions
+                    .map(|limit| limit - num_transactions),
+                max_num_logs: config.max_num_logs.map(|limit| limit - num_logs),
+                max_num_traces: config.max_num_traces.map(|limit| limit - num_traces),
+                ..config.clone()
+            };
+            let mut inner_rx = match run_stream(client.clone(), query.clone(), run_config).await {
+                Ok(rx) => rx,
+                Err(e) => {
+                    tx.send(Err(e)).await.ok();
+                    return;
+                }
+            };
+            while let Some(mut resp) = inner_rx.recv().await {
+                let is_err = resp.is_err();
+                if let Ok(resp) = &mut resp {
+                    if let Some(from_block) = reorgs.observe(resp) {
+                        // refetch the replaced blocks, flagging the next response so the
+                        // consumer drops what it got from the old fork. The replaced blocks can
+                        // start above this response if the stream is further behind than the
+                        // unfinalized blocks, it is refetched from its own start then.
+                        let from_block =
+                            cmp::max(cmp::min(from_block, query.from_block), start_block);
+                        log::warn!(
+                            "detected a reorg in the response up to block {}, rolling back to \
+                             block {}",
+                            resp.next_block,
+                            from_block
+                        );
+                        rollback = Some(match rollback {
+                            Some(Rollback { from_block: prev }) => Rollback {
+                                from_block: cmp::min(prev, from_block),
+                            },
+                            None => Rollback { from_block },
+                        });
+                        query.from_block = from_block;
+                        continue 'follow;
+                    }
+                    resp.rollback = rollback.take();
+                    query.from_block = resp.next_block;
+                    num_blocks += count_rows(&resp.data.blocks);
+                    num_transactions += count_rows(&resp.data.transactions);
+                    num_logs += count_rows(&resp.data.logs);
+                    num_traces += count_rows(&resp.data.traces);
+                }
+                if tx.send(resp).await.is_err() || is_err {
+                    return;
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+/// Scans the logs blooms of the range, then streams the query over the blocks whose bloom may
+/// match it, ending with a response without data for the end of the scanned range.
+///
+/// The candidate ranges are fetched by a single stream, so small ranges run concurrently
+/// instead of one after another.
+fn bloom_prescan(
     client: Arc<crate::Client>,
     query: Query,
     config: StreamConfig,
+) -> mpsc::Receiver<Result<ArrowResponse>> {
+    let (tx, rx) = mpsc::channel(config.concurrency.unwrap_or(10));
+
+    spawn_until_closed(tx.clone(), async move {
+        let (ranges, last) = match scan_blooms(&client, &query, &config).await {
+            Ok(scan) => scan,
+            Err(e) => {
+                tx.send(Err(e)).await.ok();
+                return;
+            }
+        };
+        log::debug!(
+            "bloom prescan found {} candidate block ranges",
+            ranges.len()
+        );
+
+        let mut num_blocks = 0;
+        let mut num_transactions = 0;
+        let mut num_logs = 0;
+        let mut num_traces = 0;
+
+        let mut inner_rx =
+            match run_stream_over(client.clone(), query, config.clone(), Some(ranges)).await {
+                Ok(rx) => rx,
+                Err(e) => {
+                    tx.send(Err(e)).await.ok();
+                    return;
+                }
+            };
+        while let Some(resp) = inner_rx.recv().await {
+            let is_err = resp.is_err();
+            if let Ok(resp) = &resp {
+                num_blocks += count_rows(&resp.data.blocks);
+                num_transactions += count_rows(&resp.data.transactions);
+                num_logs += count_rows(&resp.data.logs);
+                num_traces += count_rows(&resp.data.traces);
+            }
+            if tx.send(resp).await.is_err() || is_err {
+                return;
+            }
+        }
+
+        // the stream stopped early, the end of the scanned range wasn't reached
+        if check_entity_limit(num_blocks, config.max_num_blocks)
+            || check_entity_limit(num_transactions, config.max_num_transactions)
+            || check_entity_limit(num_logs, config.max_num_logs)
+            || check_entity_limit(num_traces, config.max_num_traces)
+        {
+            return;
+        }
+        if let Some(last) = last {
+            tx.send(Ok(last)).await.ok();
+        }
+    });
+
+    rx
+}
+
+/// Streams the `number` and `logs_bloom` of every block in the range of the query, returning
+/// the ranges of blocks whose bloom may match its log selections and the last response of the
+/// scan with its data removed.
+async fn scan_blooms(
+    client: &Arc<crate::Client>,
+    query: &Query,
+    config: &StreamConfig,
+) -> Result<(Vec<(u64, u64)>, Option<ArrowResponse>)> {
+    let scan_query = Query {
+        from_block: query.from_block,
+        to_block: query.to_block,
+        include_all_blocks: true,
+        field_selection: FieldSelection {
+            block: ["number", "logs_bloom"].map(String::from).into(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    // only the options affecting how the range is fetched apply to the scan
+    let scan_config = StreamConfig {
+        batch_size: config.batch_size,
+        max_batch_size: config.max_batch_size,
+        min_batch_size: config.min_batch_size,
+        concurrency: config.concurrency,
+        auto_concurrency: config.auto_concurrency,
+        response_bytes_ceiling: config.response_bytes_ceiling,
+        response_bytes_floor: config.response_bytes_floor,
+        response_time_ceiling_millis: config.response_time_ceiling_millis,
+        load_balancing: config.load_balancing,
+        metrics: config.metrics.clone(),
+        ..Default::default()
+    };
+
+    let mut rx = run_stream(client.clone(), scan_query, scan_config)
+        .await
+        .context("start bloom scan")?;
+    let mut candidates = CandidateRanges::default();
+    let mut last = None;
+    while let Some(resp) = rx.recv().await {
+        let mut resp = resp.context("scan blooms")?;
+        for batch in resp.data.blocks.iter() {
+            let number = batch.u64_column("number")?;
+            let bloom = batch.column::<BinaryArray<i32>>("logs_bloom")?;
+            for (number, bloom) in number.values_iter().zip(bloom.iter()) {
+                if bloom.is_none_or(|bloom| bloom_may_match(bloom, &query.logs)) {
+                    candidates.push(*number);
+                }
+            }
+        }
+        resp.data = ArrowResponseData::default();
+        last = Some(resp);
+    }
+
+    Ok((candidates.into_ranges(), last))
+}
+
+/// Sleeps until the next height poll, sending a height request every `keep_warm` in between.
+async fn wait_for_poll(
+    client: &crate::Client,
+    poll_interval: Duration,
+    keep_warm: Option<Duration>,
+) {
+    let keep_warm = match keep_warm.filter(|keep_warm| *keep_warm < poll_interval) {
+        Some(keep_warm) => keep_warm,
+        None => return tokio::time::sleep(poll_interval).await,
+    };
+
+    let deadline = Instant::now() + poll_interval;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining <= keep_warm {
+            return tokio::time::sleep(remaining).await;
+        }
+        tokio::time::sleep(keep_warm).await;
+
+        let (_, url) = client.endpoints.current();
+        if let Err(e) = client.get_height_impl(url.clone(), None).await {
+            log::debug!("keep warm request failed: {:?}", e);
+        }
+    }
+}
+
+async fn run_stream(
+    client: Arc<crate::Client>,
+    query: Query,
+    config: StreamConfig,
+) -> Result<mpsc::Receiver<Result<ArrowResponse>>> {
+    run_stream_over(client, query, config, None).await
+}
+
+/// Runs the query like `run_stream`, only fetching the given `[from_block, to_block)` ranges
+/// if they are set. The ranges have to be in increasing order and the stream can't be
+/// reversed.
+async fn run_stream_over(
+    client: Arc<crate::Client>,
+    query: Query,
+    mut config: StreamConfig,
+    ranges: Option<Vec<(u64, u64)>>,
 ) -> Result<mpsc::Receiver<Result<ArrowResponse>>> {
-    let concurrency = config.concurrency.unwrap_or(10);
+    #[cfg(feature = "websocket")]
+    if config.transport == crate::StreamTransport::WebSocket {
+        return ws::stream_arrow(client, query, config).await;
+    }
+
+    let start = Instant::now();
+    let mut concurrency = config.concurrency.unwrap_or(10);
+    if let Some(max_connections) = client.max_connections() {
+        if concurrency > max_connections {
+            log::warn!(
+                "stream concurrency {} is above max_connections {} of the client, running {} \
+                 requests in parallel",
+                concurrency,
+                max_connections,
+                max_connections
+            );
+            concurrency = max_connections;
+        }
+    }
+    if config.on_progress.is_some() || config.skip_failed_ranges {
+        config
+            .metrics
+            .get_or_insert_with(|| Arc::new(StreamMetrics::default()));
+    }
+    let auto_concurrency = config.auto_concurrency.then(|| {
+        // retries are counted through the metrics of the stream
+        let metrics = config
+            .metrics
+            .get_or_insert_with(|| Arc::new(StreamMetrics::default()))
+            .clone();
+        let aimd = AimdConcurrency::new(cmp::min(2, concurrency), concurrency, Instant::now());
+        (Arc::new(aimd), metrics)
+    });
     let batch_size = config.batch_size.unwrap_or(1000);
     let max_batch_size = config.max_batch_size.unwrap_or(200_000);
     let min_batch_size = config.min_batch_size.unwrap_or(200);
     let response_size_ceiling = config.response_bytes_ceiling.unwrap_or(500_000);
     let response_size_floor = config.response_bytes_floor.unwrap_or(250_000);
+    let response_time_ceiling = config
+        .response_time_ceiling_millis
+        .map(Duration::from_millis);
     let reverse = config.reverse.unwrap_or_default();
+    let load_balancing = config.load_balancing;
+    let unordered = config.unordered;
+    let range_failures = Arc::new(RangeFailurePolicy {
+        retries: config.range_retries.unwrap_or(0),
+        skip: config.skip_failed_ranges,
+        metrics: config.metrics.clone(),
+    });
 
     let step = Arc::new(AtomicU64::new(batch_size));
 
-    let (tx, rx) = mpsc::channel(concurrency * 2);
+    let max_buffered_bytes = config.max_buffered_bytes;
+    // the budget can only hold back responses it counts, so the receiver only gets one slot
+    let (tx, rx) = mpsc::channel(if max_buffered_bytes.is_some() {
+        1
+    } else {
+        concurrency * 2
+    });
+    let buffered_bytes = Arc::new(AtomicU64::new(0));
 
     let to_block = match query.to_block {
         Some(to_block) => to_block,
         None => client.get_height().await.context("get height")?,
     };
 
-    tokio::spawn(async move {
+    if let Some(metrics) = config.metrics.as_ref() {
+        if !reverse {
+            metrics.start_progress(query.from_block, to_block, start);
+        }
+        metrics.record_batch_size(batch_size);
+    }
+
+    let backoff = Arc::new(PayloadBackoff {
+        step: step.clone(),
+        min_batch_size,
+        to_block,
+        metrics: config.metrics.clone(),
+    });
+
+    spawn_until_closed(tx.clone(), async move {
         let mut query = query;
 
+        let mut num_blocks = 0;
+        let mut num_transactions = 0;
+        let mut num_logs = 0;
+        let mut num_traces = 0;
+        let limit_reached = |num_blocks, num_transactions, num_logs, num_traces| {
+            check_entity_limit(num_blocks, config.max_num_blocks)
+                || check_entity_limit(num_transactions, config.max_num_transactions)
+                || check_entity_limit(num_logs, config.max_num_logs)
+                || check_entity_limit(num_traces, config.max_num_traces)
+        };
+
+        let mut ranges = ranges.map(VecDeque::from);
         if !reverse {
-            let initial_res = client.get_arrow(&query).await.context("get initial data");
+            // the first request covers the start of the first range
+            if let Some(ranges) = ranges.as_ref() {
+                match ranges.front() {
+                    Some(&(from_block, to_block)) => {
+                        query.from_block = from_block;
+                        query.to_block = Some(to_block);
+                    }
+                    None => return,
+                }
+            }
+            if let Some(control) = config.control.as_ref() {
+                control.wait_while_paused().await;
+            }
+            let initial_res = backoff
+                .get_arrow(&client, &mut query, None)
+                .await
+                .map(|(res, _)| res)
+                .context("get initial data");
             match initial_res {
                 Ok(res) => {
                     let res = match map_responses(config.clone(), vec![res], reverse).await {
@@ -63,9 +805,20 @@ pub async fn stream_arrow(
                     };
 
                     query.from_block = res.next_block;
+                    num_blocks += count_rows(&res.data.blocks);
+                    num_transactions += count_rows(&res.data.transactions);
+                    num_logs += count_rows(&res.data.logs);
+                    num_traces += count_rows(&res.data.traces);
                     if tx.send(Ok(res)).await.is_err() {
                         return;
                     }
+                    if let Some(metrics) = config.metrics.as_ref() {
+                        metrics.record_first_batch(start.elapsed());
+                        report_progress(&config, metrics, query.from_block);
+                    }
+                    if limit_reached(num_blocks, num_transactions, num_logs, num_traces) {
+                        return;
+                    }
                 }
                 Err(e) => {
                     tx.send(Err(e)).await.ok();
@@ -74,7 +827,17 @@ pub async fn stream_arrow(
             }
         }
 
-        let range_iter = BlockRangeIterator::new(query.from_block, to_block, step.clone(), reverse);
+        let range_iter = match ranges.as_mut() {
+            Some(ranges) => {
+                if let Some((from_block, _)) = ranges.front_mut() {
+                    *from_block = query.from_block;
+                }
+                BlockRangeIterator::over_ranges(std::mem::take(ranges), step.clone())
+            }
+            None => BlockRangeIterator::new(query.from_block, to_block, step.clone(), reverse),
+        };
+        // unordered streams queue responses by the order they finished in instead
+        let num_finished = Arc::new(AtomicU64::new(0));
 
         let mut futs = range_iter
             .enumerate()
@@ -82,8 +845,21 @@ pub async fn stream_arrow(
                 let mut query = query.clone();
                 query.from_block = start;
                 query.to_block = Some(end);
+                let endpoint = load_balancing.map(|lb| client.endpoints.pick(lb, req_idx));
                 let client = client.clone();
-                async move { (generation, req_idx, run_query_to_end(client, query).await) }
+                let backoff = backoff.clone();
+                let num_finished = num_finished.clone();
+                let range_failures = range_failures.clone();
+                async move {
+                    let resps =
+                        run_query_to_end(client, query, &backoff, endpoint, &range_failures).await;
+                    let queue_idx = if unordered {
+                        num_finished.fetch_add(1, Ordering::SeqCst) as usize
+                    } else {
+                        req_idx
+                    };
+                    (generation, queue_idx, resps)
+                }
             })
             .peekable();
 
@@ -91,29 +867,93 @@ pub async fn stream_arrow(
         // Using unordered parallelization gives a big boost in performance.
         let (res_tx, mut res_rx) = mpsc::channel(concurrency * 2);
 
-        tokio::spawn(async move {
+        let control = config.control.clone();
+        let buffered = buffered_bytes.clone();
+        let spill_dir = config.spill_dir.clone();
+        let metrics = config.metrics.clone();
+        spawn_until_closed(res_tx.clone(), async move {
             let mut set = JoinSet::new();
             let mut queue = BTreeMap::new();
             let mut next_req_idx = 0;
 
+            let limit = || match auto_concurrency.as_ref() {
+                Some((aimd, _)) => aimd.limit(),
+                None => concurrency,
+            };
+            let record = |resps: &Result<(Vec<ArrowResponse>, u64)>| {
+                if let (Some((aimd, metrics)), Ok((_, size))) = (auto_concurrency.as_ref(), resps) {
+                    aimd.record(metrics.num_retries(), *size, Instant::now());
+                }
+            };
+            // responses that can't be passed on yet are moved to disk if they don't fit into the
+            // budget, unless they are the next ones anyway
+            let enqueue =
+                |queue: &mut BTreeMap<_, _>, next_req_idx, (generation, req_idx, resps)| {
+                    record(&resps);
+                    let size = decoded_bytes(&resps);
+                    let over_budget = max_buffered_bytes
+                        .is_some_and(|max| buffered.load(Ordering::SeqCst) + size > max);
+                    let queued = match (spill_dir.as_deref(), resps) {
+                        (Some(dir), Ok(resps)) if over_budget && req_idx != next_req_idx => {
+                            tokio::task::block_in_place(|| {
+                                SpilledResponses::write(dir, resps, metrics.as_deref())
+                            })
+                            .map(Queued::Spilled)
+                            .unwrap_or_else(|e| Queued::InMemory(Err(e.context("spill responses"))))
+                        }
+                        (_, resps) => {
+                            buffered.fetch_add(size, Ordering::SeqCst);
+                            Queued::InMemory(resps)
+                        }
+                    };
+                    queue.insert(req_idx, (generation, queued));
+                };
+            let dequeue = |queued| match queued {
+                Queued::InMemory(resps) => resps,
+                Queued::Spilled(spilled) => {
+                    let resps = tokio::task::block_in_place(|| spilled.read(metrics.as_deref()))
+                        .context("read spilled responses");
+                    buffered.fetch_add(decoded_bytes(&resps), Ordering::SeqCst);
+                    resps
+                }
+            };
+
             while futs.peek().is_some() {
                 while let Some(res) = set.try_join_next() {
-                    let (generation, req_idx, resps) = res.unwrap();
-                    queue.insert(req_idx, (generation, resps));
+                    enqueue(&mut queue, next_req_idx, res.unwrap());
                 }
-                while set.len() >= concurrency {
-                    let (generation, req_idx, resps) = set.join_next().await.unwrap().unwrap();
-                    queue.insert(req_idx, (generation, resps));
+                while set.len() >= limit() {
+                    enqueue(
+                        &mut queue,
+                        next_req_idx,
+                        set.join_next().await.unwrap().unwrap(),
+                    );
                 }
-                if queue.len() < concurrency * 2 {
-                    futs.by_ref().take(concurrency - set.len()).for_each(|fut| {
-                        set.spawn(fut);
-                    });
+                if control.as_ref().is_some_and(|control| control.is_paused()) {
+                    // the requests in flight are still passed on, only new ones wait
+                    match set.join_next().await {
+                        Some(res) => enqueue(&mut queue, next_req_idx, res.unwrap()),
+                        None => {
+                            if let Some(control) = control.as_ref() {
+                                control.wait_while_paused().await;
+                            }
+                        }
+                    }
+                } else if queue.len() < concurrency * 2
+                    && (spill_dir.is_some()
+                        || max_buffered_bytes
+                            .is_none_or(|max| buffered.load(Ordering::SeqCst) <= max))
+                {
+                    futs.by_ref()
+                        .take(limit().saturating_sub(set.len()))
+                        .for_each(|fut| {
+                            set.spawn(fut);
+                        });
                 } else {
                     tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
                 }
-                while let Some(resps) = queue.remove(&next_req_idx) {
-                    if res_tx.send(resps).await.is_err() {
+                while let Some((generation, queued)) = queue.remove(&next_req_idx) {
+                    if res_tx.send((generation, dequeue(queued))).await.is_err() {
                         return;
                     }
                     next_req_idx += 1;
@@ -121,22 +961,16 @@ pub async fn stream_arrow(
             }
 
             while let Some(res) = set.join_next().await {
-                let (generation, req_idx, resps) = res.unwrap();
-                queue.insert(req_idx, (generation, resps));
+                enqueue(&mut queue, next_req_idx, res.unwrap());
             }
-            while let Some(resps) = queue.remove(&next_req_idx) {
-                if res_tx.send(resps).await.is_err() {
+            while let Some((generation, queued)) = queue.remove(&next_req_idx) {
+                if res_tx.send((generation, dequeue(queued))).await.is_err() {
                     return;
                 }
                 next_req_idx += 1;
             }
         });
 
-        let mut num_blocks = 0;
-        let mut num_transactions = 0;
-        let mut num_logs = 0;
-        let mut num_traces = 0;
-
         // Generation is used so if we change batch_size we only want to change it again
         // based on the new batch size we just set.
         // If we don't check generations then we might apply same change to batch size multiple times.
@@ -151,6 +985,7 @@ pub async fn stream_arrow(
             };
 
             let (resps, resps_size) = resps;
+            let resps_latency = resps.iter().map(|r| r.transfer.latency).sum::<Duration>();
             let resps = match map_responses(config.clone(), resps, reverse).await {
                 Ok(resps) => resps,
                 Err(e) => {
@@ -161,31 +996,29 @@ pub async fn stream_arrow(
 
             if generation == next_generation {
                 next_generation += 1;
-                if resps_size > response_size_ceiling {
-                    let ratio = response_size_ceiling as f64 / resps_size as f64;
-
+                let ratio = batch_size_ratio(
+                    resps_size,
+                    resps_latency,
+                    response_size_ceiling,
+                    response_size_floor,
+                    response_time_ceiling,
+                );
+                if let Some(ratio) = ratio {
                     step.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| {
                         // extract batch_size value
                         let x = x as u32;
 
-                        let batch_size = cmp::max((x as f64 * ratio) as u64, min_batch_size);
-                        let step = batch_size | u64::from(next_generation) << 32;
-                        Some(step)
-                    })
-                    .ok();
-                } else if resps_size < response_size_floor {
-                    let ratio = response_size_floor as f64 / resps_size as f64;
-
-                    step.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| {
-                        let x = x as u32;
-
-                        let batch_size = cmp::min((x as f64 * ratio) as u64, max_batch_size);
+                        let batch_size = ((x as f64 * ratio) as u64)
+                            .clamp(min_batch_size, max_batch_size.max(min_batch_size));
                         let step = batch_size | u64::from(next_generation) << 32;
                         Some(step)
                     })
                     .ok();
                 }
             }
+            if let Some(metrics) = config.metrics.as_ref() {
+                metrics.record_batch_size(u64::from(step.load(Ordering::SeqCst) as u32));
+            }
 
             for resp in resps {
                 num_blocks += count_rows(&resp.data.blocks);
@@ -193,17 +1026,19 @@ pub async fn stream_arrow(
                 num_logs += count_rows(&resp.data.logs);
                 num_traces += count_rows(&resp.data.traces);
 
+                let next_block = resp.next_block;
+                let resp_bytes = resp.transfer.decompressed_bytes;
                 if tx.send(Ok(resp)).await.is_err() {
                     return;
                 }
-            }
-
-            if check_entity_limit(num_blocks, config.max_num_blocks)
-                || check_entity_limit(num_transactions, config.max_num_transactions)
-                || check_entity_limit(num_logs, config.max_num_logs)
-                || check_entity_limit(num_traces, config.max_num_traces)
-            {
-                return;
+                buffered_bytes.fetch_sub(resp_bytes, Ordering::SeqCst);
+                if let Some(metrics) = config.metrics.as_ref() {
+                    metrics.record_first_batch(start.elapsed());
+                    report_progress(&config, metrics, next_block);
+                }
+                if limit_reached(num_blocks, num_transactions, num_logs, num_traces) {
+                    return;
+                }
             }
         }
     });
@@ -211,6 +1046,127 @@ pub async fn stream_arrow(
     Ok(rx)
 }
 
+/// Fails if failed ranges are skipped while progress is checkpointed, since the checkpoint would
+/// move past the skipped blocks and resuming would never fetch them.
+pub(crate) fn check_skip_failed_ranges(config: &StreamConfig) -> Result<()> {
+    if config.skip_failed_ranges && config.checkpoint.is_some() {
+        return Err(anyhow!(
+            "config.skip_failed_ranges can't be combined with config.checkpoint"
+        ));
+    }
+    Ok(())
+}
+
+fn check_unordered_config(config: &StreamConfig) -> Result<()> {
+    let conflict = if config.follow {
+        Some("config.follow")
+    } else if config.reverse.unwrap_or_default() {
+        Some("config.reverse")
+    } else if config.bloom_prescan {
+        Some("config.bloom_prescan")
+    } else if config.checkpoint.is_some() {
+        // responses are checkpointed as they are passed on, which would skip unfinished ranges
+        Some("config.checkpoint")
+    } else {
+        None
+    };
+    if let Some(conflict) = conflict {
+        return Err(anyhow!(
+            "config.unordered can't be combined with {}",
+            conflict
+        ));
+    }
+    #[cfg(feature = "websocket")]
+    if config.transport == crate::StreamTransport::WebSocket {
+        return Err(anyhow!(
+            "config.unordered can't be combined with the websocket transport"
+        ));
+    }
+    Ok(())
+}
+
+/// Decompressed size of the responses of a range request, which is close to the memory their
+/// Arrow data takes up.
+/// Responses of a range request waiting for the ranges before it.
+enum Queued {
+    InMemory(Result<(Vec<ArrowResponse>, u64)>),
+    Spilled(SpilledResponses),
+}
+
+fn decoded_bytes(resps: &Result<(Vec<ArrowResponse>, u64)>) -> u64 {
+    match resps {
+        Ok((resps, _)) => resps.iter().map(|r| r.transfer.decompressed_bytes).sum(),
+        Err(_) => 0,
+    }
+}
+
+/// Factor to scale the batch size by after a range request, None to keep it.
+///
+/// Ranges that produced more than `size_ceiling` bytes or took longer than `time_ceiling` in
+/// total shrink, ranges below `size_floor` grow, though not by more than would keep them under
+/// `time_ceiling`.
+fn batch_size_ratio(
+    size: u64,
+    latency: Duration,
+    size_ceiling: u64,
+    size_floor: u64,
+    time_ceiling: Option<Duration>,
+) -> Option<f64> {
+    let time_ratio = time_ceiling
+        .filter(|_| !latency.is_zero())
+        .map(|ceiling| ceiling.as_secs_f64() / latency.as_secs_f64());
+
+    if size > size_ceiling || time_ratio.is_some_and(|ratio| ratio < 1.0) {
+        let size_ratio = (size_ceiling as f64 / size as f64).min(1.0);
+        Some(time_ratio.map_or(size_ratio, |ratio| ratio.min(size_ratio)))
+    } else if size < size_floor {
+        let size_ratio = size_floor as f64 / size as f64;
+        Some(time_ratio.map_or(size_ratio, |ratio| ratio.min(size_ratio)))
+            .filter(|ratio| *ratio > 1.0)
+    } else {
+        None
+    }
+}
+
+/// Records that the stream reached `next_block` and passes the progress to the listener.
+fn report_progress(config: &StreamConfig, metrics: &StreamMetrics, next_block: u64) {
+    metrics.record_progress(next_block);
+    if let (Some(listener), Some(progress)) = (config.on_progress.as_ref(), metrics.progress()) {
+        listener.on_progress(&progress);
+    }
+}
+
+/// Drops the rows at or before `resume` from every table of the response.
+fn drop_handled_rows(data: &mut ArrowResponseData, resume: &ResumePoint) -> Result<()> {
+    let drop = |batches: &[ArrowBatch], column: &str, index: Option<(&str, u64)>| {
+        batches
+            .iter()
+            .map(|batch| drop_rows_until(batch, column, resume.block_number, index))
+            .collect::<Result<Vec<_>>>()
+    };
+    let tx_index = |column| resume.transaction_index.map(|index| (column, index));
+    data.blocks = drop(&data.blocks, "number", None).context("blocks")?;
+    data.transactions = drop(
+        &data.transactions,
+        "block_number",
+        tx_index("transaction_index"),
+    )
+    .context("transactions")?;
+    data.logs = drop(
+        &data.logs,
+        "block_number",
+        resume.log_index.map(|index| ("log_index", index)),
+    )
+    .context("logs")?;
+    data.traces = drop(
+        &data.traces,
+        "block_number",
+        tx_index("transaction_position"),
+    )
+    .context("traces")?;
+    Ok(())
+}
+
 fn count_rows(batches: &[ArrowBatch]) -> usize {
     batches.iter().map(|b| b.chunk.len()).sum()
 }
@@ -239,9 +1195,28 @@ async fn map_responses(
     }
 
     rayon_async::spawn(move || {
-        responses
+        let start = Instant::now();
+        let res: Result<Vec<_>> = responses
             .into_iter()
-            .map(|resp| {
+            .map(|mut resp| {
+                if let Some(resume) = cfg.resume_after.as_ref() {
+                    drop_handled_rows(&mut resp.data, resume)
+                        .context("drop rows before resume point")?;
+                }
+                if let Some(shard) = cfg.address_shard {
+                    resp.data.logs = resp
+                        .data
+                        .logs
+                        .iter()
+                        .map(|batch| filter_logs_by_address_shard(batch, shard))
+                        .collect::<Result<_>>()
+                        .context("filter logs by address shard")?;
+                }
+                if cfg.dedup_transactions {
+                    resp.data.transactions = dedup_transactions(&resp.data.transactions)
+                        .context("dedup transactions")?;
+                }
+
                 Ok(ArrowResponse {
                     data: ArrowResponseData {
                         decoded_logs: match cfg.event_signature.as_ref() {
@@ -263,6 +1238,46 @@ async fn map_responses(
                                 .collect::<Result<Vec<_>>>()?,
                             None => Vec::new(),
                         },
+                        decoded_events: cfg
+                            .event_routes
+                            .iter()
+                            .map(|(name, sig)| {
+                                let batches = resp
+                                    .data
+                                    .logs
+                                    .iter()
+                                    .map(|batch| {
+                                        let batch = decode_event_logs_batch(sig, batch)
+                                            .with_context(|| {
+                                                format!("decode logs for route '{}'", name)
+                                            })?;
+                                        map_batch(
+                                            cfg.column_mapping.as_ref().map(|cm| &cm.decoded_log),
+                                            cfg.hex_output,
+                                            batch,
+                                            reverse,
+                                        )
+                                        .context("map batch")
+                                    })
+                                    .collect::<Result<Vec<_>>>()?;
+
+                                Ok((name.clone(), batches))
+                            })
+                            .collect::<Result<_>>()?,
+                        trace_summary: if cfg.trace_summary {
+                            resp.data
+                                .traces
+                                .iter()
+                                .map(|batch| {
+                                    let batch =
+                                        summarize_traces(batch).context("summarize traces")?;
+                                    map_batch(None, cfg.hex_output, batch, reverse)
+                                        .context("map batch")
+                                })
+                                .collect::<Result<Vec<_>>>()?
+                        } else {
+                            Vec::new()
+                        },
                         blocks: resp
                             .data
                             .blocks
@@ -281,6 +1296,12 @@ async fn map_responses(
                             .transactions
                             .into_iter()
                             .map(|batch| {
+                                let batch = if cfg.nest_list_columns {
+                                    nest_transaction_list_columns(batch)
+                                        .context("nest list columns")?
+                                } else {
+                                    batch
+                                };
                                 map_batch(
                                     cfg.column_mapping.as_ref().map(|cm| &cm.transaction),
                                     cfg.hex_output,
@@ -319,7 +1340,17 @@ async fn map_responses(
                     ..resp
                 })
             })
-            .collect()
+            .collect();
+        if let Some(metrics) = cfg.metrics.as_ref() {
+            metrics.record_decode(start.elapsed());
+        }
+        match cfg.transform.as_ref() {
+            Some(transform) => res?
+                .into_iter()
+                .map(|resp| transform.transform(resp).context("transform response"))
+                .collect(),
+            None => res,
+        }
     })
     .await
     .unwrap()
@@ -409,6 +1440,12 @@ fn reverse_array(array: &dyn Array) -> Result<Box<dyn Array>> {
                 .rev(),
         )
         .boxed()),
+        ArrowDataType::List(_) => {
+            reverse_list(array.as_any().downcast_ref::<ListArray<i32>>().unwrap())
+        }
+        ArrowDataType::LargeList(_) => {
+            reverse_list(array.as_any().downcast_ref::<ListArray<i64>>().unwrap())
+        }
         dt => Err(anyhow!(
             "reversing an array of datatype {:?} is not supported",
             dt
@@ -416,9 +1453,45 @@ fn reverse_array(array: &dyn Array) -> Result<Box<dyn Array>> {
     }
 }
 
+/// Reverses the rows of a list array, keeping the order of the items within each row.
+fn reverse_list<O: Offset>(array: &ListArray<O>) -> Result<Box<dyn Array>> {
+    let rows = (0..array.len())
+        .rev()
+        .map(|i| array.value(i))
+        .collect::<Vec<_>>();
+    let offsets = Offsets::<O>::try_from_lengths(rows.iter().map(|row| row.len()))
+        .context("build offsets")?;
+    let values = if rows.is_empty() {
+        new_empty_array(array.values().data_type().clone())
+    } else {
+        concatenate(&rows.iter().map(|row| row.as_ref()).collect::<Vec<_>>())
+            .context("concatenate list items")?
+    };
+    let validity = array
+        .validity()
+        .map(|validity| validity.iter().rev().collect::<Bitmap>());
+
+    Ok(
+        ListArray::<O>::try_new(array.data_type().clone(), offsets.into(), values, validity)
+            .context("build list array")?
+            .boxed(),
+    )
+}
+
+/// What a range request does once the client ran out of retries, see
+/// `StreamConfig::range_retries` and `StreamConfig::skip_failed_ranges`.
+struct RangeFailurePolicy {
+    retries: usize,
+    skip: bool,
+    metrics: Option<Arc<StreamMetrics>>,
+}
+
 async fn run_query_to_end(
     client: Arc<crate::Client>,
     query: Query,
+    backoff: &PayloadBackoff,
+    endpoint: Option<usize>,
+    failures: &RangeFailurePolicy,
 ) -> Result<(Vec<ArrowResponse>, u64)> {
     let mut resps = Vec::new();
 
@@ -426,13 +1499,50 @@ async fn run_query_to_end(
 
     let mut size = 0;
 
+    let range_start = query.from_block;
     let mut query = query;
+    let mut num_failures = 0;
 
     loop {
-        let (resp, resp_size) = client
-            .get_arrow_with_size(&query)
+        let from_block = query.from_block;
+        let (resp, resp_size) = match backoff
+            .get_arrow(&client, &mut query, endpoint)
             .await
-            .context("get data")?;
+            .context("get data")
+        {
+            Ok(res) => res,
+            Err(e) => {
+                let kind = ErrorKind::of(&e);
+                if kind.is_retryable() && num_failures < failures.retries {
+                    num_failures += 1;
+                    log::warn!(
+                        "range [{}, {}) failed, starting it again from block {} ({}/{}): {:?}",
+                        range_start,
+                        to_block,
+                        from_block,
+                        num_failures,
+                        failures.retries,
+                        e
+                    );
+                    // the client already used up its retries, so give the server time to recover
+                    tokio::time::sleep(client.range_retry_wait(num_failures)).await;
+                    continue;
+                }
+                if failures.skip && (kind.is_retryable() || kind == ErrorKind::Parse) {
+                    log::warn!(
+                        "skipping blocks [{}, {}) after the range failed: {:?}",
+                        from_block,
+                        to_block,
+                        e
+                    );
+                    if let Some(metrics) = failures.metrics.as_ref() {
+                        metrics.record_skipped_range(from_block, to_block);
+                    }
+                    break;
+                }
+                return Err(e);
+            }
+        };
         size += resp_size;
 
         let next_block = resp.next_block;
@@ -442,18 +1552,124 @@ async fn run_query_to_end(
         if next_block >= to_block {
             break;
         } else {
+            // Keep the range width the backoff settled on so we don't hit the same limit again.
+            let width = query.to_block.unwrap() - from_block;
             query.from_block = next_block;
+            query.to_block = Some(cmp::min(next_block + width, to_block));
         }
     }
 
     Ok((resps, size))
 }
 
+fn validate_column_mapping(
+    mapping: &crate::ColumnMapping,
+    query: &Query,
+    config: &StreamConfig,
+) -> Result<()> {
+    let signatures = config
+        .event_signature
+        .iter()
+        .chain(config.event_routes.values())
+        .collect::<Vec<_>>();
+
+    let decoded_log_columns = if signatures.is_empty() {
+        None
+    } else {
+        let mut columns = BTreeSet::new();
+        for sig in signatures {
+            columns.extend(decoded_log_column_names(sig).context("get decoded log columns")?);
+        }
+        Some(columns)
+    };
+
+    crate::column_mapping::validate(
+        mapping,
+        &query.field_selection,
+        decoded_log_columns.as_ref(),
+    )
+}
+
+/// Shrinks requests that the server rejects as too large and feeds the adjustment back into
+/// the batch size used for the following ranges.
+struct PayloadBackoff {
+    step: Arc<AtomicU64>,
+    min_batch_size: u64,
+    to_block: u64,
+    metrics: Option<Arc<StreamMetrics>>,
+}
+
+impl PayloadBackoff {
+    /// Executes the query, halving its block range every time the server responds
+    /// with 413 Payload Too Large. The query is left with the range that succeeded.
+    async fn get_arrow(
+        &self,
+        client: &crate::Client,
+        query: &mut Query,
+        endpoint: Option<usize>,
+    ) -> Result<(ArrowResponse, u64)> {
+        loop {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                from_block = query.from_block,
+                to_block = ?query.to_block,
+                "requesting block range"
+            );
+
+            let err = match client
+                .get_arrow_with_size(query, endpoint, self.metrics.as_deref())
+                .await
+            {
+                Ok(res) => return Ok(res),
+                Err(e) => e,
+            };
+
+            if error::http_status(&err) != Some(StatusCode::PAYLOAD_TOO_LARGE) {
+                return Err(err);
+            }
+
+            let to_block = query.to_block.unwrap_or(self.to_block);
+            let range = to_block.saturating_sub(query.from_block);
+            if range <= 1 {
+                return Err(err).context("server rejected the response of a single block range");
+            }
+            query.to_block = Some(query.from_block + range / 2);
+
+            log::debug!(
+                "response was too large, retrying with block range [{}, {})",
+                query.from_block,
+                query.from_block + range / 2
+            );
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                from_block = query.from_block,
+                batch_size = range / 2,
+                "response was too large, halving block range"
+            );
+
+            self.step
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| {
+                    // keep the generation and only halve the batch_size
+                    let generation = x >> 32;
+                    let batch_size = cmp::max(u64::from(x as u32) / 2, self.min_batch_size);
+                    Some(batch_size | generation << 32)
+                })
+                .ok();
+
+            if let Some(metrics) = self.metrics.as_ref() {
+                metrics.record_payload_too_large_backoff();
+            }
+        }
+    }
+}
+
 pub struct BlockRangeIterator {
     offset: u64,
     end: u64,
     step: Arc<AtomicU64>,
     reverse: bool,
+    // ranges to continue with once the current one is done
+    ranges: VecDeque<(u64, u64)>,
 }
 
 impl BlockRangeIterator {
@@ -464,6 +1680,7 @@ impl BlockRangeIterator {
                 end: start,
                 step,
                 reverse,
+                ranges: VecDeque::new(),
             }
         } else {
             Self {
@@ -471,17 +1688,30 @@ impl BlockRangeIterator {
                 end,
                 step,
                 reverse,
+                ranges: VecDeque::new(),
             }
         }
     }
+
+    /// Splits each of the given increasing `[start, end)` ranges into steps, one range after
+    /// the other.
+    pub fn over_ranges(ranges: VecDeque<(u64, u64)>, step: Arc<AtomicU64>) -> Self {
+        Self {
+            offset: 0,
+            end: 0,
+            step,
+            reverse: false,
+            ranges,
+        }
+    }
 }
 
 impl Iterator for BlockRangeIterator {
     type Item = (u64, u64, u32);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.offset == self.end {
-            return None;
+        while self.offset == self.end {
+            (self.offset, self.end) = self.ranges.pop_front()?;
         }
         let start = self.offset;
 
@@ -501,3 +1731,830 @@ impl Iterator for BlockRangeIterator {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::mock_server::{blocks_query, mock_client, transfers_query, MockChain, MockServer};
+    use crate::TransferStats;
+    #[cfg(feature = "test-util")]
+    use crate::{
+        ArrowResponseData, Client, ClientConfig, HeadModeConfig, MemoryCheckpoint, QueryResponse,
+    };
+
+    #[test]
+    fn test_batch_size_ratio() {
+        let fast = Duration::from_millis(100);
+        let ceiling = Some(Duration::from_secs(1));
+
+        // size only
+        assert_eq!(batch_size_ratio(1000, fast, 500, 250, None), Some(0.5));
+        assert_eq!(batch_size_ratio(100, fast, 500, 250, None), Some(2.5));
+        assert_eq!(batch_size_ratio(300, fast, 500, 250, None), None);
+
+        // slow ranges shrink even if they are small
+        assert_eq!(
+            batch_size_ratio(300, Duration::from_secs(4), 500, 250, ceiling),
+            Some(0.25)
+        );
+        assert_eq!(
+            batch_size_ratio(1000, Duration::from_secs(4), 500, 250, ceiling),
+            Some(0.25)
+        );
+        // growth is capped by the time it would take
+        assert_eq!(
+            batch_size_ratio(25, Duration::from_millis(500), 500, 250, ceiling),
+            Some(2.0)
+        );
+        assert_eq!(
+            batch_size_ratio(100, Duration::from_millis(900), 500, 250, ceiling).map(|r| r < 1.2),
+            Some(true)
+        );
+        assert_eq!(batch_size_ratio(100, fast, 500, 250, ceiling), Some(2.5));
+    }
+
+    fn check_reverse_list<O: Offset>(data_type: ArrowDataType) {
+        let values = BinaryArray::<i32>::from_slice([b"a", b"b", b"c"]);
+        let offsets = Offsets::<O>::try_from_lengths([2, 0, 1].into_iter()).unwrap();
+        let validity = Bitmap::from([true, false, true]);
+        let list =
+            ListArray::<O>::try_new(data_type, offsets.into(), values.boxed(), Some(validity))
+                .unwrap();
+
+        let reversed = reverse_array(&list).unwrap();
+        let rows = reversed
+            .as_any()
+            .downcast_ref::<ListArray<O>>()
+            .unwrap()
+            .iter()
+            .map(|row| {
+                row.map(|items| {
+                    let items = items.as_any().downcast_ref::<BinaryArray<i32>>().unwrap();
+                    items.values_iter().map(<[u8]>::to_vec).collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                Some(vec![b"c".to_vec()]),
+                None,
+                Some(vec![b"a".to_vec(), b"b".to_vec()])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reverse_list() {
+        let item = Box::new(polars_arrow::datatypes::Field::new(
+            "item",
+            ArrowDataType::Binary,
+            true,
+        ));
+        check_reverse_list::<i32>(ArrowDataType::List(item.clone()));
+        check_reverse_list::<i64>(ArrowDataType::LargeList(item));
+    }
+
+    #[test]
+    fn test_block_range_iterator_over_ranges() {
+        let step = Arc::new(AtomicU64::new(10));
+        let ranges = BlockRangeIterator::over_ranges([(5, 8), (20, 45)].into(), step.clone())
+            .map(|(start, end, _)| (start, end))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, vec![(5, 8), (20, 30), (30, 40), (40, 45)]);
+
+        assert_eq!(
+            BlockRangeIterator::over_ranges(VecDeque::new(), step).next(),
+            None
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_run_stream_over_ranges() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+
+        let config = StreamConfig {
+            concurrency: Some(4),
+            batch_size: Some(10),
+            ..Default::default()
+        };
+        let ranges = vec![(3, 5), (20, 25), (40, 100)];
+        let mut rx = run_stream_over(client, blocks_query(0, 100), config, Some(ranges))
+            .await
+            .unwrap();
+
+        let mut numbers = Vec::new();
+        let mut next_blocks = Vec::new();
+        while let Some(resp) = rx.recv().await {
+            let resp = resp.unwrap();
+            for batch in resp.data.blocks.iter() {
+                numbers.extend(batch.u64_column("number").unwrap().values_iter().copied());
+            }
+            next_blocks.push(resp.next_block);
+        }
+        let expected = (3..5).chain(20..25).chain(40..100).collect::<Vec<_>>();
+        assert_eq!(numbers, expected);
+        assert_eq!(next_blocks.first(), Some(&5));
+        assert_eq!(next_blocks.last(), Some(&100));
+        assert!(next_blocks.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    fn response(next_block: u64, blocks: &[u64]) -> ArrowResponse {
+        let batch = ArrowBatch {
+            chunk: Arc::new(polars_arrow::record_batch::RecordBatch::new(vec![
+                UInt64Array::from_slice(blocks).boxed(),
+            ])),
+            schema: Arc::new(polars_arrow::datatypes::ArrowSchema::from(vec![
+                polars_arrow::datatypes::Field::new("number", ArrowDataType::UInt64, true),
+            ])),
+        };
+        ArrowResponse {
+            archive_height: Some(next_block),
+            next_block,
+            total_execution_time: 1,
+            data: ArrowResponseData {
+                blocks: vec![batch],
+                ..Default::default()
+            },
+            rollback_guard: None,
+            rollback: None,
+            transfer: TransferStats {
+                wire_bytes: 1,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fail_if_empty() {
+        let (tx, inner_rx) = mpsc::channel(16);
+        let mut rx = fail_if_empty(inner_rx);
+        for next_block in 1..4 {
+            tx.send(Ok(response(next_block, &[]))).await.unwrap();
+        }
+        let mut rollback = response(4, &[]);
+        rollback.rollback = Some(Rollback { from_block: 2 });
+        tx.send(Ok(rollback)).await.unwrap();
+        tx.send(Ok(response(5, &[4]))).await.unwrap();
+        tx.send(Ok(response(6, &[]))).await.unwrap();
+        drop(tx);
+
+        // the empty responses before the first row are sent as one
+        let merged = rx.recv().await.unwrap().unwrap();
+        assert_eq!(merged.next_block, 4);
+        assert_eq!(merged.total_execution_time, 4);
+        assert_eq!(merged.transfer.wire_bytes, 4);
+        assert_eq!(merged.rollback, Some(Rollback { from_block: 2 }));
+        assert_eq!(rx.recv().await.unwrap().unwrap().next_block, 5);
+        assert_eq!(rx.recv().await.unwrap().unwrap().next_block, 6);
+        assert!(rx.recv().await.is_none());
+
+        let (tx, inner_rx) = mpsc::channel(16);
+        let mut rx = fail_if_empty(inner_rx);
+        for next_block in 1..100 {
+            tx.send(Ok(response(next_block, &[]))).await.unwrap();
+        }
+        drop(tx);
+        assert!(rx.recv().await.unwrap().is_err());
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reorg_during_stream() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        server.reorg_after_queries(3, 75);
+        let client = mock_client(&server);
+
+        let res = client
+            .collect(
+                blocks_query(0, 100),
+                StreamConfig {
+                    concurrency: Some(1),
+                    batch_size: Some(10),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let blocks = res.data.blocks.concat();
+        assert_eq!(blocks.len(), 100);
+        assert_eq!(server.num_queries(), 10);
+        // the guard of the last response is kept
+        let guard = res.rollback_guard.unwrap();
+        assert_eq!(guard.block_number, 99);
+        assert_eq!(guard.hash, server.chain().block(99).unwrap().hash);
+
+        // blocks fetched after the reorg are on the new fork, so the parent hashes of the
+        // collected blocks don't link up at the first reorged block
+        let broken = blocks
+            .windows(2)
+            .filter(|w| w[1].parent_hash != w[0].hash)
+            .map(|w| w[1].number)
+            .collect::<Vec<_>>();
+        assert_eq!(broken, [Some(30)]);
+        assert_eq!(
+            blocks[99].hash,
+            Some(server.chain().block(99).unwrap().hash.clone())
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cancellation_token() {
+        let server = MockServer::start(MockChain::new(10_000)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+        let token = CancellationToken::new();
+        let config = StreamConfig {
+            concurrency: Some(2),
+            batch_size: Some(10),
+            max_batch_size: Some(10),
+            cancellation_token: Some(token.clone()),
+            ..Default::default()
+        };
+
+        let mut rx = client
+            .clone()
+            .stream_arrow(blocks_query(0, 10_000), config.clone())
+            .await
+            .unwrap();
+        assert!(rx.recv().await.unwrap().is_ok());
+        token.cancel();
+        let mut last = None;
+        while let Some(resp) = rx.recv().await {
+            last = Some(resp);
+        }
+        let err = last.unwrap().unwrap_err();
+        assert!(matches!(err, Error::Cancelled { .. }));
+
+        // the requests of the stream stop with it
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let num_queries = server.num_queries();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(server.num_queries(), num_queries);
+        assert!(num_queries < 100);
+
+        let err = client
+            .collect(blocks_query(0, 10_000), config)
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_max_buffered_bytes() {
+        let server = MockServer::start(MockChain::new(1000)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+
+        let mut rx = client
+            .stream_arrow(
+                blocks_query(0, 1000),
+                StreamConfig {
+                    concurrency: Some(4),
+                    batch_size: Some(10),
+                    max_batch_size: Some(10),
+                    max_buffered_bytes: Some(1),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        // the initial request and the first round of range requests, then the budget is used up
+        assert!(server.num_queries() <= 5);
+
+        let mut next_block = 0;
+        while let Some(resp) = rx.recv().await {
+            let resp = resp.unwrap();
+            assert!(resp.next_block > next_block);
+            next_block = resp.next_block;
+        }
+        assert_eq!(next_block, 1000);
+        assert_eq!(server.num_queries(), 100);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_spill_dir() {
+        let server = MockServer::start(MockChain::new(200)).unwrap();
+        server.set_max_blocks_per_response(10);
+        server.delay_queries_from(10, Duration::from_millis(300));
+        let client = mock_client(&server);
+        let spill_dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let metrics = Arc::new(StreamMetrics::default());
+        let config = StreamConfig {
+            concurrency: Some(4),
+            batch_size: Some(10),
+            max_batch_size: Some(10),
+            ..Default::default()
+        };
+
+        let expected = client
+            .clone()
+            .collect_arrow(transfers_query(0, 200), config.clone())
+            .await
+            .unwrap();
+        let res = client
+            .clone()
+            .collect_arrow(
+                transfers_query(0, 200),
+                StreamConfig {
+                    max_buffered_bytes: Some(1),
+                    spill_dir: Some(spill_dir.clone()),
+                    metrics: Some(metrics.clone()),
+                    ..config.clone()
+                },
+            )
+            .await
+            .unwrap();
+        let chunks = |data: &ArrowResponseData| {
+            [&data.blocks, &data.transactions, &data.logs]
+                .map(|batches| batches.iter().map(|b| b.chunk.clone()).collect::<Vec<_>>())
+        };
+        assert_eq!(res.next_block, 200);
+        assert_eq!(chunks(&res.data), chunks(&expected.data));
+
+        // the ranges after the slow one were moved to disk while they waited for it
+        let report = metrics.report();
+        assert!(report.num_spilled_responses > 0);
+        assert!(report.spill_file_bytes > 0);
+        assert_eq!(std::fs::read_dir(&spill_dir).unwrap().count(), 0);
+        std::fs::remove_dir(&spill_dir).unwrap();
+
+        assert!(client
+            .clone()
+            .stream_arrow(
+                transfers_query(0, 200),
+                StreamConfig {
+                    spill_dir: Some(spill_dir),
+                    ..config
+                },
+            )
+            .await
+            .is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_unordered() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        server.delay_queries_from(10, Duration::from_millis(300));
+        let client = mock_client(&server);
+        let config = StreamConfig {
+            concurrency: Some(4),
+            batch_size: Some(10),
+            max_batch_size: Some(10),
+            unordered: true,
+            ..Default::default()
+        };
+
+        let mut rx = client
+            .clone()
+            .stream_arrow(blocks_query(0, 100), config.clone())
+            .await
+            .unwrap();
+        let mut next_blocks = Vec::new();
+        while let Some(resp) = rx.recv().await {
+            next_blocks.push(resp.unwrap().next_block);
+        }
+        // the slow range doesn't hold back the ones after it
+        assert_eq!(next_blocks[0], 10);
+        assert_ne!(next_blocks[1], 20);
+        next_blocks.sort();
+        assert_eq!(next_blocks, (1..=10).map(|i| i * 10).collect::<Vec<_>>());
+
+        let res = client
+            .clone()
+            .collect_arrow(blocks_query(0, 100), config.clone())
+            .await
+            .unwrap();
+        assert_eq!(res.next_block, 100);
+        assert_eq!(
+            res.data.blocks.iter().map(|b| b.num_rows()).sum::<usize>(),
+            100
+        );
+
+        // stopping early could leave a gap below the highest next_block
+        let err = client
+            .clone()
+            .collect_arrow(
+                blocks_query(0, 100),
+                StreamConfig {
+                    max_num_blocks: Some(50),
+                    ..config.clone()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("config.unordered"));
+
+        let err = client
+            .stream_arrow(
+                blocks_query(0, 100),
+                StreamConfig {
+                    checkpoint: Some(Arc::new(MemoryCheckpoint::new(None))),
+                    ..config
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("config.checkpoint"));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_error_on_empty() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+        let checkpoint = Arc::new(MemoryCheckpoint::default());
+        let config = StreamConfig {
+            concurrency: Some(1),
+            batch_size: Some(10),
+            error_on_empty: true,
+            ..StreamConfig::resume_from_checkpoint(checkpoint.clone())
+        };
+
+        let num_blocks = |mut rx: mpsc::Receiver<Result<ArrowResponse, Error>>| async move {
+            let mut num_blocks = 0;
+            while let Some(resp) = rx.recv().await {
+                num_blocks += resp?
+                    .data
+                    .blocks
+                    .iter()
+                    .map(|b| b.num_rows())
+                    .sum::<usize>();
+            }
+            anyhow::Ok(num_blocks)
+        };
+
+        // the mock server only returns blocks for queries with include_all_blocks
+        let mut query = blocks_query(0, 40);
+        query.include_all_blocks = false;
+        let rx = client
+            .clone()
+            .stream_arrow(query.clone(), config.clone())
+            .await
+            .unwrap();
+        assert!(num_blocks(rx).await.is_err());
+        assert_eq!(checkpoint.next_block(), None);
+
+        let rx = client
+            .clone()
+            .stream_arrow(
+                query,
+                StreamConfig {
+                    error_on_empty: false,
+                    ..config.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(num_blocks(rx).await.unwrap(), 0);
+        assert_eq!(checkpoint.next_block(), Some(40));
+
+        let rx = client
+            .stream_arrow(blocks_query(40, 100), config)
+            .await
+            .unwrap();
+        assert_eq!(num_blocks(rx).await.unwrap(), 60);
+        assert_eq!(checkpoint.next_block(), Some(100));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_follow() {
+        let server = MockServer::start(MockChain::new(20)).unwrap();
+        let client = mock_client(&server);
+
+        let mut query = blocks_query(0, 0);
+        query.to_block = None;
+        let mut rx = client
+            .stream_arrow(
+                query,
+                StreamConfig {
+                    follow: true,
+                    follow_poll_interval_millis: Some(10),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut num_blocks = 0;
+        let mut next_block = 0;
+        while next_block < 19 {
+            let res = rx.recv().await.unwrap().unwrap();
+            num_blocks += res.data.blocks.iter().map(|b| b.chunk.len()).sum::<usize>();
+            next_block = res.next_block;
+        }
+        assert_eq!(num_blocks, 19);
+
+        // the stream keeps going once the chain grows
+        server.update_chain(|chain| chain.push_blocks(10));
+        while next_block < 29 {
+            let res = rx.recv().await.unwrap().unwrap();
+            num_blocks += res.data.blocks.iter().map(|b| b.chunk.len()).sum::<usize>();
+            next_block = res.next_block;
+        }
+        assert_eq!(num_blocks, 29);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_follow_head_mode() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        let client = mock_client(&server);
+
+        let mut query = blocks_query(0, 0);
+        query.to_block = None;
+        let mut rx = client
+            .stream_arrow(
+                query,
+                StreamConfig {
+                    follow: true,
+                    head_mode: Some(HeadModeConfig {
+                        distance: 5,
+                        batch_size: Some(2),
+                        poll_interval_millis: Some(10),
+                    }),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        // the backlog is fetched in bulk
+        let res = rx.recv().await.unwrap().unwrap();
+        assert_eq!(res.next_block, 99);
+
+        // new blocks come in requests of at most 2 blocks
+        server.update_chain(|chain| chain.push_blocks(5));
+        let mut ranges = Vec::new();
+        let mut next_block = 99;
+        while next_block < 104 {
+            let res = rx.recv().await.unwrap().unwrap();
+            ranges.push((next_block, res.next_block));
+            next_block = res.next_block;
+        }
+        assert_eq!(ranges, [(99, 101), (101, 103), (103, 104)]);
+
+        // falling behind switches back to bulk requests
+        server.update_chain(|chain| chain.push_blocks(20));
+        let res = rx.recv().await.unwrap().unwrap();
+        assert_eq!(res.next_block, 124);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_follow_keep_warm() {
+        let server = MockServer::start(MockChain::new(10)).unwrap();
+        let client = mock_client(&server);
+
+        let mut query = blocks_query(0, 0);
+        query.to_block = None;
+        let mut rx = client
+            .stream_arrow(
+                query,
+                StreamConfig {
+                    follow: true,
+                    follow_poll_interval_millis: Some(10_000),
+                    keep_warm_interval_millis: Some(20),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        rx.recv().await.unwrap().unwrap();
+
+        let num_height_requests = server.num_height_requests();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(server.num_height_requests() >= num_height_requests + 3);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_range_failures() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = Arc::new(
+            Client::new(ClientConfig {
+                url: Some(server.url()),
+                max_num_retries: Some(0),
+                retry_base_ms: Some(1),
+                retry_backoff_ms: Some(1),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let config = StreamConfig {
+            concurrency: Some(4),
+            batch_size: Some(10),
+            max_batch_size: Some(10),
+            ..Default::default()
+        };
+        let num_blocks = |res: &QueryResponse<ArrowResponseData>| {
+            res.data.blocks.iter().map(|b| b.num_rows()).sum::<usize>()
+        };
+
+        server.fail_queries_from(50, 1);
+        let err = client
+            .clone()
+            .collect_arrow(blocks_query(0, 100), config.clone())
+            .await
+            .unwrap_err();
+        assert!(err.is_retryable());
+
+        // the range is started again and the stream completes
+        server.fail_queries_from(50, 2);
+        let res = client
+            .clone()
+            .collect_arrow(
+                blocks_query(0, 100),
+                StreamConfig {
+                    range_retries: Some(2),
+                    ..config.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.next_block, 100);
+        assert_eq!(num_blocks(&res), 100);
+
+        // the range keeps failing so it is skipped
+        server.fail_queries_from(50, 10);
+        let metrics = Arc::new(StreamMetrics::default());
+        let res = client
+            .clone()
+            .collect_arrow(
+                blocks_query(0, 100),
+                StreamConfig {
+                    range_retries: Some(1),
+                    skip_failed_ranges: true,
+                    metrics: Some(metrics.clone()),
+                    ..config.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.next_block, 100);
+        assert_eq!(num_blocks(&res), 90);
+        assert_eq!(metrics.skipped_ranges(), vec![(50, 60)]);
+
+        assert!(client
+            .clone()
+            .stream_arrow(
+                blocks_query(0, 100),
+                StreamConfig {
+                    skip_failed_ranges: true,
+                    checkpoint: Some(Arc::new(MemoryCheckpoint::new(None))),
+                    ..config
+                },
+            )
+            .await
+            .is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_range_retry_backoff() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = Arc::new(
+            crate::Client::new(crate::ClientConfig {
+                url: Some(server.url()),
+                max_num_retries: Some(0),
+                retry_base_ms: Some(100),
+                retry_backoff_ms: Some(1),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+
+        // a failed request takes 100ms, so restarting right away would use up the restarts
+        // before the range recovers, while waiting 100ms and then 200ms gets past it
+        server.fail_queries_from_for(50, Duration::from_millis(400));
+        let start = std::time::Instant::now();
+        let res = client
+            .clone()
+            .collect_arrow(
+                blocks_query(0, 100),
+                StreamConfig {
+                    batch_size: Some(10),
+                    max_batch_size: Some(10),
+                    range_retries: Some(3),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.next_block, 100);
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_resume_after() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+        let collect = |from_block, resume_after| {
+            let client = client.clone();
+            async move {
+                let config = StreamConfig {
+                    concurrency: Some(2),
+                    batch_size: Some(10),
+                    resume_after,
+                    ..Default::default()
+                };
+                let data = client
+                    .collect_arrow(transfers_query(from_block, 90), config)
+                    .await?
+                    .data;
+                let rows = |batches: &[ArrowBatch], block: &str, index: Option<&str>| {
+                    batches
+                        .iter()
+                        .flat_map(|batch| {
+                            let blocks = batch.u64_column(block).unwrap();
+                            let indices = index.map(|index| batch.u64_column(index).unwrap());
+                            (0..batch.chunk.len())
+                                .map(|i| {
+                                    (
+                                        blocks.value(i),
+                                        indices.as_ref().map(|indices| indices.value(i)),
+                                    )
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>()
+                };
+                Ok::<_, crate::Error>([
+                    rows(&data.blocks, "number", None),
+                    rows(
+                        &data.transactions,
+                        "block_number",
+                        Some("transaction_index"),
+                    ),
+                    rows(&data.logs, "block_number", Some("log_index")),
+                ])
+            }
+        };
+        let all = collect(0, None).await.unwrap();
+
+        // the consumer stopped after the first transaction and the second log of block 23, which
+        // has two transactions and three logs
+        let resume = ResumePoint {
+            block_number: 23,
+            transaction_index: Some(0),
+            log_index: Some(1),
+        };
+        let handled = |table: &[(u64, Option<u64>)], index: Option<u64>| {
+            table
+                .iter()
+                .filter(|&&(block, i)| block < 23 || (block == 23 && i <= index))
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        let handled = [
+            handled(&all[0], None),
+            handled(&all[1], resume.transaction_index),
+            handled(&all[2], resume.log_index),
+        ];
+
+        // restarting from the block of the partially handled response sees those rows again
+        let overlapping = collect(23, None).await.unwrap();
+        assert_eq!(overlapping[1][0], (23, Some(0)));
+        assert_eq!(overlapping[2][..2], [(23, Some(0)), (23, Some(1))]);
+
+        let resumed = collect(23, Some(resume)).await.unwrap();
+        assert_eq!(resumed[0][0], (24, None));
+        assert_eq!(resumed[1][0], (23, Some(1)));
+        assert_eq!(resumed[2][0], (23, Some(2)));
+        for ((handled, resumed), all) in handled.into_iter().zip(resumed).zip(all) {
+            assert_eq!([handled, resumed].concat(), all);
+        }
+
+        let err = client
+            .clone()
+            .collect_arrow(
+                transfers_query(0, 90),
+                StreamConfig {
+                    resume_after: Some(resume),
+                    reverse: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("config.resume_after"));
+    }
+}
@@ -0,0 +1,289 @@
+use std::{sync::Arc, time::Instant};
+
+use anyhow::{anyhow, Context, Result};
+use futures::{SinkExt, StreamExt};
+use hypersync_net_types::Query;
+use reqwest::{header::HeaderMap, Method};
+use tokio::{net::TcpStream, sync::mpsc};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Error as WsError, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+use url::Url;
+
+use super::map_responses;
+use crate::{
+    error::HttpStatusError, parse_response::parse_query_response, rayon_async, ArrowResponse,
+    StreamConfig,
+};
+
+/// Streams responses pushed by the server over a websocket connection.
+///
+/// The query is sent as a json text message after connecting to the `query/arrow-ipc/ws`
+/// endpoint. The server then pushes each response as a binary message in the same format as the
+/// `query/arrow-ipc` endpoint, continuing with new blocks as they arrive at the tip. The stream
+/// ends when the server closes the connection or when `query.to_block` is reached.
+pub async fn stream_arrow(
+    client: Arc<crate::Client>,
+    query: Query,
+    config: StreamConfig,
+) -> Result<mpsc::Receiver<Result<ArrowResponse>>> {
+    let start = Instant::now();
+
+    if config.reverse.unwrap_or_default() {
+        return Err(anyhow!(
+            "reverse streaming isn't supported by the websocket transport"
+        ));
+    }
+
+    let query_json = serde_json::to_string(&query).context("serialize query")?;
+    // only used to truncate messages with injected faults
+    #[cfg_attr(not(feature = "test-util"), allow(unused_variables))]
+    let (mut socket, fault_idx) = client
+        .with_retries("connect to websocket", None, |url| {
+            connect(&client, url, query_json.clone())
+        })
+        .await?;
+
+    let (tx, rx) = mpsc::channel(config.concurrency.unwrap_or(10) * 2);
+
+    super::spawn_until_closed(tx.clone(), async move {
+        while let Some(msg) = socket.next().await {
+            let bytes = match msg {
+                Ok(Message::Binary(bytes)) => bytes,
+                Ok(Message::Close(_)) => break,
+                // ping/pong frames are answered by tungstenite itself
+                Ok(_) => continue,
+                Err(e) => {
+                    tx.send(Err(anyhow!(e).context("read websocket message")))
+                        .await
+                        .ok();
+                    return;
+                }
+            };
+
+            #[cfg(feature = "test-util")]
+            let bytes = client
+                .inject_response_faults(fault_idx, bytes.into())
+                .to_vec();
+            let decode = client.ipc_decode;
+            let res = rayon_async::spawn(move || {
+                parse_query_response(&bytes, decode).context("parse query response")
+            })
+            .await
+            .unwrap()
+            .and_then(|(res, fingerprint)| {
+                client.check_schema_fingerprint(fingerprint)?;
+                Ok(res)
+            });
+            let res = match res {
+                Ok(res) => res,
+                Err(e) => {
+                    tx.send(Err(e)).await.ok();
+                    return;
+                }
+            };
+
+            let res = match map_responses(config.clone(), vec![res], false).await {
+                Ok(mut resps) => resps.remove(0),
+                Err(e) => {
+                    tx.send(Err(e)).await.ok();
+                    return;
+                }
+            };
+
+            let next_block = res.next_block;
+            if tx.send(Ok(res)).await.is_err() {
+                return;
+            }
+            if let Some(metrics) = config.metrics.as_ref() {
+                metrics.record_first_batch(start.elapsed());
+            }
+
+            if query
+                .to_block
+                .is_some_and(|to_block| next_block >= to_block)
+            {
+                break;
+            }
+        }
+
+        socket.close(None).await.ok();
+    });
+
+    Ok(rx)
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Opens a websocket connection to the `query/arrow-ipc/ws` endpoint of `url` and sends the
+/// query over it.
+///
+/// The handshake request goes through the same headers, authentication, interceptors and
+/// injected faults as http requests, and fails with the status code of the server if it is
+/// rejected, so `Client::with_retries` retries it and refreshes a rejected bearer token.
+async fn connect(
+    client: &crate::Client,
+    mut url: Url,
+    query_json: String,
+) -> Result<(Socket, u64)> {
+    let scheme = match url.scheme() {
+        "https" => "wss",
+        "http" => "ws",
+        scheme => return Err(anyhow!("unsupported url scheme '{}'", scheme)),
+    };
+    url.set_scheme(scheme)
+        .map_err(|_| anyhow!("set websocket url scheme"))?;
+    let mut segments = url.path_segments_mut().ok().context("get path segments")?;
+    segments.push("query");
+    segments.push("arrow-ipc");
+    segments.push("ws");
+    std::mem::drop(segments);
+
+    #[cfg(feature = "test-util")]
+    let fault_idx = client.inject_request_faults().await?;
+    #[cfg(not(feature = "test-util"))]
+    let fault_idx = 0;
+
+    let parts = client
+        .request_parts(Method::GET, url, None, HeaderMap::new())
+        .await?;
+    let mut req = parts
+        .url
+        .as_str()
+        .into_client_request()
+        .context("build websocket request")?;
+    // the handshake headers set by tungstenite are kept
+    for (name, value) in parts.headers.iter() {
+        if !req.headers().contains_key(name) {
+            req.headers_mut().insert(name, value.clone());
+        }
+    }
+
+    let start = Instant::now();
+    let (mut socket, res) = match tokio_tungstenite::connect_async(req).await {
+        Ok(connected) => connected,
+        Err(WsError::Http(res)) => {
+            client.intercept_response(
+                parts.method,
+                parts.url,
+                res.status(),
+                res.headers(),
+                start.elapsed(),
+            );
+            let body = String::from_utf8_lossy(res.body().as_deref().unwrap_or_default());
+            return Err(
+                HttpStatusError::new(res.status(), res.headers(), body.into_owned()).into(),
+            );
+        }
+        Err(e) => return Err(anyhow!(e).context("connect to websocket")),
+    };
+    client.intercept_response(
+        parts.method,
+        parts.url,
+        res.status(),
+        res.headers(),
+        start.elapsed(),
+    );
+
+    socket
+        .send(Message::Text(query_json))
+        .await
+        .context("send query")?;
+
+    Ok((socket, fault_idx))
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use futures::future::BoxFuture;
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::{
+        mock_server::{blocks_query, MockChain, MockServer},
+        Client, ClientConfig, FaultInjectionConfig, Interceptor, ResponseMeta, StreamTransport,
+        TokenProvider,
+    };
+
+    struct Counter(AtomicUsize);
+
+    impl TokenProvider for Counter {
+        fn fetch_token(&self) -> BoxFuture<'_, Result<String>> {
+            Box::pin(async move {
+                let n = self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(format!("token-{}", n))
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct Statuses(Mutex<Vec<StatusCode>>);
+
+    impl Interceptor for Statuses {
+        fn on_response(&self, res: &ResponseMeta) {
+            self.0.lock().unwrap().push(res.status);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_websocket() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        server.set_max_blocks_per_response(10);
+        server.require_bearer_token(Some("token-1"));
+        let statuses = Arc::new(Statuses::default());
+        let client = Arc::new(
+            Client::new(ClientConfig {
+                url: Some(server.url()),
+                max_num_retries: Some(0),
+                token_provider: Some(Arc::new(Counter(AtomicUsize::new(0)))),
+                interceptors: vec![statuses.clone()],
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let config = StreamConfig {
+            transport: StreamTransport::WebSocket,
+            ..Default::default()
+        };
+
+        // the first token is rejected and replaced by a fresh one
+        let mut rx = client
+            .stream_arrow(blocks_query(0, 100), config.clone())
+            .await
+            .unwrap();
+        let mut next_blocks = Vec::new();
+        while let Some(resp) = rx.recv().await {
+            next_blocks.push(resp.unwrap().next_block);
+        }
+        assert_eq!(next_blocks, (1..=10).map(|i| i * 10).collect::<Vec<_>>());
+        assert_eq!(
+            *statuses.0.lock().unwrap(),
+            [StatusCode::UNAUTHORIZED, StatusCode::SWITCHING_PROTOCOLS]
+        );
+
+        let client = Arc::new(
+            Client::new(ClientConfig {
+                url: Some(server.url()),
+                max_num_retries: Some(0),
+                bearer_token: Some("token-1".into()),
+                fault_injection: Some(FaultInjectionConfig {
+                    fail_every_nth_request: Some(1.try_into().unwrap()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let err = client
+            .stream_arrow(blocks_query(0, 100), config)
+            .await
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains("injected failure"));
+    }
+}
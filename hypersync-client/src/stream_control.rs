@@ -0,0 +1,104 @@
+use tokio::sync::watch;
+
+/// Pauses and resumes a stream from outside of it, registered with `StreamConfig::control`.
+///
+/// A paused stream stops sending new range requests but keeps its position, the requests that
+/// are already in flight still complete and their responses are passed on. Useful when the
+/// consumer of the stream can't keep up for a while and would otherwise only exert backpressure
+/// through the channel. Clones control the same streams.
+#[derive(Debug, Clone)]
+pub struct StreamControl {
+    paused: watch::Sender<bool>,
+}
+
+impl Default for StreamControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamControl {
+    /// Creates a handle that doesn't pause the stream until `pause` is called.
+    pub fn new() -> Self {
+        Self {
+            paused: watch::channel(false).0,
+        }
+    }
+
+    /// Stops the stream from sending new requests until `resume` is called.
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    /// Lets the stream continue sending requests.
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    /// Whether the stream is paused.
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Waits until the stream isn't paused.
+    pub(crate) async fn wait_while_paused(&self) {
+        let mut paused = self.paused.subscribe();
+        // the sender is held by self, so waiting can't fail
+        paused.wait_for(|paused| !*paused).await.ok();
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock_server::{blocks_query, mock_client, MockChain, MockServer};
+    use crate::StreamConfig;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stream_control() {
+        let server = MockServer::start(MockChain::new(200)).unwrap();
+        server.set_max_blocks_per_response(10);
+        let client = mock_client(&server);
+        let control = StreamControl::new();
+        control.pause();
+
+        let mut rx = client
+            .stream_arrow(
+                blocks_query(0, 200),
+                StreamConfig {
+                    concurrency: Some(2),
+                    batch_size: Some(10),
+                    max_batch_size: Some(10),
+                    control: Some(control.clone()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(server.num_queries(), 0);
+
+        control.resume();
+        let mut next_block = rx.recv().await.unwrap().unwrap().next_block;
+        control.pause();
+        // requests sent before the pause still arrive
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let num_queries = server.num_queries();
+        while let Ok(Some(resp)) = tokio::time::timeout(Duration::from_millis(100), rx.recv()).await
+        {
+            next_block = resp.unwrap().next_block;
+        }
+        assert_eq!(server.num_queries(), num_queries);
+        assert!(next_block < 200);
+
+        control.resume();
+        while let Some(resp) = rx.recv().await {
+            let resp = resp.unwrap();
+            assert!(resp.next_block > next_block);
+            next_block = resp.next_block;
+        }
+        assert_eq!(next_block, 200);
+    }
+}
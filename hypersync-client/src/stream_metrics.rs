@@ -0,0 +1,308 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+use crate::{
+    progress::{EtaEstimator, Progress},
+    TransferStats,
+};
+
+/// Metrics recorded by a stream while it is running.
+///
+/// Pass an `Arc<StreamMetrics>` in `StreamConfig::metrics` and keep a clone of it to read the
+/// values while the stream is running or after it has finished.
+#[derive(Debug, Default)]
+pub struct StreamMetrics {
+    time_to_first_batch: OnceLock<Duration>,
+    first_batch: Notify,
+    num_payload_too_large_backoffs: AtomicU64,
+    eta: Mutex<Option<EtaEstimator>>,
+    num_requests: AtomicU64,
+    num_retries: AtomicU64,
+    wire_bytes: AtomicU64,
+    decompressed_bytes: AtomicU64,
+    server_execution_micros: AtomicU64,
+    fetch_micros: AtomicU64,
+    parse_micros: AtomicU64,
+    decode_micros: AtomicU64,
+    convert_micros: AtomicU64,
+    batch_size: AtomicU64,
+    skipped_ranges: Mutex<Vec<(u64, u64)>>,
+    num_spilled_responses: AtomicU64,
+    spilled_bytes: AtomicU64,
+    spill_file_bytes: AtomicU64,
+    spill_micros: AtomicU64,
+}
+
+/// Summary of the work a stream did, for capacity planning and support tickets.
+///
+/// Stage timings are summed over concurrent requests, so they can add up to more than the wall
+/// clock time of the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExecutionReport {
+    /// Number of query requests sent to the server, including retries.
+    pub num_requests: u64,
+    /// Number of requests that were retries of a failed request.
+    pub num_retries: u64,
+    /// Number of times the block range of a request was halved after a 413 response.
+    pub num_payload_too_large_backoffs: u64,
+    /// Bytes of response bodies received, before decompression.
+    pub wire_bytes: u64,
+    /// Bytes of response bodies after decompression.
+    pub decompressed_bytes: u64,
+    /// Time the server reported spending on executing the queries.
+    pub server_execution_time: Duration,
+    /// Time spent sending requests and receiving responses.
+    pub fetch_time: Duration,
+    /// Time spent decompressing and parsing responses.
+    pub parse_time: Duration,
+    /// Time spent decoding logs and applying column mappings and hex encoding.
+    pub decode_time: Duration,
+    /// Time spent converting arrow data into rust types, only recorded by the collect functions.
+    pub convert_time: Duration,
+    /// Block range size of requests the adaptive batch size settled on.
+    pub final_batch_size: u64,
+    /// Number of responses that were moved to disk while they waited for the ranges before
+    /// them, see `StreamConfig::spill_dir`.
+    pub num_spilled_responses: u64,
+    /// Decompressed bytes of the spilled responses.
+    pub spilled_bytes: u64,
+    /// Bytes written to spill files, after lz4 compression.
+    pub spill_file_bytes: u64,
+    /// Time spent writing responses to spill files and reading them back.
+    pub spill_time: Duration,
+}
+
+impl StreamMetrics {
+    /// Time between starting the stream and the first response being sent on the channel.
+    ///
+    /// None if the stream hasn't produced a response yet.
+    pub fn time_to_first_batch(&self) -> Option<Duration> {
+        self.time_to_first_batch.get().copied()
+    }
+
+    /// Waits until the stream sent its first response and returns the time it took, see
+    /// [`StreamMetrics::time_to_first_batch`].
+    pub async fn first_batch(&self) -> Duration {
+        loop {
+            let notified = self.first_batch.notified();
+            if let Some(elapsed) = self.time_to_first_batch() {
+                return elapsed;
+            }
+            notified.await;
+        }
+    }
+
+    /// Number of times a request was rejected by the server for being too large and the
+    /// requested block range was halved.
+    pub fn num_payload_too_large_backoffs(&self) -> u64 {
+        self.num_payload_too_large_backoffs.load(Ordering::Relaxed)
+    }
+
+    /// Block ranges `[from_block, to_block)` that were skipped after failing with
+    /// `StreamConfig::skip_failed_ranges`, sorted by block. Their data is missing from the
+    /// output of the stream.
+    pub fn skipped_ranges(&self) -> Vec<(u64, u64)> {
+        let mut ranges = self.skipped_ranges.lock().unwrap().clone();
+        ranges.sort();
+        ranges
+    }
+
+    /// Current progress of the stream through its block range.
+    ///
+    /// None until the stream has started, and for reverse streams.
+    pub fn progress(&self) -> Option<Progress> {
+        self.eta.lock().unwrap().as_ref().map(|eta| eta.progress())
+    }
+
+    /// Returns a report of the work recorded so far.
+    pub fn report(&self) -> ExecutionReport {
+        let micros = |v: &AtomicU64| Duration::from_micros(v.load(Ordering::Relaxed));
+        ExecutionReport {
+            num_requests: self.num_requests.load(Ordering::Relaxed),
+            num_retries: self.num_retries.load(Ordering::Relaxed),
+            num_payload_too_large_backoffs: self.num_payload_too_large_backoffs(),
+            wire_bytes: self.wire_bytes.load(Ordering::Relaxed),
+            decompressed_bytes: self.decompressed_bytes.load(Ordering::Relaxed),
+            server_execution_time: micros(&self.server_execution_micros),
+            fetch_time: micros(&self.fetch_micros),
+            parse_time: micros(&self.parse_micros),
+            decode_time: micros(&self.decode_micros),
+            convert_time: micros(&self.convert_micros),
+            final_batch_size: self.batch_size.load(Ordering::Relaxed),
+            num_spilled_responses: self.num_spilled_responses.load(Ordering::Relaxed),
+            spilled_bytes: self.spilled_bytes.load(Ordering::Relaxed),
+            spill_file_bytes: self.spill_file_bytes.load(Ordering::Relaxed),
+            spill_time: micros(&self.spill_micros),
+        }
+    }
+
+    pub(crate) fn num_retries(&self) -> u64 {
+        self.num_retries.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_request(&self, is_retry: bool) {
+        self.num_requests.fetch_add(1, Ordering::Relaxed);
+        if is_retry {
+            self.num_retries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_fetch(&self, wire_bytes: u64, elapsed: Duration) {
+        self.wire_bytes.fetch_add(wire_bytes, Ordering::Relaxed);
+        add_micros(&self.fetch_micros, elapsed);
+    }
+
+    pub(crate) fn record_transfer(&self, transfer: &TransferStats) {
+        self.decompressed_bytes
+            .fetch_add(transfer.decompressed_bytes, Ordering::Relaxed);
+        add_micros(
+            &self.server_execution_micros,
+            transfer.server_execution_time,
+        );
+    }
+
+    pub(crate) fn record_parse(&self, elapsed: Duration) {
+        add_micros(&self.parse_micros, elapsed);
+    }
+
+    pub(crate) fn record_decode(&self, elapsed: Duration) {
+        add_micros(&self.decode_micros, elapsed);
+    }
+
+    pub(crate) fn record_convert(&self, elapsed: Duration) {
+        add_micros(&self.convert_micros, elapsed);
+    }
+
+    pub(crate) fn record_batch_size(&self, batch_size: u64) {
+        self.batch_size.store(batch_size, Ordering::Relaxed);
+    }
+
+    pub(crate) fn start_progress(&self, from_block: u64, to_block: u64, start: Instant) {
+        *self.eta.lock().unwrap() = Some(EtaEstimator::new(from_block, to_block, start));
+    }
+
+    pub(crate) fn record_progress(&self, next_block: u64) {
+        if let Some(eta) = self.eta.lock().unwrap().as_mut() {
+            eta.update(
+                Instant::now(),
+                next_block,
+                self.wire_bytes.load(Ordering::Relaxed),
+            );
+        }
+    }
+
+    pub(crate) fn record_first_batch(&self, elapsed: Duration) {
+        if self.time_to_first_batch.set(elapsed).is_ok() {
+            self.first_batch.notify_waiters();
+        }
+    }
+
+    pub(crate) fn record_skipped_range(&self, from_block: u64, to_block: u64) {
+        self.skipped_ranges
+            .lock()
+            .unwrap()
+            .push((from_block, to_block));
+    }
+
+    pub(crate) fn record_spill(
+        &self,
+        num_responses: u64,
+        bytes: u64,
+        file_bytes: u64,
+        elapsed: Duration,
+    ) {
+        self.num_spilled_responses
+            .fetch_add(num_responses, Ordering::Relaxed);
+        self.spilled_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.spill_file_bytes
+            .fetch_add(file_bytes, Ordering::Relaxed);
+        add_micros(&self.spill_micros, elapsed);
+    }
+
+    pub(crate) fn record_spill_read(&self, elapsed: Duration) {
+        add_micros(&self.spill_micros, elapsed);
+    }
+
+    pub(crate) fn record_payload_too_large_backoff(&self) {
+        self.num_payload_too_large_backoffs
+            .fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn add_micros(counter: &AtomicU64, elapsed: Duration) {
+    counter.fetch_add(
+        u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX),
+        Ordering::Relaxed,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let metrics = StreamMetrics::default();
+        metrics.record_request(false);
+        metrics.record_request(true);
+        metrics.record_fetch(100, Duration::from_millis(3));
+        metrics.record_fetch(50, Duration::from_millis(2));
+        metrics.record_parse(Duration::from_micros(10));
+        metrics.record_transfer(&TransferStats {
+            wire_bytes: 150,
+            decompressed_bytes: 600,
+            server_execution_time: Duration::from_millis(4),
+            latency: Duration::from_millis(5),
+        });
+        metrics.record_batch_size(1000);
+        metrics.record_batch_size(400);
+        metrics.record_payload_too_large_backoff();
+        metrics.record_spill(2, 600, 200, Duration::from_micros(30));
+        metrics.record_spill_read(Duration::from_micros(20));
+
+        assert_eq!(
+            metrics.report(),
+            ExecutionReport {
+                num_requests: 2,
+                num_retries: 1,
+                num_payload_too_large_backoffs: 1,
+                wire_bytes: 150,
+                decompressed_bytes: 600,
+                server_execution_time: Duration::from_millis(4),
+                fetch_time: Duration::from_millis(5),
+                parse_time: Duration::from_micros(10),
+                decode_time: Duration::ZERO,
+                convert_time: Duration::ZERO,
+                final_batch_size: 400,
+                num_spilled_responses: 2,
+                spilled_bytes: 600,
+                spill_file_bytes: 200,
+                spill_time: Duration::from_micros(50),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_first_batch() {
+        let metrics = std::sync::Arc::new(StreamMetrics::default());
+        let waiter = tokio::spawn({
+            let metrics = metrics.clone();
+            async move { metrics.first_batch().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        metrics.record_first_batch(Duration::from_millis(7));
+        metrics.record_first_batch(Duration::from_millis(9));
+        assert_eq!(waiter.await.unwrap(), Duration::from_millis(7));
+        // resolves right away once the first batch was sent
+        assert_eq!(metrics.first_batch().await, Duration::from_millis(7));
+    }
+}
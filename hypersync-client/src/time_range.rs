@@ -0,0 +1,206 @@
+use anyhow::{anyhow, Context, Result};
+use hypersync_net_types::Query;
+use polars_arrow::array::{Array, BinaryArray};
+
+use crate::{preset_query, Client, StreamConfig};
+
+/// Returns the first block with a timestamp at or after `timestamp`, or the block after the
+/// height of the server if there is none yet.
+///
+/// Block timestamps only increase, so this is a binary search that fetches the header of a
+/// single block per step, about 25 requests on a chain with tens of millions of blocks.
+pub(crate) async fn block_at_timestamp(client: &Client, timestamp: u64) -> Result<u64> {
+    let height = client.get_height().await.context("get height")?;
+    search(client, timestamp, 0, height.saturating_add(1)).await
+}
+
+/// Sets the block range of the query from `config.from_timestamp` and `config.to_timestamp`
+/// and clears them, so the stream runs over the resolved blocks as usual.
+pub(crate) async fn resolve_time_range(
+    client: &Client,
+    query: &mut Query,
+    config: &mut StreamConfig,
+) -> Result<()> {
+    let (from_timestamp, to_timestamp) = (config.from_timestamp, config.to_timestamp);
+    if from_timestamp.is_none() && to_timestamp.is_none() {
+        return Ok(());
+    }
+    if let (Some(from), Some(to)) = (from_timestamp, to_timestamp) {
+        if to < from {
+            return Err(anyhow!(
+                "config.to_timestamp {} is before config.from_timestamp {}",
+                to,
+                from
+            ));
+        }
+    }
+    if to_timestamp.is_some() && config.follow {
+        return Err(anyhow!(
+            "config.to_timestamp can't be combined with config.follow"
+        ));
+    }
+
+    let height = client.get_height().await.context("get height")?;
+    let end = height.saturating_add(1);
+    if let Some(timestamp) = from_timestamp {
+        query.from_block = search(client, timestamp, 0, end)
+            .await
+            .context("find first block of config.from_timestamp")?;
+    }
+    if let Some(timestamp) = to_timestamp {
+        query.to_block = Some(
+            search(client, timestamp, query.from_block.min(end), end)
+                .await
+                .context("find end block of config.to_timestamp")?,
+        );
+    }
+    log::debug!(
+        "resolved timestamps {:?}..{:?} to blocks {}..{:?}",
+        from_timestamp,
+        to_timestamp,
+        query.from_block,
+        query.to_block
+    );
+
+    config.from_timestamp = None;
+    config.to_timestamp = None;
+    Ok(())
+}
+
+/// First block in `[lo, hi)` with a timestamp at or after `timestamp`, `hi` if there is none.
+async fn search(client: &Client, timestamp: u64, mut lo: u64, mut hi: u64) -> Result<u64> {
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        match block_timestamp(client, mid).await? {
+            Some(block_timestamp) if block_timestamp < timestamp => lo = mid + 1,
+            // blocks the server doesn't have yet come after every timestamp it has
+            _ => hi = mid,
+        }
+    }
+    Ok(lo)
+}
+
+/// Timestamp of the block, None if the server doesn't have it.
+async fn block_timestamp(client: &Client, number: u64) -> Result<Option<u64>> {
+    let res = client
+        .get_arrow(&preset_query::block_index(number, Some(number + 1)))
+        .await
+        .with_context(|| format!("get header of block {}", number))?;
+
+    let Some(batch) = res.data.blocks.iter().find(|batch| batch.num_rows() > 0) else {
+        return Ok(None);
+    };
+    let timestamp = match batch.column::<BinaryArray<i32>>("timestamp") {
+        Ok(col) => col.get(0).map(quantity_to_u64).transpose()?,
+        Err(_) => {
+            let col = batch
+                .u64_column("timestamp")
+                .context("get timestamp column")?;
+            col.is_valid(0).then(|| col.value(0))
+        }
+    };
+    timestamp
+        .map(Some)
+        .with_context(|| format!("block {} has no timestamp", number))
+}
+
+fn quantity_to_u64(bytes: &[u8]) -> Result<u64> {
+    if bytes.len() > 8 {
+        return Err(anyhow!(
+            "timestamp of {} bytes doesn't fit in u64",
+            bytes.len()
+        ));
+    }
+    Ok(bytes
+        .iter()
+        .fold(0, |value, &b| (value << 8) | u64::from(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::mock_server::{blocks_query, mock_client, MockChain, MockServer};
+
+    #[test]
+    fn test_quantity_to_u64() {
+        assert_eq!(quantity_to_u64(&[]).unwrap(), 0);
+        assert_eq!(
+            quantity_to_u64(&1_700_000_000u64.to_be_bytes()[4..]).unwrap(),
+            1_700_000_000
+        );
+        assert!(quantity_to_u64(&[1; 9]).is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_time_range() {
+        let server = MockServer::start(MockChain::new(100)).unwrap();
+        let client = mock_client(&server);
+        let timestamp_of = |number: u64| 1_700_000_000 + number * 12;
+
+        assert_eq!(client.get_block_at_timestamp(0).await.unwrap(), 0);
+        assert_eq!(
+            client
+                .get_block_at_timestamp(timestamp_of(42))
+                .await
+                .unwrap(),
+            42
+        );
+        assert_eq!(
+            client
+                .get_block_at_timestamp(timestamp_of(42) - 5)
+                .await
+                .unwrap(),
+            42
+        );
+        // newer than every block of the server
+        assert_eq!(
+            client
+                .get_block_at_timestamp(timestamp_of(1000))
+                .await
+                .unwrap(),
+            100
+        );
+
+        let res = client
+            .clone()
+            .collect_arrow(
+                blocks_query(0, 0),
+                StreamConfig {
+                    from_timestamp: Some(timestamp_of(10) - 5),
+                    to_timestamp: Some(timestamp_of(20)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.next_block, 20);
+        let numbers = res
+            .data
+            .blocks
+            .iter()
+            .flat_map(|b| {
+                b.u64_column("number")
+                    .unwrap()
+                    .values_iter()
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, (10..20).collect::<Vec<_>>());
+
+        assert!(client
+            .clone()
+            .collect_arrow(
+                blocks_query(0, 0),
+                StreamConfig {
+                    from_timestamp: Some(timestamp_of(20)),
+                    to_timestamp: Some(timestamp_of(10)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,347 @@
+//! Conversions from the simple types into the alloy rpc types, so code written against alloy
+//! providers can consume hypersync events without adaptation.
+#![cfg(feature = "alloy")]
+
+use std::fmt::{Display, Formatter};
+
+use alloy_primitives::{Bloom, Bytes, LogData, B256, U256};
+use alloy_rpc_types_eth::{
+    AccessList as AlloyAccessList, AccessListItem, Log as AlloyLog, Parity, Receipt,
+    ReceiptEnvelope, ReceiptWithBloom, Signature, Transaction as AlloyTransaction,
+    TransactionReceipt,
+};
+use hypersync_format::{Address, Hash, Quantity, TransactionStatus};
+
+use crate::simple_types::{Event, Log, Transaction};
+
+/// Error happened during hypersync -> alloy type conversion
+#[derive(Debug, Clone)]
+pub enum ConversionError {
+    /// Value is missing. Make sure FieldSelection is correct.
+    MissingValue(String),
+    /// Conversion between types failed.
+    ConvertError(String),
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ConversionError::MissingValue(s) => write!(f, "Missing value: {}", s),
+            ConversionError::ConvertError(s) => write!(f, "Conversion error: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// An event converted into alloy types.
+///
+/// Converting the transaction and receipt needs `hash`, `from`, `nonce`, `gas`, `value`,
+/// `input`, `gas_used`, `cumulative_gas_used`, `effective_gas_price`, `logs_bloom` and `status`
+/// (or `root` before byzantium) in the transaction field selection.
+#[derive(Debug, Clone)]
+pub struct AlloyEvent {
+    /// The log of the event.
+    pub log: AlloyLog,
+    /// The transaction that emitted the log, None if the event has no transaction joined.
+    pub transaction: Option<AlloyTransaction>,
+    /// Receipt of the transaction that emitted the log, None if the event has no transaction
+    /// joined. Hypersync events only carry their own log, so the receipt's logs only contain
+    /// `log`.
+    pub receipt: Option<TransactionReceipt>,
+}
+
+impl TryFrom<Event> for AlloyEvent {
+    type Error = ConversionError;
+
+    fn try_from(value: Event) -> Result<Self, Self::Error> {
+        let log = AlloyLog::try_from(value.log)?;
+        let (transaction, receipt) = match value.transaction {
+            Some(tx) => {
+                let receipt = receipt(&tx, vec![log.clone()])?;
+                let tx = AlloyTransaction::try_from(Transaction::clone(&tx))?;
+                (Some(tx), Some(receipt))
+            }
+            None => (None, None),
+        };
+
+        Ok(AlloyEvent {
+            log,
+            transaction,
+            receipt,
+        })
+    }
+}
+
+impl TryFrom<Log> for AlloyLog {
+    type Error = ConversionError;
+
+    fn try_from(value: Log) -> Result<Self, Self::Error> {
+        let address = value.address.ok_or(ConversionError::MissingValue(
+            "Field `address` is null".into(),
+        ))?;
+        let topics = value
+            .topics
+            .into_iter()
+            .flatten()
+            .map(|topic| B256::from_slice(topic.as_ref()))
+            .collect::<Vec<_>>();
+        let data = value
+            .data
+            .ok_or(ConversionError::MissingValue("Field `data` is null".into()))?;
+
+        Ok(AlloyLog {
+            inner: alloy_primitives::Log {
+                address: address_conversion(&address),
+                data: LogData::new_unchecked(topics, Bytes::copy_from_slice(&data)),
+            },
+            block_hash: value.block_hash.as_ref().map(hash_conversion),
+            block_number: value.block_number.map(Into::into),
+            block_timestamp: None,
+            transaction_hash: value.transaction_hash.as_ref().map(hash_conversion),
+            transaction_index: value.transaction_index.map(Into::into),
+            log_index: value.log_index.map(Into::into),
+            removed: value.removed.unwrap_or(false),
+        })
+    }
+}
+
+impl TryFrom<Transaction> for AlloyTransaction {
+    type Error = ConversionError;
+
+    fn try_from(value: Transaction) -> Result<Self, Self::Error> {
+        let signature = match (&value.r, &value.s, &value.v) {
+            (Some(r), Some(s), Some(v)) => Some(Signature {
+                r: quantity_conversion(r, "r")?,
+                s: quantity_conversion(s, "s")?,
+                v: quantity_conversion(v, "v")?,
+                y_parity: value
+                    .y_parity
+                    .as_ref()
+                    .map(|y| quantity_conversion::<u64>(y, "y_parity").map(|y| Parity(y != 0)))
+                    .transpose()?,
+            }),
+            _ => None,
+        };
+
+        Ok(AlloyTransaction {
+            hash: hash_conversion(required(&value.hash, "hash")?),
+            nonce: quantity_conversion(required(&value.nonce, "nonce")?, "nonce")?,
+            block_hash: value.block_hash.as_ref().map(hash_conversion),
+            block_number: value.block_number.map(Into::into),
+            transaction_index: value.transaction_index.map(Into::into),
+            from: address_conversion(required(&value.from, "from")?),
+            to: value.to.as_ref().map(address_conversion),
+            value: quantity_conversion(required(&value.value, "value")?, "value")?,
+            gas_price: optional_quantity(&value.gas_price, "gas_price")?,
+            gas: quantity_conversion(required(&value.gas, "gas")?, "gas")?,
+            max_fee_per_gas: optional_quantity(&value.max_fee_per_gas, "max_fee_per_gas")?,
+            max_priority_fee_per_gas: optional_quantity(
+                &value.max_priority_fee_per_gas,
+                "max_priority_fee_per_gas",
+            )?,
+            max_fee_per_blob_gas: optional_quantity(
+                &value.max_fee_per_blob_gas,
+                "max_fee_per_blob_gas",
+            )?,
+            input: Bytes::copy_from_slice(required(&value.input, "input")?),
+            signature,
+            chain_id: optional_quantity(&value.chain_id, "chain_id")?,
+            blob_versioned_hashes: value
+                .blob_versioned_hashes
+                .as_ref()
+                .map(|hashes| hashes.iter().map(hash_conversion).collect()),
+            access_list: value
+                .access_list
+                .as_ref()
+                .map(|list| access_list_conversion(list))
+                .transpose()?,
+            transaction_type: value.kind.map(|kind| kind.0),
+            authorization_list: None,
+        })
+    }
+}
+
+/// Builds the receipt of the given transaction, with the given logs.
+///
+/// Hypersync returns the receipt fields as part of the transaction, see `AlloyEvent` for the
+/// fields that have to be selected.
+pub fn receipt(
+    tx: &Transaction,
+    logs: Vec<AlloyLog>,
+) -> Result<TransactionReceipt, ConversionError> {
+    let status = match (tx.status, &tx.root) {
+        (Some(status), _) => (status == TransactionStatus::Success).into(),
+        (None, Some(root)) => hash_conversion(root).into(),
+        (None, None) => {
+            return Err(ConversionError::MissingValue(
+                "Field `status` is null".into(),
+            ))
+        }
+    };
+    let logs_bloom = required(&tx.logs_bloom, "logs_bloom")?;
+    let receipt = ReceiptWithBloom {
+        receipt: Receipt {
+            status,
+            cumulative_gas_used: quantity_conversion(
+                required(&tx.cumulative_gas_used, "cumulative_gas_used")?,
+                "cumulative_gas_used",
+            )?,
+            logs,
+        },
+        logs_bloom: Bloom::try_from(logs_bloom.as_ref())
+            .map_err(|e| ConversionError::ConvertError(format!("logs_bloom: {}", e)))?,
+    };
+    let inner = match tx.kind.map(|kind| kind.0).unwrap_or(0) {
+        0 => ReceiptEnvelope::Legacy(receipt),
+        1 => ReceiptEnvelope::Eip2930(receipt),
+        2 => ReceiptEnvelope::Eip1559(receipt),
+        3 => ReceiptEnvelope::Eip4844(receipt),
+        4 => ReceiptEnvelope::Eip7702(receipt),
+        kind => {
+            return Err(ConversionError::ConvertError(format!(
+                "unsupported transaction type {}",
+                kind
+            )))
+        }
+    };
+
+    Ok(TransactionReceipt {
+        inner,
+        transaction_hash: hash_conversion(required(&tx.hash, "hash")?),
+        transaction_index: tx.transaction_index.map(Into::into),
+        block_hash: tx.block_hash.as_ref().map(hash_conversion),
+        block_number: tx.block_number.map(Into::into),
+        gas_used: quantity_conversion(required(&tx.gas_used, "gas_used")?, "gas_used")?,
+        effective_gas_price: quantity_conversion(
+            required(&tx.effective_gas_price, "effective_gas_price")?,
+            "effective_gas_price",
+        )?,
+        blob_gas_used: None,
+        blob_gas_price: None,
+        from: address_conversion(required(&tx.from, "from")?),
+        to: tx.to.as_ref().map(address_conversion),
+        contract_address: tx.contract_address.as_ref().map(address_conversion),
+        state_root: tx.root.as_ref().map(hash_conversion),
+        authorization_list: None,
+    })
+}
+
+fn required<'a, T>(value: &'a Option<T>, name: &str) -> Result<&'a T, ConversionError> {
+    value
+        .as_ref()
+        .ok_or_else(|| ConversionError::MissingValue(format!("Field `{}` is null", name)))
+}
+
+fn address_conversion(value: &Address) -> alloy_primitives::Address {
+    alloy_primitives::Address::from_slice(value.as_ref())
+}
+
+fn hash_conversion(value: &Hash) -> B256 {
+    B256::from_slice(value.as_ref())
+}
+
+fn quantity_conversion<T: TryFrom<U256>>(
+    value: &Quantity,
+    name: &str,
+) -> Result<T, ConversionError> {
+    U256::try_from_be_slice(value.as_ref())
+        .and_then(|v| T::try_from(v).ok())
+        .ok_or_else(|| ConversionError::ConvertError(format!("Field `{}` overflows", name)))
+}
+
+fn optional_quantity<T: TryFrom<U256>>(
+    value: &Option<Quantity>,
+    name: &str,
+) -> Result<Option<T>, ConversionError> {
+    value
+        .as_ref()
+        .map(|v| quantity_conversion(v, name))
+        .transpose()
+}
+
+fn access_list_conversion(
+    value: &[hypersync_format::AccessList],
+) -> Result<AlloyAccessList, ConversionError> {
+    value
+        .iter()
+        .map(|item| {
+            Ok(AccessListItem {
+                address: address_conversion(required(&item.address, "access_list.address")?),
+                storage_keys: item
+                    .storage_keys
+                    .iter()
+                    .flatten()
+                    .map(hash_conversion)
+                    .collect(),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(AlloyAccessList)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hypersync_format::{Hex, TransactionType};
+
+    use super::*;
+
+    #[test]
+    fn test_event_conversion() {
+        let hash = Hash::decode_hex(&format!("0x{}", "ab".repeat(32))).unwrap();
+        let address = Address::decode_hex(&format!("0x{}", "11".repeat(20))).unwrap();
+        let log = Log {
+            log_index: Some(3.into()),
+            transaction_index: Some(1.into()),
+            transaction_hash: Some(hash.clone()),
+            block_number: Some(100.into()),
+            address: Some(address.clone()),
+            data: Some(vec![1, 2, 3].into()),
+            topics: [Some(hash.clone()), None].into_iter().collect(),
+            ..Default::default()
+        };
+        let tx = Transaction {
+            hash: Some(hash.clone()),
+            from: Some(address.clone()),
+            nonce: Some(vec![7].into()),
+            gas: Some(vec![0x52, 0x08].into()),
+            value: Some(vec![1, 0].into()),
+            input: Some(vec![].into()),
+            gas_used: Some(vec![0x52, 0x08].into()),
+            cumulative_gas_used: Some(vec![0x52, 0x08].into()),
+            effective_gas_price: Some(vec![1].into()),
+            logs_bloom: Some(vec![0; 256].into()),
+            status: Some(TransactionStatus::Success),
+            kind: Some(TransactionType(2)),
+            ..Default::default()
+        };
+        let event = Event {
+            transaction: Some(Arc::new(tx)),
+            block: None,
+            log,
+            decoded: None,
+        };
+
+        let event = AlloyEvent::try_from(event).unwrap();
+        assert_eq!(event.log.address(), address_conversion(&address));
+        assert_eq!(event.log.topics(), &[hash_conversion(&hash)]);
+        assert_eq!(event.log.block_number, Some(100));
+        assert_eq!(event.log.log_index, Some(3));
+
+        let tx = event.transaction.unwrap();
+        assert_eq!(tx.nonce, 7);
+        assert_eq!(tx.gas, 21000);
+        assert_eq!(tx.value, U256::from(256));
+
+        let receipt = event.receipt.unwrap();
+        assert!(receipt.status());
+        assert_eq!(receipt.gas_used, 21000);
+        assert!(matches!(receipt.inner, ReceiptEnvelope::Eip1559(_)));
+        assert_eq!(receipt.inner.logs().len(), 1);
+
+        let missing = AlloyLog::try_from(Log::default()).unwrap_err();
+        assert!(matches!(missing, ConversionError::MissingValue(_)));
+    }
+}
@@ -0,0 +1,200 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use hypersync_net_types::FieldSelection;
+use polars_arrow::{
+    array::{Array, BinaryArray, UInt64Array, Utf8Array},
+    datatypes::{ArrowDataType as DataType, ArrowSchema as Schema, Field},
+};
+
+use crate::{ArrowBatch, ArrowChunk};
+
+/// Trace fields the summary is computed from.
+const REQUIRED_FIELDS: &[&str] = &[
+    "block_number",
+    "transaction_position",
+    "trace_address",
+    "type",
+    "error",
+];
+
+/// Checks that the field selection has the trace fields needed to compute the summary.
+pub(crate) fn check_field_selection(field_selection: &FieldSelection) -> Result<()> {
+    let missing = REQUIRED_FIELDS
+        .iter()
+        .filter(|field| !field_selection.trace.contains(**field))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(anyhow!(
+            "config.trace_summary needs the trace fields {:?} to be selected",
+            missing
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct TxSummary {
+    transaction_hash: Option<Vec<u8>>,
+    num_traces: u64,
+    num_internal_calls: u64,
+    num_created_contracts: u64,
+    max_call_depth: u64,
+    num_failed_subcalls: u64,
+}
+
+/// Summarizes the traces of each transaction in the batch into one row.
+///
+/// Rows are keyed by `block_number` and `transaction_position` and sorted by them. Traces that
+/// don't belong to a transaction, like block rewards, are skipped. The depth of a trace is the
+/// length of its `trace_address`, so the top level call of a transaction has depth 0 and
+/// everything deeper is an internal call.
+pub(crate) fn summarize_traces(batch: &ArrowBatch) -> Result<ArrowBatch> {
+    let block_number = batch.u64_column("block_number")?;
+    let transaction_position = batch.u64_column("transaction_position")?;
+    let trace_address = batch.column::<BinaryArray<i32>>("trace_address")?;
+    let kind = batch.column::<Utf8Array<i32>>("type")?;
+    let error = batch.column::<Utf8Array<i32>>("error")?;
+    let transaction_hash = batch.column::<BinaryArray<i32>>("transaction_hash").ok();
+
+    let mut summaries = BTreeMap::<(u64, u64), TxSummary>::new();
+    for i in 0..batch.num_rows() {
+        if !block_number.is_valid(i) || !transaction_position.is_valid(i) {
+            continue;
+        }
+        let (block, position) = (block_number.value(i), transaction_position.value(i));
+        let depth = match trace_address.get(i) {
+            Some(bytes) => bincode::deserialize::<Vec<u64>>(bytes)
+                .context("deserialize trace_address")?
+                .len() as u64,
+            None => 0,
+        };
+        let failed = error.get(i).is_some();
+
+        let summary = summaries.entry((block, position)).or_default();
+        if summary.transaction_hash.is_none() {
+            summary.transaction_hash =
+                transaction_hash.and_then(|col| col.get(i).map(|h| h.to_vec()));
+        }
+        summary.num_traces += 1;
+        summary.max_call_depth = summary.max_call_depth.max(depth);
+        match kind.get(i) {
+            Some("call") if depth > 0 => summary.num_internal_calls += 1,
+            Some("create") if !failed => summary.num_created_contracts += 1,
+            _ => (),
+        }
+        if failed && depth > 0 {
+            summary.num_failed_subcalls += 1;
+        }
+    }
+
+    let u64_col = |f: fn(&TxSummary) -> u64| {
+        UInt64Array::from_iter(summaries.values().map(|s| Some(f(s)))).boxed()
+    };
+    let mut fields = vec![
+        Field::new("block_number", DataType::UInt64, false),
+        Field::new("transaction_position", DataType::UInt64, false),
+    ];
+    let mut cols = vec![
+        UInt64Array::from_iter(summaries.keys().map(|(block, _)| Some(*block))).boxed(),
+        UInt64Array::from_iter(summaries.keys().map(|(_, position)| Some(*position))).boxed(),
+    ];
+    if transaction_hash.is_some() {
+        fields.push(Field::new("transaction_hash", DataType::Binary, true));
+        cols.push(
+            BinaryArray::<i32>::from_iter(summaries.values().map(|s| s.transaction_hash.as_ref()))
+                .boxed(),
+        );
+    }
+    for (name, col) in [
+        ("num_traces", u64_col(|s| s.num_traces)),
+        ("num_internal_calls", u64_col(|s| s.num_internal_calls)),
+        (
+            "num_created_contracts",
+            u64_col(|s| s.num_created_contracts),
+        ),
+        ("max_call_depth", u64_col(|s| s.max_call_depth)),
+        ("num_failed_subcalls", u64_col(|s| s.num_failed_subcalls)),
+    ] {
+        fields.push(Field::new(name, DataType::UInt64, false));
+        cols.push(col);
+    }
+
+    Ok(ArrowBatch {
+        chunk: Arc::new(ArrowChunk::try_new(cols).context("create arrow chunk")?),
+        schema: Arc::new(Schema::from(fields)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_traces() {
+        let addr = |a: &[u64]| bincode::serialize(&a.to_vec()).unwrap();
+        let addresses = [
+            addr(&[]),
+            addr(&[0]),
+            addr(&[0, 0]),
+            addr(&[1]),
+            addr(&[]),
+            addr(&[]),
+        ];
+        let batch = ArrowBatch {
+            chunk: Arc::new(ArrowChunk::new(vec![
+                UInt64Array::from_slice([5, 5, 5, 5, 4, 5]).boxed(),
+                UInt64Array::from([Some(1), Some(1), Some(1), Some(1), Some(0), None]).boxed(),
+                BinaryArray::<i32>::from_iter(addresses.iter().map(Some)).boxed(),
+                Utf8Array::<i32>::from([
+                    Some("call"),
+                    Some("create"),
+                    Some("call"),
+                    Some("call"),
+                    Some("create"),
+                    Some("reward"),
+                ])
+                .boxed(),
+                Utf8Array::<i32>::from([None, None, Some("Reverted"), None, Some("OOG"), None])
+                    .boxed(),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("block_number", DataType::UInt64, false),
+                Field::new("transaction_position", DataType::UInt64, true),
+                Field::new("trace_address", DataType::Binary, true),
+                Field::new("type", DataType::Utf8, true),
+                Field::new("error", DataType::Utf8, true),
+            ])),
+        };
+
+        let summary = summarize_traces(&batch).unwrap();
+        assert!(summary
+            .column::<BinaryArray<i32>>("transaction_hash")
+            .is_err());
+        let col = |name: &str| {
+            summary
+                .u64_column(name)
+                .unwrap()
+                .values_iter()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(col("block_number"), vec![4, 5]);
+        assert_eq!(col("transaction_position"), vec![0, 1]);
+        assert_eq!(col("num_traces"), vec![1, 4]);
+        assert_eq!(col("num_internal_calls"), vec![0, 2]);
+        assert_eq!(col("num_created_contracts"), vec![0, 1]);
+        assert_eq!(col("max_call_depth"), vec![0, 2]);
+        assert_eq!(col("num_failed_subcalls"), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_check_field_selection() {
+        let mut field_selection = FieldSelection::default();
+        assert!(check_field_selection(&field_selection).is_err());
+        field_selection
+            .trace
+            .extend(REQUIRED_FIELDS.iter().map(|f| f.to_string()));
+        check_field_selection(&field_selection).unwrap();
+    }
+}
@@ -1,12 +1,20 @@
-use std::sync::Arc;
+use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
 
 use crate::{
     simple_types::{Block, Event, Log, Trace, Transaction},
-    ArrowChunk, FromArrow,
+    ArrowChunk, ChainKind, ErrorKind, FromArrow,
 };
+use alloy_dyn_abi::DecodedEvent;
 use anyhow::{anyhow, Context, Result};
 use hypersync_net_types::RollbackGuard;
-use polars_arrow::datatypes::SchemaRef;
+use polars_arrow::{
+    array::{
+        Array, Int16Array, Int32Array, Int64Array, Int8Array, UInt16Array, UInt32Array,
+        UInt64Array, UInt8Array,
+    },
+    datatypes::{ArrowDataType, SchemaRef},
+};
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
 /// Query response in Arrow format
 #[derive(Default, Debug, Clone)]
@@ -23,6 +31,14 @@ pub struct ArrowResponseData {
     ///
     /// Populated only if event_singature is present.
     pub decoded_logs: Vec<ArrowBatch>,
+    /// Decoded logs of each event configured in `StreamConfig::event_routes`, keyed by route name.
+    ///
+    /// Each entry only contains the logs that match the selector of that route's event.
+    pub decoded_events: BTreeMap<String, Vec<ArrowBatch>>,
+    /// Per transaction summary of the traces response.
+    ///
+    /// Populated only if `StreamConfig::trace_summary` is set.
+    pub trace_summary: Vec<ArrowBatch>,
 }
 
 /// Query response data in Rust native format
@@ -36,17 +52,30 @@ pub struct ResponseData {
     pub logs: Vec<Vec<Log>>,
     /// Query traces response
     pub traces: Vec<Vec<Trace>>,
+    /// Logs decoded with `StreamConfig::event_signature`, one list for each list in `logs` with
+    /// an entry for each of its logs. Entries are None for logs that couldn't be decoded.
+    ///
+    /// Empty if no event signature was given.
+    pub decoded_logs: Vec<Vec<Option<DecodedEvent>>>,
 }
 
 impl From<&'_ ArrowResponse> for EventResponse {
     fn from(arrow_response: &'_ ArrowResponse) -> Self {
         let r: QueryResponse = arrow_response.into();
+        r.into()
+    }
+}
+
+impl From<QueryResponse> for EventResponse {
+    fn from(r: QueryResponse) -> Self {
         Self {
             archive_height: r.archive_height,
             next_block: r.next_block,
             total_execution_time: r.total_execution_time,
             data: vec![r.data.into()],
             rollback_guard: r.rollback_guard,
+            rollback: r.rollback,
+            transfer: r.transfer,
         }
     }
 }
@@ -87,12 +116,89 @@ impl From<&'_ ArrowResponse> for QueryResponse {
                 transactions,
                 logs,
                 traces,
+                decoded_logs: Vec::new(),
             },
             rollback_guard: arrow_response.rollback_guard.clone(),
+            rollback: arrow_response.rollback,
+            transfer: arrow_response.transfer,
         }
     }
 }
 
+/// Number of rows converted by each rayon task in `ArrowResponse::to_query_response_parallel`.
+const PARALLEL_CONVERSION_ROWS: usize = 50_000;
+
+/// Converts the batches on the rayon thread pool, splitting large batches into slices of
+/// `PARALLEL_CONVERSION_ROWS` rows. The output is in the same order as the input.
+fn convert_batches_parallel<T: FromArrow + Send>(batches: &[ArrowBatch]) -> Vec<Vec<T>> {
+    batches
+        .par_iter()
+        .map(|batch| {
+            let num_rows = batch.num_rows();
+            if num_rows <= PARALLEL_CONVERSION_ROWS {
+                return T::from_arrow(batch);
+            }
+            (0..num_rows)
+                .step_by(PARALLEL_CONVERSION_ROWS)
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .map(|offset| {
+                    let len = PARALLEL_CONVERSION_ROWS.min(num_rows - offset);
+                    T::from_arrow(&batch.slice(offset, len).unwrap())
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flatten()
+                .collect()
+        })
+        .collect()
+}
+
+impl ArrowResponse {
+    /// Same as converting with `QueryResponse::from`, but converts the batches of all tables
+    /// in parallel on the rayon thread pool. Worth it for large responses, e.g. ones collected
+    /// from a stream, where converting on one thread takes longer than downloading.
+    pub fn to_query_response_parallel(&self) -> QueryResponse {
+        let data = &self.data;
+        let ((blocks, transactions), (logs, traces)) = rayon::join(
+            || {
+                rayon::join(
+                    || convert_batches_parallel::<Block>(&data.blocks),
+                    || convert_batches_parallel::<Transaction>(&data.transactions),
+                )
+            },
+            || {
+                rayon::join(
+                    || convert_batches_parallel::<Log>(&data.logs),
+                    || convert_batches_parallel::<Trace>(&data.traces),
+                )
+            },
+        );
+
+        QueryResponse {
+            archive_height: self.archive_height,
+            next_block: self.next_block,
+            total_execution_time: self.total_execution_time,
+            data: ResponseData {
+                blocks,
+                transactions,
+                logs,
+                traces,
+                decoded_logs: Vec::new(),
+            },
+            rollback_guard: self.rollback_guard.clone(),
+            rollback: self.rollback,
+            transfer: self.transfer,
+        }
+    }
+
+    /// Same as converting with `EventResponse::from`, but converts the batches in parallel,
+    /// see [`ArrowResponse::to_query_response_parallel`].
+    pub fn to_event_response_parallel(&self) -> EventResponse {
+        self.to_query_response_parallel().into()
+    }
+}
+
 /// Query response from hypersync instance.
 /// Contain next_block field in case query didn't process all the block range
 #[derive(Debug, Clone)]
@@ -108,7 +214,47 @@ pub struct QueryResponse<T = ResponseData> {
     /// Response data
     pub data: T,
     /// Rollback guard
+    ///
+    /// Responses of the `collect` functions carry the guard of the response that reached the
+    /// highest block.
     pub rollback_guard: Option<RollbackGuard>,
+    /// Set on the first response after a follow stream detected a reorg. Data of blocks from
+    /// `Rollback::from_block` on that was returned before is from the replaced fork and has to
+    /// be dropped, this response continues from that block on the new fork.
+    pub rollback: Option<Rollback>,
+    /// Transfer metrics of the request(s) that produced this response.
+    pub transfer: TransferStats,
+}
+
+/// Reorg detected by a stream, see `QueryResponse::rollback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rollback {
+    /// First block that may have been replaced.
+    pub from_block: u64,
+}
+
+/// Transfer metrics of a query request, summed over all requests for responses that were
+/// collected from a stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferStats {
+    /// Bytes of the response body received, before decompression.
+    pub wire_bytes: u64,
+    /// Bytes of the response body after decompression.
+    pub decompressed_bytes: u64,
+    /// Time the server spent executing the query, as reported by the server.
+    pub server_execution_time: std::time::Duration,
+    /// Time between sending the request and receiving the whole response body.
+    pub latency: std::time::Duration,
+}
+
+impl TransferStats {
+    /// Adds the metrics of another request to these.
+    pub fn add(&mut self, other: &TransferStats) {
+        self.wire_bytes += other.wire_bytes;
+        self.decompressed_bytes += other.decompressed_bytes;
+        self.server_execution_time += other.server_execution_time;
+        self.latency += other.latency;
+    }
 }
 
 /// Alias for Arrow Query response
@@ -153,4 +299,280 @@ impl ArrowBatch {
             None => Err(anyhow!("field {} not found in schema", name)),
         }
     }
+
+    /// Extract an integer column by name as u64 values.
+    ///
+    /// Block numbers, transaction indices and log indices are returned as `UInt64` by current
+    /// servers, other integer widths are converted so callers don't need to care about what the
+    /// server sent. Borrows the column if it is already `UInt64`.
+    pub fn u64_column(&self, name: &str) -> Result<Cow<'_, UInt64Array>> {
+        let (idx, _) = self
+            .schema
+            .fields
+            .iter()
+            .enumerate()
+            .find(|(_, f)| f.name == name)
+            .with_context(|| anyhow!("field {} not found in schema", name))?;
+        let col = self
+            .chunk
+            .columns()
+            .get(idx)
+            .context("get column using index")?;
+        as_u64_array(col.as_ref()).with_context(|| format!("read column '{}' as u64", name))
+    }
+
+    /// Number of rows in the batch.
+    pub fn num_rows(&self) -> usize {
+        self.chunk.len()
+    }
+
+    /// Returns `len` rows starting at `offset` as a new batch.
+    ///
+    /// The returned batch shares the buffers of this one, nothing is copied.
+    pub fn slice(&self, offset: usize, len: usize) -> Result<ArrowBatch> {
+        if offset + len > self.num_rows() {
+            return Err(anyhow!(
+                "slice {}..{} out of bounds of batch with {} rows",
+                offset,
+                offset + len,
+                self.num_rows()
+            ));
+        }
+
+        let cols = self
+            .chunk
+            .columns()
+            .iter()
+            .map(|col| col.sliced(offset, len))
+            .collect();
+
+        Ok(ArrowBatch {
+            chunk: Arc::new(ArrowChunk::new(cols)),
+            schema: self.schema.clone(),
+        })
+    }
+
+    /// Returns the row ranges of each block in the batch as `(block_number, offset, len)`.
+    ///
+    /// `block_column` is the block number column of the table, `number` for blocks and
+    /// `block_number` for the other tables. Rows are expected to be ordered by block number as
+    /// they are in responses, so a block that reappears later gets a separate range.
+    pub fn block_ranges(&self, block_column: &str) -> Result<Vec<(u64, usize, usize)>> {
+        let blocks = self.u64_column(block_column)?;
+
+        let mut ranges: Vec<(u64, usize, usize)> = Vec::new();
+        for (idx, block) in blocks.iter().enumerate() {
+            let block = *block.with_context(|| format!("null {} at row {}", block_column, idx))?;
+            match ranges.last_mut() {
+                Some((last, _, len)) if *last == block => *len += 1,
+                _ => ranges.push((block, idx, 1)),
+            }
+        }
+
+        Ok(ranges)
+    }
+
+    /// Splits the batch into slices of at most `max_rows` rows without splitting any block
+    /// across slices.
+    ///
+    /// A block with more than `max_rows` rows gets a slice of its own. Slices share the buffers
+    /// of this batch, see [`ArrowBatch::slice`].
+    pub fn split_at_blocks(&self, block_column: &str, max_rows: usize) -> Result<Vec<ArrowBatch>> {
+        let mut slices = Vec::new();
+        let mut start = 0;
+        let mut len = 0;
+
+        for (_, offset, block_len) in self.block_ranges(block_column)? {
+            if len > 0 && len + block_len > max_rows {
+                slices.push(self.slice(start, len)?);
+                start = offset;
+                len = 0;
+            }
+            len += block_len;
+        }
+        if len > 0 {
+            slices.push(self.slice(start, len)?);
+        }
+
+        Ok(slices)
+    }
+}
+
+/// Result of [`crate::Client::health`].
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    /// Endpoint that was checked, the one requests currently go to.
+    pub url: url::Url,
+    /// Height of the server, None if the request failed.
+    pub height: Option<u64>,
+    /// Time it took to get the response or the error.
+    pub latency: std::time::Duration,
+    /// Message of the error if the request failed.
+    pub error: Option<String>,
+    /// Kind of the error if the request failed.
+    pub error_kind: Option<ErrorKind>,
+}
+
+impl HealthReport {
+    /// Returns true if the server responded with its height.
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Converts an integer array of any width to `UInt64Array`, failing on negative values.
+pub(crate) fn as_u64_array(col: &dyn Array) -> Result<Cow<'_, UInt64Array>> {
+    macro_rules! convert {
+        ($arr:ty) => {
+            col.as_any()
+                .downcast_ref::<$arr>()
+                .context("downcast integer column")?
+                .iter()
+                .map(|v| {
+                    v.map(|v| u64::try_from(*v).map_err(|_| anyhow!("negative value {}", v)))
+                        .transpose()
+                })
+                .collect::<Result<UInt64Array>>()
+                .map(Cow::Owned)
+        };
+    }
+
+    match col.data_type() {
+        ArrowDataType::UInt64 => col
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .map(Cow::Borrowed)
+            .context("downcast integer column"),
+        ArrowDataType::UInt32 => convert!(UInt32Array),
+        ArrowDataType::UInt16 => convert!(UInt16Array),
+        ArrowDataType::UInt8 => convert!(UInt8Array),
+        ArrowDataType::Int64 => convert!(Int64Array),
+        ArrowDataType::Int32 => convert!(Int32Array),
+        ArrowDataType::Int16 => convert!(Int16Array),
+        ArrowDataType::Int8 => convert!(Int8Array),
+        data_type => Err(anyhow!("expected an integer column, found {:?}", data_type)),
+    }
+}
+
+/// Metadata of the chain a server is serving, as returned by [`crate::Client::get_server_metadata`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerMetadata {
+    /// Chain id of the served chain.
+    pub chain_id: u64,
+    /// Height of the server. Queries can cover blocks up to this height.
+    pub height: u64,
+    /// Chain kind if the chain has a tuned `StreamConfig` preset.
+    pub chain: Option<ChainKind>,
+}
+
+#[cfg(test)]
+mod tests {
+    use polars_arrow::{
+        array::Utf8Array,
+        datatypes::{ArrowDataType as DataType, ArrowSchema as Schema, Field},
+    };
+
+    use super::*;
+
+    fn batch(blocks: &[u64]) -> ArrowBatch {
+        let names = blocks
+            .iter()
+            .map(|b| Some(b.to_string()))
+            .collect::<Vec<_>>();
+        ArrowBatch {
+            chunk: Arc::new(ArrowChunk::new(vec![
+                UInt64Array::from_slice(blocks).boxed(),
+                Utf8Array::<i32>::from(names).boxed(),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("block_number", DataType::UInt64, true),
+                Field::new("name", DataType::Utf8, true),
+            ])),
+        }
+    }
+
+    fn blocks(batch: &ArrowBatch) -> Vec<u64> {
+        batch
+            .column::<UInt64Array>("block_number")
+            .unwrap()
+            .values_iter()
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_to_query_response_parallel() {
+        let block_numbers = (0..2 * PARALLEL_CONVERSION_ROWS as u64 + 7).collect::<Vec<_>>();
+        let res = ArrowResponse {
+            archive_height: Some(10),
+            next_block: 5,
+            total_execution_time: 1,
+            data: ArrowResponseData {
+                logs: vec![batch(&block_numbers), batch(&[1, 2])],
+                ..Default::default()
+            },
+            rollback_guard: None,
+            rollback: None,
+            transfer: Default::default(),
+        };
+
+        let parallel = res.to_query_response_parallel();
+        let sequential: QueryResponse = QueryResponse::from(&res);
+        assert_eq!(parallel.data.logs, sequential.data.logs);
+        assert_eq!(parallel.data.logs[0].len(), block_numbers.len());
+        assert_eq!(
+            parallel.data.logs[0].last().unwrap().block_number,
+            Some((*block_numbers.last().unwrap()).into())
+        );
+        assert_eq!(parallel.next_block, 5);
+    }
+
+    #[test]
+    fn test_slice() {
+        let batch = batch(&[1, 2, 3, 4]);
+        let sliced = batch.slice(1, 2).unwrap();
+        assert_eq!(blocks(&sliced), vec![2, 3]);
+        assert_eq!(
+            sliced.column::<Utf8Array<i32>>("name").unwrap().value(0),
+            "2"
+        );
+        assert!(batch.slice(3, 2).is_err());
+    }
+
+    #[test]
+    fn test_u64_column() {
+        let batch = ArrowBatch {
+            chunk: Arc::new(ArrowChunk::new(vec![
+                UInt32Array::from(vec![Some(7), None]).boxed(),
+                Int64Array::from_slice([1, -1]).boxed(),
+                Utf8Array::<i32>::from_slice(["a", "b"]).boxed(),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("block_number", DataType::UInt32, true),
+                Field::new("log_index", DataType::Int64, true),
+                Field::new("name", DataType::Utf8, true),
+            ])),
+        };
+
+        let blocks = batch.u64_column("block_number").unwrap();
+        assert_eq!(blocks.iter().collect::<Vec<_>>(), vec![Some(&7), None]);
+        assert!(batch.u64_column("log_index").is_err());
+        assert!(batch.u64_column("name").is_err());
+        assert!(batch.u64_column("missing").is_err());
+    }
+
+    #[test]
+    fn test_split_at_blocks() {
+        let batch = batch(&[1, 1, 2, 3, 3, 3, 3, 4]);
+        assert_eq!(
+            batch.block_ranges("block_number").unwrap(),
+            vec![(1, 0, 2), (2, 2, 1), (3, 3, 4), (4, 7, 1)]
+        );
+
+        let slices = batch.split_at_blocks("block_number", 3).unwrap();
+        assert_eq!(
+            slices.iter().map(blocks).collect::<Vec<_>>(),
+            vec![vec![1, 1, 2], vec![3, 3, 3, 3], vec![4]]
+        );
+    }
 }
@@ -1,19 +1,24 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
 
-use alloy_dyn_abi::{DynSolType, DynSolValue, Specifier};
+use alloy_dyn_abi::{DecodedEvent, DynSolType, DynSolValue, Specifier};
 use alloy_json_abi::EventParam;
+use alloy_primitives::{Address, B256, I256, U256};
 use anyhow::{anyhow, Context, Result};
 use hypersync_schema::empty_chunk;
 use polars_arrow::{
     array::{
-        Array, ArrayFromIter, BinaryArray, BinaryViewArray, MutableArray, MutableBinaryArray,
-        MutableBooleanArray, MutableUtf8Array, Utf8Array, Utf8ViewArray,
+        growable::make_growable, Array, ArrayFromIter, BinaryArray, BinaryViewArray, BooleanArray,
+        ListArray, MutableArray, MutableBinaryArray, MutableBooleanArray, MutableUtf8Array,
+        StructArray, Utf8Array, Utf8ViewArray,
     },
     datatypes::{ArrowDataType as DataType, ArrowSchema as Schema, Field},
 };
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
-use crate::{ArrowBatch, ArrowChunk};
+use crate::{config::AddressShard, ArrowBatch, ArrowChunk};
 
 pub fn hex_encode_prefixed(bytes: &[u8]) -> String {
     let mut out = vec![0; bytes.len() * 2 + 2];
@@ -186,6 +191,330 @@ pub fn decode_logs_batch(sig: &str, batch: &ArrowBatch) -> Result<ArrowBatch> {
     })
 }
 
+/// Decodes only the logs whose topic0 matches the selector of the given event signature.
+pub fn decode_event_logs_batch(sig: &str, batch: &ArrowBatch) -> Result<ArrowBatch> {
+    let event = alloy_json_abi::Event::parse(sig).context("parse event signature")?;
+    if event.anonymous {
+        return Err(anyhow!(
+            "anonymous events can't be routed since they don't have a topic0"
+        ));
+    }
+    let selector = event.selector();
+
+    let topic0 = batch
+        .column::<BinaryArray<i32>>("topic0")
+        .context("get topic0 column, it is required for routing events")?;
+    batch
+        .column::<BinaryArray<i32>>("data")
+        .context("get data column")?;
+
+    let rows = topic0
+        .iter()
+        .enumerate()
+        .filter(|(_, topic0)| *topic0 == Some(selector.as_slice()))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    // The decoder only reads the data and topic1-3 columns so there is no need to filter the rest.
+    let (fields, cols) = batch
+        .schema
+        .fields
+        .iter()
+        .zip(batch.chunk.columns().iter())
+        .filter(|(field, _)| ["data", "topic1", "topic2", "topic3"].contains(&field.name.as_str()))
+        .map(|(field, col)| {
+            let col = col
+                .as_any()
+                .downcast_ref::<BinaryArray<i32>>()
+                .with_context(|| anyhow!("cast type of column '{}'", field.name))?;
+            let col = BinaryArray::<i32>::from_iter(rows.iter().map(|&i| col.get(i)));
+
+            Ok::<_, anyhow::Error>((field.clone(), col.boxed()))
+        })
+        .collect::<Result<(Vec<_>, Vec<_>)>>()?;
+
+    let filtered = ArrowBatch {
+        chunk: Arc::new(ArrowChunk::try_new(cols).context("create arrow chunk")?),
+        schema: Arc::new(Schema::from(fields)),
+    };
+
+    decode_logs_batch(sig, &filtered)
+}
+
+/// Converts a batch produced by `decode_logs_batch` back into decoded events, one per row.
+///
+/// Rows with a null value, e.g. logs whose body couldn't be decoded, are None.
+pub fn decoded_events_from_batch(
+    sig: &str,
+    batch: &ArrowBatch,
+) -> Result<Vec<Option<DecodedEvent>>> {
+    let sig = alloy_json_abi::Event::parse(sig).context("parse event signature")?;
+    let event = sig.resolve().context("resolve signature into event")?;
+    let num_indexed = event.indexed().len();
+
+    let cols = batch.chunk.columns();
+    if cols.len() != num_indexed + event.body().len() {
+        return Err(anyhow!(
+            "expected {} decoded columns for the event signature, got {}",
+            num_indexed + event.body().len(),
+            cols.len()
+        ));
+    }
+    let cols = cols
+        .iter()
+        .zip(event.indexed().iter().chain(event.body().iter()))
+        .zip(batch.schema.fields.iter())
+        .map(|((col, ty), field)| {
+            sol_values_from_col(col.as_ref(), ty)
+                .with_context(|| format!("read decoded column '{}'", field.name))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let selector = (!sig.anonymous).then(|| sig.selector());
+    Ok((0..batch.num_rows())
+        .map(|row| {
+            let mut indexed = cols
+                .iter()
+                .map(|col| col[row].clone())
+                .collect::<Option<Vec<_>>>()?;
+            let body = indexed.split_off(num_indexed);
+            Some(DecodedEvent {
+                selector,
+                indexed,
+                body,
+            })
+        })
+        .collect())
+}
+
+fn sol_values_from_col(col: &dyn Array, ty: &DynSolType) -> Result<Vec<Option<DynSolValue>>> {
+    match ty {
+        DynSolType::Bool => {
+            let col = col
+                .as_any()
+                .downcast_ref::<BooleanArray>()
+                .context("expected a boolean column")?;
+            Ok(col.iter().map(|v| v.map(DynSolValue::Bool)).collect())
+        }
+        DynSolType::String => {
+            let col = col
+                .as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .context("expected a utf8 column")?;
+            Ok(col
+                .iter()
+                .map(|v| v.map(|v| DynSolValue::String(v.to_owned())))
+                .collect())
+        }
+        _ => {
+            let col = col
+                .as_any()
+                .downcast_ref::<BinaryArray<i32>>()
+                .context("expected a binary column")?;
+            col.iter()
+                .map(|v| v.map(|v| sol_value_from_binary(v, ty)).transpose())
+                .collect()
+        }
+    }
+}
+
+/// Inverse of `push_sol_value_to_binary`.
+fn sol_value_from_binary(val: &[u8], ty: &DynSolType) -> Result<DynSolValue> {
+    let val = match ty {
+        DynSolType::Int(bits) => {
+            DynSolValue::Int(I256::try_from_be_slice(val).context("read int")?, *bits)
+        }
+        DynSolType::Uint(bits) => {
+            DynSolValue::Uint(U256::try_from_be_slice(val).context("read uint")?, *bits)
+        }
+        DynSolType::FixedBytes(size) => {
+            DynSolValue::FixedBytes(B256::try_from(val).context("read fixed bytes")?, *size)
+        }
+        DynSolType::Address => {
+            DynSolValue::Address(Address::try_from(val).context("read address")?)
+        }
+        DynSolType::Bytes => DynSolValue::Bytes(val.to_vec()),
+        ty => return Err(anyhow!("unsupported decoded type {}", ty)),
+    };
+    Ok(val)
+}
+
+/// Keeps only the logs whose `address` falls into the given shard.
+pub fn filter_logs_by_address_shard(batch: &ArrowBatch, shard: AddressShard) -> Result<ArrowBatch> {
+    let addresses = batch
+        .column::<BinaryArray<i32>>("address")
+        .context("get address column, it is required for sharding")?;
+
+    let rows = addresses
+        .iter()
+        .enumerate()
+        .filter(|(_, addr)| {
+            shard_of(addr.unwrap_or_default(), shard.num_shards.get()) == shard.index
+        })
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    take_rows(batch, &rows)
+}
+
+/// Returns a hex encoded address as lowercase 0x prefixed hex, whichever `HexOutput` it was
+/// encoded with.
+pub(crate) fn normalize_hex_address(addr: &str) -> String {
+    let addr = addr.to_ascii_lowercase();
+    match addr.strip_prefix("0x") {
+        Some(_) => addr,
+        None => format!("0x{}", addr),
+    }
+}
+
+/// Groups the rows of a logs batch by their `address`, keyed by the address as lowercase 0x
+/// prefixed hex. Rows without an address are grouped under `null`.
+pub(crate) fn group_rows_by_address(batch: &ArrowBatch) -> Result<BTreeMap<String, Vec<usize>>> {
+    let keys: Vec<String> = if let Ok(col) = batch.column::<BinaryArray<i32>>("address") {
+        col.iter()
+            .map(|addr| addr.map_or_else(|| "null".to_owned(), hex_encode_prefixed))
+            .collect()
+    } else {
+        // hex encoded by `StreamConfig::hex_output`
+        let col = batch
+            .column::<Utf8Array<i32>>("address")
+            .context("get address column, it is required for partitioning by address")?;
+        col.iter()
+            .map(|addr| addr.map_or_else(|| "null".to_owned(), normalize_hex_address))
+            .collect()
+    };
+
+    let mut groups = BTreeMap::<String, Vec<usize>>::new();
+    for (i, key) in keys.into_iter().enumerate() {
+        groups.entry(key).or_default().push(i);
+    }
+    Ok(groups)
+}
+
+/// Drops transactions that already appeared earlier in the given batches, keeping the first
+/// occurrence.
+///
+/// Transactions are identified by `hash`, or by `block_number` and `transaction_index` if the
+/// hash isn't selected. Fails if neither is in the batches.
+pub fn dedup_transactions(batches: &[ArrowBatch]) -> Result<Vec<ArrowBatch>> {
+    let mut seen = HashSet::new();
+
+    batches
+        .iter()
+        .map(|batch| {
+            let keys: Vec<Option<Vec<u8>>> = match batch.column::<BinaryArray<i32>>("hash") {
+                Ok(hashes) => hashes.iter().map(|h| h.map(|h| h.to_vec())).collect(),
+                Err(_) => {
+                    let blocks = batch.u64_column("block_number").context(
+                        "get hash or block_number column, one is required for deduplication",
+                    )?;
+                    let indices = batch.u64_column("transaction_index").context(
+                        "get hash or transaction_index column, one is required for deduplication",
+                    )?;
+                    blocks
+                        .iter()
+                        .zip(indices.iter())
+                        .map(|(block, idx)| {
+                            Some([block?.to_be_bytes(), idx?.to_be_bytes()].concat())
+                        })
+                        .collect()
+                }
+            };
+
+            let rows = keys
+                .into_iter()
+                .enumerate()
+                // rows without a key can't be matched so they are kept
+                .filter(|(_, key)| match key {
+                    Some(key) => seen.insert(key.clone()),
+                    None => true,
+                })
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+
+            take_rows(batch, &rows)
+        })
+        .collect()
+}
+
+/// Drops the rows at or before a resume position, read from the `block_column` block number
+/// column, e.g. `number` for blocks and `block_number` for the other tables.
+///
+/// Rows of blocks before `block_number` are dropped. Rows of `block_number` itself are dropped
+/// up to and including the given `(column, index)` position within the block, or all of them if
+/// no position is given.
+pub(crate) fn drop_rows_until(
+    batch: &ArrowBatch,
+    block_column: &str,
+    block_number: u64,
+    index: Option<(&str, u64)>,
+) -> Result<ArrowBatch> {
+    let column = |name: &str| {
+        batch.u64_column(name).with_context(|| {
+            format!(
+                "get {} column, it is required for config.resume_after",
+                name
+            )
+        })
+    };
+    let blocks = column(block_column)?;
+    let indices = index
+        .map(|(name, index)| Ok::<_, anyhow::Error>((column(name)?, index)))
+        .transpose()?;
+
+    let rows = blocks
+        .iter()
+        .enumerate()
+        .filter(|&(i, block)| match block {
+            Some(&block) if block == block_number => match indices.as_ref() {
+                Some((indices, index)) => !indices.is_valid(i) || indices.value(i) > *index,
+                None => false,
+            },
+            Some(&block) => block > block_number,
+            // rows without a block number can't be placed so they are kept
+            None => true,
+        })
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    take_rows(batch, &rows)
+}
+
+/// Returns the given rows of the batch as a new batch.
+pub(crate) fn take_rows(batch: &ArrowBatch, rows: &[usize]) -> Result<ArrowBatch> {
+    if rows.len() == batch.chunk.len() {
+        return Ok(batch.clone());
+    }
+
+    let cols = batch
+        .chunk
+        .columns()
+        .iter()
+        .map(|col| {
+            let mut growable = make_growable(&[&**col], true, rows.len());
+            for &row in rows.iter() {
+                // SAFETY: rows are collected from indices of this batch.
+                unsafe { growable.extend(0, row, 1) };
+            }
+            growable.as_box()
+        })
+        .collect::<Vec<_>>();
+
+    Ok(ArrowBatch {
+        chunk: Arc::new(ArrowChunk::try_new(cols).context("create arrow chunk")?),
+        schema: batch.schema.clone(),
+    })
+}
+
+/// Returns the address interpreted as a big endian integer modulo `num_shards`.
+fn shard_of(address: &[u8], num_shards: u64) -> u64 {
+    let num_shards = u128::from(num_shards);
+    let rem = address
+        .iter()
+        .fold(0u128, |rem, &b| (rem * 256 + u128::from(b)) % num_shards);
+    rem as u64
+}
+
 fn decode_body_col<'a, I: ExactSizeIterator<Item = Option<&'a DynSolValue>>>(
     vals: I,
     ty: &DynSolType,
@@ -353,6 +682,15 @@ fn push_sol_value_to_binary(
     Ok(())
 }
 
+/// Returns the names of the columns that decoding logs with the given signature produces.
+pub fn decoded_log_column_names(sig: &str) -> Result<Vec<String>> {
+    let sig = alloy_json_abi::Event::parse(sig).context("parse event signature")?;
+    let schema =
+        schema_from_event_signature(&sig).context("build arrow schema from event signature")?;
+
+    Ok(schema.fields.into_iter().map(|f| f.name).collect())
+}
+
 fn schema_from_event_signature(sig: &alloy_json_abi::Event) -> Result<Schema> {
     let event = sig.resolve().context("resolve signature into event")?;
 
@@ -433,23 +771,7 @@ pub fn map_batch_to_binary_view(batch: ArrowBatch) -> ArrowBatch {
         .chunk
         .arrays()
         .iter()
-        .map(|col| match col.data_type() {
-            DataType::Binary => BinaryViewArray::arr_from_iter(
-                col.as_any()
-                    .downcast_ref::<BinaryArray<i32>>()
-                    .unwrap()
-                    .iter(),
-            )
-            .boxed(),
-            DataType::Utf8 => Utf8ViewArray::arr_from_iter(
-                col.as_any()
-                    .downcast_ref::<Utf8Array<i32>>()
-                    .unwrap()
-                    .iter(),
-            )
-            .boxed(),
-            _ => col.clone(),
-        })
+        .map(|col| array_to_binary_view(&**col))
         .collect::<Vec<_>>();
 
     let fields = cols
@@ -475,10 +797,61 @@ pub fn map_batch_to_binary_view(batch: ArrowBatch) -> ArrowBatch {
     }
 }
 
+/// Maps Binary and Utf8 arrays, including the ones nested inside lists and structs, to their
+/// view counterparts.
+fn array_to_binary_view(col: &dyn Array) -> Box<dyn Array> {
+    match col.data_type() {
+        DataType::Binary => BinaryViewArray::arr_from_iter(
+            col.as_any()
+                .downcast_ref::<BinaryArray<i32>>()
+                .unwrap()
+                .iter(),
+        )
+        .boxed(),
+        DataType::Utf8 => Utf8ViewArray::arr_from_iter(
+            col.as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .unwrap()
+                .iter(),
+        )
+        .boxed(),
+        DataType::LargeList(field) => {
+            let col = col.as_any().downcast_ref::<ListArray<i64>>().unwrap();
+            let values = array_to_binary_view(&**col.values());
+            let field = Field::new(
+                field.name.clone(),
+                values.data_type().clone(),
+                field.is_nullable,
+            );
+            ListArray::<i64>::new(
+                DataType::LargeList(Box::new(field)),
+                col.offsets().clone(),
+                values,
+                col.validity().cloned(),
+            )
+            .boxed()
+        }
+        DataType::Struct(fields) => {
+            let col = col.as_any().downcast_ref::<StructArray>().unwrap();
+            let values = col
+                .values()
+                .iter()
+                .map(|v| array_to_binary_view(&**v))
+                .collect::<Vec<_>>();
+            let fields = fields
+                .iter()
+                .zip(values.iter())
+                .map(|(f, v)| Field::new(f.name.clone(), v.data_type().clone(), f.is_nullable))
+                .collect();
+            StructArray::new(DataType::Struct(fields), values, col.validity().cloned()).boxed()
+        }
+        _ => col.to_boxed(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use alloy_json_abi::Event;
-    use alloy_primitives::I256;
 
     use super::*;
 
@@ -515,4 +888,199 @@ mod tests {
 
         assert_eq!(input_val, output_val);
     }
+
+    #[test]
+    fn test_decode_event_logs_batch_filters_by_selector() {
+        let sig = "Approval(address indexed owner, address indexed spender, uint256 value)";
+        let selector = Event::parse(sig).unwrap().selector();
+        let other = [1u8; 32];
+        let owner = [2u8; 32];
+        let spender = [3u8; 32];
+        let value = [4u8; 32];
+
+        let topic0 = BinaryArray::<i32>::from_iter([Some(selector.as_slice()), Some(&other)]);
+        let topic1 = BinaryArray::<i32>::from_iter([Some(&owner), Some(&owner)]);
+        let topic2 = BinaryArray::<i32>::from_iter([Some(&spender), Some(&spender)]);
+        let data = BinaryArray::<i32>::from_iter([Some(&value), Some(&value)]);
+
+        let batch = ArrowBatch {
+            chunk: Arc::new(ArrowChunk::new(vec![
+                topic0.boxed(),
+                topic1.boxed(),
+                topic2.boxed(),
+                data.boxed(),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("topic0", DataType::Binary, true),
+                Field::new("topic1", DataType::Binary, true),
+                Field::new("topic2", DataType::Binary, true),
+                Field::new("data", DataType::Binary, true),
+            ])),
+        };
+
+        let decoded = decode_event_logs_batch(sig, &batch).unwrap();
+
+        assert_eq!(decoded.chunk.len(), 1);
+        assert!(decoded.column::<BinaryArray<i32>>("value").is_ok());
+    }
+
+    #[test]
+    fn test_decoded_events_from_batch() {
+        let sig = "Transfer(address indexed from, address indexed to, uint256 amount)";
+        let mut from = [0u8; 32];
+        from[12..].copy_from_slice(&[1; 20]);
+        let mut to = [0u8; 32];
+        to[12..].copy_from_slice(&[2; 20]);
+        let amount = U256::from(1_000u64).to_be_bytes::<32>();
+
+        let batch = ArrowBatch {
+            chunk: Arc::new(ArrowChunk::new(vec![
+                BinaryArray::<i32>::from_iter([Some(&from), Some(&from)]).boxed(),
+                BinaryArray::<i32>::from_iter([Some(&to), Some(&to)]).boxed(),
+                // the body of the second log is too short to decode
+                BinaryArray::<i32>::from_iter([Some(&amount[..]), Some(&amount[..4])]).boxed(),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("topic1", DataType::Binary, true),
+                Field::new("topic2", DataType::Binary, true),
+                Field::new("data", DataType::Binary, true),
+            ])),
+        };
+
+        let decoded = decode_logs_batch(sig, &batch).unwrap();
+        let events = decoded_events_from_batch(sig, &decoded).unwrap();
+
+        assert_eq!(events.len(), 2);
+        let event = events[0].as_ref().unwrap();
+        assert_eq!(event.selector, Some(Event::parse(sig).unwrap().selector()));
+        assert_eq!(
+            event.indexed,
+            [
+                DynSolValue::Address(Address::repeat_byte(1)),
+                DynSolValue::Address(Address::repeat_byte(2))
+            ]
+        );
+        assert_eq!(event.body, [DynSolValue::Uint(U256::from(1_000u64), 256)]);
+        assert!(events[1].is_none());
+
+        assert!(decoded_events_from_batch("Approval(address indexed owner)", &decoded).is_err());
+    }
+
+    #[test]
+    fn test_filter_logs_by_address_shard() {
+        let addresses = [[0u8; 20], [1u8; 20], [2u8; 20], [3u8; 20]];
+        let batch = ArrowBatch {
+            chunk: Arc::new(ArrowChunk::new(vec![
+                BinaryArray::<i32>::from_iter(addresses.iter().map(Some)).boxed(),
+                polars_arrow::array::UInt64Array::from_slice([0, 1, 2, 3]).boxed(),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("address", DataType::Binary, true),
+                Field::new("log_index", DataType::UInt64, true),
+            ])),
+        };
+
+        let mut log_indices = Vec::new();
+        for index in 0..3 {
+            let shard = AddressShard {
+                num_shards: std::num::NonZeroU64::new(3).unwrap(),
+                index,
+            };
+            let filtered = filter_logs_by_address_shard(&batch, shard).unwrap();
+            let col = filtered
+                .column::<polars_arrow::array::UInt64Array>("log_index")
+                .unwrap();
+            log_indices.push(col.values_iter().copied().collect::<Vec<_>>());
+        }
+
+        // 256 == 1 (mod 3) so an address of twenty equal bytes b is in shard 20 * b % 3
+        assert_eq!(log_indices, [vec![0, 3], vec![2], vec![1]]);
+    }
+
+    #[test]
+    fn test_dedup_transactions() {
+        let batch = |hashes: &[Option<&[u8]>], idx: &[u64]| ArrowBatch {
+            chunk: Arc::new(ArrowChunk::new(vec![
+                BinaryArray::<i32>::from_iter(hashes.iter().copied()).boxed(),
+                polars_arrow::array::UInt64Array::from_slice(idx).boxed(),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("hash", DataType::Binary, true),
+                Field::new("transaction_index", DataType::UInt64, true),
+            ])),
+        };
+        let indices = |batch: &ArrowBatch| {
+            batch
+                .column::<polars_arrow::array::UInt64Array>("transaction_index")
+                .unwrap()
+                .values_iter()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+
+        let deduped = dedup_transactions(&[
+            batch(&[Some(b"a"), Some(b"b"), Some(b"a"), None], &[0, 1, 2, 3]),
+            batch(&[Some(b"b"), Some(b"c"), None], &[4, 5, 6]),
+        ])
+        .unwrap();
+
+        assert_eq!(indices(&deduped[0]), vec![0, 1, 3]);
+        assert_eq!(indices(&deduped[1]), vec![5, 6]);
+    }
+
+    #[test]
+    fn test_drop_rows_until() {
+        let u64s = |values: &[Option<u64>]| {
+            polars_arrow::array::UInt64Array::from(values.to_vec()).boxed()
+        };
+        // logs of a response that overlaps the part of block 10 that was already handled
+        let logs = ArrowBatch {
+            chunk: Arc::new(ArrowChunk::new(vec![
+                u64s(&[
+                    Some(9),
+                    None,
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    Some(11),
+                ]),
+                u64s(&[Some(7), Some(0), Some(0), Some(1), None, Some(2), Some(0)]),
+            ])),
+            schema: Arc::new(Schema::from(vec![
+                Field::new("block_number", DataType::UInt64, true),
+                Field::new("log_index", DataType::UInt64, true),
+            ])),
+        };
+        let rows = |batch: &ArrowBatch| {
+            let blocks = batch.u64_column("block_number").unwrap();
+            let indices = batch.u64_column("log_index").unwrap();
+            blocks
+                .iter()
+                .zip(indices.iter())
+                .map(|(block, index)| (block.copied(), index.copied()))
+                .collect::<Vec<_>>()
+        };
+
+        let kept = drop_rows_until(&logs, "block_number", 10, Some(("log_index", 1))).unwrap();
+        assert_eq!(
+            rows(&kept),
+            [
+                (None, Some(0)),
+                (Some(10), None),
+                (Some(10), Some(2)),
+                (Some(11), Some(0))
+            ]
+        );
+        // the whole resume block was handled
+        let kept = drop_rows_until(&logs, "block_number", 10, None).unwrap();
+        assert_eq!(rows(&kept), [(None, Some(0)), (Some(11), Some(0))]);
+        let kept = drop_rows_until(&logs, "block_number", 8, Some(("log_index", 1))).unwrap();
+        assert_eq!(kept.chunk.len(), 7);
+
+        assert!(drop_rows_until(&logs, "number", 10, None).is_err());
+        assert!(
+            drop_rows_until(&logs, "block_number", 10, Some(("transaction_index", 1))).is_err()
+        );
+    }
 }
@@ -0,0 +1,336 @@
+use std::{fmt::Write as _, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use hypersync_net_types::Query;
+use polars_arrow::{
+    array::{get_display, Array, BinaryArray, BinaryViewArray, BooleanArray, PrimitiveArray},
+    datatypes::{ArrowDataType, Metadata},
+    io::ipc::write::{StreamWriter, WriteOptions},
+};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    config::OutputFormat, export_metadata::export_metadata, rayon_async, util::hex_encode_prefixed,
+    ArrowBatch, ArrowResponse, Client, StreamConfig,
+};
+
+pub async fn collect_to_writer<W: AsyncWrite + Unpin>(
+    client: Arc<Client>,
+    query: Query,
+    config: StreamConfig,
+    mut writer: W,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut encoder = Encoder::new(format);
+    if let OutputFormat::ArrowIpc = format {
+        encoder.metadata = export_metadata(&client, &query, config.export_timestamp)
+            .await
+            .context("get export metadata")?;
+    }
+
+    let mut rx = client
+        .stream_arrow(query, config)
+        .await
+        .context("start stream")?;
+
+    while let Some(resp) = rx.recv().await {
+        let resp = resp.context("get response")?;
+
+        let (buf, enc) = rayon_async::spawn(move || {
+            let mut buf = Vec::new();
+            let res = encoder.encode_response(&resp, &mut buf).map(|_| buf);
+            (res, encoder)
+        })
+        .await
+        .context("join encode task")?;
+        encoder = enc;
+        let buf = buf.context("encode response")?;
+
+        writer.write_all(&buf).await.context("write to output")?;
+    }
+
+    writer.flush().await.context("flush output")?;
+
+    Ok(())
+}
+
+/// Encodes responses into the given output format, keeping the state needed between batches.
+struct Encoder {
+    format: OutputFormat,
+    /// Table name and column names of the last CSV header that was written.
+    csv_header: Option<(String, Vec<String>)>,
+    /// Added to the schema metadata of Arrow IPC output.
+    metadata: Metadata,
+}
+
+impl Encoder {
+    fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            csv_header: None,
+            metadata: Metadata::new(),
+        }
+    }
+
+    fn encode_response(&mut self, resp: &ArrowResponse, buf: &mut Vec<u8>) -> Result<()> {
+        let data = &resp.data;
+        let tables = [
+            ("blocks", &data.blocks),
+            ("transactions", &data.transactions),
+            ("logs", &data.logs),
+            ("traces", &data.traces),
+            ("decoded_logs", &data.decoded_logs),
+            ("trace_summary", &data.trace_summary),
+        ]
+        .into_iter()
+        .chain(
+            data.decoded_events
+                .iter()
+                .map(|(name, batches)| (name.as_str(), batches)),
+        );
+
+        for (table, batches) in tables {
+            for batch in batches.iter().filter(|b| !b.chunk.is_empty()) {
+                self.encode_batch(table, batch, buf)
+                    .with_context(|| format!("encode {} batch", table))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn encode_batch(&mut self, table: &str, batch: &ArrowBatch, buf: &mut Vec<u8>) -> Result<()> {
+        match self.format {
+            OutputFormat::JsonLines => encode_json_lines(table, batch, buf),
+            OutputFormat::Csv => self.encode_csv(table, batch, buf),
+            OutputFormat::ArrowIpc => encode_arrow_ipc(table, batch, &self.metadata, buf),
+        }
+    }
+
+    fn encode_csv(&mut self, table: &str, batch: &ArrowBatch, buf: &mut Vec<u8>) -> Result<()> {
+        let names = batch
+            .schema
+            .fields
+            .iter()
+            .map(|f| f.name.clone())
+            .collect::<Vec<_>>();
+        let header = (table.to_owned(), names);
+
+        if self.csv_header.as_ref() != Some(&header) {
+            let mut line = String::from("table");
+            for name in header.1.iter() {
+                line.push(',');
+                push_csv_field(&mut line, name);
+            }
+            line.push('\n');
+            buf.extend_from_slice(line.as_bytes());
+            self.csv_header = Some(header);
+        }
+
+        let mut line = String::new();
+        for row in 0..batch.chunk.len() {
+            line.clear();
+            push_csv_field(&mut line, table);
+            for col in batch.chunk.columns() {
+                line.push(',');
+                if col.is_valid(row) {
+                    push_csv_field(&mut line, &value_to_string(&**col, row)?);
+                }
+            }
+            line.push('\n');
+            buf.extend_from_slice(line.as_bytes());
+        }
+
+        Ok(())
+    }
+}
+
+fn encode_json_lines(table: &str, batch: &ArrowBatch, buf: &mut Vec<u8>) -> Result<()> {
+    // Keys are written by hand instead of through serde_json::Map so they keep the column order.
+    for row in 0..batch.chunk.len() {
+        buf.extend_from_slice(b"{\"table\":");
+        serde_json::to_writer(&mut *buf, table).context("serialize table name")?;
+        for (field, col) in batch.schema.fields.iter().zip(batch.chunk.columns()) {
+            buf.push(b',');
+            serde_json::to_writer(&mut *buf, &field.name).context("serialize column name")?;
+            buf.push(b':');
+            serde_json::to_writer(&mut *buf, &value_to_json(&**col, row)?)
+                .context("serialize value")?;
+        }
+        buf.extend_from_slice(b"}\n");
+    }
+
+    Ok(())
+}
+
+fn encode_arrow_ipc(
+    table: &str,
+    batch: &ArrowBatch,
+    metadata: &Metadata,
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    let mut schema = (*batch.schema).clone();
+    schema.metadata = metadata.clone();
+    schema
+        .metadata
+        .insert("hypersync.table".to_owned(), table.to_owned());
+
+    let mut writer = StreamWriter::new(buf, WriteOptions { compression: None });
+    writer.start(&schema, None).context("write schema")?;
+    writer.write(&batch.chunk, None).context("write batch")?;
+    writer.finish().context("finish stream")?;
+
+    Ok(())
+}
+
+fn value_to_json(col: &dyn Array, row: usize) -> Result<serde_json::Value> {
+    if !col.is_valid(row) {
+        return Ok(serde_json::Value::Null);
+    }
+
+    macro_rules! primitive {
+        ($t:ty) => {
+            col.as_any()
+                .downcast_ref::<PrimitiveArray<$t>>()
+                .unwrap()
+                .value(row)
+                .into()
+        };
+    }
+
+    let value = match col.data_type() {
+        ArrowDataType::Boolean => col
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap()
+            .value(row)
+            .into(),
+        ArrowDataType::UInt8 => primitive!(u8),
+        ArrowDataType::UInt16 => primitive!(u16),
+        ArrowDataType::UInt32 => primitive!(u32),
+        ArrowDataType::UInt64 => primitive!(u64),
+        ArrowDataType::Int8 => primitive!(i8),
+        ArrowDataType::Int16 => primitive!(i16),
+        ArrowDataType::Int32 => primitive!(i32),
+        ArrowDataType::Int64 => primitive!(i64),
+        ArrowDataType::Float32 => primitive!(f32),
+        ArrowDataType::Float64 => primitive!(f64),
+        _ => value_to_string(col, row)?.into(),
+    };
+
+    Ok(value)
+}
+
+/// Formats a non-null value, binary values are formatted as prefixed hex.
+fn value_to_string(col: &dyn Array, row: usize) -> Result<String> {
+    match col.data_type() {
+        ArrowDataType::Binary => Ok(hex_encode_prefixed(
+            col.as_any()
+                .downcast_ref::<BinaryArray<i32>>()
+                .unwrap()
+                .value(row),
+        )),
+        ArrowDataType::BinaryView => Ok(hex_encode_prefixed(
+            col.as_any()
+                .downcast_ref::<BinaryViewArray>()
+                .unwrap()
+                .value(row),
+        )),
+        _ => {
+            let mut out = String::new();
+            get_display(col, "")(&mut out, row)
+                .map_err(|_| anyhow!("format {:?} value", col.data_type()))?;
+            Ok(out)
+        }
+    }
+}
+
+fn push_csv_field(line: &mut String, value: &str) {
+    if value.contains([',', '"', '\n', '\r']) {
+        line.push('"');
+        for c in value.chars() {
+            if c == '"' {
+                line.push('"');
+            }
+            line.push(c);
+        }
+        line.push('"');
+    } else {
+        line.write_str(value).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use polars_arrow::{
+        array::{UInt64Array, Utf8Array},
+        datatypes::{ArrowSchema, Field},
+    };
+
+    use super::*;
+    use crate::ArrowChunk;
+
+    fn batch() -> ArrowBatch {
+        let schema = ArrowSchema::from(vec![
+            Field::new("number", ArrowDataType::UInt64, false),
+            Field::new("hash", ArrowDataType::Binary, true),
+            Field::new("note", ArrowDataType::Utf8, true),
+        ]);
+        let chunk = ArrowChunk::new(vec![
+            UInt64Array::from_slice([1, 2]).boxed(),
+            BinaryArray::<i32>::from([Some(b"\xab".as_slice()), None]).boxed(),
+            Utf8Array::<i32>::from([Some("a,b"), Some("c")]).boxed(),
+        ]);
+
+        ArrowBatch {
+            chunk: Arc::new(chunk),
+            schema: Arc::new(schema),
+        }
+    }
+
+    #[test]
+    fn test_encode_json_lines() {
+        let mut buf = Vec::new();
+        encode_json_lines("blocks", &batch(), &mut buf).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"table\":\"blocks\",\"number\":1,\"hash\":\"0xab\",\"note\":\"a,b\"}\n\
+             {\"table\":\"blocks\",\"number\":2,\"hash\":null,\"note\":\"c\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_csv() {
+        let mut encoder = Encoder::new(OutputFormat::Csv);
+        let mut buf = Vec::new();
+        encoder.encode_batch("blocks", &batch(), &mut buf).unwrap();
+        encoder.encode_batch("blocks", &batch(), &mut buf).unwrap();
+
+        let expected_rows = "blocks,1,0xab,\"a,b\"\nblocks,2,,c\n";
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            format!("table,number,hash,note\n{expected_rows}{expected_rows}")
+        );
+    }
+
+    #[test]
+    fn test_encode_arrow_ipc_metadata() {
+        let mut encoder = Encoder::new(OutputFormat::ArrowIpc);
+        encoder
+            .metadata
+            .insert("hypersync.chain_id".to_owned(), "1".to_owned());
+        let mut buf = Vec::new();
+        encoder.encode_batch("logs", &batch(), &mut buf).unwrap();
+
+        let metadata =
+            polars_arrow::io::ipc::read::read_stream_metadata(&mut buf.as_slice()).unwrap();
+        assert_eq!(
+            metadata.schema.metadata,
+            Metadata::from([
+                ("hypersync.chain_id".to_owned(), "1".to_owned()),
+                ("hypersync.table".to_owned(), "logs".to_owned()),
+            ])
+        );
+    }
+}
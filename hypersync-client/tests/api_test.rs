@@ -520,3 +520,24 @@ async fn test_decode_string_param_into_arrow() {
 
     dbg!(data.data.decoded_logs);
 }
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn test_api_collect_block_index() {
+    let client = Arc::new(Client::new(ClientConfig::default()).unwrap());
+
+    let res = client
+        .collect_block_index(18_000_000, Some(18_001_000), StreamConfig::default())
+        .await
+        .unwrap();
+
+    assert_eq!(res.chunk.len(), 1_000);
+
+    let number = res.column::<UInt64Array>("number").unwrap();
+    let timestamp = res.column::<UInt64Array>("timestamp").unwrap();
+    assert_eq!(number.value(0), 18_000_000);
+    assert!(timestamp
+        .values_iter()
+        .zip(timestamp.values_iter().skip(1))
+        .all(|(a, b)| a <= b));
+}
@@ -19,6 +19,8 @@ pub enum Error {
     DecodeNumberFromHex(String),
     #[error("Invalid Bloom Filter from bytes")]
     BloomFilterFromBytes,
+    #[error("Invalid address checksum. Value was: \"{0}\"")]
+    InvalidChecksum(String),
 }
 
 pub type Result<T> = StdResult<T, Error>;
@@ -1,10 +1,12 @@
 mod error;
+mod parse_report;
 mod types;
 
 pub use error::{Error, Result};
+pub use parse_report::{parse_lines, parse_list, InvalidEntry, ParseReport};
 pub use types::{
     AccessList, Address, Block, BlockHeader, BlockNumber, BloomFilter, Data, DebugBlockTrace,
-    DebugTxTrace, FilterWrapper, FixedSizeData, Hash, Hex, Log, LogArgument, LogIndex, Nonce,
-    Quantity, Trace, TraceAction, TraceResult, Transaction, TransactionIndex, TransactionReceipt,
-    TransactionStatus, TransactionType, Withdrawal,
+    DebugTxTrace, FilterWrapper, FixedSizeData, Hash, Hex, LenientFixedSizeData, Log, LogArgument,
+    LogIndex, Nonce, Quantity, Trace, TraceAction, TraceResult, Transaction, TransactionIndex,
+    TransactionReceipt, TransactionStatus, TransactionType, Withdrawal,
 };
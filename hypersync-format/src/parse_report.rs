@@ -0,0 +1,113 @@
+use std::{fmt, result::Result as StdResult, str::FromStr};
+
+use crate::Error;
+
+/// Maximum number of invalid entries listed by the `Display` impl of `ParseReport`.
+const MAX_DISPLAYED: usize = 20;
+
+/// An entry that failed to parse, see `parse_list`.
+#[derive(Debug)]
+pub struct InvalidEntry {
+    /// 1-based line number of the entry.
+    pub line: usize,
+    /// The entry as it was given, without surrounding whitespace.
+    pub value: String,
+    /// Why the entry couldn't be parsed.
+    pub error: Error,
+}
+
+/// Every entry that failed to parse in a call to `parse_list` or `parse_lines`.
+#[derive(Debug)]
+pub struct ParseReport {
+    /// Invalid entries in the order they appeared.
+    pub invalid: Vec<InvalidEntry>,
+}
+
+impl fmt::Display for ParseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid entries:", self.invalid.len())?;
+        for entry in self.invalid.iter().take(MAX_DISPLAYED) {
+            write!(
+                f,
+                "\n  line {}: \"{}\": {}",
+                entry.line, entry.value, entry.error
+            )?;
+        }
+        if self.invalid.len() > MAX_DISPLAYED {
+            write!(f, "\n  ... and {} more", self.invalid.len() - MAX_DISPLAYED)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseReport {}
+
+/// Parses every entry of the list, e.g. the addresses of a log selection.
+///
+/// Entries are trimmed and empty ones are skipped. Instead of stopping at the first malformed
+/// entry, all of them are collected into the returned `ParseReport` with their line numbers so
+/// a large list can be fixed in one go.
+pub fn parse_list<T, I>(entries: I) -> StdResult<Vec<T>, ParseReport>
+where
+    T: FromStr<Err = Error>,
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut values = Vec::new();
+    let mut invalid = Vec::new();
+
+    for (idx, entry) in entries.into_iter().enumerate() {
+        let entry = entry.as_ref().trim();
+        if entry.is_empty() {
+            continue;
+        }
+        match entry.parse() {
+            Ok(value) => values.push(value),
+            Err(error) => invalid.push(InvalidEntry {
+                line: idx + 1,
+                value: entry.to_owned(),
+                error,
+            }),
+        }
+    }
+
+    if invalid.is_empty() {
+        Ok(values)
+    } else {
+        Err(ParseReport { invalid })
+    }
+}
+
+/// Parses one entry per line, see `parse_list`.
+pub fn parse_lines<T: FromStr<Err = Error>>(input: &str) -> StdResult<Vec<T>, ParseReport> {
+    parse_list(input.lines())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Address;
+
+    #[test]
+    fn test_parse_lines_reports_all_invalid() {
+        let input = "0xdAC17F958D2ee523a2206206994597C13D831ec7\n\
+                     \n\
+                     0x1234\n\
+                     \x20 0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48 \n\
+                     dAC17F958D2ee523a2206206994597C13D831ec7\n";
+
+        let report = parse_lines::<Address>(input).unwrap_err();
+        let lines = report.invalid.iter().map(|e| e.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 5]);
+        assert_eq!(report.invalid[0].value, "0x1234");
+        assert!(report
+            .to_string()
+            .starts_with("2 invalid entries:\n  line 3: \"0x1234\""));
+
+        let addresses = parse_lines::<Address>(
+            "0xdAC17F958D2ee523a2206206994597C13D831ec7\n0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+        )
+        .unwrap();
+        assert_eq!(addresses.len(), 2);
+    }
+}
@@ -133,7 +133,28 @@ impl<const N: usize> fmt::Display for FixedSizeData<N> {
     }
 }
 
-struct FixedSizeDataVisitor<const N: usize>;
+/// `FixedSizeData` that also deserializes from strings with surrounding whitespace, a `0X`
+/// prefix or no prefix at all, for input written by hand like queries.
+///
+/// `FixedSizeData` itself has to start with `0x` exactly, like `FromStr` requires. Upper and
+/// mixed case digits are accepted by both.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LenientFixedSizeData<const N: usize>(pub FixedSizeData<N>);
+
+impl<'de, const N: usize> Deserialize<'de> for LenientFixedSizeData<N> {
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_str(FixedSizeDataVisitor { lenient: true })
+            .map(Self)
+    }
+}
+
+struct FixedSizeDataVisitor<const N: usize> {
+    lenient: bool,
+}
 
 impl<'de, const N: usize> Visitor<'de> for FixedSizeDataVisitor<N> {
     type Value = FixedSizeData<N>;
@@ -146,7 +167,12 @@ impl<'de, const N: usize> Visitor<'de> for FixedSizeDataVisitor<N> {
     where
         E: de::Error,
     {
-        let buf = decode_hex(value).map_err(|e| E::custom(e.to_string()))?;
+        let buf = if self.lenient {
+            decode_hex_lenient(value)
+        } else {
+            decode_hex(value)
+        }
+        .map_err(|e| E::custom(e.to_string()))?;
 
         Self::Value::try_from(buf).map_err(|e| E::custom(e.to_string()))
     }
@@ -157,7 +183,7 @@ impl<'de, const N: usize> Deserialize<'de> for FixedSizeData<N> {
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_str(FixedSizeDataVisitor)
+        deserializer.deserialize_str(FixedSizeDataVisitor { lenient: false })
     }
 }
 
@@ -182,6 +208,16 @@ fn decode_hex(value: &str) -> Result<Vec<u8>> {
     super::util::decode_hex(val).map_err(Error::DecodeHex)
 }
 
+fn decode_hex_lenient(value: &str) -> Result<Vec<u8>> {
+    let value = value.trim();
+    let val = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+
+    super::util::decode_hex(val).map_err(Error::DecodeHex)
+}
+
 impl<const N: usize> fmt::Debug for FixedSizeData<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "FixedSizeData<{}>({})", N, self.encode_hex())
@@ -191,8 +227,10 @@ impl<const N: usize> fmt::Debug for FixedSizeData<N> {
 #[cfg(test)]
 mod tests {
     type FixedSizeData = super::FixedSizeData<4>;
+    type FSD4 = FixedSizeData;
     use hex_literal::hex;
     use serde_test::{assert_tokens, Token};
+    use std::str::FromStr;
 
     #[test]
     fn test_serde_empty() {
@@ -236,6 +274,20 @@ mod tests {
         assert!(data.is_err());
     }
 
+    #[test]
+    fn test_deserialize_lenient() {
+        let expected = FSD4::from(hex!("42feed00"));
+        for value in ["\"0x42FeEd00\"", "\"42feed00\"", "\" 0X42FEED00\\n\""] {
+            let data: super::LenientFixedSizeData<4> = serde_json::from_str(value).unwrap();
+            assert_eq!(data.0, expected);
+        }
+
+        let data: FSD4 = serde_json::from_str("\"0x42FeEd00\"").unwrap();
+        assert_eq!(data, expected);
+        assert!(serde_json::from_str::<FSD4>("\"42feed00\"").is_err());
+        assert!(serde_json::from_str::<FSD4>("\" 0x42feed00\"").is_err());
+    }
+
     /// test to_string
     #[test]
     fn test_display() {
@@ -14,7 +14,7 @@ mod withdrawal;
 
 pub use bloom_filter_wrapper::FilterWrapper;
 pub use data::Data;
-pub use fixed_size_data::FixedSizeData;
+pub use fixed_size_data::{FixedSizeData, LenientFixedSizeData};
 pub use hex::Hex;
 pub use quantity::Quantity;
 pub use transaction_status::TransactionStatus;
@@ -0,0 +1,240 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::{JoinMode, Query};
+
+/// Fields that are usually much larger than the others in their table, selecting them is the
+/// most common reason for huge responses.
+const LARGE_FIELDS: &[(&str, &[&str])] = &[
+    (
+        "block",
+        &["logs_bloom", "extra_data", "uncles", "withdrawals"],
+    ),
+    (
+        "transaction",
+        &[
+            "input",
+            "access_list",
+            "blob_versioned_hashes",
+            "logs_bloom",
+            "authorization_list",
+        ],
+    ),
+    ("log", &["data"]),
+    ("trace", &["input", "output", "init", "code"]),
+];
+
+/// Accumulates the conditions of one selection, which have an AND relationship.
+#[derive(Default)]
+struct Conditions(Vec<String>);
+
+impl Conditions {
+    fn any_of(&mut self, field: &str, num_values: usize) {
+        if num_values > 0 {
+            self.0.push(format!("{} in {} value(s)", field, num_values));
+        }
+    }
+
+    fn filter(&mut self, field: &str, is_set: bool) {
+        if is_set {
+            self.0
+                .push(format!("{} in bloom filter (checked client side)", field));
+        }
+    }
+
+    fn finish(self, table: &str) -> String {
+        if self.0.is_empty() {
+            format!("every {} in the range", table)
+        } else {
+            self.0.join(" AND ")
+        }
+    }
+}
+
+impl Query {
+    /// Returns a human readable description of what the query asks the server to do.
+    ///
+    /// Lists the selections applied to each table, the joins the server performs because of
+    /// the join mode, and the selected fields that dominate the payload size. Useful for finding
+    /// out why a query is slow or returns more data than expected.
+    pub fn explain(&self) -> String {
+        let mut out = String::new();
+
+        match self.to_block {
+            Some(to_block) => writeln!(
+                out,
+                "block range: [{}, {}), {} block(s)",
+                self.from_block,
+                to_block,
+                to_block.saturating_sub(self.from_block)
+            ),
+            None => writeln!(out, "block range: [{}, end of data)", self.from_block),
+        }
+        .unwrap();
+
+        let mut selections = Vec::new();
+        for sel in self.logs.iter() {
+            let mut c = Conditions::default();
+            c.any_of("address", sel.address.len());
+            c.filter("address", sel.address_filter.is_some());
+            for (i, topic) in sel.topics.iter().enumerate() {
+                c.any_of(&format!("topic{}", i), topic.len());
+            }
+            selections.push(("logs", c.finish("log")));
+        }
+        for sel in self.transactions.iter() {
+            let mut c = Conditions::default();
+            c.any_of("from", sel.from.len());
+            c.filter("from", sel.from_filter.is_some());
+            c.any_of("to", sel.to.len());
+            c.filter("to", sel.to_filter.is_some());
+            c.any_of("sighash", sel.sighash.len());
+            if let Some(status) = sel.status {
+                c.0.push(format!("status = {}", status));
+            }
+            c.any_of("type", sel.kind.len());
+            c.any_of("contract_address", sel.contract_address.len());
+            c.filter("contract_address", sel.contract_address_filter.is_some());
+            c.any_of("hash", sel.hash.len());
+            selections.push(("transactions", c.finish("transaction")));
+        }
+        for sel in self.traces.iter() {
+            let mut c = Conditions::default();
+            c.any_of("from", sel.from.len());
+            c.filter("from", sel.from_filter.is_some());
+            c.any_of("to", sel.to.len());
+            c.filter("to", sel.to_filter.is_some());
+            c.any_of("address", sel.address.len());
+            c.filter("address", sel.address_filter.is_some());
+            c.any_of("call_type", sel.call_type.len());
+            c.any_of("reward_type", sel.reward_type.len());
+            c.any_of("type", sel.kind.len());
+            c.any_of("sighash", sel.sighash.len());
+            selections.push(("traces", c.finish("trace")));
+        }
+        for sel in self.blocks.iter() {
+            let mut c = Conditions::default();
+            c.any_of("hash", sel.hash.len());
+            c.any_of("miner", sel.miner.len());
+            selections.push(("blocks", c.finish("block")));
+        }
+
+        writeln!(
+            out,
+            "selections (rows matching any selection are returned):"
+        )
+        .unwrap();
+        if selections.is_empty() {
+            writeln!(out, "  none").unwrap();
+        }
+        for (table, desc) in selections.iter() {
+            writeln!(out, "  {}: {}", table, desc).unwrap();
+        }
+        if self.include_all_blocks {
+            writeln!(
+                out,
+                "  blocks: every block in the range (include_all_blocks)"
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "joins ({:?} join mode):", self.join_mode).unwrap();
+        match self.join_mode {
+            JoinMode::Default => {
+                writeln!(out, "  transactions of matched logs").unwrap();
+                writeln!(out, "  traces of matched and joined transactions").unwrap();
+                writeln!(out, "  blocks of every returned row").unwrap();
+            }
+            JoinMode::JoinAll => {
+                writeln!(
+                    out,
+                    "  every table is joined to every other, e.g. a matched log brings in its \
+                     transaction, then all logs and traces of that transaction"
+                )
+                .unwrap();
+                writeln!(out, "  blocks of every returned row").unwrap();
+            }
+            JoinMode::JoinNothing => {
+                writeln!(out, "  none, only matched rows are returned").unwrap()
+            }
+        }
+
+        writeln!(out, "selected fields:").unwrap();
+        let tables: [(&str, &BTreeSet<String>); 4] = [
+            ("block", &self.field_selection.block),
+            ("transaction", &self.field_selection.transaction),
+            ("log", &self.field_selection.log),
+            ("trace", &self.field_selection.trace),
+        ];
+        for (table, fields) in tables {
+            if fields.is_empty() {
+                writeln!(out, "  {}: none, the table isn't returned", table).unwrap();
+                continue;
+            }
+            let large = LARGE_FIELDS
+                .iter()
+                .find(|(t, _)| *t == table)
+                .map(|(_, large)| {
+                    large
+                        .iter()
+                        .filter(|f| fields.contains(**f))
+                        .copied()
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            write!(out, "  {}: {} field(s)", table, fields.len()).unwrap();
+            if !large.is_empty() {
+                write!(out, ", large: {}", large.join(", ")).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
+
+        let limits = [
+            ("blocks", self.max_num_blocks),
+            ("transactions", self.max_num_transactions),
+            ("logs", self.max_num_logs),
+            ("traces", self.max_num_traces),
+        ]
+        .into_iter()
+        .filter_map(|(table, limit)| limit.map(|limit| format!("{} {}", limit, table)))
+        .collect::<Vec<_>>();
+        if !limits.is_empty() {
+            writeln!(out, "response size limits: {}", limits.join(", ")).unwrap();
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{LogSelection, TransactionSelection};
+
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        let mut query = Query {
+            from_block: 100,
+            to_block: Some(200),
+            logs: vec![LogSelection::default()],
+            transactions: vec![TransactionSelection {
+                sighash: vec![[1, 2, 3, 4].into()],
+                status: Some(1),
+                ..Default::default()
+            }],
+            max_num_logs: Some(1000),
+            ..Default::default()
+        };
+        query.field_selection.log.insert("data".into());
+        query.field_selection.log.insert("address".into());
+
+        let explained = query.explain();
+        assert!(explained.contains("block range: [100, 200), 100 block(s)"));
+        assert!(explained.contains("logs: every log in the range"));
+        assert!(explained.contains("transactions: sighash in 1 value(s) AND status = 1"));
+        assert!(explained.contains("transactions of matched logs"));
+        assert!(explained.contains("log: 2 field(s), large: data"));
+        assert!(explained.contains("transaction: none, the table isn't returned"));
+        assert!(explained.contains("response size limits: 1000 logs"));
+    }
+}
@@ -1,8 +1,15 @@
 use std::collections::BTreeSet;
 
 use arrayvec::ArrayVec;
-use hypersync_format::{Address, FilterWrapper, FixedSizeData, Hash, LogArgument};
-use serde::{Deserialize, Serialize};
+use hypersync_format::{
+    Address, FilterWrapper, FixedSizeData, Hash, LenientFixedSizeData, LogArgument,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+
+mod explain;
+mod range_set;
+
+pub use range_set::RangeSet;
 
 pub type Sighash = FixedSizeData<4>;
 
@@ -10,15 +17,37 @@ pub mod hypersync_net_types_capnp {
     include!(concat!(env!("OUT_DIR"), "/hypersync_net_types_capnp.rs"));
 }
 
+/// Deserializes the hex values of a selection leniently, see `LenientFixedSizeData`, so queries
+/// written by hand don't fail on formatting.
+fn lenient_hex<'de, D, const N: usize>(deserializer: D) -> Result<Vec<FixedSizeData<N>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values = Vec::<LenientFixedSizeData<N>>::deserialize(deserializer)?;
+    Ok(values.into_iter().map(|value| value.0).collect())
+}
+
+/// Like `lenient_hex`, for the topics of a log selection.
+fn lenient_topics<'de, D>(deserializer: D) -> Result<ArrayVec<Vec<LogArgument>, 4>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let topics = ArrayVec::<Vec<LenientFixedSizeData<32>>, 4>::deserialize(deserializer)?;
+    Ok(topics
+        .into_iter()
+        .map(|topic| topic.into_iter().map(|value| value.0).collect())
+        .collect())
+}
+
 #[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct BlockSelection {
     /// Hash of a block, any blocks that have one of these hashes will be returned.
     /// Empty means match all.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub hash: Vec<Hash>,
     /// Miner address of a block, any blocks that have one of these miners will be returned.
     /// Empty means match all.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub miner: Vec<Address>,
 }
 
@@ -26,13 +55,13 @@ pub struct BlockSelection {
 pub struct LogSelection {
     /// Address of the contract, any logs that has any of these addresses will be returned.
     /// Empty means match all.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub address: Vec<Address>,
     #[serde(default)]
     pub address_filter: Option<FilterWrapper>,
     /// Topics to match, each member of the top level array is another array, if the nth topic matches any
     ///  topic specified in nth element of topics, the log will be returned. Empty means match all.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_topics")]
     pub topics: ArrayVec<Vec<LogArgument>, 4>,
 }
 
@@ -41,19 +70,19 @@ pub struct TransactionSelection {
     /// Address the transaction should originate from. If transaction.from matches any of these, the transaction
     /// will be returned. Keep in mind that this has an and relationship with to filter, so each transaction should
     /// match both of them. Empty means match all.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub from: Vec<Address>,
     #[serde(default)]
     pub from_filter: Option<FilterWrapper>,
     /// Address the transaction should go to. If transaction.to matches any of these, the transaction will
     /// be returned. Keep in mind that this has an and relationship with from filter, so each transaction should
     /// match both of them. Empty means match all.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub to: Vec<Address>,
     #[serde(default)]
     pub to_filter: Option<FilterWrapper>,
     /// If first 4 bytes of transaction input matches any of these, transaction will be returned. Empty means match all.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub sighash: Vec<Sighash>,
     /// If transaction.status matches this value, the transaction will be returned.
     pub status: Option<u8>,
@@ -62,7 +91,7 @@ pub struct TransactionSelection {
     #[serde(default)]
     pub kind: Vec<u8>,
     /// If transaction.contract_address matches any of these values, the transaction will be returned.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub contract_address: Vec<Address>,
     /// Bloom filter to filter by transaction.contract_address field. If the bloom filter contains the hash
     /// of transaction.contract_address then the transaction will be returned. This field doesn't utilize the server side filtering
@@ -71,21 +100,21 @@ pub struct TransactionSelection {
     pub contract_address_filter: Option<FilterWrapper>,
     /// If transaction.hash matches any of these values the transaction will be returned.
     /// empty means match all.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub hash: Vec<Hash>,
 }
 
 #[derive(Default, Serialize, Deserialize, Clone, Debug)]
 pub struct TraceSelection {
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub from: Vec<Address>,
     #[serde(default)]
     pub from_filter: Option<FilterWrapper>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub to: Vec<Address>,
     #[serde(default)]
     pub to_filter: Option<FilterWrapper>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub address: Vec<Address>,
     #[serde(default)]
     pub address_filter: Option<FilterWrapper>,
@@ -96,7 +125,7 @@ pub struct TraceSelection {
     #[serde(default)]
     #[serde(rename = "type")]
     pub kind: Vec<String>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_hex")]
     pub sighash: Vec<Sighash>,
 }
 
@@ -160,19 +189,14 @@ pub struct Query {
     pub join_mode: JoinMode,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Copy)]
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Copy)]
 pub enum JoinMode {
+    #[default]
     Default,
     JoinAll,
     JoinNothing,
 }
 
-impl Default for JoinMode {
-    fn default() -> Self {
-        Self::Default
-    }
-}
-
 #[derive(Default, Serialize, Deserialize, Clone, Debug)]
 pub struct FieldSelection {
     #[serde(default)]
@@ -196,7 +220,7 @@ pub struct ChainId {
 }
 
 /// Guard for detecting rollbacks
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RollbackGuard {
     /// Block number of last block scanned in memory
     pub block_number: u64,
@@ -209,3 +233,41 @@ pub struct RollbackGuard {
     /// Parent hash of first block scanned in memory
     pub first_parent_hash: Hash,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_hand_written_hex() {
+        let json = r#"{
+            "from_block": 0,
+            "logs": [{
+                "address": ["0xDAC17F958D2EE523A2206206994597C13D831EC7"],
+                "topics": [[" ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"]]
+            }],
+            "transactions": [{"sighash": ["A9059CBB"]}]
+        }"#;
+
+        let query: Query = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            query.logs[0].address[0].to_string(),
+            "0xdac17f958d2ee523a2206206994597c13d831ec7"
+        );
+        assert_eq!(
+            query.logs[0].topics[0][0].to_string(),
+            "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+        assert_eq!(query.transactions[0].sighash[0].to_string(), "0xa9059cbb");
+
+        // data from the server is parsed strictly
+        let guard = r#"{
+            "block_number": 1,
+            "timestamp": 2,
+            "hash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "first_block_number": 1,
+            "first_parent_hash": "0000000000000000000000000000000000000000000000000000000000000000"
+        }"#;
+        assert!(serde_json::from_str::<RollbackGuard>(guard).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Query;
+
+/// A normalized set of half open `[from_block, to_block)` block ranges.
+///
+/// Inserted ranges are merged with any ranges they overlap or touch, so the set always holds
+/// disjoint, sorted ranges. This is useful for splitting an export over several queries or
+/// workers without fetching any block twice.
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<Range<u64>>", into = "Vec<Range<u64>>")]
+pub struct RangeSet {
+    /// Maps the start of each range to its end.
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl RangeSet {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a range, merging it with the ranges it overlaps or touches.
+    ///
+    /// Returns the parts of the range that were already in the set. Empty ranges are ignored.
+    pub fn insert(&mut self, range: Range<u64>) -> Vec<Range<u64>> {
+        if range.is_empty() {
+            return Vec::new();
+        }
+
+        let overlaps = self.intersection(&range);
+
+        let mut start = range.start;
+        let mut end = range.end;
+
+        // The only range that starts before `start` and can touch it is the one right before it.
+        if let Some((&prev_start, &prev_end)) = self.ranges.range(..start).next_back() {
+            if prev_end >= start {
+                start = prev_start;
+                end = end.max(prev_end);
+            }
+        }
+
+        let merged = self
+            .ranges
+            .range(start..=end)
+            .map(|(&s, &e)| (s, e))
+            .collect::<Vec<_>>();
+        for (s, e) in merged {
+            self.ranges.remove(&s);
+            end = end.max(e);
+        }
+
+        self.ranges.insert(start, end);
+
+        overlaps
+    }
+
+    /// Removes a range from the set, splitting the ranges it partially covers.
+    pub fn remove(&mut self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let affected = self
+            .ranges
+            .range(..range.end)
+            .filter(|(_, &e)| e > range.start)
+            .map(|(&s, &e)| (s, e))
+            .collect::<Vec<_>>();
+
+        for (s, e) in affected {
+            self.ranges.remove(&s);
+            if s < range.start {
+                self.ranges.insert(s, range.start);
+            }
+            if e > range.end {
+                self.ranges.insert(range.end, e);
+            }
+        }
+    }
+
+    /// Returns the parts of the given range that are in the set.
+    pub fn intersection(&self, range: &Range<u64>) -> Vec<Range<u64>> {
+        self.ranges
+            .range(..range.end)
+            .filter(|(_, &e)| e > range.start)
+            .map(|(&s, &e)| s.max(range.start)..e.min(range.end))
+            .collect()
+    }
+
+    /// Returns the parts of the given range that are not in the set.
+    pub fn gaps(&self, within: Range<u64>) -> Vec<Range<u64>> {
+        let mut gaps = Vec::new();
+        let mut offset = within.start;
+
+        for covered in self.intersection(&within) {
+            if covered.start > offset {
+                gaps.push(offset..covered.start);
+            }
+            offset = covered.end;
+        }
+        if offset < within.end {
+            gaps.push(offset..within.end);
+        }
+
+        gaps
+    }
+
+    /// Returns true if the block is in the set.
+    pub fn contains(&self, block: u64) -> bool {
+        self.ranges
+            .range(..=block)
+            .next_back()
+            .is_some_and(|(_, &e)| e > block)
+    }
+
+    /// Iterates over the disjoint ranges in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = Range<u64>> + '_ {
+        self.ranges.iter().map(|(&s, &e)| s..e)
+    }
+
+    /// Total number of blocks in the set.
+    pub fn num_blocks(&self) -> u64 {
+        self.ranges.iter().map(|(s, e)| e - s).sum()
+    }
+
+    /// Returns true if the set doesn't contain any blocks.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Creates one copy of the query per range, with `from_block` and `to_block` set to the
+    /// bounds of that range.
+    pub fn queries(&self, query: &Query) -> Vec<Query> {
+        self.iter()
+            .map(|range| Query {
+                from_block: range.start,
+                to_block: Some(range.end),
+                ..query.clone()
+            })
+            .collect()
+    }
+}
+
+impl FromIterator<Range<u64>> for RangeSet {
+    fn from_iter<T: IntoIterator<Item = Range<u64>>>(iter: T) -> Self {
+        let mut set = Self::new();
+        for range in iter {
+            set.insert(range);
+        }
+        set
+    }
+}
+
+impl From<Vec<Range<u64>>> for RangeSet {
+    fn from(ranges: Vec<Range<u64>>) -> Self {
+        ranges.into_iter().collect()
+    }
+}
+
+impl From<RangeSet> for Vec<Range<u64>> {
+    fn from(set: RangeSet) -> Self {
+        set.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_merges_and_reports_overlaps() {
+        let mut set = RangeSet::new();
+        assert!(set.insert(10..20).is_empty());
+        assert!(set.insert(30..40).is_empty());
+        // touching ranges are merged without being reported as overlaps
+        assert!(set.insert(20..25).is_empty());
+        assert_eq!(set.insert(15..35), vec![15..25, 30..35]);
+
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![10..40]);
+        assert_eq!(set.num_blocks(), 30);
+    }
+
+    #[test]
+    fn test_remove_and_gaps() {
+        let mut set = RangeSet::new();
+        set.insert(0..100);
+        set.remove(20..30);
+        set.remove(90..120);
+
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![0..20, 30..90]);
+        assert_eq!(set.gaps(10..100), vec![20..30, 90..100]);
+        assert!(set.contains(19));
+        assert!(!set.contains(20));
+        assert!(set.contains(30));
+    }
+}
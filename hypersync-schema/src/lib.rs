@@ -77,9 +77,9 @@ pub fn transaction() -> SchemaRef {
         Field::new("max_priority_fee_per_gas", quantity_dt(), true),
         Field::new("max_fee_per_gas", quantity_dt(), true),
         Field::new("chain_id", quantity_dt(), true),
-        Field::new("cumulative_gas_used", quantity_dt(), false),
-        Field::new("effective_gas_price", quantity_dt(), false),
-        Field::new("gas_used", quantity_dt(), false),
+        Field::new("cumulative_gas_used", quantity_dt(), true),
+        Field::new("effective_gas_price", quantity_dt(), true),
+        Field::new("gas_used", quantity_dt(), true),
         Field::new("contract_address", addr_dt(), true),
         Field::new("logs_bloom", DataType::BinaryView, false),
         Field::new("type", DataType::UInt8, true),